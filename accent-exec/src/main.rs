@@ -1,25 +1,30 @@
-use accent::ast;
-use accent::parser;
-use accent::token;
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 use std::fs;
 use std::path::Path;
 
-fn run_file(data: String) {
-    let mut toks = accent::token::gen_toks(data);
-    let mut sts = accent::ast::stmt_gen(&toks);
-
-    let mut md = accent::Module::new();
-    md.stmts = sts;
+fn run_file(data: String, tree_walker: bool) {
+    accent::parser::stack::set_budget(accent::STACK_SIZE / 2);
+    if tree_walker {
+        accent::parser::set_mode(accent::parser::Mode::Tree);
+    }
 
-    let fref = accent::parser::function::Function::Native {
-        name: String::from("print"),
-        f: accent::native_print,
+    let toks = accent::token::gen_toks(data);
+    let sts = match accent::ast::stmt_gen(&toks) {
+        Ok(sts) => sts,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     };
 
-    md.add_var(&String::from("print"), accent::Object::Funct(fref));
+    let mut md = accent::Module::new();
+    md.stmts = sts;
+    accent::load_builtins(&mut md);
 
-    accent::parser::mod_exec(&mut md);
+    if let accent::Completion::Throw(e) = accent::parser::mod_exec(&mut md) {
+        eprintln!("{}", accent::parser::errors::uncaught_message(&e));
+        std::process::exit(1);
+    }
 }
 
 fn main() {
@@ -27,6 +32,12 @@ fn main() {
         .version("0.1.0")
         .about("A JavaScript interpreter written in Rust")
         .arg(Arg::new("file").help("JavaScript file to execute").index(1))
+        .arg(
+            Arg::new("tree-walker")
+                .long("tree-walker")
+                .action(ArgAction::SetTrue)
+                .help("Run by walking the syntax tree instead of compiling to bytecode"),
+        )
         .get_matches();
 
     // println!("Arguments received:");
@@ -40,7 +51,14 @@ fn main() {
             println!("File to execute: {}", file_path);
 
             let data = fs::read_to_string(path).unwrap();
-            run_file(data);
+            let tree_walker = matches.get_flag("tree-walker");
+
+            std::thread::Builder::new()
+                .stack_size(accent::STACK_SIZE)
+                .spawn(move || run_file(data, tree_walker))
+                .unwrap()
+                .join()
+                .unwrap();
         } else {
             eprintln!("Error: File '{}' does not exist", file_path);
             std::process::exit(1);
@@ -0,0 +1,111 @@
+/*
+ * Times a few loop- and call-heavy scripts on the VM and on the tree-walker.
+ *
+ *     cargo run --release --example bench [runs]
+ *
+ * Each script runs `runs` times (5 by default) in each mode; the best time counts.
+ */
+
+use std::time::{Duration, Instant};
+
+use accent::parser::{self, Mode};
+use accent::{ast, token, Completion, Module};
+
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "loop",
+        "let sum = 0;
+         for (let i = 0; i < 1000000; i++) { sum = sum + i % 7; }",
+    ),
+    (
+        "nested",
+        "let n = 0;
+         for (let i = 0; i < 1000; i++) {
+             for (let j = 0; j < 1000; j++) { if ((i ^ j) & 1) n++; }
+         }",
+    ),
+    (
+        "array",
+        "let a = [];
+         for (let i = 0; i < 200000; i++) a.push(i * 2);
+         let s = 0;
+         for (let k = 0; k < 5; k++) {
+             for (let i = 0; i < a.length; i++) { if (a[i] % 3 == 0) s += a[i]; }
+         }",
+    ),
+    (
+        "calls",
+        "function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+         fib(25);",
+    ),
+    (
+        "objects",
+        "class P { constructor(x) { this.x = x; } step() { this.x = this.x + 1; return this; } }
+         let p = new P(0);
+         let o = { n: 0 };
+         for (let i = 0; i < 200000; i++) { p.step(); o.n += p.x % 3; }",
+    ),
+    (
+        "closures",
+        "let fs = [];
+         for (let i = 0; i < 100000; i++) fs.push(() => i);
+         let t = 0;
+         for (let i = 0; i < fs.length; i++) t += fs[i]();",
+    ),
+];
+
+/* the best of `runs` runs of `src` in `mode` */
+fn time(src: &str, mode: Mode, runs: usize) -> Duration {
+    let stmts = ast::stmt_gen(&token::gen_toks(src.to_string())).expect("benchmarks parse");
+    parser::set_mode(mode);
+
+    (0..runs)
+        .map(|_| {
+            let mut md = Module::new();
+            md.stmts = stmts.clone();
+            accent::load_builtins(&mut md);
+
+            let start = Instant::now();
+            let c = parser::mod_exec(&mut md);
+            let t = start.elapsed();
+
+            assert!(matches!(c, Completion::Normal), "benchmark failed: {c:?}");
+            t
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    let runs = std::env::args()
+        .nth(1)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(5);
+
+    let report = move || {
+        parser::stack::set_budget(accent::STACK_SIZE / 2);
+        println!(
+            "{:<10} {:>10} {:>10} {:>8}",
+            "script", "tree", "vm", "speedup"
+        );
+
+        for (name, src) in SCRIPTS {
+            let tree = time(src, Mode::Tree, runs);
+            let vm = time(src, Mode::Vm, runs);
+
+            println!(
+                "{name:<10} {:>8.1}ms {:>8.1}ms {:>7.1}x",
+                tree.as_secs_f64() * 1000.0,
+                vm.as_secs_f64() * 1000.0,
+                tree.as_secs_f64() / vm.as_secs_f64(),
+            );
+        }
+    };
+
+    std::thread::Builder::new()
+        .stack_size(accent::STACK_SIZE)
+        .spawn(report)
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -1,735 +1,2021 @@
-use std::collections::HashMap;
+pub use crate::token::{EOperator, Lexeme, Span, Token};
+pub use error::{ParseError, ParseErrorKind};
+pub use expr::{bigint, constants, Expr, ExprKind, FuncForm, ObjProp, PropKey, TemplateSite};
+pub use stmt::{ClassMember, MethodKind, Stmt, StmtKind, VarQualifier};
 
-use crate::parser::function::Function;
-pub use crate::token::{EOperator, Token};
-pub use arithmetic::Arithmetic;
-pub use expr::{constants, Expr};
-pub use stmt::{Stmt, VarQualifier};
-
-mod arithmetic;
+mod error;
 mod expr;
 mod stmt;
 
-pub fn expr_gen(toks: &[Token]) -> expr::Expr {
-    let mut res: Expr = Expr::Const(constants::Const::Undef);
-    let mut i = 0;
+use std::rc::Rc;
+
+use crate::parser::stack;
+use crate::token::is_line_terminator;
+
+/* span covering every token of `toks` */
+fn span_of(toks: &[Lexeme]) -> Span {
+    match (toks.first(), toks.last()) {
+        (Some(f), Some(l)) => f.span.to(l.span),
+        _ => Span::default(),
+    }
+}
+
+/* zero-width span just past the last token, used for "found end of input" */
+fn eof_span(toks: &[Lexeme]) -> Span {
+    match toks.last() {
+        Some(l) => Span {
+            start: l.span.end,
+            end: l.span.end,
+            line: l.span.line,
+            col: l.span.col + (l.span.end - l.span.start),
+        },
+        None => Span {
+            start: 0,
+            end: 0,
+            line: 1,
+            col: 1,
+        },
+    }
+}
+
+/**
+ * Drops newlines and comments, which the parser does not look at,
+ * and records for every remaining token whether a line break came before it (for ASI).
+ * A block comment spanning lines counts as a line break.
+ */
+fn strip_trivia(toks: &[Lexeme]) -> (Vec<Lexeme>, Vec<bool>) {
+    let mut res = Vec::new();
+    let mut nl_before = Vec::new();
+    let mut saw_nl = false;
+
+    for l in toks {
+        match &l.tok {
+            Token::Newline => saw_nl = true,
+            Token::Comment(c) => saw_nl |= c.starts_with("/*") && c.contains(is_line_terminator),
+            _ => {
+                res.push(l.clone());
+                nl_before.push(saw_nl);
+                saw_nl = false;
+            }
+        }
+    }
+
+    (res, nl_before)
+}
+
+/* for every `(`, the index of the `)` closing it, or None if it is never closed */
+fn closing_parens(toks: &[Lexeme]) -> Vec<Option<usize>> {
+    let mut res = vec![None; toks.len()];
+    let mut open = Vec::new();
+
+    for (j, l) in toks.iter().enumerate() {
+        match l.tok {
+            Token::Operator(EOperator::LParen) => open.push(j),
+            Token::Operator(EOperator::RParen) => {
+                if let Some(o) = open.pop() {
+                    res[o] = Some(j);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    res
+}
+
+fn is_op(l: Option<&Lexeme>, op: EOperator) -> bool {
+    matches!(l, Some(Lexeme { tok: Token::Operator(o), .. }) if *o == op)
+}
+
+/* left and right binding power of a binary operator; the higher, the tighter it binds */
+fn binary_bp(tok: &Token) -> Option<(u8, u8)> {
+    let bp = match tok {
+        Token::Operator(o) => match o {
+            EOperator::Nullish | EOperator::Or => (4, 5),
+            EOperator::And => (6, 7),
+            EOperator::BitOr => (8, 9),
+            EOperator::BitXor => (10, 11),
+            EOperator::BitAnd => (12, 13),
+            EOperator::Eqeq | EOperator::Neq | EOperator::Eqeqeq | EOperator::Neqeq => (14, 15),
+            EOperator::Le | EOperator::Ge | EOperator::Leq | EOperator::Geq => (16, 17),
+            EOperator::Lshift | EOperator::Rshift | EOperator::Urshift => (18, 19),
+            EOperator::Plus | EOperator::Minus => (20, 21),
+            EOperator::Multiply | EOperator::Divide | EOperator::Modulus => (22, 23),
+            /* right associative: `a ** b ** c` is `a ** (b ** c)` */
+            EOperator::Pow => (25, 24),
+            _ => return None,
+        },
+        Token::Keyword(k) if k == "in" || k == "instanceof" => (16, 17),
+        _ => return None,
+    };
+
+    Some(bp)
+}
+
+/* cooked strings, raw strings and substitutions of a template literal */
+type TemplateParts = (Vec<Option<constants::JsStr>>, Vec<String>, Vec<Expr>);
+
+/* what `super` may be used for where the cursor is */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SuperUse {
+    #[default]
+    None,
+    /* `super.prop`, in class methods and initializers */
+    Member,
+    /* also `super(args)`, in the constructor of a class that extends another */
+    Call,
+}
+
+/* recursive descent parser for statements, Pratt parser for expressions; walks the token list with a cursor */
+
+struct Parser {
+    toks: Vec<Lexeme>,
+    i: usize,
+    eof: Span,
+    loop_depth: usize, /* loops enclosing the cursor inside the current function */
+    switch_depth: usize, /* the same for `switch` statements, which `break` also leaves */
+    labels: Vec<(String, bool)>, /* enclosing labels, and whether each one labels a loop */
+    parenthesized: Vec<Span>, /* spans of expressions written inside `( )` */
+    nl_before: Vec<bool>, /* whether a line break precedes each token */
+    closing: Vec<Option<usize>>, /* where each `(` is closed, so arrows are spotted without rescanning */
+    super_use: SuperUse,         /* what the enclosing class member allows `super` to do */
+}
+
+impl Parser {
+    fn new(toks: &[Lexeme]) -> Parser {
+        let (toks, nl_before) = strip_trivia(toks);
+        let closing = closing_parens(&toks);
+
+        Parser {
+            eof: eof_span(&toks),
+            toks,
+            i: 0,
+            loop_depth: 0,
+            switch_depth: 0,
+            labels: Vec::new(),
+            parenthesized: Vec::new(),
+            nl_before,
+            closing,
+            super_use: SuperUse::None,
+        }
+    }
+
+    /* whether a line break separates the token `n` places ahead of the cursor from the one before it */
+    fn newline_at(&self, n: usize) -> bool {
+        self.nl_before.get(self.i + n).copied().unwrap_or(false)
+    }
+
+    fn peek(&self) -> Option<&Lexeme> {
+        self.toks.get(self.i)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&Lexeme> {
+        self.toks.get(self.i + n)
+    }
+
+    fn err_expected(&self, what: &str) -> ParseError {
+        ParseError::expected(what, self.peek(), self.eof)
+    }
+
+    fn expect_op(&mut self, op: EOperator) -> Result<(), ParseError> {
+        if is_op(self.peek(), op.clone()) {
+            self.i += 1;
+            Ok(())
+        } else {
+            Err(self.err_expected(&format!("'{op}'")))
+        }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> Result<(String, Span), ParseError> {
+        match self.peek() {
+            Some(Lexeme {
+                tok: Token::Identifier(id),
+                span,
+            }) => {
+                let r = (id.clone(), *span);
+                self.i += 1;
+                Ok(r)
+            }
+            _ => Err(self.err_expected(what)),
+        }
+    }
+
+    /* span from the token at `start` up to the last consumed token */
+    fn span_from(&self, start: usize) -> Span {
+        let end = self.i.max(start + 1).min(self.toks.len());
+        span_of(&self.toks[start.min(end)..end])
+    }
+
+    /**
+     * `;`, or automatic semicolon insertion: the semicolon may be left out
+     * before a line break, a closing `}` or the end of input.
+     */
+    fn terminator(&mut self) -> Result<(), ParseError> {
+        match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(EOperator::Semicolon)) => {
+                self.i += 1;
+                Ok(())
+            }
+            Some(Token::Operator(EOperator::RBrace)) | None => Ok(()),
+            Some(_) if self.newline_at(0) => Ok(()),
+            Some(_) => Err(self.err_expected("';'")),
+        }
+    }
+
+    /* whether a statement ends here, e.g. after `return`; no line break is allowed in between */
+    fn at_stmt_end(&self) -> bool {
+        self.newline_at(0)
+            || matches!(
+                self.peek().map(|l| &l.tok),
+                Some(Token::Operator(EOperator::Semicolon | EOperator::RBrace)) | None
+            )
+    }
+
+    /* Expression: assignment expressions separated by commas */
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let first = self.assign()?;
+
+        if !is_op(self.peek(), EOperator::Comma) {
+            return Ok(first);
+        }
+
+        let mut v = vec![first];
+        while is_op(self.peek(), EOperator::Comma) {
+            self.i += 1;
+            v.push(self.assign()?);
+        }
+
+        Ok(Expr::new(ExprKind::Sequence(v), self.span_from(start)))
+    }
+
+    /* rejects nesting once it has used up the stack budget, e.g. thousands of `(` or `{` */
+    fn check_depth(&self) -> Result<(), ParseError> {
+        match stack::exhausted() {
+            true => Err(ParseError::new(
+                ParseErrorKind::TooDeep,
+                self.peek(),
+                self.eof,
+                String::from("too much nesting"),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    fn error_at(&self, at: usize, msg: &str) -> ParseError {
+        ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            self.toks.get(at),
+            self.eof,
+            String::from(msg),
+        )
+    }
+
+    fn invalid_target(&self, start: usize, msg: &str) -> ParseError {
+        ParseError::new(
+            ParseErrorKind::InvalidAssignmentTarget,
+            self.toks.get(start),
+            self.eof,
+            String::from(msg),
+        )
+    }
+
+    /* whether the cursor is at the parameters of an arrow function: `x =>` or `(...) =>` */
+    fn at_arrow(&self) -> bool {
+        match self.peek().map(|l| &l.tok) {
+            /* no line break is allowed before `=>` */
+            Some(Token::Identifier(_)) => {
+                is_op(self.peek_at(1), EOperator::Arrow) && !self.newline_at(1)
+            }
+            Some(Token::Operator(EOperator::LParen)) => match self.closing[self.i] {
+                Some(j) => {
+                    is_op(self.toks.get(j + 1), EOperator::Arrow)
+                        && !self.nl_before.get(j + 1).copied().unwrap_or(false)
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /* `x => ...`, `(a, b) => ...`; the body is a block or a single expression */
+    fn arrow(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+
+        let args = if is_op(self.peek(), EOperator::LParen) {
+            self.params()?
+        } else {
+            let (name, span) = self.expect_ident("parameter name")?;
+            vec![Expr::new(ExprKind::Var(name), span)]
+        };
+
+        self.expect_op(EOperator::Arrow)?;
+
+        let body = if is_op(self.peek(), EOperator::LBrace) {
+            self.fn_body()?
+        } else {
+            let e = self.assign()?;
+            let span = e.span;
+            vec![Stmt::new(StmtKind::ReturnStmt(e), span)]
+        };
+
+        Ok(Expr::new(
+            ExprKind::Function {
+                name: None,
+                args: args.into(),
+                body: body.into(),
+                form: FuncForm::Arrow,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* `function name?(args) { body }` as an expression */
+    fn function_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let name = match self.peek().map(|l| &l.tok) {
+            Some(Token::Identifier(_)) => Some(self.expect_ident("function name")?.0),
+            _ => None,
+        };
+        let args = self.params()?;
+        let body = self.plain_body()?;
+
+        Ok(Expr::new(
+            ExprKind::Function {
+                name,
+                args: args.into(),
+                body: body.into(),
+                form: FuncForm::Plain,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* AssignmentExpression; `=` and the compound forms associate to the right */
+    fn assign(&mut self) -> Result<Expr, ParseError> {
+        self.check_depth()?;
+        if self.at_arrow() {
+            return self.arrow();
+        }
+
+        let start = self.i;
+        let target = self.conditional()?;
+
+        let op = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(o)) if *o == EOperator::Eq || o.compound_base().is_some() => {
+                o.clone()
+            }
+            _ => return Ok(target),
+        };
+
+        if !target.is_simple_target() {
+            return Err(self.invalid_target(start, "invalid assignment target"));
+        }
+
+        self.i += 1;
+        let val = self.assign()?;
+
+        Ok(Expr::new(
+            ExprKind::Assign {
+                op,
+                target: Box::new(target),
+                val: Box::new(val),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* `test ? cons : alt` */
+    fn conditional(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let test = self.binary(0)?;
+
+        if !is_op(self.peek(), EOperator::Question) {
+            return Ok(test);
+        }
+
+        self.i += 1;
+        let cons = self.assign()?;
+        self.expect_op(EOperator::Colon)?;
+        let alt = self.assign()?;
+
+        Ok(Expr::new(
+            ExprKind::Conditional {
+                test: Box::new(test),
+                cons: Box::new(cons),
+                alt: Box::new(alt),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* binary operators binding tighter than `min_bp` */
+    fn binary(&mut self, min_bp: u8) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let mut lhs = self.unary()?;
+
+        while let Some(l) = self.peek() {
+            let Some((lbp, rbp)) = binary_bp(&l.tok) else {
+                break;
+            };
+
+            if lbp < min_bp {
+                break;
+            }
+
+            let op = l.clone();
+            self.i += 1;
+            let rhs = self.binary(rbp)?;
+            lhs = self.binary_node(op, lhs, rhs, start)?;
+        }
+
+        Ok(lhs)
+    }
 
-    while i < toks.len() {
-        if let Some(t) = toks.get(i) {
-            match t {
-                Token::Identifier(id) => {
-                    res = Expr::Var(id.clone());
+    fn is_parenthesized(&self, e: &Expr) -> bool {
+        self.parenthesized.contains(&e.span)
+    }
+
+    /* `Some(is_nullish)` for `&&`, `||` and `??` expressions written without parentheses */
+    fn bare_logical(&self, e: &Expr) -> Option<bool> {
+        match &e.kind {
+            ExprKind::Logical { op, .. } if !self.is_parenthesized(e) => {
+                Some(*op == EOperator::Nullish)
+            }
+            _ => None,
+        }
+    }
+
+    fn binary_node(
+        &self,
+        op: Lexeme,
+        lhs: Expr,
+        rhs: Expr,
+        start: usize,
+    ) -> Result<Expr, ParseError> {
+        let span = self.span_from(start);
+
+        let kind = match &op.tok {
+            Token::Keyword(k) if k == "in" => ExprKind::In {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+            Token::Keyword(_) => ExprKind::Instanceof {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+            Token::Operator(o @ (EOperator::And | EOperator::Or | EOperator::Nullish)) => {
+                /* `a ?? b || c` is a syntax error without parentheses */
+                let nullish = *o == EOperator::Nullish;
+
+                if [&lhs, &rhs]
+                    .iter()
+                    .any(|e| self.bare_logical(e).is_some_and(|n| n != nullish))
+                {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedToken,
+                        Some(&op),
+                        self.eof,
+                        String::from("cannot mix '??' with '&&' or '||' without parentheses"),
+                    ));
                 }
-                Token::Integer(v) => {
-                    res = Expr::Const(constants::Const::Integer(*v));
+
+                ExprKind::Logical {
+                    op: o.clone(),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
                 }
-                Token::Float(f) => {
-                    res = Expr::Const(constants::Const::Float(*f));
+            }
+            Token::Operator(o) => {
+                let bare_unary = matches!(
+                    lhs.kind,
+                    ExprKind::Unary { .. }
+                        | ExprKind::Typeof(_)
+                        | ExprKind::Void(_)
+                        | ExprKind::Delete(_)
+                ) && !self.is_parenthesized(&lhs);
+
+                if *o == EOperator::Pow && bare_unary {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedToken,
+                        Some(&op),
+                        self.eof,
+                        String::from(
+                            "unparenthesized unary expression can't appear on the left-hand side of '**'",
+                        ),
+                    ));
                 }
-                Token::Str { v, is_raw, is_fmt } => {
-                    res = Expr::Const(constants::Const::Str {
-                        v: v.to_string(),
-                        is_raw: *is_raw,
-                        is_fmt: *is_fmt,
-                    })
+
+                ExprKind::Binary {
+                    op: o.clone(),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        Ok(Expr::new(kind, span))
+    }
+
+    /* prefix operators */
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        self.check_depth()?;
+        let start = self.i;
+        let Some(l) = self.peek() else {
+            return Err(self.err_expected("expression"));
+        };
+
+        let kind = match &l.tok {
+            Token::Operator(
+                op @ (EOperator::Minus | EOperator::Plus | EOperator::Not | EOperator::BitNot),
+            ) => {
+                let op = op.clone();
+                self.i += 1;
+
+                ExprKind::Unary {
+                    op,
+                    e: Box::new(self.unary()?),
+                }
+            }
+            Token::Operator(op @ (EOperator::Incr | EOperator::Decr)) => {
+                let op = op.clone();
+                self.i += 1;
+
+                let target_start = self.i;
+                let target = self.unary()?;
+
+                if !target.is_simple_target() {
+                    return Err(self.invalid_target(
+                        target_start,
+                        "invalid left-hand side expression in prefix operation",
+                    ));
                 }
-                Token::Operator(
-                    EOperator::Plus | EOperator::Minus | EOperator::Multiply | EOperator::Divide,
-                ) => {
-                    let mut v: Vec<Arithmetic> = Vec::new();
-                    v.push(Arithmetic::Node(Box::new(res.clone())));
-
-                    let mut precedence_map: HashMap<EOperator, i8> = HashMap::new();
-
-                    precedence_map.insert(EOperator::Plus, 10);
-                    precedence_map.insert(EOperator::Minus, 10);
-                    precedence_map.insert(EOperator::Multiply, 20);
-                    precedence_map.insert(EOperator::Divide, 20);
-
-                    let op = match t {
-                        Token::Operator(p) => p,
-                        _ => unreachable!(),
-                    };
-                    v.push(Arithmetic::Op(op.to_owned()));
-
-                    let mut gb = 0;
-                    let mut last_arg_idx = i + 1;
-                    let mut j = i + 1;
-
-                    while j < toks.len() {
-                        if let Some(jv) = toks.get(j) {
-                            match jv {
-                                Token::Operator(
-                                    EOperator::Plus
-                                    | EOperator::Minus
-                                    | EOperator::Multiply
-                                    | EOperator::Divide,
-                                ) if gb == 0 => {
-                                    v.push(Arithmetic::Node(Box::new(expr_gen(
-                                        &toks[last_arg_idx..j],
-                                    ))));
-
-                                    if let Token::Operator(o) = jv {
-                                        v.push(Arithmetic::Op(o.to_owned()));
-                                    }
-
-                                    last_arg_idx = j + 1;
-                                }
-                                Token::Operator(
-                                    EOperator::LParen | EOperator::LBrace | EOperator::LBracket,
-                                ) => gb += 1,
-                                Token::Operator(
-                                    EOperator::RParen | EOperator::RBrace | EOperator::RBracket,
-                                ) => gb -= 1,
-                                _ => (),
-                            }
-                        }
-                        j += 1;
-                    }
 
-                    v.push(Arithmetic::Node(Box::new(expr_gen(&toks[last_arg_idx..j]))));
-
-                    let mut v_pf: Vec<Arithmetic> = Vec::new();
-                    let mut stack: Vec<Arithmetic> = Vec::new();
-
-                    for (j, jv) in v.iter().enumerate() {
-                        match jv {
-                            Arithmetic::Op(o) => {
-                                while stack.len() > 0 {
-                                    match stack.last().unwrap() {
-                                        Arithmetic::Op(e) => {
-                                            if precedence_map.get(e) >= precedence_map.get(o) {
-                                                v_pf.push(stack.pop().unwrap());
-                                            } else {
-                                                break;
-                                            }
-                                        }
-                                        _ => break,
-                                    }
-                                }
-
-                                stack.push(jv.to_owned());
-                            }
-
-                            Arithmetic::Node(n) => {
-                                v_pf.push(Arithmetic::Node(n.to_owned()));
-                            }
-                            _ => (),
+                ExprKind::Update {
+                    op,
+                    prefix: true,
+                    target: Box::new(target),
+                }
+            }
+            Token::Keyword(k) if k == "typeof" || k == "void" || k == "delete" => {
+                let k = k.clone();
+                self.i += 1;
+                let e = Box::new(self.unary()?);
+
+                match k.as_str() {
+                    "typeof" => ExprKind::Typeof(e),
+                    "void" => ExprKind::Void(e),
+                    _ => ExprKind::Delete(e),
+                }
+            }
+            _ => return self.postfix(),
+        };
+
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /* `e++` / `e--` */
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let e = self.call_member()?;
+
+        /* `a\n++b` is `a; ++b;` */
+        let op = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(op @ (EOperator::Incr | EOperator::Decr)))
+                if !self.newline_at(0) =>
+            {
+                op.clone()
+            }
+            _ => return Ok(e),
+        };
+
+        if !e.is_simple_target() {
+            return Err(self.invalid_target(
+                start,
+                "invalid left-hand side expression in postfix operation",
+            ));
+        }
+
+        self.i += 1;
+
+        Ok(Expr::new(
+            ExprKind::Update {
+                op,
+                prefix: false,
+                target: Box::new(e),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* member accesses, calls and `new`: `a.b[c](d)` */
+    fn call_member(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let mut e = if self.is_keyword("new") {
+            self.new_expr()?
+        } else {
+            self.primary()?
+        };
+
+        /* whether a `?.` was seen, which makes the whole expression a chain */
+        let mut chained = false;
+
+        loop {
+            e = match self.peek().map(|l| &l.tok) {
+                Some(Token::Operator(EOperator::LParen)) => {
+                    let args = self.args()?;
+
+                    Expr::new(
+                        ExprKind::Call {
+                            callee: Box::new(e),
+                            args,
+                        },
+                        self.span_from(start),
+                    )
+                }
+                Some(Token::Operator(EOperator::Dot | EOperator::LBracket)) => {
+                    self.member(e, start)?
+                }
+                Some(Token::Operator(EOperator::OptChain)) => {
+                    chained = true;
+                    self.i += 1;
+                    let opt = Expr::new(ExprKind::Optional(Box::new(e)), self.span_from(start));
+
+                    match self.peek().map(|l| &l.tok) {
+                        Some(Token::Operator(EOperator::LParen)) => {
+                            let args = self.args()?;
+
+                            Expr::new(
+                                ExprKind::Call {
+                                    callee: Box::new(opt),
+                                    args,
+                                },
+                                self.span_from(start),
+                            )
                         }
+                        Some(Token::Operator(EOperator::LBracket)) => self.member(opt, start)?,
+                        _ => self.dot_member(opt, start)?,
                     }
+                }
+                Some(Token::Template { head: true, .. }) if chained => {
+                    return Err(self.error_at(self.i, "Invalid tagged template on optional chain"));
+                }
+                Some(Token::Template { head: true, .. }) => {
+                    let (cooked, raw, exprs) = self.template_parts()?;
+
+                    Expr::new(
+                        ExprKind::TaggedTemplate {
+                            tag: Box::new(e),
+                            site: Rc::new(TemplateSite { cooked, raw }),
+                            exprs,
+                        },
+                        self.span_from(start),
+                    )
+                }
+                _ if chained => {
+                    let span = e.span;
+                    return Ok(Expr::new(ExprKind::Chain(Box::new(e)), span));
+                }
+                _ => return Ok(e),
+            };
+        }
+    }
 
-                    while stack.len() > 0 {
-                        v_pf.push(stack.pop().unwrap());
-                    }
+    /* the pieces of a template literal, which starts at the current token */
+    fn template_parts(&mut self) -> Result<TemplateParts, ParseError> {
+        let (mut cooked, mut raw, mut exprs) = (Vec::new(), Vec::new(), Vec::new());
+
+        loop {
+            let Some(Token::Template {
+                cooked: c,
+                raw: r,
+                head,
+                tail,
+            }) = self.peek().map(|l| &l.tok)
+            else {
+                return Err(self.err_expected("'}'"));
+            };
+
+            if *head != cooked.is_empty() {
+                return Err(self.err_expected("'}'"));
+            }
+
+            let tail = *tail;
+            cooked.push(c.as_deref().map(constants::JsStr::from));
+            raw.push(r.to_string());
+            self.i += 1;
+
+            if tail {
+                return Ok((cooked, raw, exprs));
+            }
+            exprs.push(self.expr()?);
+        }
+    }
 
-                    // println!("{}", v.len());
-                    // for j in &v {
-                    //     println!("> {j:?}");
-                    // }
+    /* `.prop` or `[index]` after `obj`, which started at `start` */
+    fn member(&mut self, obj: Expr, start: usize) -> Result<Expr, ParseError> {
+        if is_op(self.peek(), EOperator::Dot) {
+            self.i += 1;
+            return self.dot_member(obj, start);
+        }
 
-                    // println!("{}", v_pf.len());
-                    // for j in &v_pf {
-                    //     println!(": {j:?}");
-                    // }
+        self.expect_op(EOperator::LBracket)?;
+        let index = self.expr()?;
+        self.expect_op(EOperator::RBracket)?;
 
-                    res = Expr::Arith(v_pf);
-                    i = toks.len();
+        let kind = ExprKind::Index {
+            obj: Box::new(obj),
+            index: Box::new(index),
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /* the `prop` or `#name` after the `.` or `?.` that follows `obj` */
+    fn dot_member(&mut self, obj: Expr, start: usize) -> Result<Expr, ParseError> {
+        let kind = match self.peek().map(|l| &l.tok) {
+            Some(Token::PrivateName(name)) => {
+                let name = name.clone();
+                self.i += 1;
+                ExprKind::PrivateMember {
+                    obj: Box::new(obj),
+                    name,
                 }
-                Token::Bool(b) => res = Expr::Const(constants::Const::Bool(*b)),
-                Token::Operator(EOperator::LParen) => {
-                    if let Expr::Var(ref vname) = res {
-                        let mut gb = 0;
-                        let mut last_arg_idx = i + 1;
-                        let mut args: Vec<Box<Expr>> = Vec::new();
-                        let mut j = i + 1;
-
-                        while j < toks.len() {
-                            let d = toks.get(j).unwrap();
-
-                            match d {
-                                Token::Operator(
-                                    EOperator::LBrace | EOperator::LBracket | EOperator::LParen,
-                                ) => gb += 1,
-                                Token::Operator(EOperator::RBrace | EOperator::RBracket) => gb -= 1,
-                                Token::Operator(EOperator::RParen) => {
-                                    if gb == 0 {
-                                        if j != last_arg_idx {
-                                            args.push(Box::new(expr_gen(&toks[last_arg_idx..j])));
-                                        }
-                                        break;
-                                    } else {
-                                        gb -= 1;
-                                    }
-                                }
-                                Token::Operator(EOperator::Comma) if gb == 0 => {
-                                    args.push(Box::new(expr_gen(&toks[last_arg_idx..j])));
-                                    last_arg_idx = j + 1;
-                                }
-                                _ => (),
-                            }
-
-                            j += 1;
-                        }
+            }
+            _ => ExprKind::Member {
+                obj: Box::new(obj),
+                prop: self.prop_name()?,
+            },
+        };
 
-                        res = Expr::FuncCallSimple {
-                            name: vname.to_string(),
-                            args,
-                        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /* key of an object literal entry */
+    fn prop_key(&mut self) -> Result<PropKey, ParseError> {
+        let key = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(EOperator::LBracket)) => {
+                self.i += 1;
+                let e = self.assign()?;
+                self.expect_op(EOperator::RBracket)?;
+                return Ok(PropKey::Computed(Box::new(e)));
+            }
+            Some(Token::Str { v, .. }) => v.to_string(),
+            Some(Token::Integer(n)) => n.to_string(),
+            Some(Token::Float(f)) => constants::Const::Float(*f).to_string(),
+            Some(Token::BigInt(n)) => n.to_string(),
+            _ => return Ok(PropKey::Named(self.prop_name()?)),
+        };
+
+        self.i += 1;
+        Ok(PropKey::Named(key))
+    }
 
-                        i = j;
+    /* `{ a: 1, b, [k]: v, m() {}, ...o }` */
+    fn object(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        self.expect_op(EOperator::LBrace)?;
+
+        let mut props = Vec::new();
+        let mut proto = false;
+
+        while !is_op(self.peek(), EOperator::RBrace) {
+            if is_op(self.peek(), EOperator::Ellipsis) {
+                self.i += 1;
+                props.push(ObjProp::Spread(self.assign()?));
+            } else if let Some(kind) = self.accessor_kind() {
+                let fn_start = self.i;
+                self.i += 1;
+                let key = self.prop_key()?;
+                let args = self.params()?;
+                let body = self.plain_body()?;
+
+                let val = Expr::new(
+                    ExprKind::Function {
+                        name: None,
+                        args: args.into(),
+                        body: body.into(),
+                        form: FuncForm::Method,
+                    },
+                    self.span_from(fn_start),
+                );
+                props.push(ObjProp::Accessor { key, kind, val });
+            } else {
+                let key_start = self.i;
+                let key_lex = self.peek().cloned();
+                let key = self.prop_key()?;
+                let is_colon = is_op(self.peek(), EOperator::Colon);
+
+                let val = match (self.peek().map(|l| &l.tok), &key, key_lex) {
+                    (Some(Token::Operator(EOperator::Colon)), _, _) => {
+                        self.i += 1;
+                        self.assign()?
                     }
-                }
-                Token::Keyword(k) => {
-                    if k == "new" {
-                        res = Expr::NewConstruct(Box::new(expr_gen(&toks[i + 1..toks.len()])));
-                        break;
+                    (Some(Token::Operator(EOperator::LParen)), _, _) => {
+                        let args = self.params()?;
+                        let body = self.plain_body()?;
+
+                        Expr::new(
+                            ExprKind::Function {
+                                name: None,
+                                args: args.into(),
+                                body: body.into(),
+                                form: FuncForm::Method,
+                            },
+                            self.span_from(key_start),
+                        )
+                    }
+                    (
+                        _,
+                        PropKey::Named(name),
+                        Some(Lexeme {
+                            tok: Token::Identifier(_),
+                            span,
+                        }),
+                    ) => Expr::new(ExprKind::Var(name.clone()), span),
+                    _ => return Err(self.err_expected("':'")),
+                };
+
+                match key {
+                    PropKey::Named(k) if k == "__proto__" && is_colon => {
+                        if proto {
+                            return Err(self.error_at(
+                                key_start,
+                                "Duplicate __proto__ fields are not allowed in object literals",
+                            ));
+                        }
+                        proto = true;
+                        props.push(ObjProp::Proto(val));
                     }
+                    key => props.push(ObjProp::KeyValue { key, val }),
                 }
-                _ => (),
+            }
+
+            if is_op(self.peek(), EOperator::Comma) {
+                self.i += 1;
+            } else if !is_op(self.peek(), EOperator::RBrace) {
+                return Err(self.err_expected("',' or '}'"));
             }
         }
 
-        i += 1;
+        self.i += 1;
+        Ok(Expr::new(ExprKind::Object(props), self.span_from(start)))
     }
 
-    res
-}
+    /* whether an object literal entry starts with a `get` or `set` modifier rather than a key of that name */
+    fn accessor_kind(&self) -> Option<MethodKind> {
+        let kind = match self.peek().map(|l| &l.tok) {
+            Some(Token::Identifier(w)) if w == "get" => MethodKind::Get,
+            Some(Token::Identifier(w)) if w == "set" => MethodKind::Set,
+            _ => return None,
+        };
+
+        match self.peek_at(1).map(|l| &l.tok) {
+            Some(Token::Operator(
+                EOperator::Colon | EOperator::LParen | EOperator::Comma | EOperator::RBrace,
+            ))
+            | None => None,
+            _ => Some(kind),
+        }
+    }
 
-pub fn stmt_gen(toks: &[Token]) -> Vec<stmt::Stmt> {
-    let mut res: Vec<stmt::Stmt> = Vec::new();
-    let mut i = 0;
-
-    while i < toks.len() {
-        if let Some(t) = toks.get(i) {
-            match t {
-                Token::Keyword(kw) => {
-                    if kw == "let" || kw == "var" || kw == "const" {
-                        let qual = match kw.as_str() {
-                            "let" => VarQualifier::Let,
-                            "const" => VarQualifier::Const,
-                            "var" => VarQualifier::Var,
-                            _ => unreachable!(),
-                        };
-
-                        if let Some(Token::Identifier(id)) = toks.get(i + 1) {
-                            /* simple definition */
-                            assert!(matches!(
-                                toks.get(i + 2).unwrap(),
-                                Token::Operator(EOperator::Eq)
-                            ));
+    /* names after `.` may be reserved words: `a.new`, `b.null` */
+    fn prop_name(&mut self) -> Result<String, ParseError> {
+        let name = match self.peek().map(|l| &l.tok) {
+            Some(Token::Identifier(s) | Token::Keyword(s)) => s.clone(),
+            Some(Token::Bool(b)) => b.to_string(),
+            Some(Token::Null) => String::from("null"),
+            Some(Token::Undef) => String::from("undefined"),
+            _ => return Err(self.err_expected("property name")),
+        };
+
+        self.i += 1;
+        Ok(name)
+    }
 
-                            let mut j = i + 3;
-
-                            /* find semicolon */
-                            while let Some(tj) = toks.get(j) {
-                                match tj {
-                                    Token::Operator(EOperator::Semicolon) => {
-                                        break;
-                                    }
-                                    _ => j += 1,
-                                }
-                            }
-
-                            let st = Stmt::VardeclSimple {
-                                name: String::from(id),
-                                val: expr_gen(&toks[i + 3..j]),
-                                qual,
-                            };
-                            res.push(st);
-
-                            i = j;
-                        }
-                    } else if kw == "function" {
-                        /* get name */
-                        let name = toks.get(i + 1).unwrap();
-
-                        /* check '(' */
-                        assert!(matches!(
-                            toks.get(i + 2).unwrap(),
-                            Token::Operator(EOperator::LParen)
-                        ));
+    /* `new C(args)`; the argument list is optional and belongs to the innermost `new` */
+    fn new_expr(&mut self) -> Result<Expr, ParseError> {
+        self.check_depth()?;
+        let start = self.i;
+        self.i += 1;
 
-                        let mut gb = 0;
-                        let mut args: Vec<Expr> = Vec::new();
-                        let mut last_idx = i + 3;
-
-                        for j in i + 3..toks.len() {
-                            let t = toks.get(j).unwrap();
-
-                            match t {
-                                Token::Operator(
-                                    EOperator::LParen | EOperator::LBrace | EOperator::LBracket,
-                                ) => gb += 1,
-                                Token::Operator(
-                                    EOperator::RParen | EOperator::RBrace | EOperator::RBracket,
-                                ) => {
-                                    if matches!(t, Token::Operator(EOperator::RParen)) {
-                                        args.push(expr_gen(&toks[last_idx..j]));
-                                        last_idx = j + 1;
-                                        break;
-                                    }
-
-                                    gb -= 1;
-                                }
-
-                                Token::Operator(EOperator::Comma) => {
-                                    if gb == 0 {
-                                        args.push(expr_gen(&toks[last_idx..j]));
-                                        last_idx = j + 1;
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
+        let mut callee = if self.is_keyword("new") {
+            self.new_expr()?
+        } else {
+            self.primary()?
+        };
 
-                        gb = 0;
-                        let mut block_end_idx = last_idx;
-
-                        for j in last_idx + 1..toks.len() {
-                            let t = toks.get(j).unwrap();
-
-                            match t {
-                                Token::Operator(
-                                    EOperator::LParen | EOperator::LBrace | EOperator::LBracket,
-                                ) => gb += 1,
-                                Token::Operator(
-                                    EOperator::RParen | EOperator::RBrace | EOperator::RBracket,
-                                ) => {
-                                    if matches!(t, Token::Operator(EOperator::RBrace)) {
-                                        block_end_idx = j;
-                                        break;
-                                    }
-                                    gb -= 1;
-                                }
-                                _ => (),
-                            }
-                        }
+        while is_op(self.peek(), EOperator::Dot) || is_op(self.peek(), EOperator::LBracket) {
+            callee = self.member(callee, start + 1)?;
+        }
 
-                        let stmt_tree = stmt_gen(&toks[last_idx..block_end_idx]);
+        let args = if is_op(self.peek(), EOperator::LParen) {
+            self.args()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Expr::new(
+            ExprKind::New {
+                callee: Box::new(callee),
+                args,
+            },
+            self.span_from(start),
+        ))
+    }
 
-                        let st = Stmt::Funcdecl {
-                            name: match name {
-                                Token::Identifier(n) => n.to_string(),
-                                _ => panic!("Invalid syntax for function declaration"),
-                            },
-                            args,
-                            body: stmt_tree,
-                        };
-
-                        res.push(st);
-                        i = block_end_idx - 1;
-                    } else if kw == "return" {
-                        let mut end_idx = 0;
-                        let mut gb = 0;
-
-                        for j in i + 1..toks.len() {
-                            let t = toks.get(j).unwrap();
-
-                            match t {
-                                Token::Operator(
-                                    EOperator::LParen | EOperator::LBrace | EOperator::LBracket,
-                                ) => gb += 1,
-                                Token::Operator(
-                                    EOperator::RParen | EOperator::RBrace | EOperator::RBracket,
-                                ) => gb -= 1,
-
-                                Token::Operator(EOperator::Semicolon) => {
-                                    if gb == 0 {
-                                        end_idx = j;
-                                        break;
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
+    /* an argument or array element, which may be `...e` */
+    fn spreadable(&mut self) -> Result<Expr, ParseError> {
+        if !is_op(self.peek(), EOperator::Ellipsis) {
+            return self.assign();
+        }
 
-                        let st = Stmt::ReturnStmt(expr_gen(&toks[i + 1..end_idx]));
+        let start = self.i;
+        self.i += 1;
+        let e = self.assign()?;
 
-                        res.push(st);
-                        i = end_idx;
-                    } else if kw == "function" {
-                        let mut name: String = String::from("func_undefined");
+        Ok(Expr::new(
+            ExprKind::Spread(Box::new(e)),
+            self.span_from(start),
+        ))
+    }
 
-                        match toks.get(i + 1) {
-                            Some(Token::Identifier(n)) => {
-                                name = n.clone();
-                            }
-                            Some(_) => println!("syntax error."),
-                            None => println!("unexpected EOF."),
-                        }
+    /* `[a, , ...b]`; a comma right after another one leaves a hole */
+    fn array(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        self.expect_op(EOperator::LBracket)?;
 
-                        assert!(matches!(
-                            toks.get(i + 2).unwrap(),
-                            Token::Operator(EOperator::LParen)
-                        )); // check '('
-
-                        let mut gb = 0;
-                        let mut last_arg_idx = i + 3;
-                        let mut args: Vec<Expr> = Vec::new();
-
-                        for j in i + 3..toks.len() {
-                            match toks.get(j).unwrap() {
-                                Token::Operator(
-                                    EOperator::LBrace | EOperator::LBracket | EOperator::LParen,
-                                ) => gb += 1,
-                                Token::Operator(EOperator::RParen) => {
-                                    if gb == 0 {
-                                        if last_arg_idx != i + 3 {
-                                            args.push(expr_gen(&toks[last_arg_idx..j]));
-                                        }
-                                        last_arg_idx = j + 1;
-                                        break;
-                                    }
-
-                                    gb -= 1;
-                                }
-                                Token::Operator(EOperator::RBrace | EOperator::RBracket) => gb -= 1,
-                                Token::Operator(EOperator::Comma) if gb == 0 => {
-                                    args.push(expr_gen(&toks[last_arg_idx..j]));
-                                    last_arg_idx = j + 1;
-                                }
-                                _ => (),
-                            }
-                        }
+        let mut elems = Vec::new();
 
-                        gb = 0;
-                        let mut stmts: Vec<Stmt> = Vec::new();
-                        let mut block_end_idx = last_arg_idx;
-
-                        for j in last_arg_idx + 1..toks.len() {
-                            match toks.get(j).unwrap() {
-                                Token::Operator(
-                                    EOperator::LBrace | EOperator::LBracket | EOperator::LParen,
-                                ) => gb += 1,
-                                Token::Operator(EOperator::RBrace) => {
-                                    if gb == 0 {
-                                        block_end_idx = j;
-                                        break;
-                                    }
-
-                                    gb -= 1;
-                                }
-                                Token::Operator(EOperator::RParen | EOperator::RBracket) => gb -= 1,
-                                _ => (),
-                            }
-                        }
+        while !is_op(self.peek(), EOperator::RBracket) {
+            if is_op(self.peek(), EOperator::Comma) {
+                self.i += 1;
+                elems.push(None);
+                continue;
+            }
 
-                        stmts = stmt_gen(&toks[last_arg_idx + 1..block_end_idx]);
+            elems.push(Some(self.spreadable()?));
 
-                        let st: Stmt = Stmt::Funcdecl {
-                            name,
-                            args,
-                            body: stmts,
-                        };
-
-                        res.push(st);
-                        i = block_end_idx;
-                    } else if kw == "class" {
-                        let name = match toks.get(i + 1).unwrap() {
-                            Token::Identifier(id) => id.to_string(),
-                            _ => panic!("syntax error"),
-                        };
-
-                        let mut block_st_idx = i + 2;
-                        while let Some(Token::Newline) = toks.get(block_st_idx) {
-                            block_st_idx += 1;
-                        }
+            if is_op(self.peek(), EOperator::Comma) {
+                self.i += 1;
+            } else if !is_op(self.peek(), EOperator::RBracket) {
+                return Err(self.err_expected("',' or ']'"));
+            }
+        }
 
-                        assert!(matches!(
-                            toks.get(block_st_idx).unwrap(),
-                            Token::Operator(EOperator::LBrace)
-                        )); /* check { */
-
-                        let mut gb = 0;
-                        let mut j = block_st_idx + 1;
-
-                        let mut vec_vars: Vec<Stmt> = Vec::new();
-                        let mut vec_fns: Vec<Stmt> = Vec::new();
-
-                        while j < toks.len() {
-                            let d = toks.get(j).unwrap();
-
-                            match d {
-                                Token::Operator(EOperator::LBrace | EOperator::LBracket) => gb += 1,
-                                Token::Operator(EOperator::RBrace) => {
-                                    if gb == 0 {
-                                        break;
-                                    }
-                                    gb -= 1;
-                                }
-                                Token::Operator(EOperator::RParen | EOperator::RBracket) => gb -= 1,
-
-                                Token::Operator(EOperator::LParen) if gb == 0 => {
-                                    if let Some(Token::Identifier(fname)) = toks.get(j - 1) {
-                                        let mut gb2 = 0;
-                                        let mut last_arg_idx = j + 1;
-                                        let mut args: Vec<Expr> = Vec::new();
-
-                                        for k in j + 1..toks.len() {
-                                            match toks.get(k).unwrap() {
-                                                Token::Operator(
-                                                    EOperator::LBrace
-                                                    | EOperator::LBracket
-                                                    | EOperator::LParen,
-                                                ) => gb2 += 1,
-                                                Token::Operator(EOperator::RParen) => {
-                                                    if gb2 == 0 {
-                                                        if last_arg_idx != k {
-                                                            args.push(expr_gen(
-                                                                &toks[last_arg_idx..k],
-                                                            ));
-                                                        }
-                                                        last_arg_idx = k + 1;
-                                                        break;
-                                                    }
-
-                                                    gb2 -= 1;
-                                                }
-                                                Token::Operator(
-                                                    EOperator::RBrace | EOperator::RBracket,
-                                                ) => gb2 -= 1,
-                                                Token::Operator(EOperator::Comma) if gb2 == 0 => {
-                                                    args.push(expr_gen(&toks[last_arg_idx..k]));
-                                                    last_arg_idx = k + 1;
-                                                }
-                                                _ => (),
-                                            }
-                                        }
-
-                                        gb2 = 0;
-                                        let mut stmts: Vec<Stmt> = Vec::new();
-                                        let mut block_end_idx = last_arg_idx;
-
-                                        for k in last_arg_idx + 1..toks.len() {
-                                            match toks.get(k).unwrap() {
-                                                Token::Operator(
-                                                    EOperator::LBrace
-                                                    | EOperator::LBracket
-                                                    | EOperator::LParen,
-                                                ) => gb2 += 1,
-                                                Token::Operator(EOperator::RBrace) => {
-                                                    if gb2 == 0 {
-                                                        block_end_idx = k;
-                                                        break;
-                                                    }
-
-                                                    gb2 -= 1;
-                                                }
-                                                Token::Operator(
-                                                    EOperator::RParen | EOperator::RBracket,
-                                                ) => gb2 -= 1,
-                                                _ => (),
-                                            }
-                                        }
-
-                                        stmts = stmt_gen(&toks[last_arg_idx + 1..block_end_idx]);
-
-                                        vec_fns.push(Stmt::Funcdecl {
-                                            name: fname.to_string(),
-                                            args,
-                                            body: stmts,
-                                        });
-
-                                        j = block_end_idx;
-                                    } else {
-                                        gb -= 1;
-                                    }
-                                }
-
-                                Token::Operator(EOperator::Eq) if gb == 0 => {
-                                    if let Some(Token::Identifier(vname)) = toks.get(j - 1) {
-                                        /* simple var declaration */
-                                        let mut semicolon_idx = j + 1;
-                                        let mut gb2 = 0;
-
-                                        while semicolon_idx < toks.len() {
-                                            match toks.get(semicolon_idx).unwrap() {
-                                                Token::Operator(
-                                                    EOperator::LBrace
-                                                    | EOperator::LBracket
-                                                    | EOperator::LParen,
-                                                ) => gb2 += 1,
-                                                Token::Operator(
-                                                    EOperator::RParen | EOperator::RBracket,
-                                                ) => gb2 -= 1,
-                                                Token::Operator(EOperator::Semicolon)
-                                                    if gb2 == 0 =>
-                                                {
-                                                    break;
-                                                }
-                                                _ => (),
-                                            }
-                                            semicolon_idx += 1;
-                                        }
-
-                                        vec_vars.push(Stmt::VardeclSimple {
-                                            name: vname.to_string(),
-                                            val: expr_gen(&toks[j + 1..semicolon_idx]),
-                                            qual: VarQualifier::Let,
-                                        });
-
-                                        j = semicolon_idx;
-                                    } else {
-                                        panic!("syntax error");
-                                    }
-                                }
-
-                                _ => (),
-                            }
-
-                            j += 1;
-                        }
+        self.i += 1;
+        Ok(Expr::new(ExprKind::Array(elems), self.span_from(start)))
+    }
 
-                        res.push(Stmt::ClassDecl {
-                            name,
-                            vars: vec_vars,
-                            fns: vec_fns,
-                        });
+    /* `( a, b, ... )`; a trailing comma is allowed */
+    fn args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect_op(EOperator::LParen)?;
 
-                        i = j;
-                    }
+        let mut args = Vec::new();
+
+        while !is_op(self.peek(), EOperator::RParen) {
+            args.push(self.spreadable()?);
+
+            if is_op(self.peek(), EOperator::Comma) {
+                self.i += 1;
+            } else {
+                break;
+            }
+        }
+
+        self.expect_op(EOperator::RParen)?;
+        Ok(args)
+    }
+
+    /* literals, identifiers, `this`, function, object and array literals and parenthesized expressions */
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        let Some(l) = self.peek() else {
+            return Err(self.err_expected("expression"));
+        };
+
+        let kind = match &l.tok {
+            Token::Identifier(id) => ExprKind::Var(id.clone()),
+            Token::Keyword(k) if k == "this" => ExprKind::This,
+            Token::Keyword(k) if k == "super" => return self.super_expr(),
+            /* `#name in obj` checks whether `obj` has the private member */
+            Token::PrivateName(name) if matches!(self.peek_at(1), Some(Lexeme { tok: Token::Keyword(k), .. }) if k == "in") => {
+                ExprKind::PrivateName(name.clone())
+            }
+            Token::Keyword(k) if k == "function" => return self.function_expr(),
+            Token::Keyword(k) if k == "class" => return self.class_expr(),
+            Token::Operator(EOperator::LBrace) => return self.object(),
+            Token::Operator(EOperator::LBracket) => return self.array(),
+            Token::Integer(v) => ExprKind::Const(constants::Const::Integer(*v)),
+            Token::Float(f) => ExprKind::Const(constants::Const::Float(*f)),
+            Token::BigInt(v) => ExprKind::Const(constants::Const::BigInt(v.clone())),
+            Token::Bool(b) => ExprKind::Const(constants::Const::Bool(*b)),
+            Token::Undef => ExprKind::Const(constants::Const::Undef),
+            Token::Null => ExprKind::Const(constants::Const::Null),
+            Token::Str { v, is_raw, is_fmt } => ExprKind::Const(constants::Const::Str {
+                v: v.clone(),
+                is_raw: *is_raw,
+                is_fmt: *is_fmt,
+            }),
+            Token::Template { .. } => {
+                let (cooked, _, exprs) = self.template_parts()?;
+
+                /* only tagged templates may contain invalid escapes */
+                let Some(quasis) = cooked.into_iter().collect() else {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidLiteral,
+                        self.toks.get(start),
+                        self.eof,
+                        String::from("invalid escape sequence in template literal"),
+                    ));
+                };
+
+                return Ok(Expr::new(
+                    ExprKind::Template { quasis, exprs },
+                    self.span_from(start),
+                ));
+            }
+            Token::Operator(EOperator::LParen) => {
+                self.i += 1;
+                let mut e = self.expr()?;
+                self.expect_op(EOperator::RParen)?;
+
+                e.span = self.span_from(start);
+                self.parenthesized.push(e.span);
+                return Ok(e);
+            }
+            _ => return Err(self.err_expected("expression")),
+        };
+
+        self.i += 1;
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /* `super(args)`, `super.prop` or `super[key]` */
+    fn super_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let kind = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(EOperator::LParen)) if self.super_use == SuperUse::Call => {
+                ExprKind::SuperCall(self.args()?)
+            }
+            Some(Token::Operator(EOperator::Dot)) if self.super_use != SuperUse::None => {
+                self.i += 1;
+                ExprKind::SuperMember(PropKey::Named(self.prop_name()?))
+            }
+            Some(Token::Operator(EOperator::LBracket)) if self.super_use != SuperUse::None => {
+                self.i += 1;
+                let key = self.expr()?;
+                self.expect_op(EOperator::RBracket)?;
+                ExprKind::SuperMember(PropKey::Computed(Box::new(key)))
+            }
+            _ => return Err(self.error_at(start, "'super' keyword unexpected here")),
+        };
+
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /* expression statement, including its terminator */
+    fn expr_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        let e = self.expr()?;
+        let st = Stmt::new(StmtKind::Expr(e), self.span_from(start));
+
+        self.terminator()?;
+        Ok(st)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect_op(EOperator::LBrace)?;
+
+        let mut res = Vec::new();
+
+        while !is_op(self.peek(), EOperator::RBrace) {
+            if self.peek().is_none() {
+                return Err(self.err_expected("'}'"));
+            }
+
+            self.stmt(&mut res)?;
+        }
+
+        self.i += 1;
+        self.check_redeclared(&res)?;
+        Ok(res)
+    }
+
+    /**
+     * Fails on the first name `stmts` declare twice in the block they make up. `let`, `const`
+     * and classes take a name nothing else there has; functions may share one with each other
+     * and with `var`s.
+     */
+    fn check_redeclared<'s, I>(&self, stmts: I) -> Result<(), ParseError>
+    where
+        I: IntoIterator<Item = &'s Stmt> + Clone,
+    {
+        let mut vars = Vec::new();
+        for st in stmts.clone() {
+            st.var_names(&mut vars);
+        }
+
+        let mut seen: Vec<(&str, bool)> = Vec::new();
+        for st in stmts {
+            let Some(n) = st.lexical_name() else {
+                continue;
+            };
+            let is_fn = matches!(st.kind, StmtKind::Funcdecl { .. });
+            let clash =
+                seen.iter().any(|&(m, f)| m == n && !(is_fn && f)) || (!is_fn && vars.contains(&n));
+
+            if clash {
+                let at = self.toks.iter().find(|l| {
+                    l.span.start >= st.span.start
+                        && matches!(&l.tok, Token::Identifier(id) if id == n)
+                });
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidDeclaration,
+                    at,
+                    self.eof,
+                    format!("Identifier '{n}' has already been declared"),
+                ));
+            }
+            seen.push((n, is_fn));
+        }
+
+        Ok(())
+    }
+
+    fn params(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect_op(EOperator::LParen)?;
+
+        let mut args = Vec::new();
+
+        while !is_op(self.peek(), EOperator::RParen) {
+            let (name, span) = self.expect_ident("parameter name")?;
+            args.push(Expr::new(ExprKind::Var(name), span));
+
+            if is_op(self.peek(), EOperator::Comma) {
+                self.i += 1;
+            } else if !is_op(self.peek(), EOperator::RParen) {
+                return Err(self.err_expected("',' or ')'"));
+            }
+        }
+
+        self.i += 1;
+        Ok(args)
+    }
+
+    /* `let a = 1, b;` produces one statement per declarator */
+    fn vardecl(&mut self, qual: VarQualifier, res: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        self.declarations(qual, res)?;
+        self.terminator()
+    }
+
+    /* the declarations after `let`, `const` or `var`, up to where the statement should end */
+    fn declarations(&mut self, qual: VarQualifier, res: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        self.i += 1;
+
+        loop {
+            let start = self.i;
+            let (name, _) = self.expect_ident("variable name")?;
+
+            let val = if is_op(self.peek(), EOperator::Eq) {
+                self.i += 1;
+                Some(self.assign()?)
+            } else if matches!(qual, VarQualifier::Const) {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidDeclaration,
+                    self.toks.get(self.i - 1),
+                    self.eof,
+                    "Missing initializer in const declaration".to_string(),
+                ));
+            } else {
+                None
+            };
+
+            res.push(Stmt::new(
+                StmtKind::VardeclSimple {
+                    name,
+                    val,
+                    qual: qual.clone(),
+                },
+                self.span_from(start),
+            ));
+
+            if !is_op(self.peek(), EOperator::Comma) {
+                return Ok(());
+            }
+            self.i += 1;
+        }
+    }
+
+    /* function bodies cannot break out of loops around the function */
+    fn fn_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let labels = std::mem::take(&mut self.labels);
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let switch_depth = std::mem::replace(&mut self.switch_depth, 0);
+
+        let body = self.block();
+
+        self.labels = labels;
+        self.loop_depth = loop_depth;
+        self.switch_depth = switch_depth;
+        body
+    }
+
+    /* runs `f` with `super` allowed to do `sup`; arrows inside keep what their scope allows */
+    fn with_super<T>(
+        &mut self,
+        sup: SuperUse,
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let outer = std::mem::replace(&mut self.super_use, sup);
+        let r = f(self);
+
+        self.super_use = outer;
+        r
+    }
+
+    /* body of a function that is not a class method, in which `super` means nothing */
+    fn plain_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.with_super(SuperUse::None, Self::fn_body)
+    }
+
+    fn funcdecl(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let (name, _) = self.expect_ident("function name")?;
+        let args = self.params()?;
+        let body = self.plain_body()?;
+
+        Ok(Stmt::new(
+            StmtKind::Funcdecl {
+                name,
+                args: args.into(),
+                body: body.into(),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn classdecl(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let (name, _) = self.expect_ident("class name")?;
+        let (parent, members) = self.class_tail()?;
+
+        Ok(Stmt::new(
+            StmtKind::ClassDecl {
+                name,
+                parent,
+                members,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* `class [Name] [extends P] { ... }` as an expression */
+    fn class_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let name = match self.peek().map(|l| &l.tok) {
+            Some(Token::Identifier(_)) => Some(self.expect_ident("class name")?.0),
+            _ => None,
+        };
+        let (parent, members) = self.class_tail()?;
+
+        Ok(Expr::new(
+            ExprKind::Class {
+                name,
+                parent: parent.map(Box::new),
+                members,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* the `extends` clause and the body of a class */
+    fn class_tail(&mut self) -> Result<(Option<Expr>, Vec<ClassMember>), ParseError> {
+        let parent = if self.is_keyword("extends") {
+            self.i += 1;
+            Some(self.call_member()?)
+        } else {
+            None
+        };
+        self.expect_op(EOperator::LBrace)?;
+
+        let mut members = Vec::new();
+        let mut privates = Vec::new();
+
+        loop {
+            match self.peek().map(|l| &l.tok) {
+                Some(Token::Operator(EOperator::RBrace)) => {
+                    self.i += 1;
+                    break;
                 }
-                Token::Operator(EOperator::LParen) => {
-                    if i > 0 {
-                        if let Token::Identifier(func_name) = &toks[i - 1] {
-                            let mut gb = 0;
-                            let mut args = Vec::new();
-                            let mut last_arg_start = i + 1;
-                            let mut end_idx = i;
-
-                            for j in (i + 1)..toks.len() {
-                                match &toks[j] {
-                                    Token::Operator(
-                                        EOperator::LParen | EOperator::LBrace | EOperator::LBracket,
-                                    ) => gb += 1,
-                                    Token::Operator(EOperator::RParen) => {
-                                        if gb == 0 {
-                                            if last_arg_start < j {
-                                                args.push(expr_gen(&toks[last_arg_start..j]));
-                                            }
-                                            end_idx = j;
-                                            break;
-                                        }
-                                        gb -= 1;
-                                    }
-                                    Token::Operator(EOperator::RBrace | EOperator::RBracket) => {
-                                        gb -= 1
-                                    }
-                                    Token::Operator(EOperator::Comma) if gb == 0 => {
-                                        args.push(expr_gen(&toks[last_arg_start..j]));
-                                        last_arg_start = j + 1;
-                                    }
-                                    _ => {}
-                                }
-                            }
-
-                            /* for j in &args {
-                                println!("{j:?}");
-                            } */
-
-                            let mut semicolon_idx = end_idx;
-                            for j in (end_idx + 1)..toks.len() {
-                                if let Token::Operator(EOperator::Semicolon) = &toks[j] {
-                                    semicolon_idx = j;
-                                    break;
-                                }
-                            }
-
-                            let st = Stmt::FunctCallSimple {
-                                name: func_name.clone(),
-                                args,
-                            };
-                            res.push(st);
-
-                            i = semicolon_idx;
-                        }
-                    }
+                Some(Token::Operator(EOperator::Semicolon)) => self.i += 1,
+                _ => {
+                    let m = self.class_member(parent.is_some(), &members, &mut privates)?;
+                    members.push(m);
                 }
+            }
+        }
 
-                Token::Operator(EOperator::Eq) => {
-                    let mut name_vec: Vec<Token> = Vec::new();
-                    let mut semicolon_idx = i;
-                    let mut j = i - 1;
-                    let mut gb = 0;
-
-                    while (j as i32) >= 0 {
-                        let d = toks.get(j).unwrap();
-
-                        match d {
-                            Token::Operator(EOperator::Semicolon) if gb == 0 => {
-                                break;
-                            }
-                            Token::Operator(
-                                EOperator::LBrace | EOperator::LBracket | EOperator::LParen,
-                            ) => gb += 1,
-                            Token::Operator(
-                                EOperator::RBrace | EOperator::RBracket | EOperator::RParen,
-                            ) => gb -= 1,
-
-                            Token::Newline => {
-                                if j != 0 {
-                                    j -= 1;
-                                } else {
-                                    break;
-                                }
-
-                                continue;
-                            }
-                            _ => (),
-                        }
+        Ok((parent, members))
+    }
 
-                        name_vec.push(d.clone());
+    /* whether the token `n` places ahead is the identifier `w` */
+    fn is_word_at(&self, n: usize, w: &str) -> bool {
+        matches!(self.peek_at(n), Some(Lexeme { tok: Token::Identifier(id), .. }) if id == w)
+    }
 
-                        if j != 0 {
-                            j -= 1;
-                        } else {
-                            break;
-                        }
-                    }
+    /* whether the token `n` places ahead can follow the name of a class member */
+    fn ends_member_name(&self, n: usize) -> bool {
+        matches!(
+            self.peek_at(n).map(|l| &l.tok),
+            Some(Token::Operator(
+                EOperator::LParen | EOperator::Eq | EOperator::Semicolon | EOperator::RBrace
+            )) | None
+        ) || self.newline_at(n)
+    }
 
-                    name_vec.reverse();
-                    j = i + 1;
-                    gb = 0;
-
-                    while j < toks.len() {
-                        let d = toks.get(j).unwrap();
-
-                        match d {
-                            Token::Operator(EOperator::Semicolon) if gb == 0 => {
-                                semicolon_idx = j;
-                                break;
-                            }
-                            Token::Operator(
-                                EOperator::LBrace | EOperator::LBracket | EOperator::LParen,
-                            ) => gb += 1,
-                            Token::Operator(
-                                EOperator::RBrace | EOperator::RBracket | EOperator::RParen,
-                            ) => gb -= 1,
-                            _ => (),
-                        }
+    /**
+     * A method, accessor, field or static block.
+     * `static`, `get` and `set` are modifiers unless they are the member's own name, as in `get() {}`.
+     * `privates` collects the private names declared so far, which may only repeat as a getter and setter pair.
+     */
+    fn class_member(
+        &mut self,
+        derived: bool,
+        members: &[ClassMember],
+        privates: &mut Vec<(String, MethodKind)>,
+    ) -> Result<ClassMember, ParseError> {
+        let is_static = self.is_word_at(0, "static") && !self.ends_member_name(1);
+        if is_static {
+            self.i += 1;
+
+            if is_op(self.peek(), EOperator::LBrace) {
+                let body = self.with_super(SuperUse::Member, Self::fn_body)?;
+                return Ok(ClassMember::StaticBlock(body));
+            }
+        }
+
+        let mut kind = MethodKind::Method;
+        for (w, k) in [("get", MethodKind::Get), ("set", MethodKind::Set)] {
+            if self.is_word_at(0, w) && !self.ends_member_name(1) {
+                kind = k;
+                self.i += 1;
+            }
+        }
 
-                        j += 1;
+        let name_at = self.i;
+        let key = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(EOperator::LBracket)) => {
+                self.i += 1;
+                let e = self.assign()?;
+                self.expect_op(EOperator::RBracket)?;
+                PropKey::Computed(Box::new(e))
+            }
+            tok => {
+                let name = match tok {
+                    Some(Token::Identifier(s) | Token::Keyword(s) | Token::PrivateName(s)) => {
+                        s.clone()
                     }
+                    Some(Token::Str { v, .. }) => v.to_string(),
+                    _ => return Err(self.err_expected("class member")),
+                };
+                self.i += 1;
+                PropKey::Named(name)
+            }
+        };
+        /* a computed name is never `constructor` or private */
+        let name = match &key {
+            PropKey::Named(n) => n.clone(),
+            PropKey::Computed(_) => String::new(),
+        };
+
+        let is_ctor = name == "constructor" && !is_static;
+        if name == "#constructor" {
+            return Err(self.error_at(
+                name_at,
+                "Classes may not have a private field named '#constructor'",
+            ));
+        }
+        if name.starts_with('#') {
+            if privates.iter().any(|(n, k)| {
+                *n == name && (*k == kind || kind == MethodKind::Method || *k == MethodKind::Method)
+            }) {
+                return Err(self.error_at(
+                    name_at,
+                    &format!("Identifier '{name}' has already been declared"),
+                ));
+            }
+            privates.push((name.clone(), kind));
+        }
 
-                    if name_vec.len() == 1 {
-                        res.push(Stmt::VardeclSimple {
-                            name: match name_vec.get(0).unwrap() {
-                                Token::Identifier(id) => id.to_string(),
-                                _ => String::from("undefined"),
-                            },
-                            val: expr_gen(&toks[i + 1..semicolon_idx]),
-                            qual: VarQualifier::Let,
-                        });
+        if !is_op(self.peek(), EOperator::LParen) {
+            if kind != MethodKind::Method {
+                return Err(self.err_expected("'('"));
+            }
+            if is_ctor {
+                return Err(
+                    self.error_at(name_at, "Classes may not have a field named 'constructor'")
+                );
+            }
+
+            let val = if is_op(self.peek(), EOperator::Eq) {
+                self.i += 1;
+                Some(self.with_super(SuperUse::Member, Self::assign)?)
+            } else {
+                None
+            };
+            self.terminator()?;
+
+            return Ok(ClassMember::Field {
+                key,
+                is_static,
+                val,
+            });
+        }
+
+        let mut sup = SuperUse::Member;
+        if is_ctor {
+            if kind != MethodKind::Method {
+                return Err(self.error_at(name_at, "Class constructor may not be an accessor"));
+            }
+            if members.iter().any(
+                |m| matches!(m, ClassMember::Method { key: PropKey::Named(n), is_static: false, .. } if n == "constructor"),
+            ) {
+                return Err(self.error_at(name_at, "a class may only have one constructor"));
+            }
+            if derived {
+                sup = SuperUse::Call;
+            }
+        }
+
+        let args = self.params()?;
+        let body = self.with_super(sup, Self::fn_body)?;
+
+        Ok(ClassMember::Method {
+            key,
+            kind,
+            is_static,
+            args: args.into(),
+            body: body.into(),
+        })
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Lexeme { tok: Token::Keyword(k), .. }) if k == kw)
+    }
+
+    fn trystmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let block = self.block()?;
+        let mut param = None;
+        let mut handler = None;
+        let mut finalizer = None;
+
+        if self.is_keyword("catch") {
+            self.i += 1;
+
+            if is_op(self.peek(), EOperator::LParen) {
+                self.i += 1;
+                param = Some(self.expect_ident("catch parameter")?.0);
+                self.expect_op(EOperator::RParen)?;
+            }
+
+            handler = Some(self.block()?);
+        }
+
+        if self.is_keyword("finally") {
+            self.i += 1;
+            finalizer = Some(self.block()?);
+        }
+
+        if handler.is_none() && finalizer.is_none() {
+            return Err(self.err_expected("'catch' or 'finally'"));
+        }
+
+        Ok(Stmt::new(
+            StmtKind::Try {
+                block,
+                param,
+                handler,
+                finalizer,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* a single statement, used as the body of `if`, loops and labels */
+    fn sub_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        let mut v = Vec::new();
+        self.stmt(&mut v)?;
+
+        if v.len() == 1 {
+            Ok(v.pop().unwrap())
+        } else {
+            Ok(Stmt::new(StmtKind::Block(v), self.span_from(start)))
+        }
+    }
+
+    fn loop_body(&mut self) -> Result<Box<Stmt>, ParseError> {
+        self.loop_depth += 1;
+        let body = self.sub_stmt();
+        self.loop_depth -= 1;
+
+        Ok(Box::new(body?))
+    }
+
+    /* `( expr )` */
+    fn paren_expr(&mut self) -> Result<Expr, ParseError> {
+        self.expect_op(EOperator::LParen)?;
+        let e = self.expr()?;
+        self.expect_op(EOperator::RParen)?;
+        Ok(e)
+    }
+
+    fn ifstmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let cond = self.paren_expr()?;
+        let then_st = Box::new(self.sub_stmt()?);
+
+        let else_st = if self.is_keyword("else") {
+            self.i += 1;
+            Some(Box::new(self.sub_stmt()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::new(
+            StmtKind::If {
+                cond,
+                then_st,
+                else_st,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn whilestmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let cond = self.paren_expr()?;
+        let body = self.loop_body()?;
+
+        Ok(Stmt::new(
+            StmtKind::While { cond, body },
+            self.span_from(start),
+        ))
+    }
+
+    fn dowhile(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let body = self.loop_body()?;
+
+        if !self.is_keyword("while") {
+            return Err(self.err_expected("'while'"));
+        }
+        self.i += 1;
+
+        let cond = self.paren_expr()?;
+        let st = Stmt::new(StmtKind::DoWhile { body, cond }, self.span_from(start));
+
+        /* the semicolon after `do ... while (...)` is optional */
+        if is_op(self.peek(), EOperator::Semicolon) {
+            self.i += 1;
+        }
+
+        Ok(st)
+    }
+
+    /**
+     * The left side of a `for...in` or `for...of` header when the cursor is at one, which moves
+     * past the `in` or `of`, and whether it is `of`: a declaration of one name, or a target.
+     */
+    fn for_in_of_left(&mut self) -> Result<Option<(Stmt, bool)>, ParseError> {
+        let start = self.i;
+        let in_or_of = |l: Option<&Lexeme>| match l.map(|l| &l.tok) {
+            Some(Token::Keyword(k)) if k == "in" => Some(false),
+            Some(Token::Identifier(k)) if k == "of" => Some(true),
+            _ => None,
+        };
+
+        let left = match self.peek().map(|l| &l.tok) {
+            Some(Token::Keyword(kw)) if kw == "let" || kw == "const" || kw == "var" => {
+                let (Some(Token::Identifier(name)), Some(of)) =
+                    (self.peek_at(1).map(|l| &l.tok), in_or_of(self.peek_at(2)))
+                else {
+                    return Ok(None);
+                };
+                let qual = match kw.as_str() {
+                    "let" => VarQualifier::Let,
+                    "const" => VarQualifier::Const,
+                    _ => VarQualifier::Var,
+                };
+
+                /* each iteration gives it a value, so a `const` here needs no initializer */
+                let kind = StmtKind::VardeclSimple {
+                    name: name.clone(),
+                    val: None,
+                    qual,
+                };
+                self.i += 2;
+                (Stmt::new(kind, self.span_from(start)), of)
+            }
+            Some(Token::Operator(EOperator::Semicolon)) => return Ok(None),
+            _ => {
+                let target = self.call_member();
+                let (Ok(target), Some(of)) = (target, in_or_of(self.peek())) else {
+                    self.i = start;
+                    return Ok(None);
+                };
+
+                if !target.is_simple_target() {
+                    let msg = match of {
+                        true => "invalid left-hand side in for-of loop",
+                        false => "invalid left-hand side in for-in loop",
+                    };
+                    return Err(self.invalid_target(start, msg));
+                }
+                (Stmt::new(StmtKind::Expr(target), self.span_from(start)), of)
+            }
+        };
+
+        self.i += 1;
+        Ok(Some(left))
+    }
+
+    fn forstmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+        self.expect_op(EOperator::LParen)?;
+
+        if let Some((left, of)) = self.for_in_of_left()? {
+            let right = if of { self.assign()? } else { self.expr()? };
+            self.expect_op(EOperator::RParen)?;
+            let body = self.loop_body()?;
+
+            /* `for (let x of a) { var x; }` declares `x` twice */
+            self.check_redeclared([&left, &*body])?;
+
+            return Ok(Stmt::new(
+                StmtKind::ForInOf {
+                    left: Box::new(left),
+                    right,
+                    body,
+                    of,
+                },
+                self.span_from(start),
+            ));
+        }
+
+        let init_start = self.i;
+        let init = match self.peek().map(|l| &l.tok) {
+            Some(Token::Operator(EOperator::Semicolon)) => {
+                self.i += 1;
+                None
+            }
+            Some(Token::Keyword(kw)) if kw == "let" || kw == "const" || kw == "var" => {
+                let qual = match kw.as_str() {
+                    "let" => VarQualifier::Let,
+                    "const" => VarQualifier::Const,
+                    _ => VarQualifier::Var,
+                };
+
+                /* no semicolons are ever inserted in a `for` header */
+                let mut v = Vec::new();
+                self.declarations(qual, &mut v)?;
+                self.expect_op(EOperator::Semicolon)?;
+
+                if v.len() == 1 {
+                    v.pop().map(Box::new)
+                } else {
+                    Some(Box::new(Stmt::new(
+                        StmtKind::Block(v),
+                        self.span_from(init_start),
+                    )))
+                }
+            }
+            _ => {
+                let e = self.expr()?;
+                let st = Stmt::new(StmtKind::Expr(e), self.span_from(init_start));
+                self.expect_op(EOperator::Semicolon)?;
+                Some(Box::new(st))
+            }
+        };
+
+        let test = if is_op(self.peek(), EOperator::Semicolon) {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        self.expect_op(EOperator::Semicolon)?;
+
+        let update = if is_op(self.peek(), EOperator::RParen) {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        self.expect_op(EOperator::RParen)?;
+
+        let body = self.loop_body()?;
+
+        let decls = init.as_deref().map(Stmt::declarations).unwrap_or_default();
+        self.check_redeclared(decls.iter().chain([&*body]))?;
+
+        Ok(Stmt::new(
+            StmtKind::For {
+                init,
+                test,
+                update,
+                body,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /* `switch (disc) { case a: ... default: ... }`; the statements of all cases make up one block */
+    fn switchstmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 1;
+
+        let disc = self.paren_expr()?;
+        self.expect_op(EOperator::LBrace)?;
+
+        let mut cases: Vec<(Option<Expr>, usize)> = Vec::new();
+        let mut body = Vec::new();
+        self.switch_depth += 1;
+
+        while !is_op(self.peek(), EOperator::RBrace) {
+            let test = if self.is_keyword("case") {
+                self.i += 1;
+                Some(self.expr()?)
+            } else if self.is_keyword("default") {
+                if cases.iter().any(|(t, _)| t.is_none()) {
+                    return Err(
+                        self.error_at(self.i, "more than one default clause in switch statement")
+                    );
+                }
+                self.i += 1;
+                None
+            } else {
+                return Err(self.err_expected("'case', 'default' or '}'"));
+            };
+            self.expect_op(EOperator::Colon)?;
+            cases.push((test, body.len()));
+
+            while !(self.is_keyword("case")
+                || self.is_keyword("default")
+                || is_op(self.peek(), EOperator::RBrace))
+            {
+                if self.peek().is_none() {
+                    return Err(self.err_expected("'}'"));
+                }
+                self.stmt(&mut body)?;
+            }
+        }
+
+        self.switch_depth -= 1;
+        self.i += 1;
+        self.check_redeclared(&body)?;
+
+        Ok(Stmt::new(
+            StmtKind::Switch { disc, cases, body },
+            self.span_from(start),
+        ))
+    }
+
+    /* `break`/`continue` with an optional label */
+    fn jump(&mut self, is_break: bool) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        let kw = self.toks[start].clone();
+        self.i += 1;
+
+        let label = match self.peek() {
+            Some(Lexeme {
+                tok: Token::Identifier(l),
+                ..
+            }) if !self.newline_at(0) => {
+                let l = l.clone();
+                self.i += 1;
+                Some(l)
+            }
+            _ => None,
+        };
+
+        let valid = match &label {
+            None => self.loop_depth > 0 || (is_break && self.switch_depth > 0),
+            Some(l) => self
+                .labels
+                .iter()
+                .any(|(name, is_loop)| name == l && (is_break || *is_loop)),
+        };
+
+        if !valid {
+            let msg = match (&label, is_break) {
+                (Some(l), _) if !self.labels.iter().any(|(name, _)| name == l) => {
+                    format!("undefined label '{l}'")
+                }
+                (_, true) => String::from("illegal break statement"),
+                (_, false) => {
+                    String::from("illegal continue statement: no surrounding iteration statement")
+                }
+            };
+
+            return Err(ParseError::new(
+                ParseErrorKind::IllegalJump,
+                Some(&kw),
+                self.eof,
+                msg,
+            ));
+        }
+
+        let kind = if is_break {
+            StmtKind::Break(label)
+        } else {
+            StmtKind::Continue(label)
+        };
+
+        let st = Stmt::new(kind, self.span_from(start));
+        self.terminator()?;
+        Ok(st)
+    }
+
+    fn labeled(&mut self, label: String) -> Result<Stmt, ParseError> {
+        let start = self.i;
+        self.i += 2;
+
+        let is_loop = matches!(
+            self.peek().map(|l| &l.tok),
+            Some(Token::Keyword(k)) if k == "for" || k == "while" || k == "do"
+        ) || matches!(
+            (self.peek().map(|l| &l.tok), self.peek_at(1).map(|l| &l.tok)),
+            (
+                Some(Token::Identifier(_)),
+                Some(Token::Operator(EOperator::Colon))
+            )
+        );
+
+        self.labels.push((label.clone(), is_loop));
+        let body = self.sub_stmt();
+        self.labels.pop();
+
+        Ok(Stmt::new(
+            StmtKind::Labeled {
+                label,
+                body: Box::new(body?),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn stmt(&mut self, res: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        self.check_depth()?;
+        let start = self.i;
+        let Some(l) = self.peek() else {
+            return Err(self.err_expected("statement"));
+        };
+
+        match &l.tok {
+            Token::Keyword(kw) => match kw.as_str() {
+                "let" => self.vardecl(VarQualifier::Let, res)?,
+                "const" => self.vardecl(VarQualifier::Const, res)?,
+                "var" => self.vardecl(VarQualifier::Var, res)?,
+                "function" => res.push(self.funcdecl()?),
+                "class" => res.push(self.classdecl()?),
+                "return" => {
+                    let kspan = l.span;
+                    self.i += 1;
+
+                    /* `return\nx` returns undefined */
+                    let val = if self.at_stmt_end() {
+                        Expr::new(ExprKind::Const(constants::Const::Undef), kspan)
                     } else {
-                        panic!("feature in development.");
+                        self.expr()?
+                    };
+
+                    res.push(Stmt::new(StmtKind::ReturnStmt(val), self.span_from(start)));
+                    self.terminator()?;
+                }
+                "try" => res.push(self.trystmt()?),
+                "if" => res.push(self.ifstmt()?),
+                "while" => res.push(self.whilestmt()?),
+                "do" => res.push(self.dowhile()?),
+                "for" => res.push(self.forstmt()?),
+                "switch" => res.push(self.switchstmt()?),
+                "break" => res.push(self.jump(true)?),
+                "continue" => res.push(self.jump(false)?),
+                "throw" => {
+                    self.i += 1;
+
+                    if self.newline_at(0) {
+                        return Err(ParseError::new(
+                            ParseErrorKind::UnexpectedToken,
+                            self.peek(),
+                            self.eof,
+                            String::from("illegal newline after throw"),
+                        ));
                     }
+                    let val = self.expr()?;
+
+                    res.push(Stmt::new(StmtKind::Throw(val), self.span_from(start)));
+                    self.terminator()?;
+                }
+                "new" | "this" | "super" | "typeof" | "void" | "delete" => {
+                    res.push(self.expr_stmt()?)
                 }
-                _ => (),
+                _ => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unsupported,
+                        Some(l),
+                        self.eof,
+                        format!("{} is not supported yet", l.tok),
+                    ))
+                }
+            },
+            Token::Identifier(name) => {
+                if is_op(self.peek_at(1), EOperator::Colon) {
+                    let name = name.clone();
+                    res.push(self.labeled(name)?);
+                } else {
+                    res.push(self.expr_stmt()?);
+                }
+            }
+            Token::Operator(EOperator::LBrace) => {
+                let body = self.block()?;
+                res.push(Stmt::new(StmtKind::Block(body), self.span_from(start)));
             }
+            Token::Operator(EOperator::Semicolon) => self.i += 1,
+            _ => res.push(self.expr_stmt()?),
         }
 
-        i += 1;
+        Ok(())
     }
+}
 
-    res
+/* the first malformed literal the lexer found, if any */
+fn check_invalid(toks: &[Lexeme], eof: Span) -> Result<(), ParseError> {
+    match toks.iter().find(|l| matches!(l.tok, Token::Invalid(_))) {
+        Some(
+            l @ Lexeme {
+                tok: Token::Invalid(msg),
+                ..
+            },
+        ) => Err(ParseError::new(
+            ParseErrorKind::InvalidLiteral,
+            Some(l),
+            eof,
+            msg.clone(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/* parsing recurses for nested constructs, so it gets the same stack budget as running scripts */
+pub fn expr_gen(toks: &[Lexeme]) -> Result<Expr, ParseError> {
+    stack::run(|| {
+        let mut p = Parser::new(toks);
+        check_invalid(&p.toks, p.eof)?;
+        let e = p.expr()?;
+
+        if p.peek().is_some() {
+            return Err(p.err_expected("end of input"));
+        }
+
+        Ok(e)
+    })
+}
+
+pub fn stmt_gen(toks: &[Lexeme]) -> Result<Vec<stmt::Stmt>, ParseError> {
+    stack::run(|| {
+        let mut p = Parser::new(toks);
+        check_invalid(&p.toks, p.eof)?;
+        let mut res: Vec<stmt::Stmt> = Vec::new();
+
+        while p.peek().is_some() {
+            if is_op(p.peek(), EOperator::RBrace) {
+                return Err(p.err_expected("statement"));
+            }
+
+            p.stmt(&mut res)?;
+        }
+
+        p.check_redeclared(&res)?;
+        Ok(res)
+    })
 }
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use crate::token::{Lexeme, Span, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /* a token that cannot appear here */
+    UnexpectedToken,
+    /* input ended in the middle of a construct */
+    UnexpectedEof,
+    /* left hand side of `=` is not something that can be assigned to */
+    InvalidAssignmentTarget,
+    /* `break`/`continue` with no matching loop or label */
+    IllegalJump,
+    /* a malformed literal such as `0x` or `1e` */
+    InvalidLiteral,
+    /* valid JS that the parser does not handle yet */
+    Unsupported,
+    /* constructs nested deeper than the stack allows */
+    TooDeep,
+    /* a name declared twice in the same scope, or a `const` with no value */
+    InvalidDeclaration,
+}
+
+/**
+ * Error produced by `stmt_gen`/`expr_gen`.
+ * `tok` is the offending token (`None` at end of input) and `span` is where it was found.
+ */
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub tok: Option<Token>,
+    pub span: Span,
+    pub msg: String,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, found: Option<&Lexeme>, eof: Span, msg: String) -> ParseError {
+        ParseError {
+            kind,
+            tok: found.map(|l| l.tok.clone()),
+            span: found.map_or(eof, |l| l.span),
+            msg,
+        }
+    }
+
+    /* "expected X, found Y" */
+    pub fn expected(what: &str, found: Option<&Lexeme>, eof: Span) -> ParseError {
+        let kind = match found {
+            Some(_) => ParseErrorKind::UnexpectedToken,
+            None => ParseErrorKind::UnexpectedEof,
+        };
+
+        let found_s = match found {
+            Some(l) => l.tok.to_string(),
+            None => Token::Eof.to_string(),
+        };
+
+        ParseError::new(
+            kind,
+            found,
+            eof,
+            format!("expected {what}, found {found_s}"),
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: SyntaxError: {}", self.span, self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,16 +1,277 @@
-use super::arithmetic::Arithmetic;
+use std::rc::Rc;
 
+use super::stmt::{ClassMember, MethodKind, Stmt};
+use crate::token::{EOperator, Span};
+
+pub mod bigint;
 pub mod constants;
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Const(constants::Const),
 
     Var(String),
 
-    Arith(Vec<Arithmetic>), /* postfix form */
+    This,
+
+    /* `!e`, `-e`, `+e`, `~e` */
+    Unary {
+        op: EOperator,
+        e: Box<Expr>,
+    },
+
+    Typeof(Box<Expr>),
+
+    Void(Box<Expr>),
+
+    Delete(Box<Expr>),
+
+    /* `++e`, `e--`, ...; `op` is `Incr` or `Decr` */
+    Update {
+        op: EOperator,
+        prefix: bool,
+        target: Box<Expr>,
+    },
+
+    /* arithmetic, comparison, equality, shift and bitwise operators */
+    Binary {
+        op: EOperator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+
+    /* `&&`, `||` and `??`; the right side is only evaluated when needed */
+    Logical {
+        op: EOperator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+
+    /* `key in obj` */
+    In {
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+
+    Instanceof {
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+
+    /* `test ? cons : alt` */
+    Conditional {
+        test: Box<Expr>,
+        cons: Box<Expr>,
+        alt: Box<Expr>,
+    },
+
+    /* `target = val`, or a compound form such as `+=` / `??=` */
+    Assign {
+        op: EOperator,
+        target: Box<Expr>,
+        val: Box<Expr>,
+    },
+
+    /* `a, b, c` */
+    Sequence(Vec<Expr>),
+
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+
+    New {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+
+    /**
+     * `function name(args) { body }` with an optional name, or an arrow function.
+     * An arrow with an expression body gets a body of a single `return`.
+     */
+    Function {
+        name: Option<String>,
+        args: Rc<[Expr]>,
+        body: Rc<[Stmt]>,
+        form: FuncForm,
+    },
+
+    /* `[1, , ...rest]`; `None` is a hole */
+    Array(Vec<Option<Expr>>),
+
+    /* `...e` inside an array literal or an argument list */
+    Spread(Box<Expr>),
+
+    /* `{ a: 1, [k]: v, ...o }` */
+    Object(Vec<ObjProp>),
+
+    /* `obj.prop` */
+    Member {
+        obj: Box<Expr>,
+        prop: String,
+    },
+
+    /* `obj[index]` */
+    Index {
+        obj: Box<Expr>,
+        index: Box<Expr>,
+    },
+
+    /* `obj.#name`; `name` keeps its `#` */
+    PrivateMember {
+        obj: Box<Expr>,
+        name: String,
+    },
+
+    /* `class [Name] [extends P] { members }` used as a value */
+    Class {
+        name: Option<String>,
+        parent: Option<Box<Expr>>,
+        members: Vec<ClassMember>,
+    },
+
+    /**
+     * `a?.b.c`, `a?.[i]` or `f?.()`: the whole chain, which is `undefined` as soon as an `Optional` part is nullish.
+     * Only the object or callee right before a `?.` is wrapped in `Optional`.
+     */
+    Chain(Box<Expr>),
+
+    Optional(Box<Expr>),
+
+    /* `#name`, which may only appear on the left of `in` */
+    PrivateName(String),
+
+    /* `super(args)` in the constructor of a class that extends another */
+    SuperCall(Vec<Expr>),
+
+    /* `super.prop` or `super[key]` in a class body */
+    SuperMember(PropKey),
+
+    /* `a${x}b`; `quasis` has one more entry than `exprs` */
+    Template {
+        quasis: Vec<constants::JsStr>,
+        exprs: Vec<Expr>,
+    },
+
+    /**
+     * ``tag`a${x}b` ``, which calls `tag` with the strings and then the values of `exprs`.
+     * A cooked string is `None` where the source had an invalid escape.
+     */
+    TaggedTemplate {
+        tag: Box<Expr>,
+        site: Rc<TemplateSite>,
+        exprs: Vec<Expr>,
+    },
+}
+
+/* the strings of a tagged template; every evaluation of the call site passes the tag the same array of them */
+#[derive(Debug)]
+pub struct TemplateSite {
+    pub cooked: Vec<Option<constants::JsStr>>,
+    pub raw: Vec<String>,
+}
+
+/* key of an object literal entry */
+#[derive(Debug, Clone)]
+pub enum PropKey {
+    Named(String),       /* `a: 1`, `"a": 1`, `1: a` */
+    Computed(Box<Expr>), /* `[k]: v` */
+}
+
+#[derive(Debug, Clone)]
+pub enum ObjProp {
+    /* shorthand `a` and methods `m() {}` become `a: a` and `m: function () {}` */
+    KeyValue {
+        key: PropKey,
+        val: Expr,
+    },
+    /* `get k() {}` or `set k(v) {}`; `val` is the function */
+    Accessor {
+        key: PropKey,
+        kind: MethodKind,
+        val: Expr,
+    },
+    Spread(Expr),
+    /* `__proto__: v`, which sets the object's prototype instead of adding a property */
+    Proto(Expr),
+}
+
+/* the sorts of function literal, which differ in how they get `this` and whether `new` takes them */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuncForm {
+    /* `function` declarations and expressions, which can also be constructors */
+    Plain,
+    /* arrows take `this` from their scope instead of binding their own */
+    Arrow,
+    /* methods and accessors in object literals and classes */
+    Method,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind, span }
+    }
+
+    /* whether `self` can be assigned to */
+    pub fn is_simple_target(&self) -> bool {
+        matches!(
+            self.kind,
+            ExprKind::Var(_)
+                | ExprKind::Member { .. }
+                | ExprKind::Index { .. }
+                | ExprKind::PrivateMember { .. }
+                | ExprKind::SuperMember(_)
+        )
+    }
+}
+
+impl Drop for Expr {
+    /* frees a left-deep chain such as `1 + 1 + ...` or `a.b.c...` in a loop, not one frame per link */
+    fn drop(&mut self) {
+        let mut next = self.kind.take_head();
+        while let Some(mut e) = next {
+            next = e.kind.take_head();
+        }
+    }
+}
 
-    FuncCallSimple { name: String, args: Vec<Box<Expr>> },
+impl ExprKind {
+    /* the leftmost operand of an operator, member access or call, which is evaluated first */
+    pub fn head(&self) -> Option<&Expr> {
+        use ExprKind::*;
+        match self {
+            Binary { lhs, .. } | Logical { lhs, .. } | In { lhs, .. } | Instanceof { lhs, .. } => {
+                Some(lhs)
+            }
+            Member { obj, .. } | Index { obj, .. } | PrivateMember { obj, .. } => Some(obj),
+            Call { callee, .. } => Some(callee),
+            TaggedTemplate { tag, .. } => Some(tag),
+            Chain(e) | Optional(e) => Some(e),
+            _ => None,
+        }
+    }
 
-    NewConstruct(Box<Expr>),
+    /* detaches `head`, leaving `self` without children */
+    fn take_head(&mut self) -> Option<Box<Expr>> {
+        use ExprKind::*;
+        match std::mem::replace(self, This) {
+            Binary { lhs, .. } | Logical { lhs, .. } | In { lhs, .. } | Instanceof { lhs, .. } => {
+                Some(lhs)
+            }
+            Member { obj, .. } | Index { obj, .. } | PrivateMember { obj, .. } => Some(obj),
+            Call { callee, .. } => Some(callee),
+            TaggedTemplate { tag, .. } => Some(tag),
+            Chain(e) | Optional(e) => Some(e),
+            other => {
+                *self = other;
+                None
+            }
+        }
+    }
 }
@@ -0,0 +1,449 @@
+/*
+ * Arbitrary-precision integers, the values of BigInts.
+ *
+ * A `BigInt` is a sign and a magnitude of 32-bit limbs, least significant first, with no
+ * zero limbs at the top; zero has no limbs and is never negative. The bitwise operators
+ * work on the infinite two's complement form scripts see.
+ */
+
+use std::{cmp::Ordering, fmt, rc::Rc};
+
+/* results wider than this many bits throw a RangeError, as they do in other engines */
+pub const MAX_BITS: u64 = 1 << 24;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigInt {
+    neg: bool,
+    /* shared between copies, like the units of a `JsStr` */
+    mag: Rc<[u32]>,
+}
+
+/* drops the zero limbs at the top */
+fn trim(mut v: Vec<u32>) -> Vec<u32> {
+    while v.last() == Some(&0) {
+        v.pop();
+    }
+    v
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+
+    for (i, &x) in a.iter().enumerate() {
+        let s = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        out.push(s as u32);
+        carry = s >> 32;
+    }
+    out.push(carry as u32);
+    trim(out)
+}
+
+/* `a - b` for `a >= b` */
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+
+    for (i, &x) in a.iter().enumerate() {
+        let d = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        out.push(d as u32);
+        borrow = (d < 0) as i64;
+    }
+    trim(out)
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let mut out = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + out[i + j] as u64 + carry;
+            out[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        out[i + b.len()] = carry as u32;
+    }
+    trim(out)
+}
+
+/* `v * m + a` in place */
+fn mul_add_small(v: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for x in v.iter_mut() {
+        let t = *x as u64 * m as u64 + carry;
+        *x = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        v.push(carry as u32);
+    }
+}
+
+/* `a / d` and `a % d` for a one-limb divisor */
+fn divrem_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut r = 0u64;
+
+    for i in (0..a.len()).rev() {
+        let cur = (r << 32) | a[i] as u64;
+        q[i] = (cur / d as u64) as u32;
+        r = cur % d as u64;
+    }
+    (trim(q), r as u32)
+}
+
+/* `v << s` for `s < 32`, with a limb more for what is shifted out at the top */
+fn shl_bits(v: &[u32], s: u32) -> Vec<u32> {
+    let mut out = Vec::with_capacity(v.len() + 1);
+    let mut carry = 0;
+
+    for &x in v {
+        out.push((x << s) | carry);
+        carry = if s == 0 { 0 } else { x >> (32 - s) };
+    }
+    out.push(carry);
+    out
+}
+
+/* `v >> s` for `s < 32` */
+fn shr_bits(v: &[u32], s: u32) -> Vec<u32> {
+    let out = (0..v.len()).map(|i| match s {
+        0 => v[i],
+        _ => (v[i] >> s) | (v.get(i + 1).unwrap_or(&0) << (32 - s)),
+    });
+    trim(out.collect())
+}
+
+/* `a / b` and `a % b` for magnitudes, `b` not zero, by Knuth's algorithm D */
+fn divrem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (q, r) = divrem_small(a, b[0]);
+        return (q, trim(vec![r]));
+    }
+
+    /* with the top bit of the divisor set, each estimated quotient limb is off by at most 2 */
+    let s = b[b.len() - 1].leading_zeros();
+    let b = trim(shl_bits(b, s));
+    let mut a = shl_bits(a, s);
+    let n = b.len();
+    let m = a.len() - n;
+    let (top, next) = (b[n - 1] as u64, b[n - 2] as u64);
+    let mut q = vec![0u32; m];
+
+    for j in (0..m).rev() {
+        let num = ((a[j + n] as u64) << 32) | a[j + n - 1] as u64;
+        let mut qhat = num / top;
+        let mut rhat = num % top;
+        while qhat > u32::MAX as u64 || qhat * next > ((rhat << 32) | a[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += top;
+            if rhat > u32::MAX as u64 {
+                break;
+            }
+        }
+
+        let mut borrow = 0;
+        let mut carry = 0;
+        for i in 0..n {
+            let p = qhat * b[i] as u64 + carry;
+            carry = p >> 32;
+            let t = a[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            a[i + j] = t as u32;
+            borrow = (t < 0) as i64;
+        }
+        let t = a[j + n] as i64 - borrow - carry as i64;
+        a[j + n] = t as u32;
+
+        /* the estimate was one too big: add the divisor back */
+        if t < 0 {
+            qhat -= 1;
+            let mut carry = 0;
+            for i in 0..n {
+                let s = a[i + j] as u64 + b[i] as u64 + carry;
+                a[i + j] = s as u32;
+                carry = s >> 32;
+            }
+            a[j + n] = a[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+
+    (trim(q), shr_bits(&a[..n], s))
+}
+
+impl BigInt {
+    fn new(neg: bool, mag: Vec<u32>) -> BigInt {
+        let mag = trim(mag);
+        BigInt {
+            neg: neg && !mag.is_empty(),
+            mag: mag.into(),
+        }
+    }
+
+    pub fn from_i64(i: i64) -> BigInt {
+        let m = i.unsigned_abs();
+        BigInt::new(i < 0, vec![m as u32, (m >> 32) as u32])
+    }
+
+    /* the digits of a literal in `radix`, without sign or separators */
+    pub fn parse(digits: &str, radix: u32) -> Option<BigInt> {
+        if digits.is_empty() {
+            return None;
+        }
+
+        let mut mag = Vec::new();
+        for c in digits.chars() {
+            mul_add_small(&mut mag, radix, c.to_digit(radix)?);
+        }
+        Some(BigInt::new(false, mag))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    /* the number of bits of the magnitude */
+    pub fn bits(&self) -> u64 {
+        match self.mag.last() {
+            Some(top) => self.mag.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /* the magnitude, when it fits */
+    fn to_u64(&self) -> Option<u64> {
+        match self.mag[..] {
+            [] => Some(0),
+            [lo] => Some(lo as u64),
+            [lo, hi] => Some(((hi as u64) << 32) | lo as u64),
+            _ => None,
+        }
+    }
+
+    /* the nearest Number, ties to even */
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.bits();
+        let m = match bits {
+            ..=64 => self.to_u64().unwrap_or_default() as f64,
+            _ => {
+                /* the top 64 bits, with the lowest set when any bit below them is */
+                let shift = bits - 64;
+                let top = self.shr_mag(shift).to_u64().unwrap_or_default();
+                let sticky = self.low_bits_set(shift);
+                (top | sticky as u64) as f64 * 2f64.powi(shift.min(i32::MAX as u64) as i32)
+            }
+        };
+
+        if self.neg {
+            -m
+        } else {
+            m
+        }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt {
+            neg: !self.neg && !self.is_zero(),
+            mag: self.mag.clone(),
+        }
+    }
+
+    pub fn add(&self, o: &BigInt) -> BigInt {
+        if self.neg == o.neg {
+            return BigInt::new(self.neg, add_mag(&self.mag, &o.mag));
+        }
+
+        match cmp_mag(&self.mag, &o.mag) {
+            Ordering::Less => BigInt::new(o.neg, sub_mag(&o.mag, &self.mag)),
+            _ => BigInt::new(self.neg, sub_mag(&self.mag, &o.mag)),
+        }
+    }
+
+    pub fn sub(&self, o: &BigInt) -> BigInt {
+        self.add(&o.neg())
+    }
+
+    pub fn mul(&self, o: &BigInt) -> BigInt {
+        BigInt::new(self.neg != o.neg, mul_mag(&self.mag, &o.mag))
+    }
+
+    /* the quotient, rounded towards zero, and the remainder, which has the sign of `self` */
+    pub fn div_rem(&self, o: &BigInt) -> Option<(BigInt, BigInt)> {
+        if o.is_zero() {
+            return None;
+        }
+
+        let (q, r) = divrem_mag(&self.mag, &o.mag);
+        Some((BigInt::new(self.neg != o.neg, q), BigInt::new(self.neg, r)))
+    }
+
+    /* `self ** e` for `e >= 0`; `None` when the result would be wider than `MAX_BITS` */
+    pub fn pow(&self, e: &BigInt) -> Option<BigInt> {
+        if e.is_zero() {
+            return Some(BigInt::from_i64(1));
+        }
+        /* 0, 1 and -1 stay small whatever the exponent */
+        if self.bits() <= 1 {
+            let odd = e.mag[0] & 1 == 1;
+            return Some(if odd { self.clone() } else { self.mul(self) });
+        }
+
+        let mut e = e
+            .to_u64()
+            .filter(|e| e.saturating_mul(self.bits() - 1) <= MAX_BITS)?;
+        let mut base = self.clone();
+        let mut r = BigInt::from_i64(1);
+        while e > 0 {
+            if e & 1 == 1 {
+                r = r.mul(&base);
+            }
+            e >>= 1;
+            if e > 0 {
+                base = base.mul(&base);
+            }
+        }
+        Some(r)
+    }
+
+    /* whether any of the lowest `n` bits of the magnitude is set */
+    fn low_bits_set(&self, n: u64) -> bool {
+        let limbs = ((n / 32) as usize).min(self.mag.len());
+        self.mag[..limbs].iter().any(|&x| x != 0)
+            || self
+                .mag
+                .get(limbs)
+                .is_some_and(|&x| x & ((1u32 << (n % 32)) - 1) != 0)
+    }
+
+    /* the magnitude shifted right by `n` bits */
+    fn shr_mag(&self, n: u64) -> BigInt {
+        let limbs = (n / 32) as usize;
+        if limbs >= self.mag.len() {
+            return BigInt::default();
+        }
+        BigInt::new(false, shr_bits(&self.mag[limbs..], (n % 32) as u32))
+    }
+
+    /* `self << n`, where a negative `n` shifts right, rounding towards negative infinity */
+    pub fn shl(&self, n: &BigInt) -> Option<BigInt> {
+        if self.is_zero() {
+            return Some(BigInt::default());
+        }
+
+        if n.neg {
+            let n = n.to_u64().unwrap_or(u64::MAX);
+            let q = self.shr_mag(n);
+            if !self.neg {
+                return Some(q);
+            }
+            /* a negative value moves down to the next integer when it loses any set bit */
+            let q = match self.low_bits_set(n) {
+                true => q.add(&BigInt::from_i64(1)),
+                false => q,
+            };
+            return Some(q.neg());
+        }
+
+        let n = n.to_u64().filter(|&n| n <= MAX_BITS)?;
+        let mut mag = vec![0u32; (n / 32) as usize];
+        mag.extend(shl_bits(&self.mag, (n % 32) as u32));
+        Some(BigInt::new(self.neg, mag))
+    }
+
+    /* `!self` in two's complement, which is `-self - 1` */
+    pub fn not(&self) -> BigInt {
+        self.add(&BigInt::from_i64(1)).neg()
+    }
+
+    /* the lowest `len` limbs of the two's complement form */
+    fn twos(&self, len: usize) -> Vec<u32> {
+        let mut v = match self.neg {
+            true => sub_mag(&self.mag, &[1]),
+            false => self.mag.to_vec(),
+        };
+        v.resize(len, 0);
+        if self.neg {
+            v.iter_mut().for_each(|x| *x = !*x);
+        }
+        v
+    }
+
+    fn from_twos(mut v: Vec<u32>) -> BigInt {
+        let neg = v.last().is_some_and(|x| x >> 31 == 1);
+        if neg {
+            v.iter_mut().for_each(|x| *x = !*x);
+            v = add_mag(&v, &[1]);
+        }
+        BigInt::new(neg, v)
+    }
+
+    /* `f` limb by limb on the two's complement forms, for `&`, `|` and `^` */
+    pub fn bitwise(&self, o: &BigInt, f: impl Fn(u32, u32) -> u32) -> BigInt {
+        /* one limb more than either needs keeps the sign */
+        let len = self.mag.len().max(o.mag.len()) + 1;
+        let (a, b) = (self.twos(len), o.twos(len));
+        BigInt::from_twos(a.iter().zip(&b).map(|(&x, &y)| f(x, y)).collect())
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, o: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, o: &BigInt) -> Ordering {
+        match (self.neg, o.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &o.mag),
+            (true, true) => cmp_mag(&o.mag, &self.mag),
+        }
+    }
+}
+
+/* in decimal, nine digits at a time */
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.to_vec();
+        while !mag.is_empty() {
+            let (q, r) = divrem_small(&mag, 1_000_000_000);
+            chunks.push(r);
+            mag = q;
+        }
+
+        if self.neg {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap_or(&0))?;
+        for c in chunks {
+            write!(f, "{c:09}")?;
+        }
+        Ok(())
+    }
+}
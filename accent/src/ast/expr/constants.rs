@@ -1,15 +1,39 @@
-use std::fmt::{self};
+use super::bigint::BigInt;
+use std::{
+    cell::{Ref, RefCell},
+    cmp::Ordering,
+    fmt::{self},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
 
+/* largest integer that every Number next to it can still be told apart from */
+pub const MAX_EXACT_INT: i64 = 1 << 53;
+
+/* the longest string a script can make, in code units, as in V8 */
+pub const MAX_STR_LENGTH: usize = (1 << 29) - 24;
+
+/* a string would be longer than `MAX_STR_LENGTH` */
+#[derive(Debug)]
+pub struct TooLong;
+
+/**
+ * Scripts see a single Number type, an IEEE-754 double.
+ * `Integer` is only a faster form of the integral Numbers in `-2^53..=2^53` and must never
+ * hold anything outside that range; -0 is always a `Float`.
+ */
 #[derive(Debug, Clone)]
 pub enum Const {
     Integer(i64),
     Float(f64),
+    BigInt(BigInt),
     Str {
-        v: String,
+        v: JsStr,
         is_raw: bool,
         is_fmt: bool,
     },
     Undef,
+    Null,
     Bool(bool),
 }
 
@@ -17,14 +41,208 @@ impl fmt::Display for Const {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Const::Integer(i) => write!(f, "{i}"),
+            Const::BigInt(i) => write!(f, "{i}n"),
             Const::Bool(b) => write!(f, "{}", if *b { "true" } else { "false" }),
-            Const::Float(fl) => write!(f, "{fl}"),
+            Const::Float(fl) => write!(f, "{}", number_to_string(*fl)),
             Const::Str {
                 v,
                 is_raw: _,
                 is_fmt: _,
             } => write!(f, "{v}"),
             Const::Undef => write!(f, "undefined"),
+            Const::Null => write!(f, "null"),
+        }
+    }
+}
+
+/**
+ * A string as the UTF-16 code units scripts see. A lone surrogate, such as half of an
+ * emoji cut by `slice`, is kept as it is, so the halves can be joined again later.
+ * Printing a string, or using it as a property key, turns a lone surrogate into U+FFFD.
+ *
+ * Copies share their code units, and a string is the first `len` units of its buffer.
+ * Appending to a string that ends where its buffer does extends the buffer in place, which
+ * the other strings sharing it cannot see, so building a string with `+=` is not quadratic.
+ */
+#[derive(Clone, Default)]
+pub struct JsStr {
+    buf: Rc<RefCell<Vec<u16>>>,
+    len: usize,
+}
+
+impl JsStr {
+    pub fn units(&self) -> Ref<'_, [u16]> {
+        Ref::map(self.buf.borrow(), |b| &b[..self.len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /* appends `s`, unless the result would be longer than `MAX_STR_LENGTH` */
+    pub fn push(&mut self, s: &JsStr) -> Result<(), TooLong> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        if self.len + s.len() > MAX_STR_LENGTH {
+            return Err(TooLong);
+        }
+
+        /* `s` may share the buffer, so its units are read before the buffer can grow */
+        let tail = s.units().to_vec();
+        if let Ok(mut b) = self.buf.try_borrow_mut() {
+            if b.len() == self.len {
+                b.extend_from_slice(&tail);
+                self.len = b.len();
+                return Ok(());
+            }
+        }
+
+        let mut u = Vec::with_capacity((self.len + tail.len()).max(2 * self.len));
+        u.extend_from_slice(&self.units());
+        u.extend_from_slice(&tail);
+        *self = JsStr::from(u);
+        Ok(())
+    }
+
+    /* the code points, each as a string; a lone surrogate is one of its own */
+    pub fn code_points(&self) -> Vec<JsStr> {
+        let u = self.units();
+        let mut res = Vec::new();
+        let mut i = 0;
+
+        while i < u.len() {
+            let pair =
+                matches!(u[i], 0xD800..=0xDBFF) && matches!(u.get(i + 1), Some(0xDC00..=0xDFFF));
+            let n = if pair { 2 } else { 1 };
+            res.push(JsStr::from(&u[i..i + n]));
+            i += n;
+        }
+
+        res
+    }
+}
+
+impl PartialEq for JsStr {
+    fn eq(&self, other: &JsStr) -> bool {
+        *self.units() == *other.units()
+    }
+}
+
+impl Eq for JsStr {}
+
+impl PartialOrd for JsStr {
+    fn partial_cmp(&self, other: &JsStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JsStr {
+    fn cmp(&self, other: &JsStr) -> Ordering {
+        self.units().cmp(&other.units())
+    }
+}
+
+impl Hash for JsStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.units().hash(state);
+    }
+}
+
+impl From<&str> for JsStr {
+    fn from(s: &str) -> JsStr {
+        JsStr::from(s.encode_utf16().collect::<Vec<u16>>())
+    }
+}
+
+impl From<String> for JsStr {
+    fn from(s: String) -> JsStr {
+        JsStr::from(s.as_str())
+    }
+}
+
+impl From<&String> for JsStr {
+    fn from(s: &String) -> JsStr {
+        JsStr::from(s.as_str())
+    }
+}
+
+impl From<Vec<u16>> for JsStr {
+    fn from(u: Vec<u16>) -> JsStr {
+        JsStr {
+            len: u.len(),
+            buf: Rc::new(RefCell::new(u)),
         }
     }
 }
+
+impl From<&[u16]> for JsStr {
+    fn from(u: &[u16]) -> JsStr {
+        JsStr::from(u.to_vec())
+    }
+}
+
+impl fmt::Display for JsStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        char::decode_utf16(self.units().iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .try_for_each(|c| write!(f, "{c}"))
+    }
+}
+
+impl fmt::Debug for JsStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+/**
+ * Number::toString(x) with radix 10: the shortest digits that read back as `x`,
+ * in plain notation from 1e-6 up to 1e21 and in exponent notation outside it.
+ */
+pub fn number_to_string(x: f64) -> String {
+    if x.is_nan() {
+        return String::from("NaN");
+    }
+    if x == 0.0 {
+        /* -0 included */
+        return String::from("0");
+    }
+    if x.is_infinite() {
+        return String::from(if x < 0.0 { "-Infinity" } else { "Infinity" });
+    }
+
+    /* Rust already finds the shortest round-tripping digits, e.g. "1.2345e-7" */
+    let sci = format!("{:e}", x.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    /* the value is 0.digits * 10^n */
+    let n = exp.parse::<i32>().unwrap_or(0) + 1;
+
+    let body = if k <= n && n <= 21 {
+        digits + &"0".repeat((n - k) as usize)
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        let sign = if n - 1 < 0 { '-' } else { '+' };
+        let rest = if k > 1 {
+            format!(".{}", &digits[1..])
+        } else {
+            String::new()
+        };
+        format!("{}{rest}e{sign}{}", &digits[..1], (n - 1).abs())
+    };
+
+    if x < 0.0 {
+        format!("-{body}")
+    } else {
+        body
+    }
+}
@@ -1,6 +1,7 @@
-use crate::ast::expr;
+use std::rc::Rc;
 
-use super::expr::Expr;
+use super::expr::{Expr, PropKey};
+use crate::token::Span;
 
 #[derive(Debug, Clone)]
 pub enum VarQualifier {
@@ -9,15 +10,45 @@ pub enum VarQualifier {
     Var,
 }
 
+/* what a class method defines */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodKind {
+    Method,
+    Get,
+    Set,
+}
+
+/* an element of a class body; private names keep their `#` */
+#[derive(Debug, Clone)]
+pub enum ClassMember {
+    /* `name(args) { body }`, or `get name() {}` / `set name(v) {}`; the name may be computed, as in `[k]() {}` */
+    Method {
+        key: PropKey,
+        kind: MethodKind,
+        is_static: bool,
+        args: Rc<[Expr]>,
+        body: Rc<[Stmt]>,
+    },
+    /* `name = val;` or just `name;`, set on every new instance or, when static, on the class */
+    Field {
+        key: PropKey,
+        is_static: bool,
+        val: Option<Expr>,
+    },
+    /* `static { body }`, run once with `this` bound to the class */
+    StaticBlock(Vec<Stmt>),
+}
+
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub enum StmtKind {
     /**
      * Simple variable declaration statement
      * ```let a = b;```
+     * `val` is `None` for `let a;` and `var a;`.
      */
     VardeclSimple {
         name: String,
-        val: expr::Expr,
+        val: Option<Expr>,
         qual: VarQualifier,
     },
 
@@ -31,15 +62,15 @@ pub enum Stmt {
      */
     Funcdecl {
         name: String,
-        args: Vec<Expr>,
-        body: Vec<Stmt>,
+        args: Rc<[Expr]>,
+        body: Rc<[Stmt]>,
     },
 
     /**
-     * Simple function call statement
-     * ```name(arg1, arg2, ...);```
+     * Expression evaluated for its side effects
+     * ```f(a, b);``` / ```a.b = c;``` / ```i++;```
      */
-    FunctCallSimple { name: String, args: Vec<expr::Expr> },
+    Expr(Expr),
 
     /**
      * Return statement
@@ -47,9 +78,193 @@ pub enum Stmt {
      */
     ReturnStmt(Expr),
 
+    /**
+     * Class declaration; the constructor is the method named `constructor`.
+     * ```js
+     * class Point extends Shape { x = 0; constructor(x) { super(); this.x = x; } norm() { return this.x; } }
+     * ```
+     */
     ClassDecl {
         name: String,
-        vars: Vec<Stmt>,
-        fns: Vec<Stmt>,
+        parent: Option<Expr>,
+        members: Vec<ClassMember>,
+    },
+
+    /**
+     * Throw statement
+     * ```throw <expr>;```
+     */
+    Throw(Expr),
+
+    /**
+     * Try statement; at least one of `handler`/`finalizer` is present.
+     * `param` is `None` for `catch { ... }` without a binding.
+     * ```js
+     * try { ... } catch (e) { ... } finally { ... }
+     * ```
+     */
+    Try {
+        block: Vec<Stmt>,
+        param: Option<String>,
+        handler: Option<Vec<Stmt>>,
+        finalizer: Option<Vec<Stmt>>,
+    },
+
+    /**
+     * Block statement
+     * ```{ ... }```
+     */
+    Block(Vec<Stmt>),
+
+    /**
+     * ```js
+     * if (cond) then_st else else_st
+     * ```
+     */
+    If {
+        cond: Expr,
+        then_st: Box<Stmt>,
+        else_st: Option<Box<Stmt>>,
     },
+
+    /**
+     * ```while (cond) body```
+     */
+    While { cond: Expr, body: Box<Stmt> },
+
+    /**
+     * ```do body while (cond);```
+     */
+    DoWhile { body: Box<Stmt>, cond: Expr },
+
+    /**
+     * ```for (init; test; update) body```
+     * every clause of the header is optional
+     */
+    For {
+        init: Option<Box<Stmt>>,
+        test: Option<Expr>,
+        update: Option<Expr>,
+        body: Box<Stmt>,
+    },
+
+    /**
+     * ```for (left in obj) body``` / ```for (left of iterable) body```
+     * `left` is a declaration without a value, or an expression statement with the target
+     */
+    ForInOf {
+        left: Box<Stmt>,
+        right: Expr,
+        body: Box<Stmt>,
+        of: bool,
+    },
+
+    /**
+     * ```switch (disc) { case a: ...; default: ... }```
+     * the cases share one block, `body`; each runs from the statement its index points at,
+     * and a `None` test is the `default` case
+     */
+    Switch {
+        disc: Expr,
+        cases: Vec<(Option<Expr>, usize)>,
+        body: Vec<Stmt>,
+    },
+
+    /**
+     * ```break;``` / ```break label;```
+     */
+    Break(Option<String>),
+
+    /**
+     * ```continue;``` / ```continue label;```
+     */
+    Continue(Option<String>),
+
+    /**
+     * ```label: body```
+     */
+    Labeled { label: String, body: Box<Stmt> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Stmt {
+        Stmt { kind, span }
+    }
+
+    /* the name this binds in the block it stands in, for `let`, `const`, classes and functions */
+    pub fn lexical_name(&self) -> Option<&str> {
+        match &self.kind {
+            StmtKind::VardeclSimple {
+                name,
+                qual: VarQualifier::Let | VarQualifier::Const,
+                ..
+            }
+            | StmtKind::Funcdecl { name, .. }
+            | StmtKind::ClassDecl { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /* the names `var` declares anywhere in this statement, outside the functions in it */
+    pub fn var_names<'s>(&'s self, out: &mut Vec<&'s str>) {
+        match &self.kind {
+            StmtKind::VardeclSimple {
+                name,
+                qual: VarQualifier::Var,
+                ..
+            } => out.push(name),
+            StmtKind::Try {
+                block,
+                handler,
+                finalizer,
+                ..
+            } => {
+                for st in [Some(block), handler.as_ref(), finalizer.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                {
+                    st.var_names(out);
+                }
+            }
+            StmtKind::Block(body) => body.iter().for_each(|st| st.var_names(out)),
+            StmtKind::If {
+                then_st, else_st, ..
+            } => {
+                then_st.var_names(out);
+                if let Some(e) = else_st {
+                    e.var_names(out);
+                }
+            }
+            StmtKind::While { body, .. }
+            | StmtKind::DoWhile { body, .. }
+            | StmtKind::Labeled { body, .. } => body.var_names(out),
+            StmtKind::For { init, body, .. } => {
+                if let Some(init) = init {
+                    init.var_names(out);
+                }
+                body.var_names(out);
+            }
+            StmtKind::ForInOf { left, body, .. } => {
+                left.var_names(out);
+                body.var_names(out);
+            }
+            StmtKind::Switch { body, .. } => body.iter().for_each(|st| st.var_names(out)),
+            _ => (),
+        }
+    }
+
+    /* the declarations of a `for` initializer; `let a = 0, b = 1` comes as a block of them */
+    pub fn declarations(&self) -> &[Stmt] {
+        match &self.kind {
+            StmtKind::Block(v) => v,
+            _ => std::slice::from_ref(self),
+        }
+    }
 }
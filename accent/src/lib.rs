@@ -2,30 +2,82 @@ pub mod ast;
 pub mod parser;
 pub mod token;
 
-use std::fs;
+use parser::{builtins, classes::ClassD, errors::ERROR_TYPES};
+pub use parser::{completion::Completion, module::Module, obj::Object};
 
-pub use parser::{module::Module, obj::Object};
-
-pub fn native_print(args: &[Object], md: &mut Module) -> Object {
+pub fn native_print(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
     for i in args {
         print!("{i} ");
     }
     println!();
-    Object::Const(ast::constants::Const::Undef)
+    Ok(Object::Const(ast::constants::Const::Undef))
+}
+
+/**
+ * `gc()`: a debug hook that runs a full collection and returns the heap statistics,
+ * e.g. `{ freed: 12, live: 40, allocated: 300, collected: 12, collections: 1 }`.
+ */
+pub fn native_gc(_this: &Object, _args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    let freed = parser::heap::collect();
+    let stats = parser::heap::stats();
+
+    let mut props = parser::props::Props::ordinary();
+    for (k, v) in [
+        ("freed", freed),
+        ("live", stats.live),
+        ("allocated", stats.allocated),
+        ("collected", stats.collected),
+        ("collections", stats.collections),
+    ] {
+        props.set(k, parser::ops::int(v as i64));
+    }
+
+    Ok(Object::obj(props))
+}
+
+/**
+ * Stack size a host can give the thread running scripts, along with a budget of
+ * `STACK_SIZE / 2` through `parser::stack::set_budget`, so that `MAX_CALL_DEPTH` nested
+ * calls fit. On any other thread deep recursion still ends in a RangeError, only sooner.
+ */
+pub const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+/* registers the global functions and classes every script can use */
+pub fn load_builtins(md: &mut Module) {
+    md.add_var("print", builtins::native("print", native_print));
+    md.add_var("gc", builtins::native("gc", native_gc));
+
+    md.add_var("Array", builtins::array::constructor());
+    md.add_var("String", builtins::string::constructor());
+    md.add_var("Number", builtins::number::constructor());
+    md.add_var("Object", builtins::object::constructor());
+
+    md.add_var("NaN", parser::ops::num(f64::NAN));
+    md.add_var("Infinity", parser::ops::num(f64::INFINITY));
+
+    for name in ERROR_TYPES {
+        md.add_var(
+            name,
+            Object::Class(ClassD::Native {
+                name: name.to_string(),
+            }),
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::{function::Function, mod_exec, module::Module};
+    use std::{collections::BTreeMap, fs};
+
+    use crate::parser::{errors::uncaught_message, mod_exec, module::Module, set_mode, Mode};
 
     use super::*;
 
     #[test]
     fn tok_test() {
-        let data =
-            String::from(fs::read_to_string("src/tests/test.js").expect("File does not exist"));
+        let data = fs::read_to_string("src/tests/test.js").expect("File does not exist");
 
-        let mut toks = token::gen_toks(data);
+        let toks = token::gen_toks(data);
 
         for i in toks {
             println!("{:?}", i);
@@ -34,45 +86,2365 @@ mod tests {
 
     #[test]
     fn stmt_test() {
-        let data =
-            String::from(fs::read_to_string("src/tests/test.js").expect("File does not exist"));
+        let data = fs::read_to_string("src/tests/test.js").expect("File does not exist");
 
-        let mut toks = token::gen_toks(data);
-        let mut sts = ast::stmt_gen(&toks);
+        let toks = token::gen_toks(data);
+        let sts = ast::stmt_gen(&toks).unwrap();
 
         for (i, iv) in sts.iter().enumerate() {
             println!("{} {:?}", i, iv);
         }
     }
 
+    #[test]
+    fn span_test() {
+        let data = String::from("let a = 10;\nprint(a, b);");
+
+        let toks = token::gen_toks(data);
+        let pr = toks
+            .iter()
+            .find(|l| matches!(&l.tok, token::Token::Identifier(id) if id == "print"))
+            .unwrap();
+
+        assert_eq!((pr.span.line, pr.span.col), (2, 1));
+        assert_eq!((pr.span.start, pr.span.end), (12, 17));
+
+        let sts = ast::stmt_gen(&toks).unwrap();
+        assert_eq!(sts[1].span.line, 2);
+
+        match &sts[1].kind {
+            ast::StmtKind::Expr(ast::Expr {
+                kind: ast::ExprKind::Call { args, .. },
+                ..
+            }) => {
+                assert_eq!((args[1].span.line, args[1].span.col), (2, 10));
+            }
+            _ => panic!("expected a call statement"),
+        }
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let toks = token::gen_toks(String::from("let a = 1;\nlet b 10;"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+
+        assert_eq!(e.kind, ast::ParseErrorKind::UnexpectedToken);
+        assert!(matches!(e.tok, Some(token::Token::Integer(10))));
+        assert_eq!((e.span.line, e.span.col), (2, 7));
+        assert_eq!(e.msg, "expected ';', found number '10'");
+
+        let toks = token::gen_toks(String::from("print(1 + 2"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+
+        assert_eq!(e.kind, ast::ParseErrorKind::UnexpectedEof);
+        assert_eq!(
+            e.to_string(),
+            "1:12: SyntaxError: expected ')', found end of input"
+        );
+
+        let toks = token::gen_toks(String::from("function (a) {}"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+
+        assert_eq!(e.msg, "expected function name, found '('");
+
+        let errors = [
+            (
+                "let z = 1;\nlet z = 2;",
+                (2, 5),
+                "Identifier 'z' has already been declared",
+            ),
+            (
+                "var y; { let y; } class y {}",
+                (1, 25),
+                "Identifier 'y' has already been declared",
+            ),
+            (
+                "{ let w; { var w; } }",
+                (1, 7),
+                "Identifier 'w' has already been declared",
+            ),
+            (
+                "const q;",
+                (1, 7),
+                "Missing initializer in const declaration",
+            ),
+        ];
+
+        for (src, at, msg) in errors {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+
+            assert_eq!(e.kind, ast::ParseErrorKind::InvalidDeclaration, "{src}");
+            assert_eq!(
+                ((e.span.line, e.span.col), e.msg.as_str()),
+                (at, msg),
+                "{src}"
+            );
+        }
+
+        let e = ast::stmt_gen(&token::gen_toks(String::from(
+            "({ __proto__: 1, '__proto__': 2 })",
+        )))
+        .unwrap_err();
+        assert_eq!(
+            e.msg,
+            "Duplicate __proto__ fields are not allowed in object literals"
+        );
+
+        let fine =
+            "var a; var a; function f() {} function f() {} var f; { let a; } let b; { let b; }";
+        assert!(ast::stmt_gen(&token::gen_toks(String::from(fine))).is_ok());
+    }
+
+    /* runs `src` with the builtins loaded, the way `mode` says */
+    fn run_in(src: &str, mode: Mode) -> (Module, Completion) {
+        let toks = token::gen_toks(String::from(src));
+
+        let mut md = Module::new();
+        md.stmts = ast::stmt_gen(&toks).unwrap();
+        load_builtins(&mut md);
+
+        set_mode(mode);
+        let c = mod_exec(&mut md);
+        set_mode(Mode::Vm);
+        (md, c)
+    }
+
+    /* how a script ended, and the globals that can be printed without following cycles */
+    fn outcome(md: &Module, c: &Completion) -> (String, BTreeMap<String, String>) {
+        let c = match c {
+            Completion::Throw(e) => uncaught_message(e),
+            c => format!("{c:?}"),
+        };
+        let globals = md
+            .env
+            .vars()
+            .into_iter()
+            .filter(|(_, v)| matches!(v, Object::Const(_) | Object::Array(_)))
+            .map(|(n, v)| (n, v.to_string()))
+            .collect();
+
+        (c, globals)
+    }
+
+    /* runs `src` on the VM, checking that the tree-walker gets the same results */
+    fn run(src: &str) -> (Module, Completion) {
+        let (tree_md, tree_c) = run_in(src, Mode::Tree);
+        let (md, c) = run_in(src, Mode::Vm);
+
+        assert_eq!(outcome(&md, &c), outcome(&tree_md, &tree_c));
+        (md, c)
+    }
+
+    #[test]
+    fn runtime_error_test() {
+        let (_, c) = run("let a = 1;\nprint(a, b);");
+        let Completion::Throw(e) = c else {
+            panic!("expected a ReferenceError");
+        };
+
+        assert_eq!(
+            uncaught_message(&e),
+            "Uncaught ReferenceError: b is not defined\n    at 2:10"
+        );
+
+        let (_, c) = run("let f = 1;\nf(2);");
+        assert!(
+            matches!(c, Completion::Throw(Object::ClassObj(ref o)) if o.borrow().name == "TypeError")
+        );
+
+        /* the stack budget stops runaway recursion well within the default test thread stack */
+        for src in [
+            "function r(n) { return r(n + 1); }\nr(0);",
+            "function r(n) { try { [n].forEach(r); } finally { n++; } }\nr(0);",
+            "class C { get x() { return this.x; } }\nnew C().x;",
+        ] {
+            let (_, c) = run(src);
+            let Completion::Throw(e) = c else {
+                panic!("expected a RangeError: {src}");
+            };
+            assert!(
+                uncaught_message(&e)
+                    .starts_with("Uncaught RangeError: Maximum call stack size exceeded"),
+                "{src}"
+            );
+        }
+
+        /* on the VM, calls between coded functions take no native stack */
+        let (md, _) = run_in(
+            "function d(n) { return n == 0 ? 0 : d(n - 1) + 1; }\nlet depth = d(1500);",
+            Mode::Vm,
+        );
+        assert_eq!(md.get_var("depth").unwrap().to_string(), "1500");
+
+        let (md, c) =
+            run("function f(a) { return a; }\nlet x = f();\nlet e = new TypeError(\"bad\");");
+        assert!(matches!(c, Completion::Normal));
+        assert_eq!(md.get_var("x").unwrap().to_string(), "undefined");
+        assert_eq!(md.get_var("e").unwrap().to_string(), "TypeError: bad");
+    }
+
+    #[test]
+    fn long_chain_test() {
+        /* a left-deep chain nests as deeply as its length, so a long one runs out of stack instead of crashing */
+        let sum = format!("let a = 1{};", "+1".repeat(50_000));
+        let member = format!("let o = {{}};\no.b = o;\nlet a = o{};", ".b".repeat(50_000));
+        for (src, mode) in [
+            (&sum, Mode::Tree),
+            (&member, Mode::Tree),
+            (&member, Mode::Vm),
+        ] {
+            let (md, c) = run_in(src, mode);
+            let Completion::Throw(e) = c else {
+                panic!("expected a RangeError");
+            };
+            assert!(uncaught_message(&e)
+                .starts_with("Uncaught RangeError: Maximum call stack size exceeded"));
+            assert!(md.get_var("a").is_none());
+        }
+
+        /* the VM compiles and runs operator chains in a loop */
+        let (md, _) = run_in(&sum, Mode::Vm);
+        assert_eq!(md.get_var("a").unwrap().to_string(), "50001");
+
+        /* a function too deep to compile throws when called, where it can be caught */
+        let (md, _) = run(&format!(
+            "let o = {{}};\no.b = o;\nfunction f() {{ return o{}; }}\nlet r;\ntry {{ f(); }} catch (e) {{ r = e.name; }}",
+            ".b".repeat(50_000)
+        ));
+        assert_eq!(md.get_var("r").unwrap().to_string(), "RangeError");
+
+        let (md, _) = run(&format!("let a = 1{};", "+1".repeat(200)));
+        assert_eq!(md.get_var("a").unwrap().to_string(), "201");
+    }
+
+    #[test]
+    fn try_test() {
+        let (md, c) = run(r#"
+            function g() {
+                try {
+                    return 1;
+                } finally {
+                    return 2;
+                }
+            }
+
+            function h() {
+                let x = 0;
+                try {
+                    throw 1;
+                } catch (e) {
+                    x = e;
+                } finally {
+                    x = x + 10;
+                }
+                return x;
+            }
+
+            let e = "outer";
+            let caught = 0;
+            try {
+                undefinedFn();
+            } catch (e) {
+                caught = e;
+            }
+
+            let bare = 0;
+            try {
+                throw new RangeError("too big");
+            } catch {
+                bare = 1;
+            }
+
+            let keep;
+            try {
+                throw 7;
+            } catch (e) {
+                keep = () => e;
+            }
+
+            let a = g();
+            let b = h();
+            let kept = keep();
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+        assert_eq!(md.get_var("a").unwrap().to_string(), "2");
+        assert_eq!(md.get_var("b").unwrap().to_string(), "11");
+        assert_eq!(md.get_var("e").unwrap().to_string(), "outer");
+        assert_eq!(
+            md.get_var("caught").unwrap().to_string(),
+            "ReferenceError: undefinedFn is not defined"
+        );
+        assert_eq!(md.get_var("bare").unwrap().to_string(), "1");
+        assert_eq!(md.get_var("kept").unwrap().to_string(), "7");
+
+        let (_, c) = run("try { throw \"oops\"; } finally { print(1); }");
+        assert!(matches!(c, Completion::Throw(ref v) if v.to_string() == "oops"));
+
+        let toks = token::gen_toks(String::from("try { }"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+        assert_eq!(e.msg, "expected 'catch' or 'finally', found end of input");
+    }
+
+    #[test]
+    fn control_flow_test() {
+        let (md, c) = run(r#"
+            let n = 5;
+            let sum = 0;
+            while (n) {
+                sum = sum + n;
+                n = n - 1;
+            }
+
+            let d = 0;
+            do {
+                d = d + 1;
+            } while (0);
+
+            let f = 0;
+            for (let i = 3; i; i = i - 1) {
+                if (i - 2) {
+                    f = f + i;
+                } else continue;
+            }
+
+            let hits = 0;
+            outer: for (let a = 3; a; a = a - 1) {
+                for (let b = 3; b; b = b - 1) {
+                    if (b - 2) {} else {
+                        continue outer;
+                    }
+                    if (a - 1) {} else break outer;
+                    hits = hits + 1;
+                }
+            }
+
+            let brk = 0;
+            while (1) {
+                brk = brk + 1;
+                break;
+            }
+
+            block: {
+                d = d + 10;
+                break block;
+                d = d + 100;
+            }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+        assert_eq!(md.get_var("sum").unwrap().to_string(), "15");
+        assert_eq!(md.get_var("d").unwrap().to_string(), "11");
+        assert_eq!(md.get_var("f").unwrap().to_string(), "4");
+        assert_eq!(md.get_var("hits").unwrap().to_string(), "2");
+        assert_eq!(md.get_var("brk").unwrap().to_string(), "1");
+
+        let toks = token::gen_toks(String::from("function f() { break; }"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::IllegalJump);
+
+        let toks = token::gen_toks(String::from("while (1) { continue nope; }"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+        assert_eq!(e.msg, "undefined label 'nope'");
+    }
+
+    #[test]
+    fn for_in_of_switch_test() {
+        let (md, c) = run(r#"
+            let vals = [];
+            for (const x of [1, 2, 3]) vals.push(x * 2);
+            for (let ch of "héy") vals.push(ch);
+
+            let base = { a: 1, b: 2 };
+            let o = Object.create(base);
+            o.c = 3;
+            o.a = 9;
+            let keys = [];
+            for (let k in o) keys.push(k);
+            for (var i in [5, 6]) keys.push(i);
+            for (let k in null) keys.push(k);
+
+            let fs = [];
+            for (let x of [1, 2, 3]) fs.push(() => x);
+            let closed = fs.map(f => f());
+
+            let t = {};
+            let slot = [];
+            for (t.v of [7]);
+            for (slot[0] in { z: 1 });
+
+            let grown = [1];
+            let n = 0;
+            for (let x of grown) {
+                if (grown.length < 5) grown.push(x + 1);
+                n++;
+            }
+
+            function sw(v) {
+                let r = [];
+                switch (v) {
+                    case 1: r.push("one");
+                    case 2: r.push("two"); break;
+                    default: r.push("default");
+                    case 3: r.push("three");
+                }
+                return r.join();
+            }
+            let cases = [sw(1), sw(2), sw(3), sw(9), sw("1")];
+
+            let seen = [];
+            outer: for (let x of [1, 2, 3, 4]) {
+                switch (x) {
+                    case 2: continue outer;
+                    case 4: break outer;
+                }
+                seen.push(x);
+            }
+
+            let hoisted;
+            switch (1) {
+                case 0:
+                case 1: hoisted = g; let q = 5; hoisted = hoisted();
+                function g() { return q; }
+            }
+
+            let errs = [];
+            try { for (let x of 5); } catch (e) { errs.push(e.name); }
+            try { for (const x of [1]) x = 2; } catch (e) { errs.push(e.name); }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("vals", "[ 2, 4, 6, h, é, y ]"),
+            ("keys", "[ c, a, b, 0, 1 ]"),
+            ("i", "1"),
+            ("closed", "[ 1, 2, 3 ]"),
+            ("t", "{ v: 7 }"),
+            ("slot", "[ z ]"),
+            ("n", "5"),
+            (
+                "cases",
+                "[ one,two, two, three, default,three, default,three ]",
+            ),
+            ("seen", "[ 1, 3 ]"),
+            ("hoisted", "5"),
+            ("errs", "[ TypeError, TypeError ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for (src, msg) in [
+            (
+                "switch (1) { case 1: continue; }",
+                "illegal continue statement: no surrounding iteration statement",
+            ),
+            (
+                "switch (1) { default: default: }",
+                "more than one default clause in switch statement",
+            ),
+            (
+                "switch (1) { case 1: let a; case 2: let a; }",
+                "Identifier 'a' has already been declared",
+            ),
+            (
+                "for (let x of []) { var x; }",
+                "Identifier 'x' has already been declared",
+            ),
+            (
+                "for (let i = 0; ; ) { var i; }",
+                "Identifier 'i' has already been declared",
+            ),
+            ("for (f() of []);", "invalid left-hand side in for-of loop"),
+            (
+                "for (let x = 1 of []);",
+                "expected ';', found identifier 'of'",
+            ),
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(e.msg, msg, "{src}");
+        }
+    }
+
+    #[test]
+    fn operators_test() {
+        let (md, c) = run(r#"
+            let a = 1 == "1";
+            let b = 1 === "1";
+            let c = null == undefined;
+            let d = null === undefined;
+            let e = true == 1;
+            let f = "b" > "a";
+            let g = 2 < 10;
+            let h = "2" < "10";
+            let i = 1 + 2 * 3 == 7 && !false;
+            let j = 0 || "x";
+            let k = 0 ?? "x";
+            let l = null ?? "y";
+            let m = 6 & 3 | 8;
+            let n = -1 >>> 28;
+            let o = 1 << 31;
+            let p = ~5;
+            let q = 7 % 4;
+            let r = 1 != 2;
+            let s = 3 >= 3;
+
+            let calls = 0;
+            function bump() {
+                calls = calls + 1;
+                return true;
+            }
+            let t = false && bump();
+            let u = true || bump();
+            let v = 1 ?? bump();
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "true"),
+            ("b", "false"),
+            ("c", "true"),
+            ("d", "false"),
+            ("e", "true"),
+            ("f", "true"),
+            ("g", "true"),
+            ("h", "false"),
+            ("i", "true"),
+            ("j", "x"),
+            ("k", "0"),
+            ("l", "y"),
+            ("m", "10"),
+            ("n", "15"),
+            ("o", "-2147483648"),
+            ("p", "-6"),
+            ("q", "3"),
+            ("r", "true"),
+            ("s", "true"),
+            ("t", "false"),
+            ("u", "true"),
+            ("v", "1"),
+            ("calls", "0"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        let toks = token::gen_toks(String::from("let x = 1 ?? 2 || 3;"));
+        assert!(ast::stmt_gen(&toks).is_err());
+
+        let toks = token::gen_toks(String::from("let x = (1 ?? 2) || 3;"));
+        assert!(ast::stmt_gen(&toks).is_ok());
+    }
+
+    #[test]
+    fn expr_test() {
+        let (md, c) = run(r#"
+            function sq(x) {
+                return x * x;
+            }
+
+            let a = sq(3) + sq(4) * 2;
+            let b = 2 ** 3 ** 2;
+            let c = (1 + 2) * 3;
+            let d = a > 40 ? "big" : "small";
+            let e, f;
+            e = f = 7;
+            e += 3;
+            f **= 2;
+            let g = 5;
+            let h = g++ + ++g;
+            let i = (1, 2, 3);
+            let j = typeof sq + " " + typeof nope + " " + typeof null;
+            let k = new TypeError("bad").message;
+            let l = "hello".length + "abc"[1];
+            let m = new RangeError("x") instanceof Error;
+            let n = null;
+            n ??= "set";
+            let o = 0;
+            o ||= sq(5);
+            let p = !sq(0) && -sq(2) < 0;
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "41"),
+            ("b", "512"),
+            ("c", "9"),
+            ("d", "big"),
+            ("e", "10"),
+            ("f", "49"),
+            ("g", "7"),
+            ("h", "12"),
+            ("i", "3"),
+            ("j", "function undefined object"),
+            ("k", "bad"),
+            ("l", "5b"),
+            ("m", "true"),
+            ("n", "set"),
+            ("o", "25"),
+            ("p", "true"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        let (_, c) = run("let u; u.x;");
+        match c {
+            Completion::Throw(e) => assert_eq!(
+                e.to_string(),
+                "TypeError: Cannot read properties of undefined (reading 'x')"
+            ),
+            _ => panic!("expected a TypeError"),
+        }
+
+        let e = ast::expr_gen(&token::gen_toks(String::from("-2 ** 2"))).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::UnexpectedToken);
+        assert!(ast::expr_gen(&token::gen_toks(String::from("(-2) ** 2"))).is_ok());
+
+        let e = ast::stmt_gen(&token::gen_toks(String::from("a + 1 = 2;"))).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::InvalidAssignmentTarget);
+
+        let e = ast::stmt_gen(&token::gen_toks(String::from("f()++;"))).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::InvalidAssignmentTarget);
+
+        /* deep nesting is a parse error rather than a stack overflow */
+        for (open, close) in [
+            ("(", ")"),
+            ("[", "]"),
+            ("- ", ""),
+            ("{a:", "}"),
+            ("f(", ")"),
+        ] {
+            let src = format!("x = {}1{};", open.repeat(100000), close.repeat(100000));
+            let e = ast::stmt_gen(&token::gen_toks(src)).unwrap_err();
+            assert_eq!(e.kind, ast::ParseErrorKind::TooDeep, "{open}");
+        }
+        let src = format!("{}x = 1;{}", "{".repeat(100000), "}".repeat(100000));
+        let e = ast::stmt_gen(&token::gen_toks(src)).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::TooDeep);
+
+        let (_, c) = run(&format!("let x = {}1{};", "[".repeat(20), "]".repeat(20)));
+        assert!(matches!(c, Completion::Normal));
+    }
+
+    #[test]
+    fn optional_chain_test() {
+        let (md, c) = run(r#"
+            let o = { a: { b: [10, 20] }, m() { return this.a.b[1]; }, n: null };
+            let a = o?.a?.b?.[0];
+            let b = o.n?.x.y.z;
+            let c = o.m?.();
+            let d = o.zz?.();
+            let i = 0;
+            let e = o.n?.[i++];
+            let f = (o.n?.x) === undefined;
+            let g;
+            try {
+                (o.n?.x).y;
+            } catch (err) {
+                g = err.name;
+            }
+            let h = o.a.b?.join?.("-");
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "10"),
+            ("b", "undefined"),
+            ("c", "20"),
+            ("d", "undefined"),
+            ("e", "undefined"),
+            ("i", "0"),
+            ("f", "true"),
+            ("g", "TypeError"),
+            ("h", "10-20"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for src in ["a?.b = 1;", "a?.b++;"] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(
+                e.kind,
+                ast::ParseErrorKind::InvalidAssignmentTarget,
+                "{src}"
+            );
+        }
+        let e = ast::stmt_gen(&token::gen_toks(String::from("a?.b`x`;"))).unwrap_err();
+        assert_eq!(e.msg, "Invalid tagged template on optional chain");
+    }
+
+    #[test]
+    fn closure_test() {
+        let (md, c) = run(r#"
+            function counter() {
+                let n = 0;
+                function next() {
+                    n += 1;
+                    return n;
+                }
+                return next;
+            }
+
+            let c1 = counter();
+            let c2 = counter();
+            c1();
+            c1();
+            let a = c1();
+            let b = c2();
+
+            function apply(f, x) {
+                return f(x);
+            }
+            function adder(k) {
+                function add(x) {
+                    return x + k;
+                }
+                return add;
+            }
+            let d = apply(adder(10), 5);
+
+            let total = 0;
+            function bump() {
+                total = total + 1;
+            }
+            bump();
+            bump();
+
+            function peek() {
+                return secret;
+            }
+            function caller() {
+                let secret = 1;
+                return peek();
+            }
+            let e = "none";
+            try {
+                caller();
+            } catch (err) {
+                e = err.message;
+            }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+        assert_eq!(md.get_var("a").unwrap().to_string(), "3");
+        assert_eq!(md.get_var("b").unwrap().to_string(), "1");
+        assert_eq!(md.get_var("d").unwrap().to_string(), "15");
+        assert_eq!(md.get_var("total").unwrap().to_string(), "2");
+        assert_eq!(
+            md.get_var("e").unwrap().to_string(),
+            "secret is not defined"
+        );
+    }
+
+    #[test]
+    fn arrow_test() {
+        let (md, c) = run(r#"
+            let sq = x => x * x;
+            let add = (a, b) => {
+                return a + b;
+            };
+            let twice = function (f, x) {
+                return f(f(x));
+            };
+            let a = twice(sq, 3);
+            let b = add(1, 2);
+            let c = (() => 7)();
+            let fact = function f(n) {
+                return n < 2 ? 1 : n * f(n - 1);
+            };
+            let d = fact(5);
+            let e = typeof f;
+            let compose = (f, g) => x => f(g(x));
+            let h = compose(sq, x => x + 1)(2);
+            let k = ((1 + (2)) * (() => 3)()) + ((x) => x)(4);
+
+            class Box {
+                v = 4;
+
+                get() {
+                    let arrow = () => this;
+                    return arrow().v;
+                }
+
+                kind() {
+                    function plain() {
+                        return this;
+                    }
+                    return typeof plain();
+                }
+            }
+            let bx = new Box();
+            let i = bx.get();
+            let j = bx.kind();
+
+            let own = function g() {
+                g = 1;
+                let inner = () => (g += 1, typeof g);
+                return [typeof g, inner()];
+            };
+            let l = own();
+            let shadowed = function s() { var s = 5; return s; };
+            let m = shadowed();
+
+            function count(a) {
+                return [arguments.length, arguments[0], arguments[2], Object.keys(arguments).length];
+            }
+            function outer() {
+                let arrow = () => arguments[0];
+                return arrow(9);
+            }
+            function param(arguments) {
+                return arguments;
+            }
+            function nested() {
+                return (function () { return arguments.length; })(1, 2);
+            }
+            let n = [count(1, 2, 3), count(), outer(7), param(4), nested(1), (() => typeof arguments)()];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "81"),
+            ("b", "3"),
+            ("c", "7"),
+            ("d", "120"),
+            ("e", "undefined"),
+            ("h", "9"),
+            ("i", "4"),
+            ("j", "undefined"),
+            ("k", "13"),
+            ("l", "[ function, function ]"),
+            ("m", "5"),
+            (
+                "n",
+                "[ [ 3, 1, 3, 3 ], [ 0, undefined, undefined, 0 ], 7, 4, 2, undefined ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        /* an unclosed `(` is an error, however many of them there are */
+        let src = format!("let x = {};", "(a, ".repeat(20000));
+        assert!(ast::stmt_gen(&token::gen_toks(src)).is_err());
+        let e =
+            ast::stmt_gen(&token::gen_toks(String::from("let f = (a, b) \n=> a;"))).unwrap_err();
+        assert_eq!(e.kind, ast::ParseErrorKind::UnexpectedToken);
+    }
+
+    #[test]
+    fn object_test() {
+        let (md, c) = run(r#"
+            let port = 8080;
+            let key = "mode";
+            let config = {
+                host: "localhost",
+                port,
+                [key + "Name"]: "dev",
+                "quoted key": 1,
+                nested: { depth: 2 },
+                count: 0,
+                inc() {
+                    this.count += 1;
+                    return this;
+                },
+            };
+
+            config.inc().inc();
+            let alias = config;
+            alias.debug = true;
+            config["port"] = config.port + 1;
+
+            let copy = { ...config, host: "example.com" };
+            delete copy.nested;
+
+            let a = config.count;
+            let b = config.debug;
+            let c = config.modeName + config["quoted key"];
+            let d = config.nested.depth;
+            let e = copy.host + " " + config.host;
+            let f = "nested" in copy;
+            let g = copy.port;
+            let h = { b: 1, a: 2, 1: "x", 0: "y" };
+            let i = config === alias;
+            let j = { x: 1 } === { x: 1 };
+            let k = "" + {};
+            let l = config.missing;
+            let temp = {
+                celsius: 20,
+                get fahrenheit() { return this.celsius * 9 / 5 + 32; },
+                set fahrenheit(f) { this.celsius = (f - 32) * 5 / 9; },
+                get: 1,
+                set() { return 2; },
+            };
+            temp.fahrenheit = 212;
+            let m = [temp.celsius, temp.fahrenheit, temp.get + temp.set()];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "2"),
+            ("b", "true"),
+            ("c", "dev1"),
+            ("d", "2"),
+            ("e", "example.com localhost"),
+            ("f", "false"),
+            ("g", "8081"),
+            ("h", "{ 0: y, 1: x, b: 1, a: 2 }"),
+            ("i", "true"),
+            ("j", "false"),
+            ("k", "[object Object]"),
+            ("l", "undefined"),
+            ("m", "[ 100, 212, 3 ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        let (_, c) = run("let o = null; o.x = 1;");
+        match c {
+            Completion::Throw(e) => assert_eq!(
+                e.to_string(),
+                "TypeError: Cannot set properties of null (setting 'x')"
+            ),
+            _ => panic!("expected a TypeError"),
+        }
+    }
+
+    #[test]
+    fn array_test() {
+        let (md, c) = run(r#"
+            let a = [1, 2, 3];
+            let b = [0, ...a, 4];
+            let holes = [1, , 3, ];
+            a[5] = 6;
+            let len = a.length;
+            let c = a[4];
+            b.length = 2;
+            let d = b;
+            let e = [..."hi", [1, [2]]];
+            let f = "" + [1, null, [2, 3]];
+            let g = 1 in holes;
+            delete a[0];
+            let h = a;
+            let big = [];
+            big[100000] = 1;
+            let i = big.length;
+            let j = big;
+            let k = a === a;
+            let m = 0;
+            for (let x = 0; x < len; x++) {
+                if (x in a) m += 1;
+            }
+            function sum(p, q, r) {
+                return p + q + r;
+            }
+            let n = sum(...[1, 2], 3);
+            let o = [];
+            o.length = 3;
+            let bad = "none";
+            try {
+                o.length = -1;
+            } catch (err) {
+                bad = err.name;
+            }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("holes", "[ 1, <1 empty item>, 3 ]"),
+            ("len", "6"),
+            ("c", "undefined"),
+            ("d", "[ 0, 1 ]"),
+            ("e", "[ h, i, [ 1, [ 2 ] ] ]"),
+            ("f", "1,,2,3"),
+            ("g", "false"),
+            ("h", "[ <1 empty item>, 2, 3, <2 empty items>, 6 ]"),
+            ("i", "100001"),
+            ("j", "[ <100000 empty items>, 1 ]"),
+            ("k", "true"),
+            ("m", "3"),
+            ("n", "6"),
+            ("o", "[ <3 empty items> ]"),
+            ("bad", "RangeError"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn array_methods_test() {
+        let (md, c) = run(r#"
+            let a = [3, 1, 2];
+            let len = a.push(4, 5);
+            let popped = a.pop();
+            let shifted = a.shift();
+            a.unshift(0);
+            let b = a;
+            let nums = [5, 1, 10, 2].sort();
+            let sorted = [5, 1, 10, 2].sort((x, y) => x - y);
+            let people = [{ n: "a", k: 1 }, { n: "b", k: 0 }, { n: "c", k: 1 }, { n: "d", k: 0 }];
+            let stable = people.sort((x, y) => x.k - y.k).map(p => p.n).join("");
+            let mapped = [1, , 3].map((x, i) => x * i);
+            let total = [1, 2, 3, 4].filter(x => x % 2).reduce((s, x) => s + x, 10);
+            let right = ["a", "b", "c"].reduceRight((s, x) => s + x);
+            let flat = [[1, [2, [3]]], 4].flat();
+            let fm = [1, 2].flatMap(x => [x, x * 10]);
+            let spliced = [1, 2, 3, 4];
+            let removed = spliced.splice(1, 2, "x");
+            let found = [1, 2, 3].find(x => x > 1) + [1, 2, 3].findLast(x => x < 3);
+            let idx = [1, 2, 3].findIndex(x => x > 5) + [1, 2, 1].lastIndexOf(1);
+            let has = [1, 0 / 0].includes(0 / 0) && [1, 0 / 0].indexOf(0 / 0) === -1;
+            let at = [1, 2, 3].at(-1);
+            let filled = Array(3).fill(0, 1);
+            let from = Array.from("ab", (c, i) => c + i).concat(Array.of(7), [[8]]);
+            let isArr = Array.isArray([]) && !Array.isArray({ length: 0 });
+            let entries = ["x", "y"].entries();
+            let t = { sum: 0 };
+            [1, 2, 3].forEach(function (x) { this.sum += x; }, t);
+            let any = [1, 2].some(x => x > 1) && ![1, 2].every(x => x > 1);
+            let grow = [1, 2];
+            grow.forEach(x => grow.push(x));
+            let undefs = ["b", undefined, "a", , "c"].sort();
+            let err = "none";
+            try {
+                [].reduce((s, x) => s + x);
+            } catch (e) {
+                err = e.name;
+            }
+            let cyc = [1];
+            cyc.push(cyc, [cyc, 2]);
+            let joined = [cyc.join(), String(cyc), "" + cyc, cyc.toString(), `${cyc}`].join("|");
+            let flatErr = "none";
+            try {
+                cyc.flat(Infinity);
+            } catch (e) {
+                flatErr = e.name;
+            }
+            let huge = [];
+            huge[1e9] = "z";
+            huge[2] = "y";
+            huge[4] = undefined;
+            let sparse = [huge.join(""), huge.join("").length];
+            huge.sort();
+            sparse.push(huge.length, huge[0], huge[1], huge[3]);
+            huge.reverse();
+            sparse.push(huge[1e9], huge[1e9 - 1], huge[2] === undefined, 2 in huge, 1e9 - 2 in huge);
+            let full = [];
+            full[4294967295] = "p";
+            full.length = 4294967295;
+            let pushErr = "none";
+            try {
+                full.push(1);
+            } catch (e) {
+                pushErr = e.name;
+            }
+            let edge = [full[4294967295], full.length, pushErr];
+            let far = [];
+            far[4294967294] = 1;
+            far[0] = "a";
+            let tooLong = [
+                () => far.keys(), () => far.values(), () => [...far], () => Array.from(far),
+                () => far.concat(far), () => far.unshift(1), () => far.splice(0, 0, 1, 2),
+            ].map(f => {
+                try {
+                    f();
+                    return "none";
+                } catch (e) {
+                    return e.name;
+                }
+            });
+            let wide = [];
+            wide[1e9] = "w";
+            wide[0] = "v";
+            let cat = wide.concat([2], 3);
+            let moved = wide.slice();
+            let first = moved.shift();
+            moved.unshift("u", "t");
+            let cutOut = moved.splice(2, 1e9 - 2);
+            let sparseOps = [
+                far.slice(1).length, far.slice(1)[4294967293], far.slice(-1)[0],
+                cat.length, cat[1e9], cat[1e9 + 2], far.flat().length,
+                far.flatMap(x => [x, x]).length, far.indexOf(1), far.lastIndexOf("a"),
+                far.includes(undefined), far.includes(2), far.reduce((s, x) => s + x),
+                far.reduceRight((s, x) => s + x), first, moved, cutOut.length, Object.keys(far),
+            ];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("len", "5"),
+            ("popped", "5"),
+            ("shifted", "3"),
+            ("b", "[ 0, 1, 2, 4 ]"),
+            ("nums", "[ 1, 10, 2, 5 ]"),
+            ("sorted", "[ 1, 2, 5, 10 ]"),
+            ("stable", "bdac"),
+            ("mapped", "[ 0, <1 empty item>, 6 ]"),
+            ("total", "14"),
+            ("right", "cba"),
+            ("flat", "[ 1, [ 2, [ 3 ] ], 4 ]"),
+            ("fm", "[ 1, 10, 2, 20 ]"),
+            ("spliced", "[ 1, x, 4 ]"),
+            ("removed", "[ 2, 3 ]"),
+            ("found", "4"),
+            ("idx", "1"),
+            ("has", "true"),
+            ("at", "3"),
+            ("filled", "[ <1 empty item>, 0, 0 ]"),
+            ("from", "[ a0, b1, 7, [ 8 ] ]"),
+            ("isArr", "true"),
+            ("entries", "[ [ 0, x ], [ 1, y ] ]"),
+            ("t", "{ sum: 6 }"),
+            ("any", "true"),
+            ("grow", "[ 1, 2, 1, 2 ]"),
+            ("undefs", "[ a, b, c, undefined, <1 empty item> ]"),
+            ("err", "TypeError"),
+            ("joined", "1,,,2|1,,,2|1,,,2|1,,,2|1,,,2"),
+            ("flatErr", "RangeError"),
+            ("edge", "[ p, 4294967295, RangeError ]"),
+            (
+                "tooLong",
+                "[ RangeError, RangeError, RangeError, RangeError, RangeError, RangeError, \
+                RangeError ]",
+            ),
+            (
+                "sparseOps",
+                "[ 4294967294, 1, 1, 1000000003, w, 3, 2, 4, 4294967294, 0, true, false, a1, 1a, \
+                v, [ u, t, <1 empty item>, w ], 999999998, [ 0, 4294967294 ] ]",
+            ),
+            (
+                "sparse",
+                "[ yz, 2, 1000000001, y, z, undefined, y, z, true, false, true ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn string_methods_test() {
+        let (md, c) = run(r#"
+            let e = "a" + String.fromCharCode(55357, 56832) + "b";
+            let units = e.length + ":" + e.charCodeAt(1) + ":" + e.codePointAt(1) + ":" + e.indexOf("b");
+            let pair = e.slice(1, 3) === String.fromCharCode(55357, 56832);
+            let last = e.at(-1) + "hello".charAt(1) + "hello".substring(4, 1);
+            let parts = "a,b,,c".split(",");
+            let chars = "abc".split("");
+            let limited = "a,b,c".split(",", 2);
+            let rep = "x-y-z".replace("-", "+") + " " + "x-y-z".replaceAll("-", "+");
+            let empty = "ab".replaceAll("", ".");
+            let pats = "abc".replace("b", "[$&$`$'$$]");
+            let fn = "aXbX".replace("X", (m, i) => i);
+            let trimmed = "[" + "  hi \n".trim() + "|" + " hi ".trimStart() + "|" + " hi ".trimEnd() + "]";
+            let padded = "5".padStart(3, "0") + " " + "ab".padEnd(5, "xy") + " " + "ab".repeat(3);
+            let cases = "Hello".toUpperCase() + "Hello".toLowerCase();
+            let search = [
+                "abcabc".lastIndexOf("c"),
+                "abc".includes("bc"),
+                "abc".startsWith("b", 1),
+                "abc".endsWith("b", 2),
+                "x".charCodeAt(5),
+            ];
+            let conv = String(12) + String([1, 2]);
+            let s = "a😀b";
+            let halves = [
+                s.split("").join("") === s,
+                s.slice(0, 2) + s.slice(2) === s,
+                s.charAt(1).charCodeAt(0),
+                `${s[1]}${s[2]}` === "😀",
+                "\uD83D" + "\uDE00" === "😀",
+                [...s].length,
+                s.charAt(1).toUpperCase().length,
+            ];
+            let err = "none";
+            try {
+                "a".repeat(-1);
+            } catch (x) {
+                err = x.name;
+            }
+            let grown = "abc";
+            let copy = grown;
+            copy += "d";
+            grown += "e";
+            let twice = grown;
+            twice += twice;
+            let shared = [grown, copy, twice].join(" ");
+            let huge = [];
+            let holes = [];
+            holes.length = 2 ** 29;
+            let big = [
+                () => "a".repeat(2 ** 40),
+                () => "a".padStart(2 ** 40),
+                () => "a".padEnd(2 ** 30, "b"),
+                () => holes.join(","),
+            ];
+            for (let i = 0; i < big.length; i++) {
+                try {
+                    big[i]();
+                } catch (x) {
+                    huge.push(x.message);
+                }
+            }
+            huge.push("".repeat(2 ** 40).length, "a".padEnd(2 ** 40, "").length, holes.join("").length);
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("units", "4:55357:128512:3"),
+            ("pair", "true"),
+            ("last", "beell"),
+            ("parts", "[ a, b, , c ]"),
+            ("chars", "[ a, b, c ]"),
+            ("limited", "[ a, b ]"),
+            ("rep", "x+y-z x+y+z"),
+            ("empty", ".a.b."),
+            ("pats", "a[bac$]c"),
+            ("fn", "a1bX"),
+            ("trimmed", "[hi|hi | hi]"),
+            ("padded", "005 abxyx ababab"),
+            ("cases", "HELLOhello"),
+            ("search", "[ 5, true, true, true, NaN ]"),
+            ("conv", "121,2"),
+            ("halves", "[ true, true, 55357, true, true, 3, 1 ]"),
+            ("err", "RangeError"),
+            ("shared", "abce abcd abceabce"),
+            (
+                "huge",
+                "[ Invalid string length, Invalid string length, Invalid string length, \
+                 Invalid string length, 0, 1, 0 ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn unicode_test() {
+        let toks = token::gen_toks(String::from("let ü = \"日本\"; ü.x;"));
+        let u = toks
+            .iter()
+            .rfind(|l| matches!(&l.tok, token::Token::Identifier(id) if id == "ü"))
+            .unwrap();
+        assert_eq!((u.span.line, u.span.col), (1, 15));
+
+        let (md, c) = run(r#"
+            let héllo = "日本 héllo";
+            let ñandú$ = 1, ℮x = 2;
+            var \u2118 = 20;
+            let sum = ñandú$ + ℮x + ℘ + \u{2118};
+            let escapes = "\u0041\u{1F600}\uD83D\uDE00\x41\0".length;
+            let esc = "\u{65e5}\u672c" === "日本";
+            let len = héllo.length + ":" + "😀".length + ":" + "😀".codePointAt(0);
+            let cont = "a\
+b";
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("héllo", "日本 héllo"),
+            ("sum", "43"),
+            ("escapes", "7"),
+            ("esc", "true"),
+            ("len", "8:2:128512"),
+            ("cont", "ab"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for src in [
+            "let \\u0069f = 3;",
+            "let n = \\u{6e}ull;",
+            "let \\u0065num = 1;",
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(
+                e.msg, "keyword must not contain escaped characters",
+                "{src}"
+            );
+        }
+
+        for (src, at) in [
+            ("let a = 1 \\ print(a)", 11),
+            ("print(1) @ print(2)", 10),
+            ("let x = 1 ¶ 2", 11),
+            ("\\u;", 1),
+            ("let \\u{110000} = 1;", 5),
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(
+                (e.span.col, e.msg.as_str()),
+                (at, "invalid or unexpected token"),
+                "{src}"
+            );
+        }
+
+        let (_, c) = run("let\u{a0}a\u{3000}=\u{feff}1;\t\u{b}\u{c}");
+        assert!(matches!(c, Completion::Normal));
+    }
+
+    #[test]
+    fn comment_test() {
+        let src = "#!/usr/bin/env accent\nlet a = 1; // one\n/* two\n */ a / 2;";
+        let comments: Vec<String> = token::gen_toks(String::from(src))
+            .into_iter()
+            .filter_map(|l| match l.tok {
+                token::Token::Comment(c) => Some(c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(comments, ["#!/usr/bin/env accent", "// one", "/* two\n */"]);
+
+        let (md, c) = run(r#"
+            // let a = 1;
+            let a = 10; // trailing
+            /* block
+               comment */ let b = a / 2 /* inline */ * 3;
+            let s = "// not /* a comment */";
+            let d = a // division needs a space
+                / 5;
+            undefined_name;
+        "#);
+
+        let Completion::Throw(e) = c else {
+            panic!("expected a ReferenceError");
+        };
+        assert_eq!(
+            uncaught_message(&e),
+            "Uncaught ReferenceError: undefined_name is not defined\n    at 9:13"
+        );
+
+        let expect = [
+            ("a", "10"),
+            ("b", "15"),
+            ("s", "// not /* a comment */"),
+            ("d", "2"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for end in ["\r", "\r\n", "\u{2028}", "\u{2029}"] {
+            let (md, c) = run(&format!("let a = 1; // c{end}let b = 2; let s = a + b;"));
+            assert!(matches!(c, Completion::Normal));
+            assert_eq!(md.get_var("s").unwrap().to_string(), "3", "{end:?}");
+        }
+
+        let toks = token::gen_toks(String::from("print(1) /* x\nprint(2)"));
+        let e = ast::stmt_gen(&toks).unwrap_err();
+        assert_eq!(e.msg, "unterminated comment");
+    }
+
+    #[test]
+    fn asi_test() {
+        let (md, c) = run(r#"
+            let a = 1
+            let b = a
+            ++b
+            function f() {
+                return
+                    42
+            }
+            let r = f()
+            let c = [1, 2]
+                .map(x => x * 2)
+            let n = 0, outer = 0
+            outer: for (let i = 0; i < 3; i++) {
+                for (let j = 0; j < 3; j++) {
+                    if (j == 1) break
+                    outer
+                    n++
+                }
+            }
+            do a++; while (a < 5) let d = a
+            let g = a
+                + b
+            /* a comment spanning
+               lines ends the statement */ let h = 3
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("a", "5"),
+            ("b", "2"),
+            ("r", "undefined"),
+            ("c", "[ 2, 4 ]"),
+            ("n", "3"),
+            ("d", "5"),
+            ("g", "7"),
+            ("h", "3"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for nl in ["\n", "\r", "\r\n", "\u{2028}", "\u{2029}"] {
+            let (md, c) = run(&format!("let a = 1{nl}let b = a{nl}/* c{nl} */ b + nope"));
+            let Completion::Throw(e) = c else {
+                panic!("expected a ReferenceError");
+            };
+            assert_eq!(md.get_var("b").unwrap().to_string(), "1", "{nl:?}");
+            assert_eq!(
+                uncaught_message(&e),
+                "Uncaught ReferenceError: nope is not defined\n    at 4:9",
+                "{nl:?}"
+            );
+        }
+
+        for (src, msg) in [
+            ("throw\nnew Error()", "illegal newline after throw"),
+            ("let a = 1 let b = 2", "expected ';', found keyword 'let'"),
+            ("let f = x\n=> x", "expected expression, found '=>'"),
+            (
+                "for (let i = 0\n i < 3; i++) {}",
+                "expected ';', found identifier 'i'",
+            ),
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(e.msg, msg, "{src}");
+        }
+    }
+
+    #[test]
+    fn number_literal_test() {
+        let (md, c) = run(r#"
+            let radix = [0xff, 0XFF, 0o17, 0b101, 0xff_ff];
+            let dec = [1e3, 2.5E+2, .5, 1., 1_000_000, 1.5e-1];
+            let big = 99999999999999999999 > 9223372036854775807 && 9223372036854775808 === 2 ** 63;
+            let n = 2n ** 64n;
+            let ops = [typeof 1n, -7n / 2n, -7n % 2n, 1n << 70n, -9n >> 1n, 0x10n, ~5n];
+            let eq = [1n == 1, 1n === 1, 2n > 1, 0n ? "t" : "f", "" + 5n];
+            let b = 5n;
+            b++;
+            let errs = [];
+            try { 1n + 1; } catch (e) { errs.push(e.name); }
+            try { 1n / 0n; } catch (e) { errs.push(e.name); }
+            try { 2n ** 100000000n; } catch (e) { errs.push(e.name); }
+            let l = 123456789012345678901234567890123456789012345678901234567890n;
+            let wide = [2n ** 127n, l * l, -(l * l) / 3n ** 50n, -(l * l) % 3n ** 50n];
+            let bits = [-l & 0xffffffffffffffffffn, -l | 5n, -l ^ l, -l >> 100n, l << 40n];
+            let cmp = [Number(2n ** 200n), l > l - 1n, -l < 1n, l == l * 1n, String(l)];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("radix", "[ 255, 255, 15, 5, 65535 ]"),
+            ("dec", "[ 1000, 250, 0.5, 1, 1000000, 0.15 ]"),
+            ("big", "true"),
+            ("n", "18446744073709551616n"),
+            (
+                "ops",
+                "[ bigint, -3n, -1n, 1180591620717411303424n, -5n, 16n, -6n ]",
+            ),
+            ("eq", "[ true, false, true, f, 5 ]"),
+            ("b", "6n"),
+            ("errs", "[ TypeError, RangeError, RangeError ]"),
+            (
+                "wide",
+                "[ 170141183460469231731687303715884105728n, \
+                 15241578753238836750495351562566681945008382873376009755225087639153757049236500533455762536198787501905199875019052100n, \
+                 -21230842006177994404585173075138204663065551322695536483583676278205049237256443448393880434162n, \
+                 -712738591718553230205762n ]",
+            ),
+            (
+                "bits",
+                "[ 1834527813255458518318n, \
+                 -123456789012345678901234567890123456789012345678901234567889n, -4n, \
+                 -97390234335958476260820679236n, \
+                 135742175046962388768696238876869623887686962388768696238876733881712640n ]",
+            ),
+            (
+                "cmp",
+                "[ 1.6069380442589903e+60, true, true, true, \
+                 123456789012345678901234567890123456789012345678901234567890 ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for (src, msg) in [
+            ("0x;", "missing digits after '0x'"),
+            ("1e+;", "missing digits in exponent"),
+            (
+                "1__0;",
+                "numeric separators are only allowed between digits",
+            ),
+            ("017;", "octal literals are not allowed, use the 0o prefix"),
+            ("1.5n;", "BigInt literals must be integers"),
+            (
+                "3in;",
+                "identifier starts immediately after numeric literal",
+            ),
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(e.msg, msg, "{src}");
+            assert_eq!(e.kind, ast::ParseErrorKind::InvalidLiteral, "{src}");
+        }
+    }
+
+    #[test]
+    fn template_test() {
+        let (md, c) = run(r#"
+            let a = 3, o = { n: "x" };
+            let s = `a=${a} b=${a + 1}${o.n}`;
+            let nested = `${`in${a}`} { } ${ { k: 1 }.k }`;
+            let multi = `1
+2`;
+            function tag(s, x, y) {
+                return s.raw.join("|") + "/" + s.join("|") + "/" + x + y;
+            }
+            let tagged = tag`x\n${1}y${2}`;
+            let bad = (s => s[0] === undefined && s.raw[0] === "\\unicode")`\unicode`;
+            let m = { p: "P", m(s) { return this.p + s[0]; } }.m`q`;
+            let id = s => s;
+            function k() { return id`a${1}`; }
+            let makers = [1, 2].map(() => () => id`b`);
+            let sites = [
+                k() === k(), k() === id`a${1}`, makers[0]() === makers[1](),
+                Object.isFrozen(k()), Object.isFrozen(k().raw),
+            ];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("s", "a=3 b=4x"),
+            ("nested", "in3 { } 1"),
+            ("multi", "1\n2"),
+            ("tagged", "x\\n|y|/x\n|y|/12"),
+            ("bad", "true"),
+            ("m", "Pq"),
+            ("sites", "[ true, false, true, true, true ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+
+        for (src, msg) in [
+            (
+                "`\\unicode`;",
+                "invalid escape sequence in template literal",
+            ),
+            ("`a ${1};", "unterminated template literal"),
+            ("'\\07';", "octal escape sequences are not allowed"),
+            ("'\\xZ1';", "invalid hexadecimal escape sequence"),
+        ] {
+            let e = ast::stmt_gen(&token::gen_toks(String::from(src))).unwrap_err();
+            assert_eq!(e.msg, msg, "{src}");
+        }
+    }
+
+    #[test]
+    fn number_test() {
+        let (md, c) = run(r#"
+            let fmt = [0.1 + 0.2, 1e21, 1e-7, 1e-6, 123e-20, -1.5e300 * 10, 1e308 * 10, 2 ** 0.5];
+            let ints = [1 + 1, 2 ** 53 + 1, 9007199254740993, Number.MAX_SAFE_INTEGER + 2, 2 ** 64];
+            let zero = [1 / -0, 1 / (0 * -5), 1 / (-4 % 2), 1 / -(0), -0 === 0, `${-0}`];
+            let special = [1 / 0, 0 / 0, NaN === NaN, 1 ** Infinity, NaN ** 0, -5 % 3, 5.5 % 2];
+            let conv = [Number(" 0x1F "), Number("1e3"), Number(""), Number("inf"), Number("-Infinity")];
+            let radix = [(255).toString(16), (0.5).toString(2), (-255).toString(36), (2 ** 60).toString(2).length];
+            let checks = [Number.isInteger(5.0), Number.isSafeInteger(2 ** 53), Number.isNaN("x")];
+            let i = 9007199254740992;
+            i++;
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            (
+                "fmt",
+                "[ 0.30000000000000004, 1e+21, 1e-7, 0.000001, 1.23e-18, -1.5e+301, Infinity, 1.4142135623730951 ]",
+            ),
+            (
+                "ints",
+                "[ 2, 9007199254740992, 9007199254740992, 9007199254740992, 18446744073709552000 ]",
+            ),
+            ("zero", "[ -Infinity, -Infinity, -Infinity, -Infinity, true, 0 ]"),
+            ("special", "[ Infinity, NaN, false, NaN, 1, -2, 1.5 ]"),
+            ("conv", "[ 31, 1000, 0, NaN, -Infinity ]"),
+            ("radix", "[ ff, 0.1, -73, 61 ]"),
+            ("checks", "[ true, false, false ]"),
+            ("i", "9007199254740992"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn coercion_test() {
+        let (md, c) = run(r#"
+            let o = { valueOf() { return 42; }, toString() { return "str"; } };
+            let p = { toString() { return "7"; } };
+            let uses = [o + 1, `${o}`, o * 2, o > 41, o == 42, String(o), [o].join(), -o, p * 2, p + 1];
+            let prims = [{} + "", [] + [], [1, 2] + 3, [5] * 2, null + 1, undefined + 1, true + true, "3" * "4", 1.5 + "x"];
+            let cmp = [1 < "2", "10" < "9", 10 < "9", null == 0, null >= 0, [2] == 2, [1, 2] == "1,2"];
+
+            let k = { toString() { return "key"; } };
+            let t = {};
+            t[k] = 1;
+            let keyed = [t.key, k in t];
+
+            let n = { valueOf() { return 2; } };
+            let args = ["abcdef".slice(n), "ab".repeat(n), [1, 2, 3].at(n), Number(5n)];
+
+            let calls = 0;
+            let c = { valueOf() { calls++; return 1; } };
+            c++;
+            c += 1;
+
+            let errs = [];
+            try { ({ valueOf() { return {}; }, toString() { return {}; } }) + 1; } catch (e) { errs.push(e.message); }
+            try { +{ valueOf() { throw new RangeError("boom"); } }; } catch (e) { errs.push(e.name); }
+            try { 1n + { valueOf() { return 1; } }; } catch (e) { errs.push(e.name); }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("uses", "[ 43, str, 84, true, true, str, str, -42, 14, 71 ]"),
+            (
+                "prims",
+                "[ [object Object], , 1,23, 10, 1, NaN, 2, 12, 1.5x ]",
+            ),
+            ("cmp", "[ true, true, false, false, true, true, true ]"),
+            ("keyed", "[ 1, true ]"),
+            ("args", "[ cdef, abab, 3, 5 ]"),
+            ("c", "3"),
+            ("calls", "1"),
+            (
+                "errs",
+                "[ Cannot convert object to primitive value, RangeError, TypeError ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn class_test() {
+        let (md, c) = run(r#"
+            class Counter {
+                count = 0;
+                step = 1;
+                label = "c" + this.step;
+                constructor(start, step) {
+                    this.count = start;
+                    if (step !== undefined) this.step = step;
+                }
+                inc() { this.count += this.step; return this; }
+                read = () => this.count;
+            }
+
+            let c = new Counter(10, 5);
+            c.inc().inc();
+            let alias = c;
+            alias.label = "shared";
+            let state = [c.count, c.read(), c.label, c instanceof Counter, "inc" in c, c === alias];
+            let other = new Counter(1);
+            let fresh = [other.count, other.step, other.label, other === new Counter(1)];
+
+            class Wrap { constructor() { return { wrapped: true }; } }
+            class Prim { constructor() { this.x = 1; return 5; } }
+            let returned = [new Wrap().wrapped, new Wrap() instanceof Wrap, new Prim().x];
+
+            class Acc { total = 0; add(x) { this.total += x; } }
+            let acc = new Acc();
+            [1, 2, 3].forEach(x => acc.add(x));
+
+            let errs = [];
+            try { Counter(); } catch (e) { errs.push(e.message); }
+            let m = c.inc;
+            try { m(); } catch (e) { errs.push(e.name); }
+
+            let kinds = { Plain: class { hi() { return "hi"; } } };
+            let Sub = class Inner extends kinds.Plain { who() { return typeof Inner; } };
+            let exprs = [new kinds.Plain().hi(), new Sub().hi(), new Sub().who(), typeof Inner];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("state", "[ 20, 20, shared, true, true, true ]"),
+            ("fresh", "[ 1, 1, c1, false ]"),
+            ("returned", "[ true, false, 1 ]"),
+            ("acc", "Acc { total: 6 }"),
+            (
+                "errs",
+                "[ Class constructor Counter cannot be invoked without 'new', TypeError ]",
+            ),
+            ("exprs", "[ hi, hi, function, undefined ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn inheritance_test() {
+        let (md, c) = run(r#"
+            class Entity {
+                #id;
+                static count = 0;
+                static #ids = [];
+                constructor(id) { this.#id = id; Entity.count++; Entity.#ids.push(id); }
+                get id() { return this.#id; }
+                set label(v) { this._label = v + "!"; }
+                describe() { return "entity " + this.#id; }
+                #secret() { return this.#id * 2; }
+                secret() { return this.#secret(); }
+                static isEntity(o) { return #id in o; }
+                static ids() { return Entity.#ids; }
+                static { this.kind = "base"; }
+            }
+
+            class User extends Entity {
+                #id = "u";
+                role = "user";
+                constructor(id, name) { super(id); this.name = name; }
+                describe() { return super.describe() + " (" + this.name + ")"; }
+                own() { return this.#id; }
+                static create(id) { return new this(id, "anon"); }
+                static ids() { return super.ids().length; }
+            }
+            class Admin extends User {}
+
+            let u = new User(1, "ann");
+            let a = Admin.create(2);
+            u.label = "x";
+            let state = [u.id, u.describe(), u.secret(), u.own(), u.role, u._label, a.describe()];
+            let kinds = [a instanceof Admin, a instanceof User, a instanceof Entity, u instanceof Admin];
+            let statics = [Entity.count, User.count, Entity.ids(), User.ids(), Admin.kind];
+            let brands = [Entity.isEntity(a), Entity.isEntity({ id: 1 })];
+
+            class Other { #id = 0; static read(o) { return o.#id; } }
+            class Fixed { get x() { return 1; } }
+            class NotFound extends Error {
+                constructor(what) { super(what + " not found"); this.what = what; }
+            }
+
+            let errs = [];
+            try { Other.read(u); } catch (e) { errs.push(e.message); }
+            try { new Fixed().x = 2; } catch (e) { errs.push(e.message); }
+            try { class Bad extends 1 {} } catch (e) { errs.push(e.message); }
+            try { throw new NotFound("page"); } catch (e) {
+                errs.push(e.message, e.what, e instanceof NotFound, e instanceof Error);
+            }
+
+            class P { constructor() { this.p = 1; } }
+            class Early extends P { constructor() { this.a = 1; super(); } }
+            class Twice extends P { constructor() { super(); super(); } }
+            class Never extends P { constructor() {} }
+            class Late extends P { constructor() { let f = () => this; super(); this.q = f().p; } }
+            class Swap extends P { constructor() { return { swapped: true }; } }
+            let supers = [new Late().q, new Swap().swapped];
+
+            /* `super()` goes on with the object a parent's constructor returns */
+            class D { constructor() { return { custom: 1 }; } }
+            class E extends D { constructor() { super(); this.e = 2; } }
+            class F extends D { f = 3; }
+            let ef = [new E().custom, new E().e, new E() instanceof E, new F().custom, new F().f];
+
+            let n = 1;
+            class J {
+                ["a" + n]() { return "a1"; }
+                static [`s${n}`] = 5;
+                get ["g" + n]() { return 7; }
+                [n + 1] = 9;
+            }
+            let computed = [new J().a1(), J.s1, new J().g1, new J()[2]];
+            [Early, Twice, Never].forEach(C => {
+                try { new C(); } catch (e) { supers.push(e.name + ": " + e.message); }
+            });
+
+            function Animal(name) { this.name = name; }
+            Animal.prototype.speak = function () { return this.name + " speaks"; };
+            Animal.kind = "animal";
+            class Dog extends Animal {
+                constructor(n) { super(n); }
+                speak() { return super.speak() + " loudly"; }
+                static kind2() { return super.kind + "!"; }
+            }
+            class Stack extends Array { top() { return this[this.length - 1]; } }
+            class Plain extends Object { constructor() { super(); this.a = 1; } }
+            class Orphan extends null {}
+            let st = new Stack(1, 2);
+            st.push(3);
+            let dog = new Dog("rex");
+            let bases = [
+                dog.speak(), dog instanceof Animal, Dog.kind2(),
+                st.top(), st.length, st instanceof Stack, Array.isArray(st),
+                new Plain().a, Object.getPrototypeOf(Orphan.prototype),
+            ];
+            try { new Orphan(); } catch (e) { bases.push(e.message); }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            (
+                "state",
+                "[ 1, entity 1 (ann), 2, u, user, x!, entity 2 (anon) ]",
+            ),
+            ("kinds", "[ true, true, true, false ]"),
+            ("statics", "[ 2, 2, [ 1, 2 ], 2, base ]"),
+            ("brands", "[ true, false ]"),
+            (
+                "errs",
+                "[ Cannot read private member #id from an object whose class did not declare it, \
+                Cannot set property x of #<Fixed> which has only a getter, \
+                Class extends value 1 is not a constructor or null, \
+                page not found, page, true, true ]",
+            ),
+            (
+                "supers",
+                "[ 1, true, \
+                ReferenceError: Must call super constructor in derived class before accessing 'this' or returning from derived constructor, \
+                ReferenceError: Super constructor may only be called once, \
+                ReferenceError: Must call super constructor in derived class before accessing 'this' or returning from derived constructor ]",
+            ),
+            ("ef", "[ 1, 2, false, 1, 3 ]"),
+            ("computed", "[ a1, 5, 7, 9 ]"),
+            (
+                "bases",
+                "[ rex speaks loudly, true, animal!, 3, 3, true, true, 1, null, \
+                Super constructor null of anonymous class is not a constructor ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn prototype_test() {
+        let (md, c) = run(r#"
+            let animal = { speak() { return this.name + " speaks"; } };
+            let dog = Object.create(animal);
+            dog.name = "rex";
+            let alias = dog;
+            alias.name = "max";
+
+            let chain = [
+                dog.speak(),
+                animal.isPrototypeOf(dog),
+                dog.hasOwnProperty("speak"),
+                "speak" in dog,
+                Object.getPrototypeOf(animal) === Object.prototype,
+                Object.getPrototypeOf(Object.create(null)),
+            ];
+
+            let point = Object.defineProperty({ x: 1 }, "y", { value: 2 });
+            Object.defineProperty(point, "sum", { get() { return this.x + this.y; }, enumerable: true });
+            let desc = Object.getOwnPropertyDescriptor(point, "y");
+            let props = [point, point.sum, Object.keys(point), Object.getOwnPropertyNames(point), desc];
+
+            let frozen = Object.freeze({ a: 1, list: [1] });
+            frozen.list.push(2);
+            let errs = [];
+            try { point.y = 3; } catch (e) { errs.push(e.message); }
+            try { point.sum = 3; } catch (e) { errs.push(e.message); }
+            try { frozen.b = 1; } catch (e) { errs.push(e.message); }
+            try { delete frozen.a; } catch (e) { errs.push(e.message); }
+            try { Object.freeze([1]).push(2); } catch (e) { errs.push(e.message); }
+            try { Object.defineProperty(point, "y", { value: 5 }); } catch (e) { errs.push(e.message); }
+            try { Object.setPrototypeOf(animal, dog); } catch (e) { errs.push(e.message); }
+
+            class Shape {}
+            class Square extends Shape {}
+            let fake = Object.create(Square.prototype);
+            let kinds = [
+                fake instanceof Shape,
+                new Square() instanceof Object,
+                Object.create(null) instanceof Object,
+                Object.isFrozen(frozen),
+                frozen.list,
+            ];
+            let copied = [Object.assign({}, { a: 1 }, null, { b: 2 }), Object.entries({ k: "v" })];
+
+            function f() {}
+            f.tag = "t";
+            let sq = new Square();
+            let identity = [
+                f === f, Square === Square, sq.constructor === Square,
+                Square.prototype.constructor === Square,
+                Object.prototype.hasOwnProperty === ({}).hasOwnProperty,
+                [].push === Array.prototype.push, f.tag, Object.keys(f),
+            ];
+            let builtins = [
+                [] instanceof Array, Object.getPrototypeOf([]) === Array.prototype,
+                Array.prototype.constructor === Array, "s".at === String.prototype.at,
+                Object.getPrototypeOf(1) === Number.prototype, "s" instanceof String,
+                Object.getPrototypeOf(f) === Object.getPrototypeOf(Shape),
+                Object.getPrototypeOf(Square) === Shape, "map" in [],
+            ];
+
+            function P(x) { this.x = x; }
+            P.prototype.twice = function () { return this.x * 2; };
+            function Q() { return { own: true }; }
+            let m = { m() {} };
+            let p = new P(4);
+            let ctors = [
+                p.twice(), p instanceof P, p.constructor === P, Object.keys(P.prototype),
+                new Q().own, new Q() instanceof Q, m.m.prototype, (() => 1).prototype,
+            ];
+            try { new m.m(); } catch (e) { ctors.push(e.message); }
+
+            let base = { hi: 1 };
+            let __proto__ = base;
+            let viaLit = { __proto__: base, own: 2 };
+            let listed = [];
+            for (let k in viaLit) listed.push(k);
+            let literal = [
+                Object.getPrototypeOf(viaLit) === base, viaLit.hi, listed,
+                Object.getPrototypeOf({ "__proto__": null }),
+                Object.getPrototypeOf({ __proto__: 1 }) === Object.prototype,
+                Object.keys({ ["__proto__"]: 1 }), Object.keys({ __proto__ }),
+                Object.keys({ __proto__() {} }),
+            ];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("chain", "[ max speaks, true, false, true, true, null ]"),
+            (
+                "props",
+                "[ { x: 1, sum: [Getter] }, 3, [ x, sum ], [ x, y, sum ], \
+                { value: 2, writable: false, enumerable: false, configurable: false } ]",
+            ),
+            (
+                "errs",
+                "[ Cannot assign to read only property 'y' of object '#<Object>', \
+                Cannot set property sum of #<Object> which has only a getter, \
+                Cannot add property b, object is not extensible, \
+                Cannot delete property 'a' of #<Object>, \
+                Cannot assign to read only property 'length' of object '[object Array]', \
+                Cannot redefine property: y, \
+                Cyclic __proto__ value ]",
+            ),
+            ("kinds", "[ true, true, false, true, [ 1, 2 ] ]"),
+            ("copied", "[ { a: 1, b: 2 }, [ [ k, v ] ] ]"),
+            (
+                "identity",
+                "[ true, true, true, true, true, true, t, [ tag ] ]",
+            ),
+            (
+                "builtins",
+                "[ true, true, true, true, true, false, true, true, true ]",
+            ),
+            (
+                "ctors",
+                "[ 8, true, true, [ twice ], true, false, undefined, undefined, \
+                m.m is not a constructor ]",
+            ),
+            (
+                "literal",
+                "[ true, 1, [ own, hi ], null, true, [ __proto__ ], [ __proto__ ], [ __proto__ ] ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn gc_test() {
+        let (md, c) = run(r#"
+            function churn(n) {
+                for (let i = 0; i < n; i++) {
+                    let parent = { i };
+                    parent.child = { parent };
+                    let self = () => self;
+                    class Node { static first = new Node(); }
+                    let a = [];
+                    a.push(a);
+                }
+            }
+
+            let before = gc();
+            churn(50);
+            let first = gc();
+            churn(50);
+            let second = gc();
+
+            let kept = { v: 1 };
+            kept.self = kept;
+            let counter = (() => { let n = 0; return () => ++n; })();
+            counter();
+            gc();
+
+            // the one object added since `first` is `first` itself
+            let cycles = [first.freed > 0, first.freed == second.freed, second.live - first.live];
+            let alive = [kept.self.self.v, counter(), gc().freed, second.collections - before.collections];
+
+            // freeing long chains of objects does not recurse once per link
+            class K { #n; constructor(n) { this.#n = n; } }
+            let list = null, nest = [], chain = {}, f = () => 0, k = null;
+            for (let i = 0; i < 50000; i++) {
+                list = { next: list };
+                nest = [nest];
+                chain = Object.create(chain);
+                let g = f;
+                f = () => g();
+                k = new K(k);
+            }
+            list = nest = chain = f = k = null;
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [("cycles", "[ true, true, 1 ]"), ("alive", "[ 1, 2, 0, 2 ]")];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn vm_test() {
+        let (md, c) = run(r#"
+            function fin(n) {
+                let log = [];
+                for (let i = 0; i < n; i++) {
+                    try {
+                        if (i == 1) continue;
+                        if (i == 3) break;
+                        log.push(i);
+                    } finally {
+                        log.push("f" + i);
+                    }
+                }
+                return log.join(" ");
+            }
+            function over() {
+                try { return "try"; } finally { return "finally"; }
+            }
+            function nested() {
+                let log = [];
+                try {
+                    try { throw new Error("inner"); }
+                    finally { log.push("a"); }
+                } catch (e) { log.push(e.message); }
+                finally { log.push("b"); }
+                return log.join(" ");
+            }
+            let e = "outer";
+            try { null.x; } catch (e) { e = e instanceof TypeError; }
+            let flow = [fin(5), over(), nested(), e];
+
+            let pairs = [];
+            outer: for (let i = 0; i < 3; i++) {
+                for (let j = 0; j < 3; j++) {
+                    if (j > i) continue outer;
+                    if (i == 2) break outer;
+                    pairs.push(i * 10 + j);
+                }
+            }
+            let fns = [];
+            for (let i = 0; i < 3; i++) { fns.push(() => i); }
+            function adder(a) { return (b) => (c) => a + b + c; }
+            let fact = function f(n) { return n < 2 ? 1 : n * f(n - 1); };
+            let scopes = [pairs.join(), fns.map((f) => f()).join(), adder(1)(2)(3), fact(5), typeof f];
+
+            class P { #x = 1; static make() { return new P(); } static has(o) { return #x in o; } get x() { return this.#x; } }
+            class Q extends P { get x() { return super.x + 1; } }
+            let o = { a: 1, b: 2 };
+            delete o.a;
+            let tag = (s, a, b) => s.raw.join("|") + a + b;
+            let fallbacks = [Q.make() instanceof P, P.has(new Q()), new Q().x, Object.keys(o).join(), ((a, b, c) => a + b + c)(...[1, 5, 3]), [0, ...[1, 2]].length, { ...o, c: 3 }.c, tag`a${1}b${2}c`];
+
+            let x = 1;
+            let y = x + (x = 5);
+            let s = 0, t;
+            for (let i = 0, j = 10; i < j; i++, j--) s += j - i;
+            t ||= "set";
+            o.b ??= 9;
+            let arr = [1, 2, 3];
+            arr[1] += 10;
+            let big = 9007199254740991;
+            big++;
+            let ops = [y, x, s, t, o.b, arr.join(), big, 7 % -3, -7 % 7, 2 ** 10, 5 / 2, "1" < 2, 1 == "1", typeof undeclared];
+
+            let w = 0, n = 0, m = 10, k = 0;
+            while (n < 10) { n++; if (n % 2) continue; w += n; }
+            while (m & 7) m--;
+            for (;;) { if (++k >= 4) break; }
+            for (let i = 5; i > 0; i -= 2) w += i;
+            let bits = [-8 >> 1, -8 >>> 28, 1 << 33, 5 & -2, 5 | 8, 5 ^ 1, 2147483647 + 1, 3 <= 3, 4 != 4];
+            let loops = [w, m, k, bits.join()];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("flow", "[ 0 f0 f1 2 f2 f3, finally, a inner b, outer ]"),
+            ("scopes", "[ 0,10,11, 0,1,2, 6, 120, undefined ]"),
+            ("fallbacks", "[ true, true, 2, b, 9, 3, 3, a|b|c12 ]"),
+            (
+                "ops",
+                "[ 6, 5, 30, set, 2, 1,12,3, 9007199254740992, 1, 0, 1024, 2.5, true, true, undefined ]",
+            ),
+            ("loops", "[ 39, 8, 4, -4,15,2,4,13,4,2147483648,true,false ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn block_scope_test() {
+        let (md, c) = run(r#"
+            let fns = [];
+            for (let i = 0; i < 3; i++) fns.push(() => i);
+            let pairs = [];
+            outer: for (let i = 0, j = 5; i < 3; i++) {
+                for (let k = 0; k < 3; k++) {
+                    if (k == 1) continue outer;
+                    pairs.push(() => i * 10 + j + k);
+                }
+            }
+            let seen = [];
+            for (let i = 0; i < 3; i++) {
+                try {
+                    if (i == 1) continue;
+                } finally {
+                    let at = i;
+                    seen.push(() => at);
+                }
+            }
+            let loops = [fns.map((f) => f()).join(), pairs.map((f) => f()).join(), seen.map((f) => f()).join()];
+
+            let x = 1;
+            { let x = 2; }
+            function shadow() { let v = 1; { let v = 2; } return v; }
+            function hoisted() { { var w = 3; } return w; }
+            let thrown;
+            try { { let hidden = 1; throw 0; } } catch (e) { thrown = typeof hidden; }
+            let blocks = [x, shadow(), hoisted(), thrown];
+
+            const k = 1;
+            let errors = [];
+            try { k = 2; } catch (e) { errors.push(e.name + ": " + e.message); }
+            try { k++; } catch (e) { errors.push(e.name); }
+            function inner() { k += 1; }
+            try { inner(); } catch (e) { errors.push(e.name); }
+            errors.push(k);
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("loops", "[ 0,1,2, 5,15,25, 0,1,2 ]"),
+            ("blocks", "[ 1, 1, 3, undefined ]"),
+            (
+                "errors",
+                "[ TypeError: Assignment to constant variable., TypeError, TypeError, 1 ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn hoisting_test() {
+        let (md, c) = run(r#"
+            let calls = [early(), typeof later, inner()];
+            function early() { return "early"; }
+            function later() {}
+            function inner() { return helper() + 1; function helper() { return 1; } }
+
+            let before = [typeof v];
+            var v = 3;
+            var v;
+            before.push(v);
+
+            var x = "outer";
+            function shadowed(a) {
+                let seen = [x, a];
+                var x = "inner", a;
+                if (false) { var never = 1; }
+                seen.push(x, a, never);
+                return seen;
+            }
+            let vars = shadowed(5);
+
+            let blocks = [];
+            { blocks.push(typeof inBlock()); function inBlock() {} }
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("calls", "[ early, function, 2 ]"),
+            ("before", "[ undefined, 3 ]"),
+            ("vars", "[ undefined, 5, inner, 5, undefined ]"),
+            ("blocks", "[ undefined ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn tdz_test() {
+        let (md, c) = run(r#"
+            let x = "outer";
+            function a() { const r = x; let x = "inner"; return r; }
+            function early() { let f = () => x; f(); let x = 1; }
+            function block() { { const y = x; let x = 1; } }
+            function assign() { x = 2; let x; }
+            function check() { typeof x; let x; }
+            function klass() { new K(); class K {} }
+
+            let errors = [];
+            for (let f of [a, early, block, assign, check, klass]) {
+                try { f(); } catch (e) { errors.push(e.name + ": " + e.message); }
+            }
+            let after = [x, typeof undeclared];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            (
+                "errors",
+                "[ ReferenceError: Cannot access 'x' before initialization, \
+                 ReferenceError: Cannot access 'x' before initialization, \
+                 ReferenceError: Cannot access 'x' before initialization, \
+                 ReferenceError: Cannot access 'x' before initialization, \
+                 ReferenceError: Cannot access 'x' before initialization, \
+                 ReferenceError: Cannot access 'K' before initialization ]",
+            ),
+            ("after", "[ outer, undefined ]"),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
+    #[test]
+    fn locals_test() {
+        let (md, c) = run(r#"
+            function count(n) {
+                let fs = [], sum = 0;
+                for (let i = 0; i < n; i++) {
+                    let sq = i * i;
+                    sum += sq;
+                    fs.push(() => i + sq);
+                }
+                return [sum, fs.map(f => f()).join()];
+            }
+            let loops = count(3);
+
+            function fresh() {
+                let seen = [];
+                for (let k = 0; k < 2; k++) {
+                    let v;
+                    seen.push(v);
+                    v = k;
+                }
+                return seen;
+            }
+            let blocks = fresh();
+
+            function fact(n) { return n < 2 ? 1 : n * fact(n - 1); }
+            let calls = [fact(5), (function (a, a2) { try { throw a; } catch (e) { return e + a2; } })(1, 2)];
+
+            class P { get v() { return 1; } m(a, b, c) { return a + b + c; } }
+            class C extends P {
+                #n = 0;
+                get v() { return super.v + 10; }
+                bump() { this.#n++; this.#n += 2; return [#n in this, this.#n, super.m(...[1, 2], 3)]; }
+            }
+            let o = { a: 1, get b() { return this.a + 1; }, ...{ c: 3 } };
+            let tag = (s, a, b) => s.raw.join("|") + a + b;
+            let misc = [new C().v, new C().bump(), o.b, delete o.a, o.a, [1, , ...[2, 3]].length,
+                o?.x?.y, o.b?.toString(), tag`a${1}b${2}`, [...[1, 5], 2].length];
+        "#);
+
+        assert!(matches!(c, Completion::Normal));
+
+        let expect = [
+            ("loops", "[ 5, 0,2,6 ]"),
+            ("blocks", "[ undefined, undefined ]"),
+            ("calls", "[ 120, 3 ]"),
+            (
+                "misc",
+                "[ 11, [ true, 3, 6 ], 2, true, undefined, 4, undefined, NaN, a|b|12, 3 ]",
+            ),
+        ];
+
+        for (name, v) in expect {
+            assert_eq!(md.get_var(name).unwrap().to_string(), v, "{name}");
+        }
+    }
+
     #[test]
     fn eg_test() {
         let data = String::from("a = 20;");
 
-        let mut eg = ast::expr_gen(&token::gen_toks(data));
+        let _eg = ast::expr_gen(&token::gen_toks(data));
     }
 
     #[test]
     fn mod_test() {
-        let data =
-            String::from(fs::read_to_string("src/tests/test.js").expect("File does not exist"));
+        let data = fs::read_to_string("src/tests/test.js").expect("File does not exist");
 
-        let mut toks = token::gen_toks(data);
-        let mut sts = ast::stmt_gen(&toks);
+        let toks = token::gen_toks(data);
+        let sts = ast::stmt_gen(&toks).unwrap();
 
         let mut md = Module::new();
         md.stmts = sts;
+        load_builtins(&mut md);
 
-        let fref = Function::Native {
-            name: String::from("print"),
-            f: native_print,
-        };
-
-        md.vtable.insert(String::from("print"), Object::Funct(fref));
-
-        mod_exec(&mut md);
+        assert!(matches!(mod_exec(&mut md), Completion::Normal));
 
-        for (i, iv) in md.vtable {
+        for (i, iv) in md.env.vars() {
             println!("{i}: {iv:?}");
         }
         println!("Program ended.");
@@ -1,92 +1,460 @@
-use function::Function;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use completion::Completion;
+use env::Env;
+use function::{Function, Proto};
 use module::Module;
-use obj::{obj_eval, Object};
+use obj::{obj_eval, truthy, Object};
 
-use crate::ast::{constants, Expr, Stmt};
+use crate::ast::{Expr, FuncForm, Stmt, StmtKind, VarQualifier};
 
+pub mod array;
+pub mod builtins;
+pub mod bytecode;
 pub mod classes;
+pub mod coerce;
+pub mod compiler;
+pub mod completion;
 pub mod ctx;
+pub mod env;
+pub mod errors;
 pub mod function;
+pub mod heap;
 pub mod module;
 pub mod obj;
+pub mod ops;
+pub mod props;
+pub mod stack;
+pub mod vm;
+
+/* how scripts and coded functions are run */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /* compiled to bytecode for the VM */
+    Vm,
+    /* by walking the syntax tree: slower, kept as the reference the VM is checked against */
+    Tree,
+}
+
+thread_local! {
+    static MODE: Cell<Mode> = const { Cell::new(Mode::Vm) };
+}
+
+pub fn mode() -> Mode {
+    MODE.get()
+}
 
-pub fn mod_exec(md: &mut Module) {
-    let stmts = md.stmts.clone();
-    for st in &stmts {
-        if md.got_rt {
-            break;
-        }
-
-        match st {
-            Stmt::FunctCallSimple { name, args } => match md.get_var(name) {
-                Some(Object::Funct(id)) => {
-                    let fref = md.get_var(name).unwrap();
-                    let args_eval: Vec<Object> = args.iter().map(|arg| obj_eval(arg, md)).collect();
-
-                    match fref {
-                        Object::Funct(Function::Native { f, .. }) => {
-                            let mut fmd = md.clone();
-                            f(&args_eval, &mut fmd);
-                        }
-                        Object::Funct(Function::Coded { name, args, body }) => {
-                            let mut fmd = Module::new();
-
-                            for (j, jv) in args.iter().enumerate() {
-                                match jv {
-                                    Expr::Var(vn) => {
-                                        fmd.add_var(vn, args_eval.get(j).unwrap().to_owned());
-                                    }
-                                    _ => (),
-                                }
-                            }
-
-                            fmd.parent = Box::new(Some(md));
-                            fmd.stmts = body.to_vec().clone();
-
-                            mod_exec(&mut fmd);
-                        }
-                        _ => unreachable!(),
+/* chooses how the current thread runs scripts from now on */
+pub fn set_mode(m: Mode) {
+    MODE.set(m);
+}
+
+/* executes a single statement; `Err` carries a thrown value */
+fn stmt_exec(st: &Stmt, md: &mut Module) -> Result<Completion, Object> {
+    heap::maybe_collect();
+
+    /* like `obj_eval`, kept to single calls so that its frame stays small */
+    match &st.kind {
+        StmtKind::Expr(e) => obj_eval(e, md).map(|_| Completion::Normal),
+        StmtKind::ReturnStmt(e) => obj_eval(e, md).map(Completion::Return),
+        StmtKind::If {
+            cond,
+            then_st,
+            else_st,
+        } => if_exec(cond, then_st, else_st.as_deref(), md),
+        StmtKind::Block(body) => scoped_exec(body, md),
+        StmtKind::While { .. }
+        | StmtKind::DoWhile { .. }
+        | StmtKind::For { .. }
+        | StmtKind::ForInOf { .. } => loop_exec(st, md, &[]),
+        _ => other_exec(st, md),
+    }
+}
+
+#[inline(never)]
+fn if_exec(
+    cond: &Expr,
+    then_st: &Stmt,
+    else_st: Option<&Stmt>,
+    md: &mut Module,
+) -> Result<Completion, Object> {
+    if truthy(&obj_eval(cond, md)?) {
+        stmt_exec(then_st, md)
+    } else if let Some(else_st) = else_st {
+        stmt_exec(else_st, md)
+    } else {
+        Ok(Completion::Normal)
+    }
+}
+
+/* the rest of the statements */
+#[inline(never)]
+fn other_exec(st: &Stmt, md: &mut Module) -> Result<Completion, Object> {
+    match &st.kind {
+        StmtKind::VardeclSimple {
+            name,
+            val: None,
+            qual: VarQualifier::Var,
+        } => md.env.declare_var(name),
+        StmtKind::VardeclSimple { name, val, qual } => {
+            let evaluated_val = match val {
+                Some(val) => obj_eval(val, md)?,
+                None => Object::undef(),
+            };
+            match qual {
+                VarQualifier::Var => md.env.define_var(name, evaluated_val),
+                VarQualifier::Let => md.add_var(name, evaluated_val),
+                VarQualifier::Const => md.env.define_const(name, evaluated_val),
+            }
+        }
+        StmtKind::Funcdecl { name, args, body } => fn_decl(name, args, body, md),
+        StmtKind::ClassDecl {
+            name,
+            parent,
+            members,
+        } => {
+            let class = obj::class_value(name, parent.as_ref(), members, md)?;
+            md.add_var(name, class);
+        }
+        StmtKind::Throw(e) => {
+            return Err(obj_eval(e, md)?);
+        }
+        StmtKind::Try {
+            block,
+            param,
+            handler,
+            finalizer,
+        } => {
+            let mut r = scoped_exec(block, md);
+
+            if let (Err(e), Some(handler)) = (&r, handler) {
+                /* the handler gets a scope of its own, holding the catch parameter */
+                let e = e.clone();
+                r = in_block(md, |md| {
+                    if let Some(p) = param {
+                        md.add_var(p, e);
                     }
+                    block_exec(handler, md)
+                });
+            }
+
+            /* finally always runs; its own return/throw replaces the earlier completion */
+            if let Some(finalizer) = finalizer {
+                match scoped_exec(finalizer, md) {
+                    Ok(Completion::Normal) => (),
+                    other => return other,
+                }
+            }
+
+            return r;
+        }
+        StmtKind::Switch { disc, cases, body } => {
+            let v = obj_eval(disc, md)?;
+            let r = match body.iter().any(|st| st.lexical_name().is_some()) {
+                true => in_block(md, |md| switch_exec(&v, cases, body, md)),
+                false => switch_exec(&v, cases, body, md),
+            };
+
+            return match r? {
+                Completion::Break(None) => Ok(Completion::Normal),
+                c => Ok(c),
+            };
+        }
+        StmtKind::Break(label) => return Ok(Completion::Break(label.clone())),
+        StmtKind::Continue(label) => return Ok(Completion::Continue(label.clone())),
+        StmtKind::Labeled { .. } => {
+            /* `a: b: for (...)` gives the loop both labels */
+            let mut labels: Vec<String> = Vec::new();
+            let mut body = st;
+
+            while let StmtKind::Labeled { label, body: inner } = &body.kind {
+                labels.push(label.clone());
+                body = inner;
+            }
+
+            let r = match body.kind {
+                StmtKind::While { .. }
+                | StmtKind::DoWhile { .. }
+                | StmtKind::For { .. }
+                | StmtKind::ForInOf { .. } => loop_exec(body, md, &labels)?,
+                _ => stmt_exec(body, md)?,
+            };
+
+            return match r {
+                Completion::Break(Some(l)) if labels.contains(&l) => Ok(Completion::Normal),
+                c => Ok(c),
+            };
+        }
+        StmtKind::Expr(_)
+        | StmtKind::ReturnStmt(_)
+        | StmtKind::If { .. }
+        | StmtKind::Block(_)
+        | StmtKind::While { .. }
+        | StmtKind::DoWhile { .. }
+        | StmtKind::For { .. }
+        | StmtKind::ForInOf { .. } => unreachable!("stmt_exec runs these"),
+    }
+
+    Ok(Completion::Normal)
+}
+
+/**
+ * What a loop does after its body completed with `c`:
+ * `None` to keep iterating, otherwise the completion of the whole loop.
+ */
+fn after_iteration(c: Completion, labels: &[String]) -> Option<Completion> {
+    match c {
+        Completion::Normal | Completion::Continue(None) => None,
+        Completion::Continue(Some(l)) if labels.contains(&l) => None,
+        Completion::Break(None) => Some(Completion::Normal),
+        c => Some(c),
+    }
+}
+
+/* runs a while, do-while or for loop; `labels` are the labels placed directly on it */
+fn loop_exec(st: &Stmt, md: &mut Module, labels: &[String]) -> Result<Completion, Object> {
+    match &st.kind {
+        StmtKind::While { cond, body } => {
+            while truthy(&obj_eval(cond, md)?) {
+                if let Some(c) = after_iteration(stmt_exec(body, md)?, labels) {
+                    return Ok(c);
                 }
-                Some(_) => println!("'{}' is not a function", name),
-                None => println!("Function '{}' does not exist", name),
-            },
-            Stmt::VardeclSimple { name, val, qual } => {
-                let evaluated_val = obj_eval(val, &md);
-                md.add_var(name, evaluated_val);
             }
-            Stmt::Funcdecl { name, args, body } => {
-                md.add_var(
-                    name,
-                    Object::Funct(Function::Coded {
-                        name: name.clone(),
-                        args: args.to_vec(),
-                        body: body.to_vec(),
-                    }),
-                );
+        }
+        StmtKind::DoWhile { body, cond } => loop {
+            if let Some(c) = after_iteration(stmt_exec(body, md)?, labels) {
+                return Ok(c);
             }
-            Stmt::ClassDecl { name, vars, fns } => {
-                let mut cmd = Module::new();
-
-                cmd.stmts = vars.clone();
-                mod_exec(&mut cmd);
-
-                cmd.stmts = fns.clone();
-                mod_exec(&mut cmd);
-
-                md.add_var(
-                    name,
-                    Object::Class(classes::ClassD::Coded {
-                        name: name.to_string(),
-                        vars: cmd.vtable,
-                    }),
-                );
+
+            if !truthy(&obj_eval(cond, md)?) {
+                break;
             }
-            Stmt::ReturnStmt(e) => {
-                md.rt = obj_eval(e, md);
-                md.got_rt = true;
+        },
+        StmtKind::For {
+            init,
+            test,
+            update,
+            body,
+        } => {
+            /* `let` declarations get a scope around the loop, copied for each iteration */
+            let init = init.as_deref().map(Stmt::declarations).unwrap_or_default();
+            if init.iter().any(|d| d.lexical_name().is_some()) {
+                return in_block(md, |md| {
+                    block_exec(init, md)?;
+                    for_exec(test, update, body, md, labels, true)
+                });
             }
-            _ => (),
+
+            block_exec(init, md)?;
+            return for_exec(test, update, body, md, labels, false);
         }
+        StmtKind::ForInOf {
+            left,
+            right,
+            body,
+            of,
+        } => {
+            let v = obj_eval(right, md)?;
+            let items = match of {
+                true => obj::iteration_items(v, || obj::describe(right), right.span)?,
+                false => obj::for_in_keys(&v),
+            };
+
+            /* `let` and `const` get a new binding for each iteration */
+            let mut i = 0;
+            while let Some(item) = obj::nth_item(&items, i) {
+                i += 1;
+                let c = match left.lexical_name() {
+                    Some(_) => in_block(md, |md| {
+                        bind_item(left, item, md)?;
+                        stmt_exec(body, md)
+                    })?,
+                    None => {
+                        bind_item(left, item, md)?;
+                        stmt_exec(body, md)?
+                    }
+                };
+
+                if let Some(c) = after_iteration(c, labels) {
+                    return Ok(c);
+                }
+            }
+        }
+        _ => return stmt_exec(st, md),
+    }
+
+    Ok(Completion::Normal)
+}
+
+/* gives the left side of a `for...in` or `for...of` loop the item of an iteration */
+fn bind_item(left: &Stmt, item: Object, md: &mut Module) -> Result<(), Object> {
+    match &left.kind {
+        StmtKind::VardeclSimple { name, qual, .. } => match qual {
+            VarQualifier::Var => md.env.define_var(name, item),
+            VarQualifier::Let => md.add_var(name, item),
+            VarQualifier::Const => md.env.define_const(name, item),
+        },
+        StmtKind::Expr(target) => obj::assign_value(target, item, md)?,
+        _ => unreachable!("the parser only gives declarations and targets"),
+    }
+
+    Ok(())
+}
+
+/**
+ * Runs the cases of a `switch` on `v` from the first whose test is `===` to it, or else from
+ * `default`. The tests run in order, those after `default` too, until one matches.
+ */
+fn switch_exec(
+    v: &Object,
+    cases: &[(Option<Expr>, usize)],
+    body: &[Stmt],
+    md: &mut Module,
+) -> Result<Completion, Object> {
+    hoist(body, md);
+
+    let mut start = None;
+    for (test, at) in cases {
+        if let Some(test) = test {
+            if ops::strict_equals(v, &obj_eval(test, md)?) {
+                start = Some(*at);
+                break;
+            }
+        }
+    }
+
+    let default = cases.iter().find(|(test, _)| test.is_none());
+    match start.or(default.map(|(_, at)| *at)) {
+        Some(at) => stmts_exec(&body[at..], md),
+        None => Ok(Completion::Normal),
+    }
+}
+
+/* the rest of a `for` loop once its initializer ran; `per_iteration` gives each iteration its own bindings */
+fn for_exec(
+    test: &Option<Expr>,
+    update: &Option<Expr>,
+    body: &Stmt,
+    md: &mut Module,
+    labels: &[String],
+    per_iteration: bool,
+) -> Result<Completion, Object> {
+    if per_iteration {
+        md.env = md.env.next_iteration();
+    }
+
+    loop {
+        if let Some(test) = test {
+            if !truthy(&obj_eval(test, md)?) {
+                break;
+            }
+        }
+
+        if let Some(c) = after_iteration(stmt_exec(body, md)?, labels) {
+            return Ok(c);
+        }
+
+        if per_iteration {
+            md.env = md.env.next_iteration();
+        }
+        if let Some(update) = update {
+            obj_eval(update, md)?;
+        }
+    }
+
+    Ok(Completion::Normal)
+}
+
+/* runs `f` in a new block scope inside the one of `md`, which comes back however `f` ends */
+fn in_block<R>(md: &mut Module, f: impl FnOnce(&mut Module) -> R) -> R {
+    let outer = md.env.clone();
+    md.env = Env::block(&outer);
+
+    let r = f(md);
+    md.env = outer;
+    r
+}
+
+/* runs a block, in a scope of its own if it declares anything there */
+fn scoped_exec(stmts: &[Stmt], md: &mut Module) -> Result<Completion, Object> {
+    match stmts.iter().any(|st| st.lexical_name().is_some()) {
+        true => in_block(md, |md| block_exec(stmts, md)),
+        false => block_exec(stmts, md),
+    }
+}
+
+fn fn_decl(name: &str, args: &Rc<[Expr]>, body: &Rc<[Stmt]>, md: &mut Module) {
+    md.add_var(
+        name,
+        Object::Funct(Function::coded(
+            Proto::new(name, args, body, FuncForm::Plain),
+            md.env.clone(),
+        )),
+    );
+}
+
+/* runs `stmts` in `md` until one of them completes abruptly; the functions they declare come first */
+fn block_exec(stmts: &[Stmt], md: &mut Module) -> Result<Completion, Object> {
+    hoist(stmts, md);
+    stmts_exec(stmts, md)
+}
+
+/* declares the names `stmts` declare in their block: the functions, and the rest uninitialized */
+fn hoist(stmts: &[Stmt], md: &mut Module) {
+    for st in stmts {
+        match &st.kind {
+            StmtKind::Funcdecl { name, args, body } => fn_decl(name, args, body, md),
+            _ => {
+                if let Some(n) = st.lexical_name() {
+                    md.env.declare_lexical(n);
+                }
+            }
+        }
+    }
+}
+
+/* runs the statements of a block other than its function declarations, which `hoist` took care of */
+fn stmts_exec(stmts: &[Stmt], md: &mut Module) -> Result<Completion, Object> {
+    for st in stmts {
+        if let StmtKind::Funcdecl { .. } = st.kind {
+            continue;
+        }
+        match stmt_exec(st, md)? {
+            Completion::Normal => (),
+            c => return Ok(c),
+        }
+    }
+
+    Ok(Completion::Normal)
+}
+
+/**
+ * Runs `md.stmts` in `md`, the way `mode()` says.
+ * Stops at the first `return` or uncaught exception and reports it as the completion.
+ */
+pub fn mod_exec(md: &mut Module) -> Completion {
+    let stmts = std::mem::take(&mut md.stmts);
+
+    let c = stack::run(|| match mode() {
+        Mode::Vm => vm::run_script(&stmts, md),
+        Mode::Tree => tree_exec(&stmts, md),
+    });
+
+    md.stmts = stmts;
+    c
+}
+
+/* runs a script or function body `stmts` in `md` by walking them, once the `var`s in it exist */
+pub fn tree_exec(stmts: &[Stmt], md: &mut Module) -> Completion {
+    for st in stmts {
+        let mut names = Vec::new();
+        st.var_names(&mut names);
+        names.iter().for_each(|n| md.env.declare_var(n));
+    }
+
+    match block_exec(stmts, md) {
+        Ok(c) => c,
+        Err(e) => Completion::Throw(e),
     }
 }
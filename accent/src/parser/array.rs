@@ -0,0 +1,372 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use super::{
+    builtins,
+    heap::{self, Trace, Tracer},
+    obj::Object,
+    props::{index_key, Props},
+};
+
+/* a write this far past the end of a dense array switches it to the sparse representation */
+const MAX_DENSE_GAP: usize = 1024;
+
+/* the most values `values` lists; past it a sparse array's holes would not fit in memory */
+const MAX_VALUES: usize = 1 << 27;
+
+#[derive(Debug, Clone)]
+enum Elements {
+    /* `None` is a hole: `[1, , 3]` */
+    Dense(Vec<Option<Object>>),
+    Sparse {
+        len: usize,
+        map: BTreeMap<usize, Object>,
+    },
+}
+
+/**
+ * Storage of an array object.
+ * Elements live in a vector as long as the array is mostly filled,
+ * `a[1e6] = 1` and `a.length = 1e6` switch to a map keyed by index instead.
+ */
+#[derive(Debug, Clone)]
+pub struct Array {
+    elems: Elements,
+    props: Props, /* non-index properties */
+    /* set by `Object.freeze`; the elements and `length` can no longer change */
+    pub frozen: bool,
+}
+
+impl Trace for Array {
+    fn trace(&self, t: &mut Tracer) {
+        match &self.elems {
+            Elements::Dense(v) => v.iter().flatten().for_each(|v| v.trace(t)),
+            Elements::Sparse { map, .. } => map.values().for_each(|v| v.trace(t)),
+        }
+        self.props.trace(t);
+    }
+
+    fn clear(&mut self) {
+        self.elems = Elements::Dense(Vec::new());
+        self.props.clear();
+    }
+}
+
+/* like `Props`, the elements are freed through `drop_contents` */
+impl Drop for Array {
+    fn drop(&mut self) {
+        let elems = std::mem::replace(&mut self.elems, Elements::Dense(Vec::new()));
+        heap::drop_contents(elems);
+    }
+}
+
+impl Array {
+    pub fn new(elems: Vec<Option<Object>>) -> Array {
+        Array {
+            elems: Elements::Dense(elems),
+            props: Props::with_proto(Some(builtins::array::prototype())),
+            frozen: false,
+        }
+    }
+
+    pub fn from_values(v: Vec<Object>) -> Array {
+        Array::new(v.into_iter().map(Some).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.elems {
+            Elements::Dense(v) => v.len(),
+            Elements::Sparse { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /* element `i`; `None` for holes and indices past the end */
+    pub fn get(&self, i: usize) -> Option<Object> {
+        match &self.elems {
+            Elements::Dense(v) => v.get(i).cloned().flatten(),
+            Elements::Sparse { map, .. } => map.get(&i).cloned(),
+        }
+    }
+
+    pub fn has(&self, i: usize) -> bool {
+        match &self.elems {
+            Elements::Dense(v) => matches!(v.get(i), Some(Some(_))),
+            Elements::Sparse { map, .. } => map.contains_key(&i),
+        }
+    }
+
+    fn make_sparse(&mut self) {
+        if let Elements::Dense(v) = &mut self.elems {
+            let len = v.len();
+            let map = std::mem::take(v)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, e)| e.map(|e| (i, e)))
+                .collect();
+
+            self.elems = Elements::Sparse { len, map };
+        }
+    }
+
+    pub fn set(&mut self, i: usize, val: Object) {
+        if i > self.len() + MAX_DENSE_GAP {
+            self.make_sparse();
+        }
+
+        match &mut self.elems {
+            Elements::Dense(v) => {
+                if i >= v.len() {
+                    v.resize(i + 1, None);
+                }
+                v[i] = Some(val);
+            }
+            Elements::Sparse { len, map } => {
+                map.insert(i, val);
+                *len = (*len).max(i + 1);
+            }
+        }
+    }
+
+    /* turns element `i` into a hole */
+    pub fn delete(&mut self, i: usize) {
+        match &mut self.elems {
+            Elements::Dense(v) => {
+                if let Some(e) = v.get_mut(i) {
+                    *e = None;
+                }
+            }
+            Elements::Sparse { map, .. } => {
+                map.remove(&i);
+            }
+        }
+    }
+
+    /* `a.length = n`: drops the elements past `n`, or adds holes up to it */
+    pub fn set_len(&mut self, n: usize) {
+        if n > self.len() + MAX_DENSE_GAP {
+            self.make_sparse();
+        }
+
+        match &mut self.elems {
+            Elements::Dense(v) => v.resize(n, None),
+            Elements::Sparse { len, map } => {
+                map.retain(|i, _| *i < n);
+                *len = n;
+            }
+        }
+    }
+
+    pub fn push(&mut self, val: Object) {
+        let n = self.len();
+        self.set(n, val);
+    }
+
+    /* every element in order, holes read as `undefined`; `Err` when there are too many to list */
+    pub fn values(&self) -> Result<Vec<Object>, String> {
+        if self.len() > MAX_VALUES {
+            return Err(String::from("Invalid array length"));
+        }
+
+        Ok((0..self.len())
+            .map(|i| self.get(i).unwrap_or_else(Object::undef))
+            .collect())
+    }
+
+    /* the elements that are not holes, in order, with their indices */
+    pub fn entries(&self) -> Vec<(usize, Object)> {
+        self.entries_in(0..self.len())
+    }
+
+    /* `entries` with the indices in `r` */
+    pub fn entries_in(&self, r: Range<usize>) -> Vec<(usize, Object)> {
+        match &self.elems {
+            Elements::Dense(v) => {
+                let r = r.start.min(v.len())..r.end.min(v.len());
+                v[r.clone()]
+                    .iter()
+                    .zip(r)
+                    .filter_map(|(e, i)| Some((i, e.clone()?)))
+                    .collect()
+            }
+            Elements::Sparse { map, .. } => map.range(r).map(|(i, e)| (*i, e.clone())).collect(),
+        }
+    }
+
+    /* an array of length `len` holding the elements `entries` */
+    pub fn from_entries(len: usize, entries: Vec<(usize, Object)>) -> Array {
+        let mut a = Array::new(Vec::new());
+        a.set_len(len);
+        for (i, v) in entries {
+            a.set(i, v);
+        }
+        a
+    }
+
+    /* the index of the first element at or after `i` that is not a hole */
+    pub fn next_index(&self, i: usize) -> Option<usize> {
+        match &self.elems {
+            Elements::Dense(v) => (i..v.len()).find(|&i| v[i].is_some()),
+            Elements::Sparse { map, .. } => map.range(i..).next().map(|(i, _)| *i),
+        }
+    }
+
+    /* the index of the last element before `i` that is not a hole */
+    pub fn prev_index(&self, i: usize) -> Option<usize> {
+        match &self.elems {
+            Elements::Dense(v) => (0..i.min(v.len())).rev().find(|&i| v[i].is_some()),
+            Elements::Sparse { map, .. } => map.range(..i).next_back().map(|(i, _)| *i),
+        }
+    }
+
+    /**
+     * Removes the `count` elements from `start`, which the caller keeps within the array,
+     * and puts `items` in their place; returns the removed ones, holes included.
+     */
+    pub fn splice(&mut self, start: usize, count: usize, items: Vec<Object>) -> Array {
+        match &mut self.elems {
+            Elements::Dense(v) => {
+                let removed = v.splice(start..start + count, items.into_iter().map(Some));
+                Array::new(removed.collect())
+            }
+            Elements::Sparse { len, map } => {
+                let mut removed = map.split_off(&start);
+                let rest = removed.split_off(&(start + count));
+                let n = items.len();
+
+                map.extend((start..).zip(items));
+                map.extend(rest.into_iter().map(|(i, e)| (i - count + n, e)));
+                *len = *len - count + n;
+
+                let removed = removed.into_iter().map(|(i, e)| (i - start, e)).collect();
+                Array::from_entries(count, removed)
+            }
+        }
+    }
+
+    /* reverses the elements in place, holes included */
+    pub fn reverse(&mut self) {
+        match &mut self.elems {
+            Elements::Dense(v) => v.reverse(),
+            Elements::Sparse { len, map } => {
+                *map = std::mem::take(map)
+                    .into_iter()
+                    .map(|(i, e)| (*len - 1 - i, e))
+                    .collect();
+            }
+        }
+    }
+
+    /* makes `vals` the first elements, followed by holes up to the length the array had */
+    pub fn set_packed(&mut self, vals: Vec<Object>) {
+        let len = self.len().max(vals.len());
+
+        self.elems = match len - vals.len() > MAX_DENSE_GAP {
+            true => Elements::Sparse {
+                len,
+                map: vals.into_iter().enumerate().collect(),
+            },
+            false => {
+                let mut v: Vec<_> = vals.into_iter().map(Some).collect();
+                v.resize(len, None);
+                Elements::Dense(v)
+            }
+        };
+    }
+
+    /* gives this array the elements of `other`, keeping the other properties */
+    pub fn copy_elems(&mut self, other: &Array) {
+        self.elems = other.elems.clone();
+    }
+
+    pub fn props(&self) -> &Props {
+        &self.props
+    }
+
+    pub fn props_mut(&mut self) -> &mut Props {
+        &mut self.props
+    }
+
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+        self.props.freeze();
+    }
+
+    pub fn get_prop(&self, key: &str) -> Object {
+        if key == "length" {
+            return Object::Const(crate::ast::constants::Const::Integer(self.len() as i64));
+        }
+
+        match index_key(key) {
+            Some(i) => self.get(i as usize),
+            None => self.props.get(key).cloned(),
+        }
+        .unwrap_or_else(Object::undef)
+    }
+
+    pub fn has_prop(&self, key: &str) -> bool {
+        match index_key(key) {
+            Some(i) => self.has(i as usize),
+            None => key == "length" || self.props.contains(key),
+        }
+    }
+
+    /* `Err` when `length` is set to something that is not a valid array length */
+    pub fn set_prop(&mut self, key: &str, v: Object) -> Result<(), String> {
+        if key == "length" {
+            let n = super::ops::to_number(&v);
+
+            if n < 0.0 || n.fract() != 0.0 || n > u32::MAX as f64 {
+                return Err(String::from("Invalid array length"));
+            }
+
+            self.set_len(n as usize);
+            return Ok(());
+        }
+
+        match index_key(key) {
+            Some(i) => self.set(i as usize, v),
+            None => self.props.set(key, v),
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_prop(&mut self, key: &str) {
+        match index_key(key) {
+            Some(i) => self.delete(i as usize),
+            None => {
+                self.props.remove(key);
+            }
+        }
+    }
+
+    /* runs of elements for printing: `Ok(value)` or `Err(number of holes)` */
+    pub fn runs(&self) -> Vec<Result<Object, usize>> {
+        let mut res: Vec<Result<Object, usize>> = Vec::new();
+        let mut i = 0;
+
+        while i < self.len() {
+            match &self.elems {
+                Elements::Sparse { map, len } if !map.contains_key(&i) => {
+                    let next = map.range(i..).next().map_or(*len, |(k, _)| *k);
+                    res.push(Err(next - i));
+                    i = next;
+                    continue;
+                }
+                _ => match self.get(i) {
+                    Some(v) => res.push(Ok(v)),
+                    None => match res.last_mut() {
+                        Some(Err(n)) => *n += 1,
+                        _ => res.push(Err(1)),
+                    },
+                },
+            }
+            i += 1;
+        }
+
+        res
+    }
+}
@@ -0,0 +1,123 @@
+use crate::ast::constants::{Const, JsStr};
+
+use super::{
+    classes, coerce,
+    errors::new_error,
+    function::{Function, NativeFn},
+    module::Module,
+    obj::{with_props_mut, Object},
+    props::{Property, Props},
+};
+
+pub mod array;
+pub mod number;
+pub mod object;
+pub mod string;
+
+pub fn native(name: &str, f: NativeFn) -> Object {
+    Object::Funct(Function::native(name, f))
+}
+
+/* the natives `fns` as the properties of a constructor */
+pub fn statics(fns: &[(&'static str, NativeFn)]) -> Vec<(&'static str, Object)> {
+    fns.iter().map(|(k, f)| (*k, native(k, *f))).collect()
+}
+
+/* an ordinary object holding the native methods `methods`, like `Array.prototype` does */
+pub fn with_methods(methods: &[(&str, NativeFn)]) -> Object {
+    let mut props = Props::ordinary();
+    for (name, f) in methods {
+        props.define(name, Property::hidden(native(name, *f)));
+    }
+
+    Object::obj(props)
+}
+
+/**
+ * The global constructor `name`, which runs `f` and holds `statics`, together with its
+ * `prototype`, whose `constructor` it becomes.
+ */
+pub fn define_constructor(
+    name: &str,
+    f: NativeFn,
+    prototype: Object,
+    statics: Vec<(&str, Object)>,
+) -> (Object, Object) {
+    let c = Function::native(name, f);
+
+    {
+        let mut props = c.props.borrow_mut();
+        props.define("prototype", classes::fixed(prototype.clone(), false));
+        for (k, v) in statics {
+            props.define(k, Property::hidden(v));
+        }
+    }
+    let c = Object::Funct(c);
+    with_props_mut(&prototype, |p| {
+        p.define("constructor", Property::hidden(c.clone()))
+    });
+
+    (c, prototype)
+}
+
+/* argument `i`, undefined when it was not passed */
+pub fn arg(args: &[Object], i: usize) -> Object {
+    args.get(i).cloned().unwrap_or_else(Object::undef)
+}
+
+pub fn type_error(md: &Module, msg: impl Into<String>) -> Object {
+    new_error("TypeError", msg, md.call_span)
+}
+
+/* argument `i` as a function to call back into */
+pub fn callback(args: &[Object], i: usize, md: &Module) -> Result<Function, Object> {
+    match arg(args, i) {
+        Object::Funct(f) => Ok(f),
+        v => Err(type_error(md, format!("{v} is not a function"))),
+    }
+}
+
+/* ToString of an argument, which may run its `toString` */
+pub fn string_of(o: &Object, md: &Module) -> Result<String, Object> {
+    coerce::to_string(o, md, md.call_span)
+}
+
+/* ToString of an argument as the code units that string methods index into */
+pub fn js_str_of(o: &Object, md: &Module) -> Result<JsStr, Object> {
+    coerce::to_js_str(o, md, md.call_span)
+}
+
+/* ToNumber of an argument, which may run its `valueOf` */
+pub fn number_of(o: &Object, md: &Module) -> Result<f64, Object> {
+    coerce::to_number(o, md, md.call_span)
+}
+
+/* ToIntegerOrInfinity */
+pub fn to_integer(o: &Object, md: &Module) -> Result<f64, Object> {
+    let n = number_of(o, md)?;
+    Ok(if n.is_nan() { 0.0 } else { n.trunc() })
+}
+
+/* a start/end argument counted from the end when negative and clamped to `0..=len` */
+pub fn relative_index(
+    o: &Object,
+    len: usize,
+    default: usize,
+    md: &Module,
+) -> Result<usize, Object> {
+    if matches!(o, Object::Const(Const::Undef)) {
+        return Ok(default);
+    }
+
+    let n = to_integer(o, md)?;
+    Ok(if n < 0.0 {
+        (len as f64 + n).max(0.0) as usize
+    } else {
+        n.min(len as f64) as usize
+    })
+}
+
+/* native functions that also work with `new` */
+pub fn is_constructor(name: &str) -> bool {
+    matches!(name, "Array" | "Object")
+}
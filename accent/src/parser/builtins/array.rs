@@ -0,0 +1,877 @@
+use std::{cell::RefCell, cmp::Ordering};
+
+use crate::ast::constants::{Const, JsStr, MAX_STR_LENGTH};
+use crate::token::Span;
+
+use super::super::{
+    array::Array,
+    errors::{invalid_string_length, new_error},
+    function::{Function, NativeFn},
+    heap::Handle,
+    module::Module,
+    obj::{call_function, get_prop, truthy, Object},
+    ops, stack,
+};
+use super::{
+    arg, callback, define_constructor, js_str_of, number_of, relative_index, statics, to_integer,
+    type_error, with_methods,
+};
+
+type ArrayRef = Handle<Array>;
+
+/* `Array.prototype` */
+const METHODS: &[(&str, NativeFn)] = &[
+    ("push", push),
+    ("pop", pop),
+    ("shift", shift),
+    ("unshift", unshift),
+    ("slice", slice),
+    ("splice", splice),
+    ("concat", concat),
+    ("join", join),
+    ("toString", to_string),
+    ("reverse", reverse),
+    ("sort", sort),
+    ("indexOf", index_of),
+    ("lastIndexOf", last_index_of),
+    ("includes", includes),
+    ("find", find),
+    ("findIndex", find_index),
+    ("findLast", find_last),
+    ("findLastIndex", find_last_index),
+    ("map", map),
+    ("filter", filter),
+    ("reduce", reduce),
+    ("reduceRight", reduce_right),
+    ("forEach", for_each),
+    ("some", some),
+    ("every", every),
+    ("flat", flat),
+    ("flatMap", flat_map),
+    ("at", at),
+    ("fill", fill),
+    ("keys", keys),
+    ("values", values),
+    ("entries", entries),
+];
+
+/* `Array.from` and the other functions on `Array` itself */
+const STATICS: &[(&str, NativeFn)] = &[("from", from), ("of", of), ("isArray", is_array)];
+thread_local! {
+    /* the global `Array` and `Array.prototype`, which arrays get their methods from */
+    static ARRAY: (Object, Object) = define_constructor(
+        "Array",
+        array_ctor,
+        with_methods(METHODS),
+        statics(STATICS),
+    );
+    /* arrays whose elements are being joined; one that contains itself joins as "" inside */
+    static JOINING: RefCell<Vec<*const Array>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn prototype() -> Object {
+    ARRAY.with(|(_, p)| p.clone())
+}
+
+/* `Array` */
+pub fn constructor() -> Object {
+    ARRAY.with(|(c, _)| c.clone())
+}
+
+/* `f` run while `a` is being joined, or None if `a` is already being joined further out */
+fn joining<R>(a: *const Array, f: impl FnOnce() -> R) -> Option<R> {
+    if JOINING.with_borrow(|j| j.contains(&a)) {
+        return None;
+    }
+
+    JOINING.with_borrow_mut(|j| j.push(a));
+    let r = f();
+    JOINING.with_borrow_mut(|j| j.pop());
+    Some(r)
+}
+
+/* the elements joined by `sep`; holes, undefined and null become "" */
+pub fn join_values(a: &Array, sep: &str) -> JsStr {
+    let sep = JsStr::from(sep);
+    let to_str = |v: &Object| Ok(ops::to_js_str(v));
+    let join = || join_entries(a.len(), a.entries(), &sep, to_str, Span::default());
+
+    joining(a, join).and_then(Result::ok).unwrap_or_default()
+}
+
+/**
+ * The elements `entries` of an array of length `len`, converted by `to_str` and joined by `sep`.
+ * Only the elements there are get converted, so a sparse array joins without visiting its holes.
+ * A result too long to be a string throws a RangeError at `span`.
+ */
+fn join_entries(
+    len: usize,
+    entries: Vec<(usize, Object)>,
+    sep: &JsStr,
+    mut to_str: impl FnMut(&Object) -> Result<JsStr, Object>,
+    span: Span,
+) -> Result<JsStr, Object> {
+    let too_long = |_| invalid_string_length(span);
+    if !sep.is_empty() && len.saturating_sub(1) > MAX_STR_LENGTH / sep.len() {
+        return Err(invalid_string_length(span));
+    }
+
+    let mut s = JsStr::default();
+    /* the separators pushed so far, which is the index of the next element */
+    let mut seps = 0;
+    let mut sep_up_to = |s: &mut JsStr, i: usize| -> Result<(), Object> {
+        if !sep.is_empty() {
+            for _ in seps..i {
+                s.push(sep).map_err(too_long)?;
+            }
+        }
+        seps = i;
+        Ok(())
+    };
+
+    for (i, v) in entries {
+        sep_up_to(&mut s, i)?;
+        if !ops::is_nullish(&v) {
+            s.push(&to_str(&v)?).map_err(too_long)?;
+        }
+    }
+    sep_up_to(&mut s, len.saturating_sub(1))?;
+
+    Ok(s)
+}
+
+/* `join_values` with objects converted through their own `toString` */
+fn join_with(a: &ArrayRef, sep: &JsStr, md: &Module) -> Result<JsStr, Object> {
+    joining(a.as_ptr(), || join_objects(a, sep, md)).unwrap_or(Ok(JsStr::default()))
+}
+
+fn join_objects(a: &ArrayRef, sep: &JsStr, md: &Module) -> Result<JsStr, Object> {
+    let (len, entries) = {
+        let a = a.borrow();
+        (a.len(), a.entries())
+    };
+
+    join_entries(len, entries, sep, |v| js_str_of(v, md), md.call_span)
+}
+
+fn this_array(this: &Object, name: &str, md: &Module) -> Result<ArrayRef, Object> {
+    match this {
+        Object::Array(a) => Ok(a.clone()),
+        _ => Err(type_error(
+            md,
+            format!("Array.prototype.{name} called on a non-array"),
+        )),
+    }
+}
+
+/* `this` as an array the method may change; frozen arrays refuse */
+fn mutable_array(this: &Object, name: &str, md: &Module) -> Result<ArrayRef, Object> {
+    let a = this_array(this, name, md)?;
+
+    if a.borrow().frozen {
+        return Err(type_error(
+            md,
+            "Cannot assign to read only property 'length' of object '[object Array]'",
+        ));
+    }
+    Ok(a)
+}
+
+fn int(i: usize) -> Object {
+    ops::int(i as i64)
+}
+
+/* the longest an array can be */
+const MAX_LENGTH: usize = u32::MAX as usize;
+
+/* the RangeError for a result longer than an array can be */
+fn invalid_length(md: &Module) -> Object {
+    new_error("RangeError", "Invalid array length", md.call_span)
+}
+
+/* the elements of `a`, holes read as `undefined`, for the methods that list every index */
+fn listed(a: &ArrayRef, md: &Module) -> Result<Vec<Object>, Object> {
+    a.borrow()
+        .values()
+        .map_err(|m| new_error("RangeError", m, md.call_span))
+}
+
+fn new_array(v: Vec<Object>) -> Object {
+    Object::array(Array::from_values(v))
+}
+
+/* element `i`, read again on every step since callbacks can change the array */
+fn elem(a: &ArrayRef, i: usize) -> Option<Object> {
+    a.borrow().get(i)
+}
+
+/* `f.call(thisArg, element, index, array)` for the iteration methods */
+fn call_back(
+    f: &Function,
+    args: &[Object],
+    v: Object,
+    i: usize,
+    a: &ArrayRef,
+    md: &Module,
+) -> Result<Object, Object> {
+    call_function(
+        f,
+        arg(args, 1),
+        vec![v, int(i), Object::Array(a.clone())],
+        md,
+        md.call_span,
+    )
+}
+
+/* `Array(n)` makes `n` holes, `Array(a, b, ...)` an array of its arguments */
+pub fn array_ctor(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    if let [n @ Object::Const(Const::Integer(_) | Const::Float(_))] = args {
+        let mut a = Array::new(Vec::new());
+        a.set_prop("length", n.clone())
+            .map_err(|m| new_error("RangeError", m, md.call_span))?;
+
+        return Ok(Object::array(a));
+    }
+
+    Ok(new_array(args.to_vec()))
+}
+
+fn from(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let src = arg(args, 0);
+    let vals = match &src {
+        Object::Array(a) => listed(a, md)?,
+        Object::Const(Const::Str { v, .. }) => {
+            v.code_points().into_iter().map(Object::str).collect()
+        }
+        _ if ops::is_nullish(&src) => {
+            return Err(type_error(md, format!("{src} is not iterable")));
+        }
+        /* array-likes: `{ length: 2, 0: 'a', 1: 'b' }` */
+        _ => {
+            let len =
+                to_integer(&get_prop(&src, "length", md, md.call_span)?, md)?.max(0.0) as usize;
+            (0..len)
+                .map(|i| get_prop(&src, &i.to_string(), md, md.call_span))
+                .collect::<Result<Vec<Object>, Object>>()?
+        }
+    };
+
+    if ops::is_nullish(&arg(args, 1)) {
+        return Ok(new_array(vals));
+    }
+
+    let f = callback(args, 1, md)?;
+    let vals = vals
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| call_function(&f, arg(args, 2), vec![v, int(i)], md, md.call_span))
+        .collect::<Result<Vec<Object>, Object>>()?;
+
+    Ok(new_array(vals))
+}
+
+fn of(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(new_array(args.to_vec()))
+}
+
+fn is_array(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::boolean(matches!(arg(args, 0), Object::Array(_))))
+}
+
+fn push(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "push", md)?;
+    let mut a = a.borrow_mut();
+
+    if a.len() + args.len() > MAX_LENGTH {
+        return Err(invalid_length(md));
+    }
+
+    for v in args {
+        a.push(v.clone());
+    }
+
+    Ok(int(a.len()))
+}
+
+fn pop(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "pop", md)?;
+    let mut a = a.borrow_mut();
+
+    match a.len() {
+        0 => Ok(Object::undef()),
+        n => {
+            let v = a.get(n - 1).unwrap_or_else(Object::undef);
+            a.set_len(n - 1);
+            Ok(v)
+        }
+    }
+}
+
+fn shift(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "shift", md)?;
+    let mut a = a.borrow_mut();
+
+    if a.is_empty() {
+        return Ok(Object::undef());
+    }
+
+    Ok(a.splice(0, 1, Vec::new())
+        .get(0)
+        .unwrap_or_else(Object::undef))
+}
+
+fn unshift(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "unshift", md)?;
+    let mut a = a.borrow_mut();
+
+    if a.len() + args.len() > MAX_LENGTH {
+        return Err(invalid_length(md));
+    }
+    a.splice(0, 0, args.to_vec());
+
+    Ok(int(a.len()))
+}
+
+/* only the elements there are get copied, so slicing a sparse array keeps its holes as holes */
+fn slice(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "slice", md)?;
+    let len = a.borrow().len();
+
+    /* converting the arguments may run scripts, which could use the array */
+    let start = relative_index(&arg(args, 0), len, 0, md)?;
+    let end = relative_index(&arg(args, 1), len, len, md)?;
+
+    let a = a.borrow();
+    let end = end.min(a.len());
+    let start = start.min(end);
+    let entries = a.entries_in(start..end);
+
+    Ok(Object::array(Array::from_entries(
+        end - start,
+        entries.into_iter().map(|(i, v)| (i - start, v)).collect(),
+    )))
+}
+
+fn splice(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "splice", md)?;
+    let len = a.borrow().len();
+
+    /* converting the arguments may run scripts, which could use the array */
+    let start = relative_index(&arg(args, 0), len, 0, md)?;
+    let count = match args.len() {
+        0 => 0,
+        1 => len - start,
+        _ => (to_integer(&args[1], md)?.max(0.0) as usize).min(len - start),
+    };
+
+    let mut a = a.borrow_mut();
+    let start = start.min(a.len());
+    let count = count.min(a.len() - start);
+    let items = args.get(2..).unwrap_or_default().to_vec();
+
+    if a.len() - count + items.len() > MAX_LENGTH {
+        return Err(invalid_length(md));
+    }
+
+    Ok(Object::array(a.splice(start, count, items)))
+}
+
+fn concat(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "concat", md)?;
+    let mut out = Array::new(Vec::new());
+    let mut len = 0;
+
+    for v in std::iter::once(Object::Array(a)).chain(args.iter().cloned()) {
+        match &v {
+            Object::Array(b) => {
+                let b = b.borrow();
+                if len + b.len() > MAX_LENGTH {
+                    return Err(invalid_length(md));
+                }
+                for (i, e) in b.entries() {
+                    out.set(len + i, e);
+                }
+                len += b.len();
+            }
+            _ if len == MAX_LENGTH => return Err(invalid_length(md)),
+            _ => {
+                out.set(len, v);
+                len += 1;
+            }
+        }
+    }
+    out.set_len(len);
+
+    Ok(Object::array(out))
+}
+
+fn join(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "join", md)?;
+    let sep = match arg(args, 0) {
+        Object::Const(Const::Undef) => JsStr::from(","),
+        s => js_str_of(&s, md)?,
+    };
+
+    Ok(Object::str(join_with(&a, &sep, md)?))
+}
+
+fn to_string(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "toString", md)?;
+    Ok(Object::str(join_with(&a, &JsStr::from(","), md)?))
+}
+
+fn reverse(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "reverse", md)?;
+    a.borrow_mut().reverse();
+
+    Ok(this.clone())
+}
+
+/* stable merge sort with a comparison that can throw */
+fn merge_sort(
+    mut v: Vec<Object>,
+    less_eq: &mut dyn FnMut(&Object, &Object) -> Result<bool, Object>,
+) -> Result<Vec<Object>, Object> {
+    if v.len() < 2 {
+        return Ok(v);
+    }
+
+    let right = v.split_off(v.len() / 2);
+    let left = merge_sort(v, less_eq)?;
+    let right = merge_sort(right, less_eq)?;
+
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let mut l = left.into_iter().peekable();
+    let mut r = right.into_iter().peekable();
+
+    while let (Some(x), Some(y)) = (l.peek(), r.peek()) {
+        /* ties keep the element from the left half first */
+        let next = if less_eq(x, y)? { l.next() } else { r.next() };
+        out.extend(next);
+    }
+    out.extend(l);
+    out.extend(r);
+
+    Ok(out)
+}
+
+/* undefined sorts after every other value and holes after that; neither reaches the comparator */
+fn sort(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "sort", md)?;
+    let cmp = match arg(args, 0) {
+        Object::Const(Const::Undef) => None,
+        Object::Funct(f) => Some(f),
+        _ => {
+            return Err(type_error(
+                md,
+                "The comparison function must be either a function or undefined",
+            ))
+        }
+    };
+
+    let entries = a.borrow().entries();
+    let (undefs, vals): (Vec<Object>, Vec<Object>) = entries
+        .into_iter()
+        .map(|(_, v)| v)
+        .partition(|v| matches!(v, Object::Const(Const::Undef)));
+
+    let md: &Module = md;
+    let mut less_eq = |x: &Object, y: &Object| match &cmp {
+        Some(f) => {
+            let r = call_function(
+                f,
+                Object::undef(),
+                vec![x.clone(), y.clone()],
+                md,
+                md.call_span,
+            )?;
+            let n = number_of(&r, md)?;
+            Ok(n <= 0.0 || n.is_nan())
+        }
+        None => {
+            let (x, y) = (js_str_of(x, md)?, js_str_of(y, md)?);
+            Ok(x.cmp(&y) != Ordering::Greater)
+        }
+    };
+
+    let mut out = merge_sort(vals, &mut less_eq)?;
+    out.extend(undefs);
+    a.borrow_mut().set_packed(out);
+
+    Ok(this.clone())
+}
+
+fn index_of(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "indexOf", md)?;
+    let len = a.borrow().len();
+    let target = arg(args, 0);
+
+    let from = relative_index(&arg(args, 1), len, 0, md)?;
+    let found = a
+        .borrow()
+        .entries_in(from..len)
+        .into_iter()
+        .find(|(_, v)| ops::strict_equals(v, &target));
+
+    Ok(found.map_or(ops::int(-1), |(i, _)| int(i)))
+}
+
+fn last_index_of(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "lastIndexOf", md)?;
+    let len = a.borrow().len();
+    let target = arg(args, 0);
+
+    let from = match args.get(1) {
+        None => len as f64 - 1.0,
+        Some(n) => {
+            let n = to_integer(n, md)?;
+            if n < 0.0 {
+                len as f64 + n
+            } else {
+                n.min(len as f64 - 1.0)
+            }
+        }
+    };
+    if from < 0.0 {
+        return Ok(ops::int(-1));
+    }
+
+    let found = a
+        .borrow()
+        .entries_in(0..from as usize + 1)
+        .into_iter()
+        .rfind(|(_, v)| ops::strict_equals(v, &target));
+
+    Ok(found.map_or(ops::int(-1), |(i, _)| int(i)))
+}
+
+/* SameValueZero: `===` except that NaN equals NaN */
+fn same_value_zero(a: &Object, b: &Object) -> bool {
+    let is_nan = |o: &Object| {
+        matches!(o, Object::Const(Const::Integer(_) | Const::Float(_)))
+            && ops::to_number(o).is_nan()
+    };
+
+    ops::strict_equals(a, b) || (is_nan(a) && is_nan(b))
+}
+
+/* holes count as undefined here, unlike in `indexOf` */
+fn includes(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "includes", md)?;
+    let len = a.borrow().len();
+    let target = arg(args, 0);
+
+    let from = relative_index(&arg(args, 1), len, 0, md)?;
+    let entries = a.borrow().entries_in(from..len);
+    let holes = entries.len() < len.saturating_sub(from);
+
+    let found = (holes && matches!(target, Object::Const(Const::Undef)))
+        || entries.iter().any(|(_, v)| same_value_zero(v, &target));
+
+    Ok(ops::boolean(found))
+}
+
+/* first (or last) element the callback accepts, with its index; holes are visited as undefined */
+fn find_impl(
+    this: &Object,
+    args: &[Object],
+    md: &Module,
+    name: &str,
+    from_end: bool,
+) -> Result<Option<(usize, Object)>, Object> {
+    let a = this_array(this, name, md)?;
+    let f = callback(args, 0, md)?;
+    let len = a.borrow().len();
+
+    let order: Box<dyn Iterator<Item = usize>> = if from_end {
+        Box::new((0..len).rev())
+    } else {
+        Box::new(0..len)
+    };
+
+    for i in order {
+        let v = elem(&a, i).unwrap_or_else(Object::undef);
+        if truthy(&call_back(&f, args, v.clone(), i, &a, md)?) {
+            return Ok(Some((i, v)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn find(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(find_impl(this, args, md, "find", false)?.map_or_else(Object::undef, |(_, v)| v))
+}
+
+fn find_index(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(find_impl(this, args, md, "findIndex", false)?.map_or(ops::int(-1), |(i, _)| int(i)))
+}
+
+fn find_last(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(find_impl(this, args, md, "findLast", true)?.map_or_else(Object::undef, |(_, v)| v))
+}
+
+fn find_last_index(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(find_impl(this, args, md, "findLastIndex", true)?.map_or(ops::int(-1), |(i, _)| int(i)))
+}
+
+/**
+ * Calls the callback on every element that is not a hole, in order,
+ * and hands each result to `each`; stops early when `each` returns false.
+ */
+fn for_each_impl(
+    this: &Object,
+    args: &[Object],
+    md: &Module,
+    name: &str,
+    each: &mut dyn FnMut(usize, Object, Object) -> bool,
+) -> Result<(), Object> {
+    let a = this_array(this, name, md)?;
+    let f = callback(args, 0, md)?;
+    let len = a.borrow().len();
+    /* the next element that is not a hole, looked up anew since callbacks can change the array */
+    let next = |from: usize| {
+        let a = a.borrow();
+        let i = a.next_index(from).filter(|&i| i < len)?;
+        Some((i, a.get(i)?))
+    };
+    let mut from = 0;
+
+    while let Some((i, v)) = next(from) {
+        from = i + 1;
+        let r = call_back(&f, args, v.clone(), i, &a, md)?;
+        if !each(i, v, r) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/* holes stay holes in the result */
+fn map(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let mut out = Array::new(Vec::new());
+    if let Object::Array(a) = this {
+        out.set_len(a.borrow().len());
+    }
+
+    for_each_impl(this, args, md, "map", &mut |i, _, r| {
+        out.set(i, r);
+        true
+    })?;
+
+    Ok(Object::array(out))
+}
+
+fn filter(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let mut out = Vec::new();
+
+    for_each_impl(this, args, md, "filter", &mut |_, v, r| {
+        if truthy(&r) {
+            out.push(v);
+        }
+        true
+    })?;
+
+    Ok(new_array(out))
+}
+
+fn for_each(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    for_each_impl(this, args, md, "forEach", &mut |_, _, _| true)?;
+    Ok(Object::undef())
+}
+
+fn some(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let mut found = false;
+
+    for_each_impl(this, args, md, "some", &mut |_, _, r| {
+        found = truthy(&r);
+        !found
+    })?;
+
+    Ok(ops::boolean(found))
+}
+
+fn every(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let mut all = true;
+
+    for_each_impl(this, args, md, "every", &mut |_, _, r| {
+        all = truthy(&r);
+        all
+    })?;
+
+    Ok(ops::boolean(all))
+}
+
+/* without an initial value the first element that is not a hole starts the accumulator */
+fn reduce_impl(
+    this: &Object,
+    args: &[Object],
+    md: &Module,
+    name: &str,
+    from_end: bool,
+) -> Result<Object, Object> {
+    let a = this_array(this, name, md)?;
+    let f = callback(args, 0, md)?;
+    let len = a.borrow().len();
+
+    /* the element after the one at `at`, which is looked up anew since callbacks can change the array */
+    let next = |at: Option<usize>| {
+        let a = a.borrow();
+        let i = match at {
+            _ if from_end => a.prev_index(at.unwrap_or(len).min(len)),
+            None => a.next_index(0),
+            Some(i) => a.next_index(i + 1),
+        }
+        .filter(|&i| i < len)?;
+        Some((i, a.get(i)?))
+    };
+
+    let mut at = None;
+    let mut acc = match args.get(1) {
+        Some(init) => init.clone(),
+        None => match next(None) {
+            Some((i, v)) => {
+                at = Some(i);
+                v
+            }
+            None => {
+                return Err(type_error(
+                    md,
+                    "Reduce of empty array with no initial value",
+                ))
+            }
+        },
+    };
+
+    while let Some((i, v)) = next(at) {
+        at = Some(i);
+        acc = call_function(
+            &f,
+            Object::undef(),
+            vec![acc, v, int(i), Object::Array(a.clone())],
+            md,
+            md.call_span,
+        )?;
+    }
+
+    Ok(acc)
+}
+
+fn reduce(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    reduce_impl(this, args, md, "reduce", false)
+}
+
+fn reduce_right(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    reduce_impl(this, args, md, "reduceRight", true)
+}
+
+/**
+ * Appends the elements of `elems` to `out`, flattening nested arrays `depth` levels deep.
+ * An array that contains itself flattened to an infinite depth runs out of stack.
+ */
+fn flatten(
+    entries: Vec<(usize, Object)>,
+    depth: f64,
+    out: &mut Vec<Object>,
+    md: &Module,
+) -> Result<(), Object> {
+    if stack::exhausted() {
+        return Err(new_error(
+            "RangeError",
+            "Maximum call stack size exceeded",
+            md.call_span,
+        ));
+    }
+
+    for (_, v) in entries {
+        match v {
+            Object::Array(b) if depth >= 1.0 => {
+                let inner = b.borrow().entries();
+                flatten(inner, depth - 1.0, out, md)?;
+            }
+            _ => out.push(v),
+        }
+    }
+
+    Ok(())
+}
+
+fn flat(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "flat", md)?;
+    let depth = match arg(args, 0) {
+        Object::Const(Const::Undef) => 1.0,
+        d => to_integer(&d, md)?,
+    };
+
+    let mut out = Vec::new();
+    let entries = a.borrow().entries();
+    flatten(entries, depth, &mut out, md)?;
+
+    Ok(new_array(out))
+}
+
+fn flat_map(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let mut out = Vec::new();
+
+    for_each_impl(this, args, md, "flatMap", &mut |_, _, r| {
+        match r {
+            Object::Array(b) => out.extend(b.borrow().entries().into_iter().map(|(_, v)| v)),
+            _ => out.push(r),
+        }
+        true
+    })?;
+
+    Ok(new_array(out))
+}
+
+fn at(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "at", md)?;
+    let len = a.borrow().len() as f64;
+
+    let n = to_integer(&arg(args, 0), md)?;
+    let i = if n < 0.0 { len + n } else { n };
+    if i < 0.0 || i >= len {
+        return Ok(Object::undef());
+    }
+
+    Ok(elem(&a, i as usize).unwrap_or_else(Object::undef))
+}
+
+fn fill(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = mutable_array(this, "fill", md)?;
+    let len = a.borrow().len();
+
+    let start = relative_index(&arg(args, 1), len, 0, md)?;
+    let end = relative_index(&arg(args, 2), len, len, md)?;
+    let mut a = a.borrow_mut();
+    for i in start..end {
+        a.set(i, arg(args, 0));
+    }
+
+    Ok(this.clone())
+}
+
+/* there are no iterators yet, so `keys`, `values` and `entries` return arrays */
+fn keys(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "keys", md)?;
+    let len = listed(&a, md)?.len();
+    Ok(new_array((0..len).map(int).collect()))
+}
+
+fn values(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "values", md)?;
+    Ok(new_array(listed(&a, md)?))
+}
+
+fn entries(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let a = this_array(this, "entries", md)?;
+    let vals = listed(&a, md)?;
+
+    Ok(new_array(
+        vals.into_iter()
+            .enumerate()
+            .map(|(i, v)| new_array(vec![int(i), v]))
+            .collect(),
+    ))
+}
@@ -0,0 +1,199 @@
+use crate::ast::constants::{number_to_string, Const, MAX_EXACT_INT};
+
+use super::super::{
+    coerce, errors::new_error, function::NativeFn, module::Module, obj::Object, ops,
+};
+use super::{arg, define_constructor, statics, to_integer, type_error, with_methods};
+
+const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/* `Number.prototype` */
+const METHODS: &[(&str, NativeFn)] = &[("toString", to_string), ("valueOf", value_of)];
+
+const STATICS: &[(&str, NativeFn)] = &[
+    ("isNaN", is_nan),
+    ("isFinite", is_finite),
+    ("isInteger", is_integer),
+    ("isSafeInteger", is_safe_integer),
+];
+
+thread_local! {
+    /* the global `Number` and `Number.prototype`, which numbers get their methods from */
+    static NUMBER: (Object, Object) = {
+        let mut props = statics(STATICS);
+        for (k, n) in [
+            ("NaN", f64::NAN),
+            ("POSITIVE_INFINITY", f64::INFINITY),
+            ("NEGATIVE_INFINITY", f64::NEG_INFINITY),
+            ("MAX_VALUE", f64::MAX),
+            ("MIN_VALUE", f64::from_bits(1)),
+            ("EPSILON", f64::EPSILON),
+            ("MAX_SAFE_INTEGER", (MAX_EXACT_INT - 1) as f64),
+            ("MIN_SAFE_INTEGER", (1 - MAX_EXACT_INT) as f64),
+        ] {
+            props.push((k, ops::num(n)));
+        }
+
+        define_constructor("Number", number_ctor, with_methods(METHODS), props)
+    };
+}
+
+pub fn prototype() -> Object {
+    NUMBER.with(|(_, p)| p.clone())
+}
+
+/* `Number` */
+pub fn constructor() -> Object {
+    NUMBER.with(|(c, _)| c.clone())
+}
+
+/* `Number(v)`, which unlike other conversions also takes BigInts */
+pub fn number_ctor(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::num(match args.first() {
+        None => 0.0,
+        Some(v) => ops::to_number(&coerce::to_numeric(v, md, md.call_span)?),
+    }))
+}
+
+/* argument `i` when it is a Number; the `Number.is*` functions never convert */
+fn number_arg(args: &[Object], i: usize) -> Option<f64> {
+    match arg(args, i) {
+        n @ Object::Const(Const::Integer(_) | Const::Float(_)) => Some(ops::to_number(&n)),
+        _ => None,
+    }
+}
+
+fn is_nan(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::boolean(number_arg(args, 0).is_some_and(f64::is_nan)))
+}
+
+fn is_finite(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::boolean(
+        number_arg(args, 0).is_some_and(f64::is_finite),
+    ))
+}
+
+fn is_integer(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::boolean(
+        number_arg(args, 0).is_some_and(|n| n.is_finite() && n.fract() == 0.0),
+    ))
+}
+
+fn is_safe_integer(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::boolean(number_arg(args, 0).is_some_and(|n| {
+        n.fract() == 0.0 && n.abs() < MAX_EXACT_INT as f64
+    })))
+}
+
+/* thisNumberValue */
+fn this_number(this: &Object, md: &Module, method: &str) -> Result<f64, Object> {
+    match this {
+        Object::Const(Const::Integer(_) | Const::Float(_)) => Ok(ops::to_number(this)),
+        _ => Err(type_error(
+            md,
+            format!("Number.prototype.{method} requires that 'this' be a Number"),
+        )),
+    }
+}
+
+fn value_of(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(ops::num(this_number(this, md, "valueOf")?))
+}
+
+fn to_string(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let x = this_number(this, md, "toString")?;
+
+    let radix = match arg(args, 0) {
+        Object::Const(Const::Undef) => 10.0,
+        r => to_integer(&r, md)?,
+    };
+    if !(2.0..=36.0).contains(&radix) {
+        return Err(new_error(
+            "RangeError",
+            "toString() radix must be between 2 and 36",
+            md.call_span,
+        ));
+    }
+
+    Ok(Object::str(if radix == 10.0 || !x.is_finite() {
+        number_to_string(x)
+    } else {
+        radix_string(x, radix as u32)
+    }))
+}
+
+/**
+ * `x` written in `radix`, with as many fraction digits as it takes to tell `x` apart
+ * from the Numbers next to it, the way V8 does it.
+ */
+fn radix_string(x: f64, radix: u32) -> String {
+    let r = radix as f64;
+    let digit = |d: f64| DIGITS[d as usize] as char;
+
+    let mut integer = x.abs().floor();
+    let mut fraction = x.abs() - integer;
+    let mut frac_digits: Vec<u8> = Vec::new();
+
+    /* half the distance to the next Number: digits past that say nothing about `x` */
+    let next = f64::from_bits(x.abs().to_bits() + 1);
+    let mut delta = (0.5 * (next - x.abs())).max(f64::from_bits(1));
+
+    if fraction >= delta {
+        loop {
+            fraction *= r;
+            delta *= r;
+            let d = fraction.floor();
+            frac_digits.push(d as u8);
+            fraction -= d;
+
+            if (fraction > 0.5 || (fraction == 0.5 && d as u8 & 1 == 1)) && fraction + delta > 1.0 {
+                /* round up, carrying into the integer part if every digit overflows */
+                loop {
+                    match frac_digits.pop() {
+                        None => {
+                            integer += 1.0;
+                            break;
+                        }
+                        Some(d) if (d as u32) + 1 < radix => {
+                            frac_digits.push(d + 1);
+                            break;
+                        }
+                        Some(_) => (),
+                    }
+                }
+                break;
+            }
+
+            if fraction < delta {
+                break;
+            }
+        }
+    }
+
+    /* past 2^53 the low digits are not known, so they are written as zeros */
+    let mut int_digits = Vec::new();
+    while integer / r >= MAX_EXACT_INT as f64 {
+        integer /= r;
+        int_digits.push('0');
+    }
+    loop {
+        let rem = integer % r;
+        int_digits.push(digit(rem));
+        integer = (integer - rem) / r;
+        if integer <= 0.0 {
+            break;
+        }
+    }
+
+    let mut s = String::new();
+    if x < 0.0 {
+        s.push('-');
+    }
+    s.extend(int_digits.iter().rev());
+    if !frac_digits.is_empty() {
+        s.push('.');
+        s.extend(frac_digits.iter().map(|d| digit(*d as f64)));
+    }
+
+    s
+}
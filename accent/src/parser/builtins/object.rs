@@ -0,0 +1,493 @@
+use crate::ast::constants::Const;
+
+use super::super::{
+    array::Array,
+    function::{FnKind, Function, NativeFn},
+    module::Module,
+    obj::{
+        find_property, get_prop, inherits, is_object, own_keys, own_property, proto_of, put_prop,
+        truthy, with_props, with_props_mut, Object,
+    },
+    ops,
+    props::{index_key, Property, Props, Slot},
+};
+use super::{arg, define_constructor, statics, string_of, type_error};
+
+const METHODS: &[(&str, NativeFn)] = &[
+    ("hasOwnProperty", has_own_property),
+    ("isPrototypeOf", is_prototype_of),
+    ("propertyIsEnumerable", property_is_enumerable),
+    ("toString", to_string),
+    ("valueOf", value_of),
+];
+
+const STATICS: &[(&str, NativeFn)] = &[
+    ("create", create),
+    ("defineProperty", define_property),
+    ("defineProperties", define_properties),
+    ("getOwnPropertyDescriptor", get_own_property_descriptor),
+    ("getOwnPropertyNames", get_own_property_names),
+    ("getPrototypeOf", get_prototype_of),
+    ("setPrototypeOf", set_prototype_of),
+    ("freeze", freeze),
+    ("isFrozen", is_frozen),
+    ("preventExtensions", prevent_extensions),
+    ("isExtensible", is_extensible),
+    ("keys", keys),
+    ("values", values),
+    ("entries", entries),
+    ("assign", assign),
+];
+
+thread_local! {
+    /**
+     * `Object.prototype`, where ordinary objects find their inherited methods, and
+     * `Function.prototype`, which inherits from it. The methods of the first are functions
+     * inheriting from the second, so both are made together.
+     */
+    static ROOTS: (Object, Object) = {
+        let object = Object::obj(Props::new());
+        let function = Object::obj(Props::with_proto(Some(object.clone())));
+
+        with_props_mut(&object, |props| {
+            for (name, f) in METHODS {
+                let kind = FnKind::Native {
+                    name: name.to_string(),
+                    f: *f,
+                };
+                let m = Function::with_proto(kind, function.clone());
+                props.define(name, Property::hidden(Object::Funct(m)));
+            }
+        });
+
+        (object, function)
+    };
+
+    /* the global `Object` and its prototype */
+    static OBJECT: (Object, Object) = define_constructor(
+        "Object",
+        object_ctor,
+        ROOTS.with(|(o, _)| o.clone()),
+        statics(STATICS),
+    );
+}
+
+pub fn prototype() -> Object {
+    OBJECT.with(|(_, p)| p.clone())
+}
+
+pub fn function_prototype() -> Object {
+    ROOTS.with(|(_, f)| f.clone())
+}
+
+/* `Object` */
+pub fn constructor() -> Object {
+    OBJECT.with(|(c, _)| c.clone())
+}
+
+/* `Object(v)` and `new Object(v)`: a new object for nullish `v`, `v` itself otherwise */
+pub fn object_ctor(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(match arg(args, 0) {
+        v if ops::is_nullish(&v) => Object::obj(Props::ordinary()),
+        v => v,
+    })
+}
+
+fn bool(b: bool) -> Object {
+    Object::Const(Const::Bool(b))
+}
+
+/* argument `i` as the object a function looks into; nullish values have no properties */
+fn object_arg(args: &[Object], i: usize, md: &Module) -> Result<Object, Object> {
+    match arg(args, i) {
+        v if ops::is_nullish(&v) => {
+            Err(type_error(md, "Cannot convert undefined or null to object"))
+        }
+        v => Ok(v),
+    }
+}
+
+/* argument `i` as a prototype: an object or null */
+fn proto_arg(args: &[Object], i: usize, md: &Module) -> Result<Option<Object>, Object> {
+    match arg(args, i) {
+        Object::Const(Const::Null) => Ok(None),
+        v if is_object(&v) => Ok(Some(v)),
+        v => Err(type_error(
+            md,
+            format!("Object prototype may only be an Object or null: {v}"),
+        )),
+    }
+}
+
+fn has_own_property(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let key = string_of(&arg(args, 0), md)?;
+    Ok(bool(own_property(this, &key).is_some()))
+}
+
+fn is_prototype_of(this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(bool(inherits(&arg(args, 0), this)))
+}
+
+fn property_is_enumerable(
+    this: &Object,
+    args: &[Object],
+    md: &mut Module,
+) -> Result<Object, Object> {
+    let key = string_of(&arg(args, 0), md)?;
+    Ok(bool(own_property(this, &key).is_some_and(|p| p.enumerable)))
+}
+
+fn to_string(this: &Object, _args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(Object::str(ops::to_string(this)))
+}
+
+fn value_of(this: &Object, _args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(this.clone())
+}
+
+/* `Object.create(proto, props)` */
+fn create(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = Object::obj(Props::with_proto(proto_arg(args, 0, md)?));
+
+    match arg(args, 1) {
+        Object::Const(Const::Undef) => Ok(o),
+        descs => define_all(&o, &descs, md).map(|_| o),
+    }
+}
+
+/* a property descriptor as its fields were given; absent fields are `None` */
+struct Descriptor {
+    value: Option<Object>,
+    writable: Option<bool>,
+    get: Option<Option<Function>>,
+    set: Option<Option<Function>>,
+    enumerable: Option<bool>,
+    configurable: Option<bool>,
+}
+
+impl Descriptor {
+    fn is_accessor(&self) -> bool {
+        self.get.is_some() || self.set.is_some()
+    }
+}
+
+/* ToPropertyDescriptor */
+fn to_descriptor(d: &Object, md: &Module) -> Result<Descriptor, Object> {
+    if !is_object(d) {
+        return Err(type_error(
+            md,
+            format!("Property description must be an object: {d}"),
+        ));
+    }
+
+    let field = |name: &str| -> Result<Option<Object>, Object> {
+        match find_property(d, name) {
+            Some(_) => get_prop(d, name, md, md.call_span).map(Some),
+            None => Ok(None),
+        }
+    };
+    let accessor = |name: &str, what: &str| -> Result<Option<Option<Function>>, Object> {
+        match field(name)? {
+            None => Ok(None),
+            Some(Object::Funct(f)) => Ok(Some(Some(f))),
+            Some(Object::Const(Const::Undef)) => Ok(Some(None)),
+            Some(v) => Err(type_error(md, format!("{what} must be a function: {v}"))),
+        }
+    };
+
+    let d = Descriptor {
+        enumerable: field("enumerable")?.map(|v| truthy(&v)),
+        configurable: field("configurable")?.map(|v| truthy(&v)),
+        value: field("value")?,
+        writable: field("writable")?.map(|v| truthy(&v)),
+        get: accessor("get", "Getter")?,
+        set: accessor("set", "Setter")?,
+    };
+
+    if d.is_accessor() && (d.value.is_some() || d.writable.is_some()) {
+        return Err(type_error(
+            md,
+            "Invalid property descriptor. Cannot both specify accessors and a value or writable attribute",
+        ));
+    }
+    Ok(d)
+}
+
+/* whether `d` asks to change the property `p`, which is only allowed while `p` is configurable */
+fn changes(p: &Property, d: &Descriptor) -> bool {
+    if d.configurable == Some(true) || d.enumerable.is_some_and(|e| e != p.enumerable) {
+        return true;
+    }
+
+    match &p.slot {
+        Slot::Data { value, writable } => {
+            d.is_accessor()
+                || (!writable
+                    && (d.writable == Some(true)
+                        || d.value
+                            .as_ref()
+                            .is_some_and(|v| !ops::strict_equals(v, value))))
+        }
+        Slot::Accessor { .. } => d.value.is_some() || d.writable.is_some() || d.is_accessor(),
+    }
+}
+
+/* ValidateAndApplyPropertyDescriptor */
+fn define(o: &Object, key: &str, d: Descriptor, md: &Module) -> Result<(), Object> {
+    /* array elements and `length` only take a value */
+    if let Object::Array(_) = o {
+        if key == "length" || index_key(key).is_some() {
+            return match d.value {
+                Some(v) => put_prop(o, key, v, md, md.call_span),
+                None => Ok(()),
+            };
+        }
+    }
+
+    let old = own_property(o, key);
+    if let Some(p) = &old {
+        if !p.configurable && changes(p, &d) {
+            return Err(type_error(md, format!("Cannot redefine property: {key}")));
+        }
+    }
+
+    let slot = match (d.is_accessor(), old.as_ref().map(|p| &p.slot)) {
+        (true, Some(Slot::Accessor { get, set })) => Slot::Accessor {
+            get: d.get.unwrap_or_else(|| get.clone()),
+            set: d.set.unwrap_or_else(|| set.clone()),
+        },
+        (true, _) => Slot::Accessor {
+            get: d.get.flatten(),
+            set: d.set.flatten(),
+        },
+        (false, Some(Slot::Data { value, writable })) => Slot::Data {
+            value: d.value.unwrap_or_else(|| value.clone()),
+            writable: d.writable.unwrap_or(*writable),
+        },
+        (false, _) => Slot::Data {
+            value: d.value.unwrap_or_else(Object::undef),
+            writable: d.writable.unwrap_or(false),
+        },
+    };
+    let p = Property {
+        slot,
+        enumerable: d
+            .enumerable
+            .unwrap_or_else(|| old.as_ref().is_some_and(|p| p.enumerable)),
+        configurable: d
+            .configurable
+            .unwrap_or_else(|| old.as_ref().is_some_and(|p| p.configurable)),
+    };
+
+    let added = with_props_mut(o, |props| {
+        let ok = props.contains(key) || props.extensible;
+        if ok {
+            props.define(key, p);
+        }
+        ok
+    });
+
+    match added {
+        Some(false) => Err(type_error(
+            md,
+            format!("Cannot define property {key}, object is not extensible"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/* the object `Object.getOwnPropertyDescriptor` hands out for `p` */
+fn from_descriptor(p: Property) -> Object {
+    let mut props = Props::ordinary();
+
+    match p.slot {
+        Slot::Data { value, writable } => {
+            props.set("value", value);
+            props.set("writable", bool(writable));
+        }
+        Slot::Accessor { get, set } => {
+            props.set("get", get.map_or_else(Object::undef, Object::Funct));
+            props.set("set", set.map_or_else(Object::undef, Object::Funct));
+        }
+    }
+    props.set("enumerable", bool(p.enumerable));
+    props.set("configurable", bool(p.configurable));
+
+    Object::obj(props)
+}
+
+fn define_property(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = arg(args, 0);
+    if !is_object(&o) {
+        return Err(type_error(md, "Object.defineProperty called on non-object"));
+    }
+
+    let key = string_of(&arg(args, 1), md)?;
+    let d = to_descriptor(&arg(args, 2), md)?;
+    define(&o, &key, d, md)?;
+
+    Ok(o)
+}
+
+/* defines every own enumerable property of `descs` on `o`, checking all descriptors first */
+fn define_all(o: &Object, descs: &Object, md: &Module) -> Result<(), Object> {
+    let mut all = Vec::new();
+    for k in own_keys(descs, false) {
+        let d = get_prop(descs, &k, md, md.call_span)?;
+        all.push((k, to_descriptor(&d, md)?));
+    }
+
+    for (k, d) in all {
+        define(o, &k, d, md)?;
+    }
+    Ok(())
+}
+
+fn define_properties(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = arg(args, 0);
+    if !is_object(&o) {
+        return Err(type_error(
+            md,
+            "Object.defineProperties called on non-object",
+        ));
+    }
+
+    define_all(&o, &object_arg(args, 1, md)?, md)?;
+    Ok(o)
+}
+
+fn get_own_property_descriptor(
+    _this: &Object,
+    args: &[Object],
+    md: &mut Module,
+) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+    let key = string_of(&arg(args, 1), md)?;
+
+    Ok(own_property(&o, &key).map_or_else(Object::undef, from_descriptor))
+}
+
+fn get_own_property_names(
+    _this: &Object,
+    args: &[Object],
+    md: &mut Module,
+) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+    Ok(Object::array(Array::from_values(
+        own_keys(&o, true).into_iter().map(Object::str).collect(),
+    )))
+}
+
+fn get_prototype_of(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+    Ok(proto_of(&o).unwrap_or(Object::Const(Const::Null)))
+}
+
+fn set_prototype_of(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+    let proto = proto_arg(args, 1, md)?;
+
+    if !is_object(&o) {
+        return Ok(o);
+    }
+    if let Some(p) = &proto {
+        if ops::strict_equals(p, &o) || inherits(p, &o) {
+            return Err(type_error(md, "Cyclic __proto__ value"));
+        }
+    }
+
+    let done = with_props_mut(&o, |props| {
+        let ok = props.extensible
+            || match (&props.proto, &proto) {
+                (None, None) => true,
+                (Some(a), Some(b)) => ops::strict_equals(a, b),
+                _ => false,
+            };
+        if ok {
+            props.proto = proto;
+        }
+        ok
+    });
+
+    match done {
+        Some(false) => Err(type_error(md, format!("{o} is not extensible"))),
+        _ => Ok(o),
+    }
+}
+
+fn freeze(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    let o = arg(args, 0);
+
+    match &o {
+        Object::Array(a) => a.borrow_mut().freeze(),
+        _ => {
+            with_props_mut(&o, Props::freeze);
+        }
+    }
+    Ok(o)
+}
+
+fn is_frozen(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    let o = arg(args, 0);
+
+    Ok(bool(match &o {
+        Object::Array(a) => {
+            let a = a.borrow();
+            a.frozen && a.props().is_frozen()
+        }
+        _ => with_props(&o, Props::is_frozen).unwrap_or(true),
+    }))
+}
+
+fn prevent_extensions(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    let o = arg(args, 0);
+    with_props_mut(&o, |p| p.extensible = false);
+    Ok(o)
+}
+
+fn is_extensible(_this: &Object, args: &[Object], _md: &mut Module) -> Result<Object, Object> {
+    Ok(bool(
+        with_props(&arg(args, 0), |p| p.extensible).unwrap_or(false),
+    ))
+}
+
+fn keys(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+    Ok(Object::array(Array::from_values(
+        own_keys(&o, false).into_iter().map(Object::str).collect(),
+    )))
+}
+
+fn values(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+
+    let mut v = Vec::new();
+    for k in own_keys(&o, false) {
+        v.push(get_prop(&o, &k, md, md.call_span)?);
+    }
+    Ok(Object::array(Array::from_values(v)))
+}
+
+fn entries(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let o = object_arg(args, 0, md)?;
+
+    let mut v = Vec::new();
+    for k in own_keys(&o, false) {
+        let val = get_prop(&o, &k, md, md.call_span)?;
+        v.push(Object::array(Array::from_values(vec![Object::str(k), val])));
+    }
+    Ok(Object::array(Array::from_values(v)))
+}
+
+/* `Object.assign(target, ...sources)`: copies through getters and setters */
+fn assign(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let target = object_arg(args, 0, md)?;
+
+    for src in args.iter().skip(1).filter(|s| !ops::is_nullish(s)) {
+        for k in own_keys(src, false) {
+            let v = get_prop(src, &k, md, md.call_span)?;
+            put_prop(&target, &k, v, md, md.call_span)?;
+        }
+    }
+    Ok(target)
+}
@@ -0,0 +1,519 @@
+use crate::ast::constants::{Const, JsStr, MAX_STR_LENGTH};
+
+use super::super::{
+    array::Array,
+    errors::{invalid_string_length, new_error},
+    function::NativeFn,
+    module::Module,
+    obj::{call_function, Object},
+    ops,
+};
+use super::{
+    arg, define_constructor, js_str_of, number_of, relative_index, statics, to_integer, type_error,
+    with_methods,
+};
+
+/* `String.prototype` */
+const METHODS: &[(&str, NativeFn)] = &[
+    ("charAt", char_at),
+    ("charCodeAt", char_code_at),
+    ("codePointAt", code_point_at),
+    ("at", at),
+    ("slice", slice),
+    ("substring", substring),
+    ("indexOf", index_of),
+    ("lastIndexOf", last_index_of),
+    ("includes", includes),
+    ("startsWith", starts_with),
+    ("endsWith", ends_with),
+    ("split", split),
+    ("replace", replace),
+    ("replaceAll", replace_all),
+    ("trim", trim),
+    ("trimStart", trim_start),
+    ("trimEnd", trim_end),
+    ("padStart", pad_start),
+    ("padEnd", pad_end),
+    ("repeat", repeat),
+    ("toUpperCase", to_upper_case),
+    ("toLowerCase", to_lower_case),
+    ("toString", value_of),
+    ("valueOf", value_of),
+];
+
+/* `String.fromCharCode` */
+const STATICS: &[(&str, NativeFn)] = &[("fromCharCode", from_char_code)];
+thread_local! {
+    /* the global `String` and `String.prototype`, which strings get their methods from */
+    static STRING: (Object, Object) = define_constructor(
+        "String",
+        string_ctor,
+        with_methods(METHODS),
+        statics(STATICS),
+    );
+}
+
+pub fn prototype() -> Object {
+    STRING.with(|(_, p)| p.clone())
+}
+
+/* `String` */
+pub fn constructor() -> Object {
+    STRING.with(|(c, _)| c.clone())
+}
+
+/* first index `>= from` where `needle` occurs in `hay`, in code units */
+fn find(hay: &[u16], needle: &[u16], from: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(from.min(hay.len()));
+    }
+
+    (from..=hay.len().saturating_sub(needle.len())).find(|i| hay[*i..].starts_with(needle))
+}
+
+/* last index `<= from` where `needle` occurs in `hay` */
+fn rfind(hay: &[u16], needle: &[u16], from: usize) -> Option<usize> {
+    if needle.len() > hay.len() {
+        return None;
+    }
+
+    (0..=from.min(hay.len() - needle.len()))
+        .rev()
+        .find(|i| hay[*i..].starts_with(needle))
+}
+
+/* `this` as a string; methods called on null or undefined throw */
+fn this_str(this: &Object, name: &str, md: &Module) -> Result<JsStr, Object> {
+    if ops::is_nullish(this) {
+        return Err(type_error(
+            md,
+            format!("String.prototype.{name} called on null or undefined"),
+        ));
+    }
+
+    js_str_of(this, md)
+}
+
+/* argument `i` as a string to search for; there are no regular expressions yet */
+fn search_arg(args: &[Object], i: usize, md: &Module) -> Result<Vec<u16>, Object> {
+    Ok(js_str_of(&arg(args, i), md)?.units().to_vec())
+}
+
+fn int(i: usize) -> Object {
+    ops::int(i as i64)
+}
+
+/* `String(v)` */
+pub fn string_ctor(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(Object::str(match args.first() {
+        None => JsStr::default(),
+        Some(v) => js_str_of(v, md)?,
+    }))
+}
+
+fn from_char_code(_this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let u = args
+        .iter()
+        .map(|c| Ok(ops::to_uint32(number_of(c, md)?) as u16))
+        .collect::<Result<Vec<u16>, Object>>()?;
+
+    Ok(Object::str(u))
+}
+
+fn value_of(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    match this {
+        Object::Const(Const::Str { .. }) => Ok(this.clone()),
+        _ => Err(type_error(
+            md,
+            "String.prototype.valueOf requires that 'this' be a String",
+        )),
+    }
+}
+
+/* the code unit at argument 0, if it is in range, and the one after it */
+fn unit_at(
+    this: &Object,
+    args: &[Object],
+    md: &Module,
+    name: &str,
+) -> Result<Option<(u16, Option<u16>)>, Object> {
+    let s = this_str(this, name, md)?;
+    let i = to_integer(&arg(args, 0), md)?;
+
+    if i < 0.0 || i >= s.len() as f64 {
+        return Ok(None);
+    }
+    let u = s.units();
+    Ok(Some((u[i as usize], u.get(i as usize + 1).copied())))
+}
+
+fn char_at(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(Object::str(match unit_at(this, args, md, "charAt")? {
+        Some((c, _)) => JsStr::from(vec![c]),
+        None => JsStr::default(),
+    }))
+}
+
+fn char_code_at(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(match unit_at(this, args, md, "charCodeAt")? {
+        Some((c, _)) => ops::int(c as i64),
+        None => ops::num(f64::NAN),
+    })
+}
+
+/* a surrogate pair starting at the index is combined into one code point */
+fn code_point_at(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(match unit_at(this, args, md, "codePointAt")? {
+        Some(pair) => match pair {
+            (hi @ 0xD800..=0xDBFF, Some(lo @ 0xDC00..=0xDFFF)) => {
+                let cp = 0x10000 + ((hi as i64 - 0xD800) << 10) + (lo as i64 - 0xDC00);
+                ops::int(cp)
+            }
+            (c, _) => ops::int(c as i64),
+        },
+        None => Object::undef(),
+    })
+}
+
+fn at(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "at", md)?;
+    let u = s.units();
+    let n = to_integer(&arg(args, 0), md)?;
+    let i = if n < 0.0 { u.len() as f64 + n } else { n };
+
+    if i < 0.0 || i >= u.len() as f64 {
+        return Ok(Object::undef());
+    }
+    Ok(Object::str(&u[i as usize..=i as usize]))
+}
+
+fn slice(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "slice", md)?;
+    let u = s.units();
+    let start = relative_index(&arg(args, 0), u.len(), 0, md)?;
+    let end = relative_index(&arg(args, 1), u.len(), u.len(), md)?.max(start);
+
+    Ok(Object::str(&u[start..end]))
+}
+
+/* unlike `slice`, negative positions count as 0 and the bounds may come in either order */
+fn substring(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "substring", md)?;
+    let u = s.units();
+    let clamp = |o: &Object, default: usize| match o {
+        Object::Const(Const::Undef) => Ok(default),
+        _ => position(o, u.len(), md),
+    };
+
+    let a = clamp(&arg(args, 0), 0)?;
+    let b = clamp(&arg(args, 1), u.len())?;
+
+    Ok(Object::str(&u[a.min(b)..a.max(b)]))
+}
+
+/* a position argument clamped to `0..=len` */
+fn position(o: &Object, len: usize, md: &Module) -> Result<usize, Object> {
+    Ok(to_integer(o, md)?.clamp(0.0, len as f64) as usize)
+}
+
+fn index_of(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "indexOf", md)?;
+    let u = s.units();
+    let from = position(&arg(args, 1), u.len(), md)?;
+
+    Ok(find(&u, &search_arg(args, 0, md)?, from).map_or(ops::int(-1), int))
+}
+
+fn last_index_of(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "lastIndexOf", md)?;
+    let u = s.units();
+    let n = number_of(&arg(args, 1), md)?;
+    let from = if n.is_nan() {
+        u.len()
+    } else {
+        n.clamp(0.0, u.len() as f64) as usize
+    };
+
+    Ok(rfind(&u, &search_arg(args, 0, md)?, from).map_or(ops::int(-1), int))
+}
+
+fn includes(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "includes", md)?;
+    let u = s.units();
+    let from = position(&arg(args, 1), u.len(), md)?;
+
+    Ok(ops::boolean(
+        find(&u, &search_arg(args, 0, md)?, from).is_some(),
+    ))
+}
+
+fn starts_with(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "startsWith", md)?;
+    let u = s.units();
+    let from = position(&arg(args, 1), u.len(), md)?;
+
+    Ok(ops::boolean(
+        u[from..].starts_with(&search_arg(args, 0, md)?),
+    ))
+}
+
+fn ends_with(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "endsWith", md)?;
+    let u = s.units();
+    let end = match arg(args, 1) {
+        Object::Const(Const::Undef) => u.len(),
+        e => position(&e, u.len(), md)?,
+    };
+
+    Ok(ops::boolean(u[..end].ends_with(&search_arg(args, 0, md)?)))
+}
+
+fn split(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "split", md)?;
+    let limit = match arg(args, 1) {
+        Object::Const(Const::Undef) => u32::MAX,
+        l => ops::to_uint32(number_of(&l, md)?),
+    } as usize;
+
+    let parts: Vec<JsStr> = match arg(args, 0) {
+        Object::Const(Const::Undef) => vec![s],
+        sep => {
+            let u = s.units();
+            let sep = js_str_of(&sep, md)?;
+            let sep = sep.units();
+
+            if sep.is_empty() {
+                u.iter().map(|c| JsStr::from(vec![*c])).collect()
+            } else {
+                let mut parts = Vec::new();
+                let mut start = 0;
+                while let Some(i) = find(&u, &sep, start) {
+                    parts.push(JsStr::from(&u[start..i]));
+                    start = i + sep.len();
+                }
+                parts.push(JsStr::from(&u[start..]));
+                parts
+            }
+        }
+    };
+
+    Ok(Object::array(Array::from_values(
+        parts.into_iter().take(limit).map(Object::str).collect(),
+    )))
+}
+
+/**
+ * GetSubstitution: the `$$`, `$&`, `` $` `` and `$'` patterns of a replacement string.
+ * `pos` is where the match `m` starts in `s`.
+ */
+fn substitute(repl: &[u16], s: &[u16], m: &[u16], pos: usize) -> Vec<u16> {
+    let mut out = Vec::new();
+    let mut units = repl.iter().copied().peekable();
+    let unit = |c: char| c as u16;
+
+    while let Some(c) = units.next() {
+        if c != unit('$') {
+            out.push(c);
+            continue;
+        }
+
+        match units.peek().map(|c| char::from_u32(*c as u32)) {
+            Some(Some('$')) => out.push(c),
+            Some(Some('&')) => out.extend_from_slice(m),
+            Some(Some('`')) => out.extend_from_slice(&s[..pos]),
+            Some(Some('\'')) => out.extend_from_slice(&s[(pos + m.len()).min(s.len())..]),
+            _ => {
+                out.push(c);
+                continue;
+            }
+        }
+        units.next();
+    }
+
+    out
+}
+
+/* replaces the first match of the search string, or every match when `all` is set */
+fn replace_impl(
+    this: &Object,
+    args: &[Object],
+    md: &Module,
+    name: &str,
+    all: bool,
+) -> Result<Object, Object> {
+    let s = this_str(this, name, md)?;
+    let s = s.units();
+    let pat = search_arg(args, 0, md)?;
+    let repl = arg(args, 1);
+
+    let mut positions = Vec::new();
+    let mut from = 0;
+    while let Some(i) = find(&s, &pat, from) {
+        positions.push(i);
+        if !all || i >= s.len() {
+            break;
+        }
+        /* an empty pattern matches between every pair of code units */
+        from = i + pat.len().max(1);
+    }
+
+    let mut out = Vec::new();
+    let mut last = 0;
+    for i in positions {
+        out.extend_from_slice(&s[last..i]);
+
+        let m = &s[i..i + pat.len()];
+        match &repl {
+            Object::Funct(f) => {
+                let r = call_function(
+                    f,
+                    Object::undef(),
+                    vec![Object::str(m), int(i), Object::str(&s[..])],
+                    md,
+                    md.call_span,
+                )?;
+                out.extend_from_slice(&js_str_of(&r, md)?.units());
+            }
+            r => out.extend(substitute(&js_str_of(r, md)?.units(), &s, m, i)),
+        }
+
+        last = i + pat.len();
+    }
+    out.extend_from_slice(&s[last..]);
+
+    Ok(Object::str(out))
+}
+
+fn replace(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    replace_impl(this, args, md, "replace", false)
+}
+
+fn replace_all(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    replace_impl(this, args, md, "replaceAll", true)
+}
+
+/* WhiteSpace and LineTerminator; Rust's `char::is_whitespace` lacks the BOM */
+pub fn is_js_space(c: char) -> bool {
+    c.is_whitespace() || c == '\u{FEFF}'
+}
+
+/* `s` without the white space at its start and/or end; all of it is in the BMP */
+fn trimmed(s: &JsStr, start: bool, end: bool) -> Object {
+    let space = |u: &u16| char::from_u32(*u as u32).is_some_and(is_js_space);
+    let u = s.units();
+    let (mut a, mut b) = (0, u.len());
+
+    while start && a < b && space(&u[a]) {
+        a += 1;
+    }
+    while end && a < b && space(&u[b - 1]) {
+        b -= 1;
+    }
+
+    Object::str(&u[a..b])
+}
+
+fn trim(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(trimmed(&this_str(this, "trim", md)?, true, true))
+}
+
+fn trim_start(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(trimmed(&this_str(this, "trimStart", md)?, true, false))
+}
+
+fn trim_end(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(trimmed(&this_str(this, "trimEnd", md)?, false, true))
+}
+
+/* the filler `padStart`/`padEnd` add to reach the target length, in code units */
+fn padding(s: &[u16], args: &[Object], md: &Module) -> Result<Vec<u16>, Object> {
+    let target = to_integer(&arg(args, 0), md)?;
+    let filler = match arg(args, 1) {
+        Object::Const(Const::Undef) => vec![b' ' as u16],
+        f => js_str_of(&f, md)?.units().to_vec(),
+    };
+
+    if target <= s.len() as f64 || filler.is_empty() {
+        return Ok(Vec::new());
+    }
+    if target > MAX_STR_LENGTH as f64 {
+        return Err(invalid_string_length(md.call_span));
+    }
+
+    Ok(filler
+        .iter()
+        .copied()
+        .cycle()
+        .take(target as usize - s.len())
+        .collect())
+}
+
+fn pad_start(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "padStart", md)?;
+    let mut out = padding(&s.units(), args, md)?;
+    out.extend_from_slice(&s.units());
+
+    Ok(Object::str(out))
+}
+
+fn pad_end(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "padEnd", md)?;
+    let mut out = s.units().to_vec();
+    out.extend(padding(&s.units(), args, md)?);
+
+    Ok(Object::str(out))
+}
+
+fn repeat(this: &Object, args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    let s = this_str(this, "repeat", md)?;
+    let n = to_integer(&arg(args, 0), md)?;
+
+    if n < 0.0 || n.is_infinite() {
+        return Err(new_error(
+            "RangeError",
+            format!("Invalid count value: {}", arg(args, 0)),
+            md.call_span,
+        ));
+    }
+
+    if !s.is_empty() && n * s.len() as f64 > MAX_STR_LENGTH as f64 {
+        return Err(invalid_string_length(md.call_span));
+    }
+
+    let out = s.units().repeat(n as usize);
+    Ok(Object::str(out))
+}
+
+/* `s` with each run of well-formed text mapped by `f`; a lone surrogate stays as it is */
+fn map_text(s: &JsStr, f: impl Fn(&str) -> String) -> Object {
+    let mut out = Vec::with_capacity(s.len());
+    let mut run = String::new();
+
+    for c in char::decode_utf16(s.units().iter().copied()) {
+        match c {
+            Ok(c) => run.push(c),
+            Err(e) => {
+                out.extend(f(&run).encode_utf16());
+                run.clear();
+                out.push(e.unpaired_surrogate());
+            }
+        }
+    }
+    out.extend(f(&run).encode_utf16());
+
+    Object::str(out)
+}
+
+fn to_upper_case(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(map_text(
+        &this_str(this, "toUpperCase", md)?,
+        str::to_uppercase,
+    ))
+}
+
+fn to_lower_case(this: &Object, _args: &[Object], md: &mut Module) -> Result<Object, Object> {
+    Ok(map_text(
+        &this_str(this, "toLowerCase", md)?,
+        str::to_lowercase,
+    ))
+}
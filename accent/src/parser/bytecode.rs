@@ -0,0 +1,291 @@
+/*
+ * The bytecode the VM runs.
+ *
+ * A `Code` is one compiled function body or script: a flat list of instructions working on
+ * a stack of values, with jumps as indices into that list. A name that no function or class
+ * nested in the body refers to is kept in a register of the running code; the others live in
+ * the slots of scopes, which closures keep alive. Names declared nowhere the compiler can see
+ * are reached by name.
+ */
+
+use std::rc::Rc;
+
+use crate::ast::{EOperator, MethodKind, TemplateSite};
+use crate::token::Span;
+
+use super::{classes::ClassDef, env::Layout, function::Proto, obj::Object};
+
+/* where a variable lives */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum At {
+    /* register i of the running code */
+    Reg(usize),
+    /* slot `slot` of the scope `hops` levels out from the running one */
+    Slot { hops: usize, slot: usize },
+    /* nowhere the compiler can see, like the globals */
+    Name,
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: String,
+    pub at: At,
+    /* a `const` there, which assignments throw on */
+    pub is_const: bool,
+}
+
+/* an operand that an instruction reads itself instead of taking it off the stack */
+#[derive(Debug, Clone, Copy)]
+pub enum Arg {
+    Stack,
+    /* register i */
+    Reg(u32),
+    /* consts[i] */
+    Const(u32),
+}
+
+/**
+ * An instruction. Operands are indices into the tables of the `Code`, except for
+ * jump targets, which are instruction indices, and counts.
+ * The comments show the stack before and after, top on the right.
+ */
+#[derive(Debug, Clone)]
+pub enum Op {
+    /* -- consts[i] */
+    Const(u32),
+    /* -- undefined */
+    Undef,
+    /* v -- */
+    Pop,
+    /* v -- v v */
+    Dup,
+    /* a b -- a b a b */
+    Dup2,
+    /* a b -- b a */
+    Swap,
+    /* a1..an v -- v */
+    Nip(u32),
+
+    /* -- v, for register i; one not declared yet reads the name from the scopes around */
+    GetReg(u32),
+    /* v -- ; again by name when the register is not declared yet */
+    SetReg(u32),
+    /* v -- ; declares the name in register i */
+    InitReg(u32),
+    /* undeclares `count` registers from `from` on, for the names of a block being entered */
+    ClearRegs {
+        from: u32,
+        count: u32,
+    },
+    /* v -- ; declares the variable in slot i of the running scope */
+    Declare(u32),
+    /* v -- ; declares vars[i], a `var` in a block, in the scope around the block that has it */
+    DeclareVar(u32),
+    /* -- v, for vars[i] */
+    Get(u32),
+    /* v -- */
+    Set(u32),
+    /* -- this; undefined outside of functions */
+    This(u32),
+    /* -- typeof vars[i], which is "undefined" for an undeclared variable */
+    TypeofVar(u32),
+    /* -- old or new; `++` or `--` on vars[i] */
+    UpdateVar {
+        var: u32,
+        op: EOperator,
+        prefix: bool,
+    },
+    /* `++` or `--` on vars[i] whose value is not used */
+    StepVar {
+        var: u32,
+        op: EOperator,
+    },
+    /* runs what follows in a new scope for blocks[i], until `PopScope` */
+    PushScope(u32),
+    PopScope,
+    /* moves a `for (let ...)` loop on to the bindings of its next iteration */
+    NextIteration,
+
+    /* v -- op v */
+    Unary(EOperator),
+    /* v -- typeof v */
+    Typeof,
+    /* l r -- l op r, for the operands that are on the stack */
+    Binary {
+        op: EOperator,
+        l: Arg,
+        r: Arg,
+    },
+    /* key obj -- key in obj */
+    In,
+    /* l r -- l instanceof r */
+    Instanceof,
+    /* v -- key; numbers that can be array indices stay numbers */
+    ToKey,
+    /* v -- String(v) */
+    ToStr,
+    /* s1..sn -- s1 + .. + sn, for strings */
+    Concat(u32),
+
+    Jump(u32),
+    /* v -- */
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    /* l r -- ; jumps unless `l op r`, with the operands of `Binary` */
+    JumpUnless {
+        op: EOperator,
+        l: Arg,
+        r: Arg,
+        to: u32,
+    },
+    /* l r -- ; jumps when `l op r`, for the test at the bottom of a loop */
+    JumpIf {
+        op: EOperator,
+        l: Arg,
+        r: Arg,
+        to: u32,
+    },
+    /* v -- v, jumping when `v` decides `&&`, `||` or `??`, and otherwise v -- */
+    And(u32),
+    Or(u32),
+    Nullish(u32),
+
+    /* obj -- obj.names[i] */
+    GetProp(u32),
+    /* obj v -- v */
+    SetProp(u32),
+    /* obj key -- obj[key] */
+    GetIndex,
+    /* obj key v -- v */
+    SetIndex,
+    /* obj -- old or new */
+    UpdateProp {
+        name: u32,
+        op: EOperator,
+        prefix: bool,
+    },
+    /* obj key -- old or new */
+    UpdateIndex {
+        op: EOperator,
+        prefix: bool,
+    },
+    /* obj key -- true, deleting `obj[key]` */
+    Delete,
+    /* obj -- obj.#name, with names[i] keeping its `#` */
+    GetPrivate(u32),
+    /* obj v -- v */
+    SetPrivate(u32),
+    /* obj -- old or new */
+    UpdatePrivate {
+        name: u32,
+        op: EOperator,
+        prefix: bool,
+    },
+    /* obj -- #name in obj */
+    HasPrivate(u32),
+    /* this key -- super[key], read with `this` as the receiver */
+    GetSuper,
+
+    /* this f a1..an -- result; names[desc] names `f` in errors */
+    Call {
+        argc: u32,
+        desc: u32,
+    },
+    /* c a1..an -- new c(a1..an) */
+    New {
+        argc: u32,
+        desc: u32,
+    },
+    /* this f args -- result, for argument lists with spreads, which are built as an array; names[i] names `f` */
+    CallArgs(u32),
+    /* c args -- new c(...args) */
+    NewArgs(u32),
+    /* this args -- this, calling the constructor of the parent class; the result may be another object to use as `this` */
+    SuperCall,
+    /* -- a function made from protos[i] */
+    Closure(u32),
+    /* a function whose name is bound in a scope of its own */
+    NamedClosure(u32),
+    /* v1..vn -- [v1..vn] */
+    Array(u32),
+    /* arr v -- arr, with `v` added at the end */
+    Append,
+    /* arr v -- arr, with the items of `v` added; names[i] describes `v` for errors */
+    AppendSpread(u32),
+    /* arr -- arr, one longer */
+    AppendHole,
+    /* -- the strings of templates[i], for a tag */
+    Strings(u32),
+    /* -- {} */
+    NewObject,
+    /* obj v -- obj */
+    InitProp(u32),
+    /* obj key v -- obj */
+    InitIndex,
+    /* obj key f -- obj, with `f` as a getter or setter */
+    InitAccessor(MethodKind),
+    /* obj v -- obj, with the own enumerable properties of `v` copied in */
+    InitSpread,
+    /* obj v -- obj, with `v` as its prototype when it is an object or null */
+    InitProto,
+    /* [parent] k1..kn -- the class classes[i] defines, taking parent when it `extends` and the names of its n computed members */
+    Class {
+        class: u32,
+        extends: bool,
+    },
+    /* v -- v, or -- when it is nullish, dropping `pop` values in all and jumping */
+    JumpIfNullish {
+        to: u32,
+        pop: u32,
+    },
+
+    /* v -- items 0, for the array `for...of` runs over; names[i] describes `v` for errors */
+    ForOf(u32),
+    /* v -- keys 0, for the array of keys `for...in` visits */
+    ForIn,
+    /* items i -- items i+1, or jumps when `i` is past the end of `items` */
+    Next(u32),
+    /* items i a1..an -- items i a1..an items[i-1], the item `Next` moved past, or undefined once gone */
+    Item(u32),
+
+    /* v -- */
+    Throw,
+    /* exceptions thrown until the matching `LeaveTry` go to the instruction given, on top of the stack */
+    EnterTry(u32),
+    LeaveTry,
+    /* v -- */
+    Return,
+    /* the end of the body, which completes normally */
+    End,
+}
+
+/* a compiled function body or script */
+#[derive(Debug, Default)]
+pub struct Code {
+    pub ops: Vec<Op>,
+    /* the source of each instruction, for errors */
+    pub spans: Vec<Span>,
+    pub consts: Vec<Object>,
+    /* property names and callee descriptions */
+    pub names: Vec<String>,
+    pub vars: Vec<Var>,
+    pub protos: Vec<Rc<Proto>>,
+    pub classes: Vec<ClassDef>,
+    /* the strings of each tagged template */
+    pub templates: Vec<Rc<TemplateSite>>,
+    /* the name each register holds */
+    pub registers: Vec<String>,
+    /**
+     * The names the body declares that closures see, which its scope has slots for.
+     * A function body with none runs in the scope of its closure.
+     */
+    pub layout: Rc<Layout>,
+    /* the same for each block with declarations of its own that closures see */
+    pub blocks: Vec<Rc<Layout>>,
+    /* where `this` goes, which arrows and scripts do not bind */
+    pub this: Option<At>,
+    /* where the `arguments` object goes, for a function that refers to it */
+    pub arguments: Option<At>,
+    /* where each parameter goes; `None` for patterns, which are not bound */
+    pub params: Vec<Option<At>>,
+}
@@ -1,22 +1,933 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{ast::Stmt, Module, Object};
+use crate::ast::{
+    constants::Const, ClassMember, Expr, ExprKind, FuncForm, MethodKind, PropKey, Stmt, StmtKind,
+};
+use crate::token::Span;
 
-use super::function::Function;
+use super::{
+    builtins,
+    env::{Env, Layout},
+    errors::{new_error, ERROR_TYPES},
+    function::{FnKind, Function, Proto},
+    heap::{self, Handle, Node, Trace, Tracer},
+    module::Module,
+    obj::{
+        call_function, construct, find_property, is_constructor, is_object, read_slot,
+        with_props_mut, Object,
+    },
+    props::{Property, Props, Slot},
+};
+
+/**
+ * A class body ready to be evaluated: every method, field initializer and static block is
+ * a function literal, which runs with `this` bound to the instance or the class. The
+ * compiler makes one for each class in the code it compiles, the tree-walker one each time
+ * it evaluates a class.
+ */
+#[derive(Debug)]
+pub struct ClassDef {
+    pub name: String,
+    /* where the expression after `extends` is, when there is one */
+    pub extends: Option<Span>,
+    /* the names the scope of the body binds: the class's own name, `class` and every `#name` */
+    pub scope: Rc<Layout>,
+    pub members: Vec<Member>,
+    /* the members whose names are computed, which are empty until `define_class` gets their keys */
+    pub computed: Vec<usize>,
+}
 
 #[derive(Debug, Clone)]
-pub enum ClassD {
-    Native {
+pub enum Member {
+    Method {
         name: String,
+        kind: MethodKind,
+        is_static: bool,
+        f: Rc<Proto>,
     },
-    Coded {
+    /* `init` returns the initial value */
+    Field {
         name: String,
-        vars: HashMap<String, Object>,
+        is_static: bool,
+        init: Option<Rc<Proto>>,
     },
+    StaticBlock(Rc<Proto>),
 }
 
+impl ClassDef {
+    /* `scopes` are the layouts of the scopes around the class, as in `Proto::scopes` */
+    pub fn new(
+        name: &str,
+        extends: Option<Span>,
+        members: &[ClassMember],
+        scopes: &[Rc<Layout>],
+    ) -> ClassDef {
+        let mut scope = Layout::new();
+        if !name.is_empty() {
+            scope.add(name);
+        }
+        scope.add("class");
+        for m in members {
+            match m {
+                ClassMember::Method {
+                    key: PropKey::Named(name),
+                    ..
+                }
+                | ClassMember::Field {
+                    key: PropKey::Named(name),
+                    ..
+                } if name.starts_with('#') => {
+                    scope.add(name);
+                }
+                _ => (),
+            }
+        }
+        let scope = Rc::new(scope);
+
+        let mut around = vec![scope.clone()];
+        around.extend(scopes.iter().cloned());
+        let method = |name: &str, body: Rc<[Stmt]>| {
+            Proto::in_scopes(name, &Rc::from([]), &body, FuncForm::Method, around.clone())
+        };
+
+        let mut computed = Vec::new();
+        let mut name_of = |i: usize, key: &PropKey| match key {
+            PropKey::Named(n) => n.clone(),
+            PropKey::Computed(_) => {
+                computed.push(i);
+                String::new()
+            }
+        };
+
+        let members = members
+            .iter()
+            .enumerate()
+            .map(|(i, m)| match m {
+                ClassMember::Method {
+                    key,
+                    kind,
+                    is_static,
+                    args,
+                    body,
+                } => {
+                    let name = name_of(i, key);
+
+                    /* a derived constructor gives back its `this`, which `super(...)` may have replaced */
+                    let body = match extends {
+                        Some(span) if name == "constructor" && !is_static => {
+                            let this = Expr::new(ExprKind::This, span);
+                            let ret = Stmt::new(StmtKind::ReturnStmt(this), span);
+                            body.iter().cloned().chain([ret]).collect()
+                        }
+                        _ => body.clone(),
+                    };
+
+                    Member::Method {
+                        f: Proto::in_scopes(&name, args, &body, FuncForm::Method, around.clone()),
+                        name,
+                        kind: *kind,
+                        is_static: *is_static,
+                    }
+                }
+                ClassMember::Field {
+                    key,
+                    is_static,
+                    val,
+                } => {
+                    let name = name_of(i, key);
+
+                    Member::Field {
+                        init: val.as_ref().map(|v| {
+                            let ret = Stmt::new(StmtKind::ReturnStmt(v.clone()), v.span);
+                            method(&name, Rc::from([ret]))
+                        }),
+                        name,
+                        is_static: *is_static,
+                    }
+                }
+                ClassMember::StaticBlock(body) => {
+                    Member::StaticBlock(method("static", Rc::from(body.as_slice())))
+                }
+            })
+            .collect();
+
+        ClassDef {
+            name: name.to_string(),
+            extends,
+            scope,
+            members,
+            computed,
+        }
+    }
+
+    /* the class with its computed member names filled in from `keys`, in order */
+    fn with_keys(&self, keys: Vec<String>) -> ClassDef {
+        let mut members = self.members.clone();
+        for (&i, key) in self.computed.iter().zip(keys) {
+            match &mut members[i] {
+                Member::Method { name, .. } | Member::Field { name, .. } => *name = key,
+                Member::StaticBlock(_) => unreachable!("static blocks have no name"),
+            }
+        }
+
+        ClassDef {
+            name: self.name.clone(),
+            extends: self.extends,
+            scope: self.scope.clone(),
+            members,
+            computed: Vec::new(),
+        }
+    }
+}
+
+/**
+ * A class declared in script.
+ * Its public members are ordinary properties: methods and accessors of `prototype`,
+ * which instances inherit, and static members of `statics`, which inherits from the parent class.
+ */
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    /* what comes after `extends` */
+    pub parent: Option<Heritage>,
+    pub ctor: Option<Function>,
+    /* `C.prototype` */
+    pub prototype: Handle<Props>,
+    /* the class's own properties: `name`, `prototype`, static members and whatever is assigned to it */
+    pub statics: Handle<Props>,
+    /* `#name` methods and accessors of instances */
+    pub private: HashMap<String, Slot>,
+    /* `static #name` fields, methods and accessors */
+    pub static_private: RefCell<HashMap<String, Slot>>,
+    /* set on every new instance, in declaration order, before the constructor body runs; the functions give their initial values */
+    pub fields: Vec<(String, Option<Function>)>,
+    /* scope of the class body, which methods and field initializers run in */
+    pub env: Env,
+}
+
+/* what a class extends */
 #[derive(Debug, Clone)]
+pub enum Heritage {
+    Class(ClassD),
+    /* a constructor function: a plain coded function, or a native one such as `Array` */
+    Function(Function),
+    /* `extends null`: instances inherit from nothing, and there is no constructor to call */
+    Null,
+}
+
+impl Heritage {
+    fn trace(&self, t: &mut Tracer) {
+        match self {
+            Heritage::Class(cd) => cd.trace(t),
+            Heritage::Function(f) => f.trace(t),
+            Heritage::Null => (),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ClassD {
+    Native { name: String },
+    Coded(Rc<Class>),
+}
+
+impl ClassD {
+    pub fn name(&self) -> &str {
+        match self {
+            ClassD::Native { name } => name,
+            ClassD::Coded(c) => &c.name,
+        }
+    }
+
+    pub fn trace(&self, t: &mut Tracer) {
+        if let ClassD::Coded(c) = self {
+            t.rc(c);
+        }
+    }
+
+    /* whether both are the same class; there is one native class of each name */
+    pub fn same(a: &ClassD, b: &ClassD) -> bool {
+        match (a, b) {
+            (ClassD::Coded(x), ClassD::Coded(y)) => Rc::ptr_eq(x, y),
+            (ClassD::Native { name: x }, ClassD::Native { name: y }) => x == y,
+            _ => false,
+        }
+    }
+}
+
+/* classes are shared like heap objects, but only their static private members can change */
+impl Node for Class {
+    fn trace_node(&self, t: &mut Tracer) -> bool {
+        let Ok(static_private) = self.static_private.try_borrow() else {
+            return false;
+        };
+
+        if let Some(p) = &self.parent {
+            p.trace(t);
+        }
+        if let Some(f) = &self.ctor {
+            f.trace(t);
+        }
+        t.edge(&self.prototype);
+        t.edge(&self.statics);
+        for s in self.private.values().chain(static_private.values()) {
+            s.trace(t);
+        }
+        self.env.trace(t);
+
+        true
+    }
+
+    fn clear_node(&self) {
+        if let Ok(mut m) = self.static_private.try_borrow_mut() {
+            m.clear();
+        }
+    }
+}
+
+/* an instance; `class` is `None` for the native errors */
+#[derive(Debug)]
 pub struct ClassO {
     pub name: String,
-    pub vars: HashMap<String, Object>,
+    pub class: Option<Rc<Class>>,
+    pub props: Props,
+    /* `#name` fields, with the class that declared them: a subclass may reuse the name */
+    pub private: Vec<(Rc<Class>, String, Object)>,
+    /* `false` until the constructor at the base of the chain runs, which in a derived class `super(...)` leads to */
+    pub initialized: bool,
+}
+
+impl Trace for ClassO {
+    fn trace(&self, t: &mut Tracer) {
+        if let Some(c) = &self.class {
+            t.rc(c);
+        }
+        self.props.trace(t);
+        for (c, _, v) in &self.private {
+            t.rc(c);
+            v.trace(t);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.class = None;
+        self.props.clear();
+        self.private.clear();
+    }
+}
+
+/* private fields can chain instances as well as properties can */
+impl Drop for ClassO {
+    fn drop(&mut self) {
+        if !self.private.is_empty() {
+            heap::drop_contents(std::mem::take(&mut self.private));
+        }
+    }
+}
+
+impl ClassO {
+    /* whether `c` or a class extending it made this instance, which private methods check */
+    pub fn is_a(&self, c: &Rc<Class>) -> bool {
+        let mut cur = self.class.clone();
+
+        while let Some(k) = cur {
+            if Rc::ptr_eq(&k, c) {
+                return true;
+            }
+            cur = match &k.parent {
+                Some(Heritage::Class(ClassD::Coded(p))) => Some(p.clone()),
+                _ => None,
+            };
+        }
+
+        false
+    }
+
+    /* `instanceof` a native class, which script classes can extend too */
+    pub fn is_native(&self, name: &str) -> bool {
+        let mut own = self.name.as_str();
+        let mut cur = self.class.clone();
+
+        while let Some(k) = cur {
+            cur = match &k.parent {
+                Some(Heritage::Class(ClassD::Coded(p))) => Some(p.clone()),
+                Some(Heritage::Class(ClassD::Native { name })) => {
+                    own = ERROR_TYPES
+                        .iter()
+                        .find(|e| *e == name)
+                        .copied()
+                        .unwrap_or_default();
+                    None
+                }
+                _ => {
+                    own = "";
+                    None
+                }
+            };
+        }
+
+        own == name || (name == "Error" && ERROR_TYPES.contains(&own))
+    }
+
+    pub fn private_field(&self, c: &Rc<Class>, name: &str) -> Option<&Object> {
+        self.private
+            .iter()
+            .find(|(k, n, _)| Rc::ptr_eq(k, c) && n == name)
+            .map(|(_, _, v)| v)
+    }
+
+    pub fn private_field_mut(&mut self, c: &Rc<Class>, name: &str) -> Option<&mut Object> {
+        self.private
+            .iter_mut()
+            .find(|(k, n, _)| Rc::ptr_eq(k, c) && n == name)
+            .map(|(_, _, v)| v)
+    }
+}
+
+/**
+ * Runs the constructor of `cd` on the already created `this`, for `new` and for `super(args)`.
+ * A class without a constructor passes its arguments on to the class it extends.
+ * Returns the object the constructor returned instead of `this`, if any.
+ */
+pub fn initialize(
+    cd: &ClassD,
+    this: &Object,
+    args: Vec<Object>,
+    md: &Module,
+    span: Span,
+) -> Result<Option<Object>, Object> {
+    let c = match cd {
+        ClassD::Coded(c) => c,
+        /* `super(msg)` in a class extending a native error gives `this` its message and stack */
+        ClassD::Native { .. } => {
+            if let (Object::ClassObj(e), Object::ClassObj(o)) =
+                (construct(cd, args, md, span)?, this)
+            {
+                let e = e.borrow();
+                let mut o = o.borrow_mut();
+                for k in e.props.keys() {
+                    if let Some(p) = e.props.property(&k) {
+                        o.props.define(&k, p.clone());
+                    }
+                }
+                o.initialized = true;
+            }
+            return Ok(None);
+        }
+    };
+
+    if c.parent.is_none() {
+        if let Object::ClassObj(o) = this {
+            o.borrow_mut().initialized = true;
+        }
+    }
+
+    let Some(ctor) = &c.ctor else {
+        let replaced = match &c.parent {
+            Some(p) => init_parent(p, this, args, md, span)?,
+            None => None,
+        };
+        init_fields(c, replaced.as_ref().unwrap_or(this), md)?;
+        return Ok(replaced);
+    };
+
+    /* fields are set up first, so the constructor can already use them; in a class that
+     * extends another, that happens when its `super(...)` call returns */
+    if c.parent.is_none() {
+        init_fields(c, this, md)?;
+    }
+
+    match call_function(ctor, this.clone(), args, md, span)? {
+        /* a derived constructor must have called `super(...)` unless it returns an object */
+        Object::Const(_) => checked_this(this.clone(), span).map(|_| None),
+        r => Ok(Some(r)),
+    }
+}
+
+/**
+ * `super(args)`, or the call a derived class without a constructor makes in its place.
+ * Returns the object the parent's constructor returned instead of `this`, if any.
+ */
+pub fn init_parent(
+    p: &Heritage,
+    this: &Object,
+    args: Vec<Object>,
+    md: &Module,
+    span: Span,
+) -> Result<Option<Object>, Object> {
+    match p {
+        Heritage::Class(cd) => return initialize(cd, this, args, md, span),
+        Heritage::Null => {
+            return Err(new_error(
+                "TypeError",
+                "Super constructor null of anonymous class is not a constructor",
+                span,
+            ))
+        }
+        Heritage::Function(_) => (),
+    }
+
+    /* a function at the base of the chain runs with `this` usable, as a base class does */
+    if let Object::ClassObj(o) = this {
+        o.borrow_mut().initialized = true;
+    }
+
+    if let Heritage::Function(f) = p {
+        match &f.kind {
+            /* a subclass of `Array` already made an array for `this`, which gets the elements */
+            FnKind::Native { .. } => {
+                let r = call_function(f, Object::undef(), args, md, span)?;
+                if let (Object::Array(a), Object::Array(r)) = (this, r) {
+                    a.borrow_mut().copy_elems(&r.borrow());
+                }
+            }
+            FnKind::Coded { .. } => {
+                let r = call_function(f, this.clone(), args, md, span)?;
+                if is_object(&r) {
+                    return Ok(Some(r));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/* the native constructor at the end of the chain of classes `c` extends, if any */
+pub fn native_base(c: &Rc<Class>) -> Option<Function> {
+    match &c.parent {
+        Some(Heritage::Class(ClassD::Coded(p))) => native_base(p),
+        Some(Heritage::Function(f)) if matches!(f.kind, FnKind::Native { .. }) => Some(f.clone()),
+        _ => None,
+    }
+}
+
+/* `this`, which a derived constructor cannot use before its `super(...)` call has returned */
+pub fn checked_this(this: Object, span: Span) -> Result<Object, Object> {
+    match &this {
+        Object::ClassObj(o) if !o.borrow().initialized => Err(new_error(
+            "ReferenceError",
+            "Must call super constructor in derived class before accessing 'this' or returning from derived constructor",
+            span,
+        )),
+        _ => Ok(this),
+    }
+}
+
+/* defines the fields `c` declares on `this`, in declaration order */
+pub fn init_fields(c: &Rc<Class>, this: &Object, md: &Module) -> Result<(), Object> {
+    for (name, init) in &c.fields {
+        let v = match init {
+            Some(f) => call_function(f, this.clone(), Vec::new(), md, md.call_span)?,
+            None => Object::undef(),
+        };
+
+        match this {
+            Object::ClassObj(o) => {
+                let mut o = o.borrow_mut();
+                if name.starts_with('#') {
+                    o.private.push((c.clone(), name.clone(), v));
+                } else {
+                    o.props.define(name, Property::data(v));
+                }
+            }
+            /* instances of a subclass of `Array` are arrays, which have no private fields */
+            _ if name.starts_with('#') => {
+                return Err(new_error(
+                    "TypeError",
+                    format!("Cannot define private field {name} on {this}"),
+                    md.call_span,
+                ))
+            }
+            _ => {
+                with_props_mut(this, |p| p.define(name, Property::data(v)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/* `slot` with the method `f` added to it; a getter and a setter make up one accessor */
+pub fn with_method(slot: Option<Slot>, kind: MethodKind, f: Function) -> Slot {
+    let (mut get, mut set) = match slot {
+        Some(Slot::Accessor { get, set }) => (get, set),
+        _ => (None, None),
+    };
+
+    match kind {
+        MethodKind::Method => {
+            return Slot::Data {
+                value: Object::Funct(f),
+                writable: true,
+            }
+        }
+        MethodKind::Get => get = Some(f),
+        MethodKind::Set => set = Some(f),
+    }
+    Slot::Accessor { get, set }
+}
+
+/* a property that is neither writable nor enumerable */
+pub fn fixed(value: Object, configurable: bool) -> Property {
+    Property {
+        slot: Slot::Data {
+            value,
+            writable: false,
+        },
+        enumerable: false,
+        configurable,
+    }
+}
+
+/**
+ * Evaluates a class, which extends `parent` if it has an `extends` clause. Methods and
+ * initializers run in a scope of the class body, where `class` is bound to the class itself
+ * for `super`, and every private `#name` to the class declaring it, which is what private
+ * accesses check objects against. Static fields and blocks run last, in order, with `this`
+ * bound to the class. `keys` name the computed members, in order.
+ */
+pub fn define_class(
+    def: &ClassDef,
+    parent: Option<Object>,
+    keys: Vec<String>,
+    md: &Module,
+) -> Result<Object, Object> {
+    let named;
+    let def = match keys.is_empty() {
+        true => def,
+        false => {
+            named = def.with_keys(keys);
+            &named
+        }
+    };
+    let parent_span = def.extends.unwrap_or_default();
+    let parent = match parent {
+        None => None,
+        Some(p) => match p {
+            Object::Class(cd @ ClassD::Coded(_)) => Some(Heritage::Class(cd)),
+            Object::Class(cd) if ERROR_TYPES.contains(&cd.name()) => Some(Heritage::Class(cd)),
+            Object::Funct(f) if is_constructor(&f) => Some(Heritage::Function(f)),
+            Object::Const(Const::Null) => Some(Heritage::Null),
+            v => {
+                return Err(new_error(
+                    "TypeError",
+                    format!("Class extends value {v} is not a constructor or null"),
+                    parent_span,
+                ))
+            }
+        },
+    };
+
+    /* instances inherit from the parent's prototype, the class itself from the parent class */
+    let (proto_parent, statics_parent) = match &parent {
+        Some(Heritage::Class(cd @ ClassD::Coded(p))) => (
+            Some(Object::Obj(p.prototype.clone())),
+            Object::Class(cd.clone()),
+        ),
+        Some(Heritage::Function(f)) => {
+            let proto = f.props.borrow().get("prototype").cloned();
+            match proto {
+                Some(p) if is_object(&p) => (Some(p), Object::Funct(f.clone())),
+                Some(Object::Const(Const::Null)) => (None, Object::Funct(f.clone())),
+                p => {
+                    return Err(new_error(
+                        "TypeError",
+                        format!(
+                            "Class extends value does not have valid prototype property {}",
+                            p.unwrap_or_else(Object::undef)
+                        ),
+                        parent_span,
+                    ))
+                }
+            }
+        }
+        Some(Heritage::Null) => (None, builtins::object::function_prototype()),
+        _ => (
+            Some(builtins::object::prototype()),
+            builtins::object::function_prototype(),
+        ),
+    };
+    let prototype = Handle::new(Props::with_proto(proto_parent));
+    let statics = Handle::new(Props::with_proto(Some(statics_parent)));
+    statics
+        .borrow_mut()
+        .define("name", fixed(Object::str(&def.name), true));
+    statics
+        .borrow_mut()
+        .define("prototype", fixed(Object::Obj(prototype.clone()), false));
+
+    let env = Env::with_layout(Some(&md.env), &def.scope);
+    let mut ctor = None;
+    let (mut private, mut static_private) = (HashMap::new(), HashMap::new());
+    let mut fields = Vec::new();
+
+    for m in &def.members {
+        match m {
+            Member::Method {
+                name,
+                kind,
+                is_static,
+                f,
+            } => {
+                let f = Function::coded(f.clone(), env.clone());
+
+                if name == "constructor" && !is_static {
+                    ctor = Some(f);
+                } else if name.starts_with('#') {
+                    let map = if *is_static {
+                        &mut static_private
+                    } else {
+                        &mut private
+                    };
+
+                    /* private methods cannot be assigned to */
+                    let slot = match with_method(map.remove(name), *kind, f) {
+                        Slot::Data { value, .. } => Slot::Data {
+                            value,
+                            writable: false,
+                        },
+                        s => s,
+                    };
+                    map.insert(name.clone(), slot);
+                } else {
+                    let mut target = match is_static {
+                        true => statics.borrow_mut(),
+                        false => prototype.borrow_mut(),
+                    };
+
+                    let slot = with_method(target.property(name).map(|p| p.slot.clone()), *kind, f);
+                    target.define(
+                        name,
+                        Property {
+                            slot,
+                            enumerable: false,
+                            configurable: true,
+                        },
+                    );
+                }
+            }
+            Member::Field {
+                name,
+                is_static: false,
+                init,
+            } => fields.push((
+                name.clone(),
+                init.as_ref()
+                    .map(|p| Function::coded(p.clone(), env.clone())),
+            )),
+            _ => (),
+        }
+    }
+
+    let class = Rc::new(Class {
+        name: def.name.clone(),
+        parent,
+        ctor,
+        prototype: prototype.clone(),
+        statics,
+        private,
+        static_private: RefCell::new(static_private),
+        fields,
+        env: env.clone(),
+    });
+    heap::track(&class);
+    let cls = Object::Class(ClassD::Coded(class.clone()));
+    prototype
+        .borrow_mut()
+        .define("constructor", Property::hidden(cls.clone()));
+
+    /* inside its body the class keeps its name, whatever happens to the outer binding */
+    if !def.name.is_empty() {
+        env.define(&def.name, cls.clone());
+    }
+    env.define("class", cls.clone());
+    for m in &def.members {
+        match m {
+            Member::Method { name, .. } | Member::Field { name, .. } if name.starts_with('#') => {
+                env.define(name, cls.clone())
+            }
+            _ => (),
+        }
+    }
+
+    for m in &def.members {
+        let run = |p: &Rc<Proto>| {
+            let f = Function::coded(p.clone(), env.clone());
+            call_function(&f, cls.clone(), Vec::new(), md, md.call_span)
+        };
+
+        match m {
+            Member::Field {
+                name,
+                is_static: true,
+                init,
+            } => {
+                let v = match init {
+                    Some(p) => run(p)?,
+                    None => Object::undef(),
+                };
+
+                if name.starts_with('#') {
+                    let slot = Slot::Data {
+                        value: v,
+                        writable: true,
+                    };
+                    class.static_private.borrow_mut().insert(name.clone(), slot);
+                } else {
+                    class.statics.borrow_mut().define(name, Property::data(v));
+                }
+            }
+            Member::StaticBlock(p) => {
+                run(p)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(cls)
+}
+
+/* the class whose body the code runs in, which `super` starts from */
+pub fn home_class(md: &Module, span: Span) -> Result<Rc<Class>, Object> {
+    match md.get_var("class") {
+        Some(Object::Class(ClassD::Coded(c))) => Ok(c),
+        _ => Err(new_error(
+            "SyntaxError",
+            "'super' keyword unexpected here",
+            span,
+        )),
+    }
+}
+
+/**
+ * `super[key]` in a method called on `this`.
+ * Methods look in the parent's prototype; static methods, whose `this` is a class, in the parent class.
+ */
+pub fn super_prop(key: &str, this: &Object, md: &Module, span: Span) -> Result<Object, Object> {
+    let c = home_class(md, span)?;
+
+    let holder = match (&c.parent, this) {
+        (Some(Heritage::Class(cd @ ClassD::Coded(_))), Object::Class(_)) => {
+            Some(Object::Class(cd.clone()))
+        }
+        (Some(Heritage::Class(ClassD::Coded(p))), _) => Some(Object::Obj(p.prototype.clone())),
+        (Some(Heritage::Class(ClassD::Native { .. })), Object::Class(_)) => None,
+        (Some(Heritage::Function(f)), Object::Class(_)) => Some(Object::Funct(f.clone())),
+        (Some(Heritage::Function(f)), _) => f.props.borrow().get("prototype").cloned(),
+        (None | Some(Heritage::Null), Object::Class(_)) => {
+            Some(builtins::object::function_prototype())
+        }
+        (Some(Heritage::Null), _) => None,
+        (_, _) => Some(builtins::object::prototype()),
+    };
+
+    match holder.and_then(|h| find_property(&h, key)) {
+        Some(p) => read_slot(p.slot, this, md, span),
+        None => Ok(Object::undef()),
+    }
+}
+
+/* the class that declared the private `name` the code refers to */
+fn private_class(name: &str, md: &Module, span: Span) -> Result<Rc<Class>, Object> {
+    match md.get_var(name) {
+        Some(Object::Class(ClassD::Coded(c))) => Ok(c),
+        _ => Err(new_error(
+            "SyntaxError",
+            format!("Private field '{name}' must be declared in an enclosing class"),
+            span,
+        )),
+    }
+}
+
+/* the private member `name` that `c` gave `base`, if `base` has it: the brand check */
+fn private_slot(base: &Object, c: &Rc<Class>, name: &str) -> Option<Slot> {
+    match base {
+        Object::ClassObj(o) => {
+            let o = o.borrow();
+            match o.private_field(c, name) {
+                Some(v) => Some(Slot::Data {
+                    value: v.clone(),
+                    writable: true,
+                }),
+                None if o.is_a(c) => c.private.get(name).cloned(),
+                None => None,
+            }
+        }
+        Object::Class(ClassD::Coded(k)) if Rc::ptr_eq(k, c) => {
+            c.static_private.borrow().get(name).cloned()
+        }
+        _ => None,
+    }
+}
+
+/* `#name in base` */
+pub fn has_private(base: &Object, name: &str, md: &Module, span: Span) -> Result<bool, Object> {
+    let c = private_class(name, md, span)?;
+    Ok(private_slot(base, &c, name).is_some())
+}
+
+/* `base.#name` */
+pub fn get_private(base: &Object, name: &str, md: &Module, span: Span) -> Result<Object, Object> {
+    let c = private_class(name, md, span)?;
+
+    match private_slot(base, &c, name) {
+        Some(Slot::Accessor { get: None, .. }) => Err(new_error(
+            "TypeError",
+            format!("'{name}' was defined without a getter"),
+            span,
+        )),
+        Some(slot) => read_slot(slot, base, md, span),
+        None => Err(new_error(
+            "TypeError",
+            format!(
+                "Cannot read private member {name} from an object whose class did not declare it"
+            ),
+            span,
+        )),
+    }
+}
+
+/* `base.#name = v` */
+pub fn put_private(
+    base: &Object,
+    name: &str,
+    v: Object,
+    md: &Module,
+    span: Span,
+) -> Result<(), Object> {
+    let c = private_class(name, md, span)?;
+
+    match base {
+        Object::ClassObj(o) => {
+            if let Some(slot) = o.borrow_mut().private_field_mut(&c, name) {
+                *slot = v;
+                return Ok(());
+            }
+        }
+        Object::Class(ClassD::Coded(k)) if Rc::ptr_eq(k, &c) => {
+            if let Some(Slot::Data {
+                value,
+                writable: true,
+            }) = c.static_private.borrow_mut().get_mut(name)
+            {
+                *value = v;
+                return Ok(());
+            }
+        }
+        _ => (),
+    }
+
+    let msg = match private_slot(base, &c, name) {
+        Some(Slot::Accessor { set: Some(s), .. }) => {
+            call_function(&s, base.clone(), vec![v], md, span)?;
+            return Ok(());
+        }
+        Some(Slot::Accessor { set: None, .. }) => format!("'{name}' was defined without a setter"),
+        Some(Slot::Data { .. }) => format!("Private method '{name}' is not writable"),
+        None => format!(
+            "Cannot write private member {name} to an object whose class did not declare it"
+        ),
+    };
+
+    Err(new_error("TypeError", msg, span))
 }
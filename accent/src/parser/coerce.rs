@@ -0,0 +1,128 @@
+/*
+ * The spec's type conversions. Unlike the ones in `ops`, these can run script code:
+ * objects become primitives through their `valueOf` and `toString` methods.
+ */
+
+use crate::ast::{
+    constants::{Const, JsStr},
+    EOperator,
+};
+use crate::token::Span;
+
+use super::{
+    errors::new_error,
+    module::Module,
+    obj::{call_function, get_prop, Object},
+    ops,
+};
+
+/* the kind of primitive ToPrimitive should prefer */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hint {
+    Default,
+    Number,
+    String,
+}
+
+pub fn is_primitive(o: &Object) -> bool {
+    matches!(o, Object::Const(_))
+}
+
+/**
+ * ToPrimitive: OrdinaryToPrimitive on objects, trying `valueOf` then `toString`
+ * (the other way around for `Hint::String`) until one returns a primitive.
+ * Objects without their own `toString` fall back to the built-in one, e.g. "[object Object]".
+ */
+pub fn to_primitive(o: &Object, hint: Hint, md: &Module, span: Span) -> Result<Object, Object> {
+    if is_primitive(o) {
+        return Ok(o.clone());
+    }
+
+    let order = match hint {
+        Hint::String => ["toString", "valueOf"],
+        Hint::Default | Hint::Number => ["valueOf", "toString"],
+    };
+
+    for name in order {
+        match get_prop(o, name, md, span)? {
+            Object::Funct(f) => {
+                let r = call_function(&f, o.clone(), Vec::new(), md, span)?;
+                if is_primitive(&r) {
+                    return Ok(r);
+                }
+            }
+            Object::Const(Const::Undef) if name == "toString" => {
+                return Ok(Object::str(ops::to_string(o)))
+            }
+            _ => (),
+        }
+    }
+
+    Err(new_error(
+        "TypeError",
+        "Cannot convert object to primitive value",
+        span,
+    ))
+}
+
+/* ToNumeric: a Number or a BigInt */
+pub fn to_numeric(o: &Object, md: &Module, span: Span) -> Result<Object, Object> {
+    let p = to_primitive(o, Hint::Number, md, span)?;
+    Ok(ops::to_numeric(&p))
+}
+
+/* ToNumber, which unlike `Number(v)` refuses BigInts */
+pub fn to_number(o: &Object, md: &Module, span: Span) -> Result<f64, Object> {
+    match to_primitive(o, Hint::Number, md, span)? {
+        Object::Const(Const::BigInt(_)) => Err(new_error(
+            "TypeError",
+            "Cannot convert a BigInt value to a number",
+            span,
+        )),
+        p => Ok(ops::to_number(&p)),
+    }
+}
+
+/* ToString; also ToPropertyKey, as there are no symbols */
+pub fn to_string(o: &Object, md: &Module, span: Span) -> Result<String, Object> {
+    let p = to_primitive(o, Hint::String, md, span)?;
+    Ok(ops::to_string(&p))
+}
+
+/* ToString without losing lone surrogates, for string values rather than keys */
+pub fn to_js_str(o: &Object, md: &Module, span: Span) -> Result<JsStr, Object> {
+    let p = to_primitive(o, Hint::String, md, span)?;
+    Ok(ops::to_js_str(&p))
+}
+
+/**
+ * The operands of `lhs op rhs` as the primitives `ops::binary` works on.
+ * `===` compares objects as they are, `==` only converts an object compared to a primitive,
+ * `+` converts without a hint and everything else wants numbers.
+ */
+pub fn operands(
+    op: &EOperator,
+    lhs: Object,
+    rhs: Object,
+    md: &Module,
+    span: Span,
+) -> Result<(Object, Object), Object> {
+    let hint = match op {
+        EOperator::Eqeqeq | EOperator::Neqeq => return Ok((lhs, rhs)),
+        EOperator::Eqeq | EOperator::Neq => {
+            if is_primitive(&lhs) == is_primitive(&rhs)
+                || ops::is_nullish(&lhs)
+                || ops::is_nullish(&rhs)
+            {
+                return Ok((lhs, rhs));
+            }
+            Hint::Default
+        }
+        EOperator::Plus => Hint::Default,
+        _ => Hint::Number,
+    };
+
+    let lhs = to_primitive(&lhs, hint, md, span)?;
+    let rhs = to_primitive(&rhs, hint, md, span)?;
+    Ok((lhs, rhs))
+}
@@ -0,0 +1,2025 @@
+/*
+ * Compiles function bodies and scripts to bytecode.
+ *
+ * Every name a body declares (`var` anywhere in it, its own `let`, `const`, `function` and
+ * `class`, and the parameters and `this` of a function) is placed when the body is compiled.
+ * One that no function or class nested in the body refers to gets a register of the running
+ * code; the others get a slot in a scope made for the body, which closures keep alive. A block
+ * declaring names of its own places them the same way, with a scope of its own only for those
+ * that closures see, as does a `catch` block with its parameter and a `for (let ...)` loop,
+ * whose scope is copied for each iteration. Names declared nowhere the compiler can see, like
+ * the globals, are reached by name. A script's own names all stay in the scope it runs in,
+ * where the scripts after it find them.
+ *
+ * `finally` blocks are compiled once for each way out of the `try`: falling off its end,
+ * an exception, and every `break`, `continue` or `return` leaving it.
+ */
+
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::ast::{
+    constants::Const, ClassMember, EOperator, Expr, ExprKind, FuncForm, ObjProp, PropKey, Stmt,
+    StmtKind, VarQualifier,
+};
+use crate::token::Span;
+
+use super::{
+    bytecode::{Arg, At, Code, Op, Var},
+    classes::ClassDef,
+    env::{Env, Layout},
+    errors::new_error,
+    function::Proto,
+    obj::{describe, Object},
+    stack,
+};
+
+/* something a jump out of a `try` statement has to undo on the way */
+#[derive(Clone, Copy)]
+enum Unwind<'a> {
+    /* an exception handler is installed */
+    Handler,
+    /* a block's scope is left */
+    Scope,
+    /**
+     * A `finally` block has to run; `targets` is the number of jump targets and `blocks`
+     * the number of blocks around the `try`.
+     */
+    Finally {
+        body: &'a [Stmt],
+        targets: usize,
+        blocks: usize,
+    },
+    /* a value is on the stack, such as the exception a `finally` block rethrows */
+    Value,
+}
+
+/* the kinds of statement `break` or `continue` can leave */
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TargetKind {
+    /* left by `break` and `continue` without a label too */
+    Loop,
+    /* left by `break` without a label too */
+    Switch,
+    /* only left by jumps naming one of its labels */
+    Labeled,
+}
+
+/* a statement `break` or `continue` can leave */
+struct Target {
+    labels: Vec<String>,
+    kind: TargetKind,
+    /* size of the unwind stack at the statement */
+    depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+/* the names a function body, script or block declares, as placed */
+#[derive(Default)]
+struct Scope {
+    id: usize,
+    /* the register and constness of each name only the running code sees */
+    regs: HashMap<String, (usize, bool)>,
+    /* the others, which have a scope at run time when there are any */
+    layout: Option<Rc<Layout>>,
+}
+
+struct Compiler<'a> {
+    code: Code,
+    /* layouts of the scopes around the code, innermost first */
+    scopes: &'a [Rc<Layout>],
+    /* the body's own names */
+    base: Scope,
+    /* the blocks being compiled, innermost last */
+    blocks: Vec<Scope>,
+    /* the names functions and classes nested in the body refer to */
+    captured: HashSet<&'a str>,
+    next_scope: usize,
+    /* the variables already placed, by the scope they are used in */
+    var_index: HashMap<(usize, String), u32>,
+    name_index: HashMap<String, u32>,
+    targets: Vec<Target>,
+    unwind: Vec<Unwind<'a>>,
+    /* the jumps out of the optional chain being compiled, taken when a `?.` meets a nullish value */
+    chain: Vec<usize>,
+    /* where compiling ran out of stack on an expression such as a long `1 + 1 + ...` */
+    too_deep: Option<Span>,
+}
+
+/**
+ * Compiles a script to run in `env`; the names it declares get slots there.
+ * Fails with where it ran out of stack if an expression nests too deeply to compile here.
+ */
+pub fn script(stmts: &[Stmt], env: &Env) -> Result<Code, Span> {
+    let mut names = Vec::new();
+    lexical(stmts, &mut names);
+    for (n, is_const) in names {
+        let i = env.slot_for(n, is_const);
+        env.put_slot(i, None);
+    }
+    for n in var_names(stmts) {
+        env.slot_for(n, false);
+        env.declare_var(n);
+    }
+
+    let scopes = env.outer_layouts();
+    let mut c = Compiler::new(&scopes, captured(&[], stmts).0);
+    c.base.layout = Some(env.layout());
+    c.code.layout = env.layout();
+    c.block(stmts);
+    c.finish()
+}
+
+/* compiles the body of a coded function, failing the way `script` does */
+pub fn function(proto: &Proto) -> Result<Code, Span> {
+    let (captured, arguments) = captured(&proto.args, &proto.body);
+    let mut c = Compiler::new(&proto.scopes, captured);
+
+    let mut names = Vec::new();
+    let arguments = arguments && proto.form != FuncForm::Arrow;
+    if proto.form != FuncForm::Arrow {
+        names.push(("this", false));
+    }
+    if arguments {
+        names.push(("arguments", false));
+    }
+    let params: Vec<_> = proto
+        .args
+        .iter()
+        .map(|a| match &a.kind {
+            ExprKind::Var(n) => {
+                names.push((n, false));
+                true
+            }
+            _ => false,
+        })
+        .collect();
+    names.extend(declared(&proto.body));
+
+    let (base, at) = c.new_scope(&names);
+    c.code.layout = base.layout.clone().unwrap_or_default();
+    c.base = base;
+    let mut at = at.into_iter();
+    if proto.form != FuncForm::Arrow {
+        c.code.this = at.next();
+    }
+    if arguments {
+        c.code.arguments = at.next();
+    }
+    c.code.params = params
+        .into_iter()
+        .map(|bound| if bound { at.next() } else { None })
+        .collect();
+
+    /* a `var` is `undefined` from the start, unless it names a parameter or `arguments` */
+    let mut vars = var_names(&proto.body);
+    vars.retain(|n| {
+        !(arguments && *n == "arguments"
+            || proto
+                .args
+                .iter()
+                .any(|a| matches!(&a.kind, ExprKind::Var(p) if p == n)))
+    });
+    vars.sort_unstable();
+    vars.dedup();
+    for n in vars {
+        c.emit(Op::Undef, Span::default());
+        c.declare(n, Span::default());
+    }
+
+    c.block(&proto.body);
+    c.finish()
+}
+
+/**
+ * The names a function body or script declares in the scope it runs in, and whether each
+ * is a `const`. Functions and classes in it have scopes of their own.
+ */
+fn declared(stmts: &[Stmt]) -> Vec<(&str, bool)> {
+    let mut out = Vec::new();
+
+    lexical(stmts, &mut out);
+    out.extend(var_names(stmts).into_iter().map(|n| (n, false)));
+
+    out
+}
+
+/* the names `var` declares anywhere in `stmts` */
+fn var_names(stmts: &[Stmt]) -> Vec<&str> {
+    let mut out = Vec::new();
+    for st in stmts {
+        st.var_names(&mut out);
+    }
+    out
+}
+
+/* the names `stmts` declare in the block they make up, other than with `var` */
+fn lexical<'s>(stmts: &'s [Stmt], out: &mut Vec<(&'s str, bool)>) {
+    for st in stmts {
+        lexical_in(st, out);
+    }
+}
+
+fn lexical_in<'s>(st: &'s Stmt, out: &mut Vec<(&'s str, bool)>) {
+    if let Some(n) = st.lexical_name() {
+        let is_const = matches!(
+            st.kind,
+            StmtKind::VardeclSimple {
+                qual: VarQualifier::Const,
+                ..
+            }
+        );
+        out.push((n, is_const));
+        return;
+    }
+
+    /* a statement that is not a block declares in the one around it */
+    match &st.kind {
+        StmtKind::If {
+            then_st, else_st, ..
+        } => {
+            lexical_in(then_st, out);
+            if let Some(e) = else_st {
+                lexical_in(e, out);
+            }
+        }
+        StmtKind::While { body, .. }
+        | StmtKind::DoWhile { body, .. }
+        | StmtKind::Labeled { body, .. } => lexical_in(body, out),
+        StmtKind::For { init, body, .. } if loop_scope(init).is_none() => lexical_in(body, out),
+        StmtKind::ForInOf { left, body, .. } if left.lexical_name().is_none() => {
+            lexical_in(body, out)
+        }
+        _ => (),
+    }
+}
+
+/* the declarations that give a `for` loop a scope of its own, when its initializer has any */
+fn loop_scope(init: &Option<Box<Stmt>>) -> Option<&[Stmt]> {
+    let decls = init.as_deref()?.declarations();
+    decls
+        .iter()
+        .any(|d| d.lexical_name().is_some())
+        .then_some(decls)
+}
+
+/**
+ * The names that functions and classes nested in a body refer to, which have to outlive its
+ * run in a scope, with `this` when one of them uses it or `super`; and whether the body
+ * refers to its own `arguments`, itself or in the arrows it holds.
+ */
+fn captured<'s>(args: &'s [Expr], body: &'s [Stmt]) -> (HashSet<&'s str>, bool) {
+    let mut c = Captures::default();
+    for a in args {
+        c.expr(a, false);
+    }
+    c.stmts(body, false);
+    (c.names, c.arguments)
+}
+
+/* whether a function with the parameters `args` and body `body` uses its `arguments` */
+pub fn uses_arguments(args: &[Expr], body: &[Stmt]) -> bool {
+    captured(args, body).1
+}
+
+/* the walk behind `captured`; `nested` is set inside a function or class */
+#[derive(Default)]
+struct Captures<'s> {
+    names: HashSet<&'s str>,
+    /* set inside a function other than an arrow, which has an `arguments` of its own */
+    own_arguments: bool,
+    arguments: bool,
+}
+
+impl<'s> Captures<'s> {
+    fn stmts(&mut self, stmts: &'s [Stmt], nested: bool) {
+        for st in stmts {
+            self.stmt(st, nested);
+        }
+    }
+
+    fn stmt(&mut self, st: &'s Stmt, nested: bool) {
+        match &st.kind {
+            StmtKind::VardeclSimple { val, .. } => {
+                if let Some(v) = val {
+                    self.expr(v, nested);
+                }
+            }
+            StmtKind::Funcdecl { args, body, .. } => self.function(args, body, false),
+            StmtKind::Expr(e) | StmtKind::ReturnStmt(e) | StmtKind::Throw(e) => {
+                self.expr(e, nested)
+            }
+            StmtKind::ClassDecl {
+                parent, members, ..
+            } => self.class(parent.as_ref(), members, nested),
+            StmtKind::Try {
+                block,
+                handler,
+                finalizer,
+                ..
+            } => {
+                self.stmts(block, nested);
+                for b in [handler, finalizer].into_iter().flatten() {
+                    self.stmts(b, nested);
+                }
+            }
+            StmtKind::Block(body) => self.stmts(body, nested),
+            StmtKind::If {
+                cond,
+                then_st,
+                else_st,
+            } => {
+                self.expr(cond, nested);
+                self.stmt(then_st, nested);
+                if let Some(e) = else_st {
+                    self.stmt(e, nested);
+                }
+            }
+            StmtKind::While { cond, body } | StmtKind::DoWhile { body, cond } => {
+                self.expr(cond, nested);
+                self.stmt(body, nested);
+            }
+            StmtKind::For {
+                init,
+                test,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(init, nested);
+                }
+                for e in [test, update].into_iter().flatten() {
+                    self.expr(e, nested);
+                }
+                self.stmt(body, nested);
+            }
+            StmtKind::ForInOf {
+                left, right, body, ..
+            } => {
+                self.stmt(left, nested);
+                self.expr(right, nested);
+                self.stmt(body, nested);
+            }
+            StmtKind::Switch { disc, cases, body } => {
+                self.expr(disc, nested);
+                for test in cases.iter().filter_map(|(t, _)| t.as_ref()) {
+                    self.expr(test, nested);
+                }
+                self.stmts(body, nested);
+            }
+            StmtKind::Labeled { body, .. } => self.stmt(body, nested),
+            StmtKind::Break(_) | StmtKind::Continue(_) => (),
+        }
+    }
+
+    fn function(&mut self, args: &'s [Expr], body: &'s [Stmt], arrow: bool) {
+        let outer = self.own_arguments;
+        self.own_arguments |= !arrow;
+
+        for a in args {
+            self.expr(a, true);
+        }
+        self.stmts(body, true);
+        self.own_arguments = outer;
+    }
+
+    /* the `extends` expression runs where the class is, its members in functions of their own */
+    fn class(&mut self, parent: Option<&'s Expr>, members: &'s [ClassMember], nested: bool) {
+        if let Some(p) = parent {
+            self.expr(p, nested);
+        }
+        /* computed names run where the class is too */
+        for m in members {
+            if let ClassMember::Method { key, .. } | ClassMember::Field { key, .. } = m {
+                self.key(key, nested);
+            }
+        }
+        let outer = std::mem::replace(&mut self.own_arguments, true);
+        for m in members {
+            match m {
+                ClassMember::Method { args, body, .. } => self.function(args, body, false),
+                ClassMember::Field { val: Some(v), .. } => self.expr(v, true),
+                ClassMember::Field { val: None, .. } => (),
+                ClassMember::StaticBlock(body) => self.stmts(body, true),
+            }
+        }
+        self.own_arguments = outer;
+    }
+
+    fn key(&mut self, key: &'s PropKey, nested: bool) {
+        if let PropKey::Computed(k) = key {
+            self.expr(k, nested);
+        }
+    }
+
+    fn expr(&mut self, mut e: &'s Expr, nested: bool) {
+        /* goes on to an operand in the loop, so that a long chain such as `1 + 1 + ...` takes no stack */
+        loop {
+            let next = match &e.kind {
+                ExprKind::Var(n) => {
+                    if nested {
+                        self.names.insert(n);
+                    }
+                    if n == "arguments" && !self.own_arguments {
+                        self.arguments = true;
+                    }
+                    None
+                }
+                ExprKind::This | ExprKind::SuperCall(_) | ExprKind::SuperMember(_) => {
+                    if nested {
+                        self.names.insert("this");
+                    }
+                    match &e.kind {
+                        ExprKind::SuperCall(args) => self.exprs(args, nested),
+                        ExprKind::SuperMember(key) => self.key(key, nested),
+                        _ => (),
+                    }
+                    None
+                }
+                ExprKind::Const(_) | ExprKind::PrivateName(_) => None,
+                ExprKind::Unary { e: x, .. }
+                | ExprKind::Typeof(x)
+                | ExprKind::Void(x)
+                | ExprKind::Delete(x)
+                | ExprKind::Update { target: x, .. }
+                | ExprKind::Spread(x)
+                | ExprKind::Member { obj: x, .. }
+                | ExprKind::PrivateMember { obj: x, .. }
+                | ExprKind::Chain(x)
+                | ExprKind::Optional(x) => Some(x),
+                ExprKind::Binary { lhs, rhs, .. }
+                | ExprKind::Logical { lhs, rhs, .. }
+                | ExprKind::In { lhs, rhs }
+                | ExprKind::Instanceof { lhs, rhs }
+                | ExprKind::Assign {
+                    target: lhs,
+                    val: rhs,
+                    ..
+                }
+                | ExprKind::Index {
+                    obj: lhs,
+                    index: rhs,
+                } => {
+                    self.expr(rhs, nested);
+                    Some(lhs)
+                }
+                ExprKind::Conditional { test, cons, alt } => {
+                    self.expr(cons, nested);
+                    self.expr(alt, nested);
+                    Some(test)
+                }
+                ExprKind::Sequence(v) | ExprKind::Template { exprs: v, .. } => {
+                    self.exprs(v, nested);
+                    None
+                }
+                ExprKind::Call { callee, args } | ExprKind::New { callee, args } => {
+                    self.exprs(args, nested);
+                    Some(callee)
+                }
+                ExprKind::TaggedTemplate { tag, exprs, .. } => {
+                    self.exprs(exprs, nested);
+                    Some(tag)
+                }
+                ExprKind::Function {
+                    args, body, form, ..
+                } => {
+                    self.function(args, body, *form == FuncForm::Arrow);
+                    None
+                }
+                ExprKind::Array(elems) => {
+                    for el in elems.iter().flatten() {
+                        self.expr(el, nested);
+                    }
+                    None
+                }
+                ExprKind::Object(entries) => {
+                    for entry in entries {
+                        match entry {
+                            ObjProp::KeyValue { key, val } | ObjProp::Accessor { key, val, .. } => {
+                                self.key(key, nested);
+                                self.expr(val, nested);
+                            }
+                            ObjProp::Spread(src) | ObjProp::Proto(src) => self.expr(src, nested),
+                        }
+                    }
+                    None
+                }
+                ExprKind::Class {
+                    parent, members, ..
+                } => {
+                    self.class(parent.as_deref(), members, nested);
+                    None
+                }
+            };
+            match next {
+                Some(x) => e = x,
+                None => return,
+            }
+        }
+    }
+
+    fn exprs(&mut self, v: &'s [Expr], nested: bool) {
+        for x in v {
+            self.expr(x, nested);
+        }
+    }
+}
+
+/* the jump `a op b` makes over `b` when `a` decides the result */
+fn short_circuit(op: &EOperator) -> Op {
+    match op {
+        EOperator::And => Op::And(0),
+        EOperator::Or => Op::Or(0),
+        _ => Op::Nullish(0),
+    }
+}
+
+impl<'a> Compiler<'a> {
+    fn new(scopes: &'a [Rc<Layout>], captured: HashSet<&'a str>) -> Compiler<'a> {
+        Compiler {
+            code: Code::default(),
+            scopes,
+            base: Scope::default(),
+            blocks: Vec::new(),
+            captured,
+            next_scope: 1,
+            var_index: HashMap::new(),
+            name_index: HashMap::new(),
+            targets: Vec::new(),
+            unwind: Vec::new(),
+            chain: Vec::new(),
+            too_deep: None,
+        }
+    }
+
+    fn finish(mut self) -> Result<Code, Span> {
+        if let Some(span) = self.too_deep {
+            return Err(span);
+        }
+        self.emit(Op::End, Span::default());
+        Ok(self.code)
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.code.ops.push(op);
+        self.code.spans.push(span);
+        self.code.ops.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.code.ops.len() as u32
+    }
+
+    /* points the jump at `at` to `target` */
+    fn patch_to(&mut self, at: usize, target: u32) {
+        match &mut self.code.ops[at] {
+            Op::Jump(t)
+            | Op::JumpIfFalse(t)
+            | Op::JumpIfTrue(t)
+            | Op::JumpUnless { to: t, .. }
+            | Op::JumpIf { to: t, .. }
+            | Op::JumpIfNullish { to: t, .. }
+            | Op::And(t)
+            | Op::Or(t)
+            | Op::Nullish(t)
+            | Op::Next(t)
+            | Op::EnterTry(t) => *t = target,
+            op => unreachable!("{op:?} does not jump"),
+        }
+    }
+
+    /* points the jump at `at` to the next instruction */
+    fn patch(&mut self, at: usize) {
+        self.patch_to(at, self.here());
+    }
+
+    fn constant(&mut self, v: Object) -> u32 {
+        self.code.consts.push(v);
+        self.code.consts.len() as u32 - 1
+    }
+
+    fn push_const(&mut self, c: Const, span: Span) {
+        let i = self.constant(Object::Const(c));
+        self.emit(Op::Const(i), span);
+    }
+
+    fn push_str(&mut self, v: &str, span: Span) {
+        let i = self.constant(Object::str(v));
+        self.emit(Op::Const(i), span);
+    }
+
+    fn name(&mut self, n: &str) -> u32 {
+        if let Some(i) = self.name_index.get(n) {
+            return *i;
+        }
+
+        self.code.names.push(n.to_string());
+        let i = self.code.names.len() as u32 - 1;
+        self.name_index.insert(n.to_string(), i);
+        i
+    }
+
+    /* the innermost scope */
+    fn current(&self) -> &Scope {
+        self.blocks.last().unwrap_or(&self.base)
+    }
+
+    /**
+     * Places `names` in a new scope: a register for each that closures do not see, and a
+     * slot for the others. Gives where each went, in order.
+     */
+    fn new_scope(&mut self, names: &[(&str, bool)]) -> (Scope, Vec<At>) {
+        let mut layout = Layout::new();
+        let mut regs: HashMap<String, (usize, bool)> = HashMap::new();
+        let mut at = Vec::with_capacity(names.len());
+
+        for &(n, is_const) in names {
+            if self.captured.contains(n) {
+                let slot = match is_const {
+                    true => layout.add_const(n),
+                    false => layout.add(n),
+                };
+                at.push(At::Slot { hops: 0, slot });
+                continue;
+            }
+
+            let r = match regs.get(n) {
+                Some((r, _)) => *r,
+                None => {
+                    self.code.registers.push(n.to_string());
+                    self.code.registers.len() - 1
+                }
+            };
+            regs.insert(n.to_string(), (r, is_const));
+            at.push(At::Reg(r));
+        }
+
+        self.next_scope += 1;
+        let scope = Scope {
+            id: self.next_scope,
+            regs,
+            layout: (!layout.is_empty()).then(|| Rc::new(layout)),
+        };
+        (scope, at)
+    }
+
+    /* the layouts of the scopes a closure made here runs in, innermost first */
+    fn frames(&self) -> Vec<Rc<Layout>> {
+        let own = self.blocks.iter().rev().chain([&self.base]);
+
+        own.filter_map(|s| s.layout.clone())
+            .chain(self.scopes.iter().cloned())
+            .collect()
+    }
+
+    /* the variable `n` refers to here */
+    fn var(&mut self, n: &str) -> u32 {
+        let key = (self.current().id, n.to_string());
+        if let Some(i) = self.var_index.get(&key) {
+            return *i;
+        }
+
+        let mut hops = 0;
+        let mut found = None;
+        for s in self.blocks.iter().rev().chain([&self.base]) {
+            if let Some(&(r, is_const)) = s.regs.get(n) {
+                found = Some((At::Reg(r), is_const));
+                break;
+            }
+            if let Some(l) = &s.layout {
+                if let Some(slot) = l.slot(n) {
+                    found = Some((At::Slot { hops, slot }, l.is_const(slot)));
+                    break;
+                }
+                hops += 1;
+            }
+        }
+        let found = found.or_else(|| {
+            self.scopes.iter().enumerate().find_map(|(k, l)| {
+                let slot = l.slot(n)?;
+                /* the `Env` drops assignments to a function expression's own name */
+                if l.is_fixed(slot) {
+                    return Some((At::Name, false));
+                }
+                Some((
+                    At::Slot {
+                        hops: hops + k,
+                        slot,
+                    },
+                    l.is_const(slot),
+                ))
+            })
+        });
+
+        let (at, is_const) = found.unwrap_or((At::Name, false));
+        self.code.vars.push(Var {
+            name: n.to_string(),
+            at,
+            is_const,
+        });
+
+        let i = self.code.vars.len() as u32 - 1;
+        self.var_index.insert(key, i);
+        i
+    }
+
+    fn get_var(&mut self, n: &str, span: Span) {
+        let v = self.var(n);
+
+        match self.code.vars[v as usize].at {
+            At::Reg(r) => self.emit(Op::GetReg(r as u32), span),
+            _ => self.emit(Op::Get(v), span),
+        };
+    }
+
+    fn set_var(&mut self, n: &str, span: Span) {
+        let v = self.var(n);
+
+        match &self.code.vars[v as usize] {
+            Var {
+                at: At::Reg(r),
+                is_const: false,
+                ..
+            } => self.emit(Op::SetReg(*r as u32), span),
+            _ => self.emit(Op::Set(v), span),
+        };
+    }
+
+    /* declares `n` with the value on the stack, in the running scope unless it is a `var` in a block */
+    fn declare(&mut self, n: &str, span: Span) {
+        let s = self.current();
+        if let Some(&(r, _)) = s.regs.get(n) {
+            self.emit(Op::InitReg(r as u32), span);
+            return;
+        }
+
+        match s.layout.as_ref().and_then(|l| l.slot(n)) {
+            Some(i) => self.emit(Op::Declare(i as u32), span),
+            None => {
+                let v = self.var(n);
+                self.emit(Op::DeclareVar(v), span)
+            }
+        };
+    }
+
+    /* runs what follows in a scope for `names`, until `leave_scope` */
+    fn enter_scope(&mut self, names: &[(&str, bool)], span: Span) {
+        let first = self.code.registers.len();
+        let (scope, _) = self.new_scope(names);
+
+        /* a block entered again, as in a loop, starts with its names undeclared */
+        let count = self.code.registers.len() - first;
+        if count > 0 {
+            let (from, count) = (first as u32, count as u32);
+            self.emit(Op::ClearRegs { from, count }, span);
+        }
+        if let Some(l) = &scope.layout {
+            self.code.blocks.push(l.clone());
+            self.emit(Op::PushScope(self.code.blocks.len() as u32 - 1), span);
+            self.unwind.push(Unwind::Scope);
+        }
+        self.blocks.push(scope);
+    }
+
+    fn leave_scope(&mut self, span: Span) {
+        let scope = self.blocks.pop().expect("a scope was entered");
+        if scope.layout.is_some() {
+            self.unwind.pop();
+            self.emit(Op::PopScope, span);
+        }
+    }
+
+    /* whether the innermost block has a scope at run time */
+    fn in_frame(&self) -> bool {
+        self.blocks.last().is_some_and(|s| s.layout.is_some())
+    }
+
+    /* a block, in a scope of its own if it declares anything there */
+    fn scoped(&mut self, stmts: &'a [Stmt], span: Span) {
+        let mut names = Vec::new();
+        lexical(stmts, &mut names);
+        if names.is_empty() {
+            return self.block(stmts);
+        }
+
+        self.enter_scope(&names, span);
+        self.block(stmts);
+        self.leave_scope(span);
+    }
+
+    /* a function literal; a named function expression gets a scope of its own holding its name */
+    fn proto(
+        &mut self,
+        name: &str,
+        args: &Rc<[Expr]>,
+        body: &Rc<[Stmt]>,
+        form: FuncForm,
+        named: bool,
+    ) -> u32 {
+        let mut scopes = Vec::new();
+        if named {
+            let mut own = Layout::new();
+            own.add_fixed(name);
+            scopes.push(Rc::new(own));
+        }
+        scopes.extend(self.frames());
+
+        let proto = Proto::in_scopes(name, args, body, form, scopes);
+        self.code.protos.push(proto);
+        self.code.protos.len() as u32 - 1
+    }
+
+    /* a class declaration or expression, leaving the class on the stack */
+    fn class(
+        &mut self,
+        name: &str,
+        parent: Option<&'a Expr>,
+        members: &'a [ClassMember],
+        span: Span,
+    ) {
+        let def = ClassDef::new(name, parent.map(|p| p.span), members, &self.frames());
+        if let Some(p) = parent {
+            self.expr(p);
+        }
+        for m in members {
+            if let ClassMember::Method { key, .. } | ClassMember::Field { key, .. } = m {
+                if let PropKey::Computed(_) = key {
+                    self.key(key, span);
+                }
+            }
+        }
+
+        self.code.classes.push(def);
+        let class = self.code.classes.len() as u32 - 1;
+        let extends = parent.is_some();
+        self.emit(Op::Class { class, extends }, span);
+    }
+
+    /* throws the SyntaxError the tree-walker gives for code that can only fail */
+    fn throw_syntax(&mut self, msg: impl Into<String>, span: Span) {
+        let e = self.constant(new_error("SyntaxError", msg, span));
+        self.emit(Op::Const(e), span);
+        self.emit(Op::Throw, span);
+    }
+
+    /* the functions a block declares are there before anything in it runs */
+    fn block(&mut self, stmts: &'a [Stmt]) {
+        let (funcs, rest): (Vec<_>, Vec<_>) = stmts
+            .iter()
+            .partition(|st| matches!(st.kind, StmtKind::Funcdecl { .. }));
+
+        for st in funcs.into_iter().chain(rest) {
+            self.stmt(st);
+        }
+    }
+
+    fn stmt(&mut self, st: &'a Stmt) {
+        let span = st.span;
+
+        match &st.kind {
+            StmtKind::Expr(e) => self.effect(e),
+            StmtKind::VardeclSimple {
+                val: None,
+                qual: VarQualifier::Var,
+                ..
+            } => (),
+            StmtKind::VardeclSimple { name, val, .. } => {
+                match val {
+                    Some(val) => self.expr(val),
+                    None => {
+                        self.emit(Op::Undef, span);
+                    }
+                }
+                self.declare(name, span);
+            }
+            StmtKind::Funcdecl { name, args, body } => {
+                let p = self.proto(name, args, body, FuncForm::Plain, false);
+                self.emit(Op::Closure(p), span);
+                self.declare(name, span);
+            }
+            StmtKind::ClassDecl {
+                name,
+                parent,
+                members,
+            } => {
+                self.class(name, parent.as_ref(), members, span);
+                self.declare(name, span);
+            }
+            StmtKind::ReturnStmt(e) => {
+                self.expr(e);
+                self.exit_to(0, true);
+                self.emit(Op::Return, span);
+            }
+            StmtKind::Throw(e) => {
+                self.expr(e);
+                self.emit(Op::Throw, span);
+            }
+            StmtKind::Try {
+                block,
+                param,
+                handler,
+                finalizer,
+            } => self.try_stmt(
+                block,
+                param.as_deref(),
+                handler.as_deref(),
+                finalizer.as_deref(),
+                span,
+            ),
+            StmtKind::Block(body) => self.scoped(body, span),
+            StmtKind::If {
+                cond,
+                then_st,
+                else_st,
+            } => {
+                let skip = self.jump_unless(cond, span);
+                self.stmt(then_st);
+
+                match else_st {
+                    Some(else_st) => {
+                        let end = self.emit(Op::Jump(0), span);
+                        self.patch(skip);
+                        self.stmt(else_st);
+                        self.patch(end);
+                    }
+                    None => self.patch(skip),
+                }
+            }
+            StmtKind::While { .. }
+            | StmtKind::DoWhile { .. }
+            | StmtKind::For { .. }
+            | StmtKind::ForInOf { .. } => self.loop_stmt(st, Vec::new()),
+            StmtKind::Switch { disc, cases, body } => self.switch(disc, cases, body, span),
+            StmtKind::Break(label) => self.jump_out(label.as_deref(), false, span),
+            StmtKind::Continue(label) => self.jump_out(label.as_deref(), true, span),
+            StmtKind::Labeled { .. } => {
+                /* `a: b: for (...)` gives the loop both labels */
+                let mut labels = Vec::new();
+                let mut body = st;
+
+                while let StmtKind::Labeled { label, body: inner } = &body.kind {
+                    labels.push(label.clone());
+                    body = inner;
+                }
+
+                match body.kind {
+                    StmtKind::While { .. }
+                    | StmtKind::DoWhile { .. }
+                    | StmtKind::For { .. }
+                    | StmtKind::ForInOf { .. } => self.loop_stmt(body, labels),
+                    _ => {
+                        self.enter(labels, TargetKind::Labeled);
+                        self.stmt(body);
+                        self.leave(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, labels: Vec<String>, kind: TargetKind) {
+        self.targets.push(Target {
+            labels,
+            kind,
+            depth: self.unwind.len(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+    }
+
+    /* ends the innermost target: `break` goes to the next instruction, `continue` to `cont` */
+    fn leave(&mut self, cont: Option<u32>) {
+        let t = self.targets.pop().expect("a target was entered");
+
+        for at in t.breaks {
+            self.patch(at);
+        }
+        for at in t.continues {
+            self.patch_to(at, cont.expect("only loops are continued"));
+        }
+    }
+
+    fn loop_stmt(&mut self, st: &'a Stmt, labels: Vec<String>) {
+        let span = st.span;
+
+        match &st.kind {
+            /* loops test at the bottom too, so that an iteration takes a single jump */
+            StmtKind::While { cond, body } => {
+                let exit = self.jump_unless(cond, span);
+                let top = self.here();
+
+                self.enter(labels, TargetKind::Loop);
+                self.stmt(body);
+                let cont = self.here();
+                self.jump_if(cond, top, span);
+                self.patch(exit);
+                self.leave(Some(cont));
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let top = self.here();
+
+                self.enter(labels, TargetKind::Loop);
+                self.stmt(body);
+                let cont = self.here();
+                self.expr(cond);
+                self.emit(Op::JumpIfTrue(top), span);
+                self.leave(Some(cont));
+            }
+            StmtKind::For {
+                init,
+                test,
+                update,
+                body,
+            } => {
+                /* `let` declarations get a scope around the loop, moved on for each iteration */
+                let scope = loop_scope(init);
+                match scope {
+                    Some(decls) => {
+                        let mut names = Vec::new();
+                        lexical(decls, &mut names);
+                        lexical_in(body, &mut names);
+                        self.enter_scope(&names, span);
+                        self.block(decls);
+                        if self.in_frame() {
+                            self.emit(Op::NextIteration, span);
+                        }
+                    }
+                    None => {
+                        if let Some(init) = init {
+                            self.stmt(init);
+                        }
+                    }
+                }
+
+                let exit = test.as_ref().map(|test| self.jump_unless(test, span));
+                let top = self.here();
+
+                self.enter(labels, TargetKind::Loop);
+                self.stmt(body);
+                let cont = self.here();
+                if scope.is_some() && self.in_frame() {
+                    self.emit(Op::NextIteration, span);
+                }
+                if let Some(update) = update {
+                    self.effect(update);
+                }
+                match test {
+                    Some(test) => self.jump_if(test, top, span),
+                    None => {
+                        self.emit(Op::Jump(top), span);
+                    }
+                }
+                if let Some(exit) = exit {
+                    self.patch(exit);
+                }
+                self.leave(Some(cont));
+                if scope.is_some() {
+                    self.leave_scope(span);
+                }
+            }
+            StmtKind::ForInOf {
+                left,
+                right,
+                body,
+                of,
+            } => {
+                self.expr(right);
+                match of {
+                    true => {
+                        let desc = self.name(&describe(right));
+                        self.emit(Op::ForOf(desc), right.span)
+                    }
+                    false => self.emit(Op::ForIn, right.span),
+                };
+                /* the array and the index into it stay on the stack while the loop runs */
+                self.unwind.extend([Unwind::Value, Unwind::Value]);
+
+                /* `let` and `const` get a scope around the loop, moved on for each iteration */
+                let scope = left.lexical_name().map(|n| {
+                    let is_const = matches!(
+                        left.kind,
+                        StmtKind::VardeclSimple {
+                            qual: VarQualifier::Const,
+                            ..
+                        }
+                    );
+                    let mut names = vec![(n, is_const)];
+                    lexical_in(body, &mut names);
+                    self.enter_scope(&names, span);
+                });
+
+                let top = self.here();
+                let exit = self.emit(Op::Next(0), span);
+                if scope.is_some() && self.in_frame() {
+                    self.emit(Op::NextIteration, span);
+                }
+                self.bind_item(left);
+
+                self.enter(labels, TargetKind::Loop);
+                self.stmt(body);
+                self.emit(Op::Jump(top), span);
+                self.patch(exit);
+                self.leave(Some(top));
+                if scope.is_some() {
+                    self.leave_scope(span);
+                }
+
+                self.unwind.truncate(self.unwind.len() - 2);
+                self.emit(Op::Pop, span);
+                self.emit(Op::Pop, span);
+            }
+            _ => unreachable!("not a loop"),
+        }
+    }
+
+    /* gives the left side of a `for...in` or `for...of` loop the item `Next` moved past */
+    fn bind_item(&mut self, left: &'a Stmt) {
+        let span = left.span;
+
+        match &left.kind {
+            StmtKind::VardeclSimple { name, .. } => {
+                self.emit(Op::Item(0), span);
+                self.declare(name, span);
+            }
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Var(name),
+                ..
+            }) => {
+                self.emit(Op::Item(0), span);
+                self.set_var(name, span);
+            }
+            StmtKind::Expr(target) => {
+                let (_, set, _, refs) = self.reference(target);
+                self.emit(Op::Item(refs), span);
+                self.emit(set, span);
+                self.emit(Op::Pop, span);
+            }
+            _ => unreachable!("the parser only gives declarations and targets"),
+        }
+    }
+
+    /**
+     * `switch (disc) { cases }`. The tests run in order with `disc` on the stack until one is
+     * `===` to it, and the cases run from there on; with none, they run from `default`.
+     */
+    fn switch(
+        &mut self,
+        disc: &'a Expr,
+        cases: &'a [(Option<Expr>, usize)],
+        body: &'a [Stmt],
+        span: Span,
+    ) {
+        self.expr(disc);
+        self.unwind.push(Unwind::Value);
+
+        let mut names = Vec::new();
+        lexical(body, &mut names);
+        if !names.is_empty() {
+            self.enter_scope(&names, span);
+        }
+        let is_func = |st: &Stmt| matches!(st.kind, StmtKind::Funcdecl { .. });
+        for st in body.iter().filter(|st| is_func(st)) {
+            self.stmt(st);
+        }
+
+        let mut starts = Vec::new();
+        for (test, at) in cases {
+            if let Some(test) = test {
+                self.emit(Op::Dup, test.span);
+                self.expr(test);
+                let (op, l, r) = (EOperator::Eqeqeq, Arg::Stack, Arg::Stack);
+                self.emit(Op::Binary { op, l, r }, test.span);
+                starts.push((self.emit(Op::JumpIfTrue(0), test.span), *at));
+            }
+        }
+        let otherwise = self.emit(Op::Jump(0), span);
+        let default = cases
+            .iter()
+            .find_map(|(test, at)| test.is_none().then_some(*at));
+
+        self.enter(Vec::new(), TargetKind::Switch);
+        for k in 0..=body.len() {
+            for &(jump, _) in starts.iter().filter(|(_, at)| *at == k) {
+                self.patch(jump);
+            }
+            if default == Some(k) {
+                self.patch(otherwise);
+            }
+            match body.get(k) {
+                Some(st) if !is_func(st) => self.stmt(st),
+                _ => (),
+            }
+        }
+        self.leave(None);
+        if default.is_none() {
+            self.patch(otherwise);
+        }
+
+        if !names.is_empty() {
+            self.leave_scope(span);
+        }
+        self.unwind.pop();
+        self.emit(Op::Pop, span);
+    }
+
+    /* `break` or `continue`, with or without a label */
+    fn jump_out(&mut self, label: Option<&str>, is_continue: bool, span: Span) {
+        let i = self
+            .targets
+            .iter()
+            .rposition(|t| match label {
+                None => match t.kind {
+                    TargetKind::Loop => true,
+                    TargetKind::Switch => !is_continue,
+                    TargetKind::Labeled => false,
+                },
+                Some(l) => {
+                    t.labels.iter().any(|x| x == l) && (t.kind == TargetKind::Loop || !is_continue)
+                }
+            })
+            .expect("the parser only accepts break and continue inside their target");
+
+        self.exit_to(self.targets[i].depth, false);
+        let at = self.emit(Op::Jump(0), span);
+
+        match is_continue {
+            true => self.targets[i].continues.push(at),
+            false => self.targets[i].breaks.push(at),
+        }
+    }
+
+    /**
+     * Undoes the unwind stack down to `depth` for a jump out of `try` statements.
+     * `keep_value` is set for `return`, which holds its value on the stack meanwhile
+     * and leaves everything else there for the function to drop.
+     */
+    fn exit_to(&mut self, depth: usize, keep_value: bool) {
+        for k in (depth..self.unwind.len()).rev() {
+            match self.unwind[k] {
+                Unwind::Handler => {
+                    self.emit(Op::LeaveTry, Span::default());
+                }
+                Unwind::Scope => {
+                    self.emit(Op::PopScope, Span::default());
+                }
+                Unwind::Value if !keep_value => {
+                    self.emit(Op::Pop, Span::default());
+                }
+                Unwind::Value => (),
+                Unwind::Finally {
+                    body,
+                    targets,
+                    blocks,
+                } => {
+                    /* the block runs as if it stood outside the `try` */
+                    let unwind = self.unwind.split_off(k);
+                    let inner = self.targets.split_off(targets);
+                    let scopes = self.blocks.split_off(blocks);
+                    if keep_value {
+                        self.unwind.push(Unwind::Value);
+                    }
+
+                    self.scoped(body, Span::default());
+
+                    self.unwind.truncate(k);
+                    self.unwind.extend(unwind);
+                    self.targets.extend(inner);
+                    self.blocks.extend(scopes);
+                }
+            }
+        }
+    }
+
+    /**
+     * `try { block } catch (param) { handler } finally { finalizer }`.
+     * The handler runs in a scope of its own, which holds the catch parameter.
+     */
+    fn try_stmt(
+        &mut self,
+        block: &'a [Stmt],
+        param: Option<&str>,
+        handler: Option<&'a [Stmt]>,
+        finalizer: Option<&'a [Stmt]>,
+        span: Span,
+    ) {
+        let base = self.unwind.len();
+        if let Some(body) = finalizer {
+            self.unwind.push(Unwind::Finally {
+                body,
+                targets: self.targets.len(),
+                blocks: self.blocks.len(),
+            });
+        }
+        /* `finally` after the `try` or `catch` block completed normally */
+        let finish = |c: &mut Compiler<'a>| {
+            if let Some(body) = finalizer {
+                let fin = c.unwind.split_off(base);
+                c.scoped(body, span);
+                c.unwind.extend(fin);
+            }
+        };
+
+        let enter = self.emit(Op::EnterTry(0), span);
+        self.unwind.push(Unwind::Handler);
+        self.scoped(block, span);
+        self.unwind.pop();
+        self.emit(Op::LeaveTry, span);
+        finish(self);
+        let mut ends = vec![self.emit(Op::Jump(0), span)];
+
+        /* where exceptions go that neither block catches, and whether they leave the handler's scope */
+        let mut uncaught = enter;
+        let mut in_scope = false;
+
+        if let Some(handler) = handler {
+            /* the exception is on the stack */
+            self.patch(enter);
+            match param {
+                Some(p) => {
+                    let mut names = vec![(p, false)];
+                    lexical(handler, &mut names);
+                    self.enter_scope(&names, span);
+                    self.declare(p, span);
+                    in_scope = self.in_frame();
+                }
+                None => {
+                    self.emit(Op::Pop, span);
+                }
+            }
+
+            if finalizer.is_some() {
+                uncaught = self.emit(Op::EnterTry(0), span);
+                self.unwind.push(Unwind::Handler);
+            }
+
+            match param {
+                Some(_) => self.block(handler),
+                None => self.scoped(handler, span),
+            }
+
+            if finalizer.is_some() {
+                self.unwind.pop();
+                self.emit(Op::LeaveTry, span);
+            }
+            if param.is_some() {
+                self.leave_scope(span);
+            }
+            finish(self);
+            ends.push(self.emit(Op::Jump(0), span));
+        }
+
+        if let Some(body) = finalizer {
+            /* an exception runs the block and is thrown again, unless the block leaves some other way */
+            self.patch(uncaught);
+            if in_scope {
+                self.emit(Op::PopScope, span);
+            }
+            self.unwind.truncate(base);
+            self.unwind.push(Unwind::Value);
+            self.scoped(body, span);
+            self.emit(Op::Throw, span);
+        }
+        self.unwind.truncate(base);
+
+        for at in ends {
+            self.patch(at);
+        }
+    }
+
+    /* an operand that can be read without running any code, other than `undefined` */
+    fn arg(&mut self, e: &Expr) -> Option<Arg> {
+        match &e.kind {
+            ExprKind::Const(Const::Undef) => None,
+            ExprKind::Const(c) => Some(Arg::Const(self.constant(Object::Const(c.clone())))),
+            ExprKind::Var(name) => {
+                let v = self.var(name);
+                match self.code.vars[v as usize].at {
+                    At::Reg(r) => Some(Arg::Reg(r as u32)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * The operands of a binary operator, pushing those that take code to evaluate.
+     * `lhs` is only read in place when `rhs` is too, which keeps them in order.
+     */
+    fn operands(&mut self, lhs: &'a Expr, rhs: &'a Expr) -> (Arg, Arg) {
+        if let Some(r) = self.arg(rhs) {
+            if let Some(l) = self.arg(lhs) {
+                return (l, r);
+            }
+            self.expr(lhs);
+            return (Arg::Stack, r);
+        }
+
+        self.expr(lhs);
+        self.expr(rhs);
+        (Arg::Stack, Arg::Stack)
+    }
+
+    /* `e` for its effects only, leaving nothing on the stack */
+    fn effect(&mut self, e: &'a Expr) {
+        let span = e.span;
+
+        match &e.kind {
+            ExprKind::Update { op, target, .. } if matches!(target.kind, ExprKind::Var(_)) => {
+                let ExprKind::Var(name) = &target.kind else {
+                    unreachable!()
+                };
+                let var = self.var(name);
+                let op = op.clone();
+                self.emit(Op::StepVar { var, op }, span);
+            }
+            ExprKind::Assign { op, target, val } => self.assign(op, target, val, span, false),
+            ExprKind::Sequence(v) => {
+                for x in v {
+                    self.effect(x);
+                }
+            }
+            _ => {
+                self.expr(e);
+                self.emit(Op::Pop, span);
+            }
+        }
+    }
+
+    /* `cond` and a jump, to be patched, taken when it is falsy */
+    fn jump_unless(&mut self, cond: &'a Expr, span: Span) -> usize {
+        if let ExprKind::Binary { op, lhs, rhs } = &cond.kind {
+            let (l, r) = self.operands(lhs, rhs);
+            let op = op.clone();
+            return self.emit(Op::JumpUnless { op, l, r, to: 0 }, cond.span);
+        }
+
+        self.expr(cond);
+        self.emit(Op::JumpIfFalse(0), span)
+    }
+
+    /* `cond` and a jump to `to`, taken when it is truthy */
+    fn jump_if(&mut self, cond: &'a Expr, to: u32, span: Span) {
+        if let ExprKind::Binary { op, lhs, rhs } = &cond.kind {
+            let (l, r) = self.operands(lhs, rhs);
+            let op = op.clone();
+            self.emit(Op::JumpIf { op, l, r, to }, cond.span);
+            return;
+        }
+
+        self.expr(cond);
+        self.emit(Op::JumpIfTrue(to), span);
+    }
+
+    /* `this`, which has to be initialized in a derived class's constructor */
+    fn this(&mut self, span: Span) {
+        let v = self.var("this");
+        self.emit(Op::This(v), span);
+    }
+
+    /* a property key, which computed keys get as a string or array index */
+    fn key(&mut self, key: &'a PropKey, span: Span) {
+        match key {
+            PropKey::Named(k) => self.push_str(k, span),
+            PropKey::Computed(k) => {
+                self.expr(k);
+                self.emit(Op::ToKey, k.span);
+            }
+        }
+    }
+
+    /* a `?.` in the chain being compiled, where `pop` values are on the stack since it started */
+    fn optional(&mut self, pop: u32, span: Span) {
+        let j = self.emit(Op::JumpIfNullish { to: 0, pop }, span);
+        self.chain.push(j);
+    }
+
+    /* pushes the `this` and the function a call of `callee` gets; `o.m()` calls `m` with `this` set to `o` */
+    fn callee(&mut self, callee: &'a Expr) {
+        let span = callee.span;
+
+        match &callee.kind {
+            ExprKind::Member { obj, prop } => {
+                self.expr(obj);
+                self.emit(Op::Dup, span);
+                let name = self.name(prop);
+                self.emit(Op::GetProp(name), span);
+            }
+            ExprKind::Index { obj, index } => {
+                self.expr(obj);
+                self.emit(Op::Dup, span);
+                self.expr(index);
+                self.emit(Op::ToKey, index.span);
+                self.emit(Op::GetIndex, span);
+            }
+            ExprKind::PrivateMember { obj, name } => {
+                self.expr(obj);
+                self.emit(Op::Dup, span);
+                let name = self.name(name);
+                self.emit(Op::GetPrivate(name), span);
+            }
+            ExprKind::SuperMember(key) => {
+                self.this(span);
+                self.emit(Op::Dup, span);
+                self.key(key, span);
+                self.emit(Op::GetSuper, span);
+            }
+            /* `o.m?.()` still calls `m` on `o` */
+            ExprKind::Optional(inner) => {
+                self.callee(inner);
+                self.optional(2, span);
+            }
+            _ => {
+                self.emit(Op::Undef, span);
+                self.expr(callee);
+            }
+        }
+    }
+
+    /* an array of `items`, which may have holes and spreads */
+    fn items(&mut self, items: impl IntoIterator<Item = Option<&'a Expr>>, span: Span) {
+        self.emit(Op::Array(0), span);
+
+        for item in items {
+            match item.map(|x| (x, &x.kind)) {
+                None => {
+                    self.emit(Op::AppendHole, span);
+                }
+                Some((_, ExprKind::Spread(src))) => {
+                    self.expr(src);
+                    let desc = self.name(&describe(src));
+                    self.emit(Op::AppendSpread(desc), src.span);
+                }
+                Some((x, _)) => {
+                    self.expr(x);
+                    self.emit(Op::Append, span);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &'a Expr) {
+        let span = e.span;
+        if stack::exhausted() {
+            self.too_deep.get_or_insert(span);
+            return;
+        }
+        let is_spread = |x: &Expr| matches!(x.kind, ExprKind::Spread(_));
+
+        match &e.kind {
+            ExprKind::Const(Const::Undef) => {
+                self.emit(Op::Undef, span);
+            }
+            ExprKind::Const(c) => self.push_const(c.clone(), span),
+            ExprKind::Var(name) => self.get_var(name, span),
+            ExprKind::This => self.this(span),
+            ExprKind::Unary { op, e } => {
+                self.expr(e);
+                self.emit(Op::Unary(op.clone()), e.span);
+            }
+            ExprKind::Typeof(inner) => match &inner.kind {
+                ExprKind::Var(name) => {
+                    let v = self.var(name);
+                    self.emit(Op::TypeofVar(v), span);
+                }
+                _ => {
+                    self.expr(inner);
+                    self.emit(Op::Typeof, span);
+                }
+            },
+            ExprKind::Void(inner) => {
+                self.expr(inner);
+                self.emit(Op::Pop, span);
+                self.emit(Op::Undef, span);
+            }
+            ExprKind::Delete(inner) => match &inner.kind {
+                ExprKind::Var(_) => self.push_const(Const::Bool(false), span),
+                ExprKind::PrivateMember { .. } => {
+                    self.throw_syntax("Private fields can not be deleted", inner.span)
+                }
+                ExprKind::Member { obj, prop } => {
+                    self.expr(obj);
+                    self.push_str(prop, span);
+                    self.emit(Op::Delete, inner.span);
+                }
+                ExprKind::Index { obj, index } => {
+                    self.expr(obj);
+                    self.expr(index);
+                    self.emit(Op::ToKey, index.span);
+                    self.emit(Op::Delete, inner.span);
+                }
+                _ => {
+                    self.expr(inner);
+                    self.emit(Op::Pop, span);
+                    self.push_const(Const::Bool(true), span);
+                }
+            },
+            ExprKind::Update { op, prefix, target } => {
+                let (op, prefix) = (op.clone(), *prefix);
+
+                match &target.kind {
+                    ExprKind::Var(name) => {
+                        let var = self.var(name);
+                        self.emit(Op::UpdateVar { var, op, prefix }, span);
+                    }
+                    ExprKind::Member { obj, prop } => {
+                        self.expr(obj);
+                        let name = self.name(prop);
+                        self.emit(Op::UpdateProp { name, op, prefix }, span);
+                    }
+                    ExprKind::PrivateMember { obj, name } => {
+                        self.expr(obj);
+                        let name = self.name(name);
+                        self.emit(Op::UpdatePrivate { name, op, prefix }, span);
+                    }
+                    /* `super.x++` updates `x` on `this` */
+                    ExprKind::Index { .. } | ExprKind::SuperMember(_) => {
+                        self.reference(target);
+                        self.emit(Op::UpdateIndex { op, prefix }, span);
+                    }
+                    _ => self.throw_syntax("Invalid left-hand side in assignment", target.span),
+                }
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                /* a left-deep chain such as `1 + 1 + ...` is compiled from its innermost link out in a loop */
+                let mut links = vec![(op, rhs, span)];
+                let mut head = &**lhs;
+                while let ExprKind::Binary { op, lhs, rhs } = &head.kind {
+                    links.push((op, rhs, head.span));
+                    head = lhs;
+                }
+
+                let mut lhs = Some(head);
+                for (op, rhs, span) in links.into_iter().rev() {
+                    let (l, r) = match lhs.take() {
+                        Some(lhs) => self.operands(lhs, rhs),
+                        None => match self.arg(rhs) {
+                            Some(r) => (Arg::Stack, r),
+                            None => {
+                                self.expr(rhs);
+                                (Arg::Stack, Arg::Stack)
+                            }
+                        },
+                    };
+                    let op = op.clone();
+                    self.emit(Op::Binary { op, l, r }, span);
+                }
+            }
+            ExprKind::Logical { op, lhs, rhs } => {
+                self.expr(lhs);
+                let skip = self.emit(short_circuit(op), span);
+                self.expr(rhs);
+                self.patch(skip);
+            }
+            ExprKind::In { lhs, rhs } => match &lhs.kind {
+                ExprKind::PrivateName(name) => {
+                    self.expr(rhs);
+                    let name = self.name(name);
+                    self.emit(Op::HasPrivate(name), span);
+                }
+                _ => {
+                    self.expr(lhs);
+                    self.emit(Op::ToStr, lhs.span);
+                    self.expr(rhs);
+                    self.emit(Op::In, span);
+                }
+            },
+            ExprKind::Instanceof { lhs, rhs } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit(Op::Instanceof, span);
+            }
+            ExprKind::Conditional { test, cons, alt } => {
+                let skip = self.jump_unless(test, span);
+                self.expr(cons);
+                let end = self.emit(Op::Jump(0), span);
+                self.patch(skip);
+                self.expr(alt);
+                self.patch(end);
+            }
+            ExprKind::Assign { op, target, val } => self.assign(op, target, val, span, true),
+            ExprKind::Sequence(v) => {
+                if v.is_empty() {
+                    self.emit(Op::Undef, span);
+                }
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Op::Pop, span);
+                    }
+                    self.expr(x);
+                }
+            }
+            ExprKind::Call { callee, args } => {
+                self.callee(callee);
+                let desc = self.name(&describe(callee));
+
+                /* arguments with spreads in them are collected in an array */
+                if args.iter().any(is_spread) {
+                    self.items(args.iter().map(Some), span);
+                    self.emit(Op::CallArgs(desc), span);
+                    return;
+                }
+                for a in args {
+                    self.expr(a);
+                }
+                let argc = args.len() as u32;
+                self.emit(Op::Call { argc, desc }, span);
+            }
+            ExprKind::New { callee, args } => {
+                self.expr(callee);
+                let desc = self.name(&describe(callee));
+
+                if args.iter().any(is_spread) {
+                    self.items(args.iter().map(Some), span);
+                    self.emit(Op::NewArgs(desc), span);
+                    return;
+                }
+                for a in args {
+                    self.expr(a);
+                }
+                let argc = args.len() as u32;
+                self.emit(Op::New { argc, desc }, span);
+            }
+            ExprKind::SuperCall(args) => {
+                /* `this` is read as it is, before `super()` initializes it */
+                self.get_var("this", span);
+                self.items(args.iter().map(Some), span);
+                self.emit(Op::SuperCall, span);
+                /* the parent's constructor may have returned another object to go on with */
+                self.emit(Op::Dup, span);
+                self.set_var("this", span);
+            }
+            ExprKind::TaggedTemplate { tag, site, exprs } => {
+                self.callee(tag);
+                self.code.templates.push(site.clone());
+                self.emit(Op::Strings(self.code.templates.len() as u32 - 1), span);
+                for x in exprs {
+                    self.expr(x);
+                }
+
+                let argc = exprs.len() as u32 + 1;
+                let desc = self.name(&describe(tag));
+                self.emit(Op::Call { argc, desc }, span);
+            }
+            ExprKind::Function {
+                name,
+                args,
+                body,
+                form,
+            } => {
+                let named = name.is_some();
+                let p = self.proto(
+                    name.as_deref().unwrap_or_default(),
+                    args,
+                    body,
+                    *form,
+                    named,
+                );
+                match named {
+                    true => self.emit(Op::NamedClosure(p), span),
+                    false => self.emit(Op::Closure(p), span),
+                };
+            }
+            ExprKind::Class {
+                name,
+                parent,
+                members,
+            } => {
+                let name = name.as_deref().unwrap_or_default();
+                self.class(name, parent.as_deref(), members, span);
+            }
+            ExprKind::Array(elems) => {
+                if elems.iter().any(|el| el.as_ref().is_none_or(is_spread)) {
+                    return self.items(elems.iter().map(Option::as_ref), span);
+                }
+                for el in elems.iter().flatten() {
+                    self.expr(el);
+                }
+                self.emit(Op::Array(elems.len() as u32), span);
+            }
+            ExprKind::Object(entries) => {
+                self.emit(Op::NewObject, span);
+
+                for entry in entries {
+                    match entry {
+                        ObjProp::KeyValue {
+                            key: PropKey::Named(k),
+                            val,
+                        } => {
+                            self.expr(val);
+                            let name = self.name(k);
+                            self.emit(Op::InitProp(name), span);
+                        }
+                        ObjProp::KeyValue {
+                            key: PropKey::Computed(k),
+                            val,
+                        } => {
+                            self.expr(k);
+                            self.emit(Op::ToStr, k.span);
+                            self.expr(val);
+                            self.emit(Op::InitIndex, span);
+                        }
+                        ObjProp::Accessor { key, kind, val } => {
+                            match key {
+                                PropKey::Named(k) => self.push_str(k, span),
+                                PropKey::Computed(k) => {
+                                    self.expr(k);
+                                    self.emit(Op::ToStr, k.span);
+                                }
+                            }
+                            self.expr(val);
+                            self.emit(Op::InitAccessor(*kind), span);
+                        }
+                        ObjProp::Spread(src) => {
+                            self.expr(src);
+                            self.emit(Op::InitSpread, src.span);
+                        }
+                        ObjProp::Proto(v) => {
+                            self.expr(v);
+                            self.emit(Op::InitProto, span);
+                        }
+                    }
+                }
+            }
+            ExprKind::Member { obj, prop } => {
+                self.expr(obj);
+                let name = self.name(prop);
+                self.emit(Op::GetProp(name), span);
+            }
+            ExprKind::Index { obj, index } => {
+                self.expr(obj);
+                self.expr(index);
+                self.emit(Op::ToKey, index.span);
+                self.emit(Op::GetIndex, span);
+            }
+            ExprKind::PrivateMember { obj, name } => {
+                self.expr(obj);
+                let name = self.name(name);
+                self.emit(Op::GetPrivate(name), span);
+            }
+            ExprKind::SuperMember(key) => {
+                self.this(span);
+                self.key(key, span);
+                self.emit(Op::GetSuper, span);
+            }
+            /* every `?.` in the chain jumps to its end, where the chain is `undefined` */
+            ExprKind::Chain(inner) => {
+                let outer = std::mem::take(&mut self.chain);
+                self.expr(inner);
+                let exits = std::mem::replace(&mut self.chain, outer);
+
+                if !exits.is_empty() {
+                    let end = self.emit(Op::Jump(0), span);
+                    for at in exits {
+                        self.patch(at);
+                    }
+                    self.emit(Op::Undef, span);
+                    self.patch(end);
+                }
+            }
+            ExprKind::Optional(inner) => {
+                self.expr(inner);
+                self.optional(1, span);
+            }
+            ExprKind::Template { quasis, exprs } => {
+                let mut parts = 0;
+
+                for (i, q) in quasis.iter().enumerate() {
+                    if i > 0 {
+                        let x = &exprs[i - 1];
+                        self.expr(x);
+                        self.emit(Op::ToStr, x.span);
+                        parts += 1;
+                    }
+                    let c = self.constant(Object::str(q.clone()));
+                    self.emit(Op::Const(c), span);
+                    parts += 1;
+                }
+                self.emit(Op::Concat(parts), span);
+            }
+            ExprKind::PrivateName(name) => {
+                self.throw_syntax(format!("Unexpected identifier '{name}'"), span)
+            }
+            ExprKind::Spread(_) => self.throw_syntax(
+                "spread is only allowed in array literals and argument lists",
+                span,
+            ),
+        }
+    }
+
+    /**
+     * Pushes the object and key of a property reference, and gives the instructions that
+     * read, write and copy it and how many values it took. `super.x` refers to `x` on `this`.
+     */
+    fn reference(&mut self, target: &'a Expr) -> (Op, Op, Op, u32) {
+        match &target.kind {
+            ExprKind::Member { obj, prop } => {
+                self.expr(obj);
+                let name = self.name(prop);
+                (Op::GetProp(name), Op::SetProp(name), Op::Dup, 1)
+            }
+            ExprKind::PrivateMember { obj, name } => {
+                self.expr(obj);
+                let name = self.name(name);
+                (Op::GetPrivate(name), Op::SetPrivate(name), Op::Dup, 1)
+            }
+            ExprKind::Index { obj, index } => {
+                self.expr(obj);
+                self.expr(index);
+                self.emit(Op::ToKey, index.span);
+                (Op::GetIndex, Op::SetIndex, Op::Dup2, 2)
+            }
+            ExprKind::SuperMember(key) => {
+                self.this(target.span);
+                self.key(key, target.span);
+                (Op::GetIndex, Op::SetIndex, Op::Dup2, 2)
+            }
+            _ => unreachable!("not a property reference"),
+        }
+    }
+
+    /* `target op val` for a variable or property target, leaving its value on the stack if `keep` */
+    fn assign(&mut self, op: &EOperator, target: &'a Expr, val: &'a Expr, span: Span, keep: bool) {
+        let base = op.compound_base();
+        let logical = matches!(
+            base,
+            Some(EOperator::And | EOperator::Or | EOperator::Nullish)
+        );
+
+        let (get, set, dup, refs) = match &target.kind {
+            ExprKind::Var(name) => {
+                match base {
+                    Some(base) if logical => {
+                        self.get_var(name, span);
+                        let skip = self.emit(short_circuit(&base), span);
+                        self.expr(val);
+                        self.emit(Op::Dup, span);
+                        self.set_var(name, span);
+                        self.patch(skip);
+                        if !keep {
+                            self.emit(Op::Pop, span);
+                        }
+                        return;
+                    }
+                    Some(op) => {
+                        let (l, r) = self.operands(target, val);
+                        self.emit(Op::Binary { op, l, r }, span);
+                    }
+                    None => self.expr(val),
+                }
+                if keep {
+                    self.emit(Op::Dup, span);
+                }
+                self.set_var(name, span);
+                return;
+            }
+            ExprKind::Member { .. }
+            | ExprKind::PrivateMember { .. }
+            | ExprKind::Index { .. }
+            | ExprKind::SuperMember(_) => self.reference(target),
+            _ => {
+                self.throw_syntax("Invalid left-hand side in assignment", target.span);
+                return;
+            }
+        };
+
+        match base {
+            None => {
+                self.expr(val);
+                self.emit(set, span);
+            }
+            Some(base) if logical => {
+                self.emit(dup, span);
+                self.emit(get, span);
+                let skip = self.emit(short_circuit(&base), span);
+                self.expr(val);
+                self.emit(set, span);
+                let end = self.emit(Op::Jump(0), span);
+                /* the current value stays, and the reference goes */
+                self.patch(skip);
+                self.emit(Op::Nip(refs), span);
+                self.patch(end);
+            }
+            Some(op) => {
+                self.emit(dup, span);
+                self.emit(get, span);
+                self.expr(val);
+                self.emit(
+                    Op::Binary {
+                        op,
+                        l: Arg::Stack,
+                        r: Arg::Stack,
+                    },
+                    span,
+                );
+                self.emit(set, span);
+            }
+        }
+        if !keep {
+            self.emit(Op::Pop, span);
+        }
+    }
+}
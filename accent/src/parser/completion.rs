@@ -0,0 +1,14 @@
+use super::obj::Object;
+
+/**
+ * Result of executing statements.
+ * `Throw` carries the thrown value (usually an Error object, see `errors::new_error`).
+ */
+#[derive(Debug, Clone)]
+pub enum Completion {
+    Normal,
+    Return(Object),
+    Throw(Object),
+    Break(Option<String>),    /* label, if any */
+    Continue(Option<String>), /* label, if any */
+}
@@ -3,10 +3,10 @@
  * AVOID USING IT AT ALL COSTS.  
  * `Module` now manages functions in `Objects`
  */
-use super::{function::Function, module::Module, obj::Object};
+use super::{function::Function, module::Module};
 
 pub struct Ctx<'a> {
-    pub mods: Vec<&'a mut Module<'a>>,
+    pub mods: Vec<&'a mut Module>,
     pub funcs: Vec<&'a mut Function>,
 }
 
@@ -18,3 +18,9 @@ impl<'a> Ctx<'a> {
         }
     }
 }
+
+impl Default for Ctx<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
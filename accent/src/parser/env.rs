@@ -0,0 +1,498 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use super::{
+    heap::{self, Handle, Trace, Tracer},
+    obj::Object,
+};
+
+const SCOPES: &str = "compiled code knows the scopes around it";
+
+/**
+ * The names compiled code declares in a scope, each with a fixed slot, so that the
+ * code can reach them by position instead of by name.
+ * Every frame running the same function body shares one layout.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    index: HashMap<String, usize>,
+    names: Vec<String>,
+    /* which of them are `const` */
+    consts: Vec<bool>,
+    /* which of them are the name a function expression has for itself, which assignments leave alone */
+    fixed: Vec<bool>,
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout::default()
+    }
+
+    /* the slot of `n`, added at the end if `n` has none yet */
+    pub fn add(&mut self, n: &str) -> usize {
+        if let Some(i) = self.index.get(n) {
+            return *i;
+        }
+
+        self.names.push(n.to_string());
+        self.consts.push(false);
+        self.fixed.push(false);
+        self.index.insert(n.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    /* like `add`, for a `const` */
+    pub fn add_const(&mut self, n: &str) -> usize {
+        let i = self.add(n);
+        self.consts[i] = true;
+        i
+    }
+
+    /* like `add`, for the name of a function expression in the scope made for it */
+    pub fn add_fixed(&mut self, n: &str) -> usize {
+        let i = self.add(n);
+        self.fixed[i] = true;
+        i
+    }
+
+    pub fn slot(&self, n: &str) -> Option<usize> {
+        self.index.get(n).copied()
+    }
+
+    pub fn name(&self, i: usize) -> &str {
+        &self.names[i]
+    }
+
+    pub fn is_const(&self, i: usize) -> bool {
+        self.consts[i]
+    }
+
+    pub fn is_fixed(&self, i: usize) -> bool {
+        self.fixed[i]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/* what a name is bound to in the scopes around some code */
+#[derive(Debug, Clone)]
+pub enum Binding {
+    Value(Object),
+    /* a `let`, `const` or `class` whose declaration has not run yet, which cannot be used */
+    Uninitialized,
+}
+
+impl Binding {
+    fn of(v: &Option<Object>) -> Binding {
+        match v {
+            Some(v) => Binding::Value(v.clone()),
+            None => Binding::Uninitialized,
+        }
+    }
+}
+
+/* how an assignment to a name went */
+pub enum Assigned {
+    Done,
+    Const,
+    Uninitialized,
+}
+
+struct Frame {
+    /* bindings of the names in `layout`; `None` until the declaration runs */
+    slots: Vec<Option<Object>>,
+    layout: Option<Rc<Layout>>,
+    /* every other binding, `None` until the declaration runs */
+    vars: HashMap<String, Option<Object>>,
+    /* the names in `vars` declared `const` */
+    consts: Vec<String>,
+    /* a block's scope, which `var` declarations pass through */
+    block: bool,
+    parent: Option<Env>,
+}
+
+/* closures keep the scopes around them alive, so freeing one can free a long chain of scopes */
+impl Drop for Frame {
+    fn drop(&mut self) {
+        let slots = std::mem::take(&mut self.slots);
+        heap::drop_contents((slots, std::mem::take(&mut self.vars), self.parent.take()));
+    }
+}
+
+impl Frame {
+    fn new(parent: Option<&Env>, layout: Option<&Rc<Layout>>, block: bool) -> Frame {
+        Frame {
+            slots: vec![None; layout.map_or(0, |l| l.len())],
+            layout: layout.cloned(),
+            vars: HashMap::new(),
+            consts: Vec::new(),
+            block,
+            parent: parent.cloned(),
+        }
+    }
+
+    fn slot(&self, n: &str) -> Option<usize> {
+        self.layout.as_ref()?.slot(n)
+    }
+
+    fn layout(&self) -> &Layout {
+        self.layout.as_ref().expect(SCOPES)
+    }
+
+    /* the binding of `n` in this scope; `None` when `n` is not declared here */
+    fn lookup(&self, n: &str) -> Option<Binding> {
+        match self.slot(n) {
+            Some(i) => Some(Binding::of(&self.slots[i])),
+            None => self.vars.get(n).map(Binding::of),
+        }
+    }
+}
+
+/**
+ * A lexical environment: one scope's bindings plus the scope it is nested in.
+ * Environments live on the heap and are shared, so a function can keep the one it was
+ * created in alive after the call that created it has returned.
+ */
+#[derive(Clone)]
+pub struct Env(Handle<Frame>);
+
+impl Default for Env {
+    fn default() -> Env {
+        Env::new(None)
+    }
+}
+
+impl Trace for Frame {
+    fn trace(&self, t: &mut Tracer) {
+        for v in self.slots.iter().chain(self.vars.values()).flatten() {
+            v.trace(t);
+        }
+        if let Some(p) = &self.parent {
+            p.trace(t);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.vars.clear();
+        self.parent = None;
+    }
+}
+
+/* how an assignment went in the scopes that declare the name */
+enum Assignment {
+    Made(Assigned),
+    /* no scope declares the name; the value comes back */
+    Undeclared(Object),
+}
+
+impl Env {
+    /* a new, empty scope inside `parent` */
+    pub fn new(parent: Option<&Env>) -> Env {
+        Env(Handle::new(Frame::new(parent, None, false)))
+    }
+
+    /* a new, empty scope for a block inside `parent` */
+    pub fn block(parent: &Env) -> Env {
+        Env(Handle::new(Frame::new(Some(parent), None, true)))
+    }
+
+    /* a new scope inside `parent` with a slot for each name of `layout` */
+    pub fn with_layout(parent: Option<&Env>, layout: &Rc<Layout>) -> Env {
+        Env(Handle::new(Frame::new(parent, Some(layout), false)))
+    }
+
+    /**
+     * The scope for the next iteration of a `for (let ...)` loop running in this one:
+     * a copy holding the same values, so that closures made in the last iteration keep
+     * the bindings they saw. While nothing else holds this scope, it is reused.
+     */
+    pub fn next_iteration(&self) -> Env {
+        if !self.0.is_shared() {
+            return self.clone();
+        }
+
+        let frame = self.0.borrow();
+        Env(Handle::new(Frame {
+            slots: frame.slots.clone(),
+            layout: frame.layout.clone(),
+            vars: frame.vars.clone(),
+            consts: frame.consts.clone(),
+            block: frame.block,
+            parent: frame.parent.clone(),
+        }))
+    }
+
+    pub fn trace(&self, t: &mut Tracer) {
+        t.edge(&self.0);
+    }
+
+    pub fn parent(&self) -> Option<Env> {
+        self.0.borrow().parent.clone()
+    }
+
+    /* the outermost scope of the chain */
+    pub fn global(&self) -> Env {
+        let mut env = self.clone();
+
+        while let Some(p) = env.parent() {
+            env = p;
+        }
+
+        env
+    }
+
+    /* the nearest binding of `n`; `None` when no scope declares it */
+    pub fn lookup(&self, n: &str) -> Option<Binding> {
+        let frame = self.0.borrow();
+
+        match frame.lookup(n) {
+            Some(b) => Some(b),
+            None => frame.parent.as_ref()?.lookup(n),
+        }
+    }
+
+    /* the value of `n`; `None` when no scope declares it or it is not initialized yet */
+    pub fn get(&self, n: &str) -> Option<Object> {
+        match self.lookup(n)? {
+            Binding::Value(v) => Some(v),
+            Binding::Uninitialized => None,
+        }
+    }
+
+    /* creates or overwrites `n` in this scope */
+    pub fn define(&self, n: &str, v: Object) {
+        let mut frame = self.0.borrow_mut();
+
+        match frame.slot(n) {
+            Some(i) => frame.slots[i] = Some(v),
+            None => {
+                frame.consts.retain(|c| c != n);
+                frame.vars.insert(n.to_string(), Some(v));
+            }
+        }
+    }
+
+    /* declares the `let`, `const` or `class` `n` in this scope, which cannot be used until it is defined */
+    pub fn declare_lexical(&self, n: &str) {
+        let mut frame = self.0.borrow_mut();
+
+        match frame.slot(n) {
+            Some(i) => frame.slots[i] = None,
+            None => {
+                frame.vars.insert(n.to_string(), None);
+            }
+        }
+    }
+
+    /* `define`, for a `const` */
+    pub fn define_const(&self, n: &str, v: Object) {
+        self.define(n, v);
+
+        let mut frame = self.0.borrow_mut();
+        if frame.slot(n).is_none() {
+            frame.consts.push(n.to_string());
+        }
+    }
+
+    /* `define` in the scope a `var` belongs to: the nearest one that is not a block's */
+    pub fn define_var(&self, n: &str, v: Object) {
+        let frame = self.0.borrow();
+
+        match (&frame.parent, frame.block) {
+            (Some(p), true) => p.define_var(n, v),
+            _ => {
+                drop(frame);
+                self.define(n, v);
+            }
+        }
+    }
+
+    /* a `var` without a value: `undefined` in the scope it belongs to, unless `n` is there already */
+    pub fn declare_var(&self, n: &str) {
+        let frame = self.0.borrow();
+
+        match (&frame.parent, frame.block) {
+            (Some(p), true) => p.declare_var(n),
+            _ if matches!(frame.lookup(n), Some(Binding::Value(_))) => (),
+            _ => {
+                drop(frame);
+                self.define(n, Object::undef());
+            }
+        }
+    }
+
+    /**
+     * Assigns to the nearest binding of `n`; an undeclared name becomes a global.
+     * A `const` or a binding not initialized yet keeps its value, as does the name of a
+     * function expression without complaint.
+     */
+    pub fn set(&self, n: &str, v: Object) -> Assigned {
+        match self.assign(n, v) {
+            Assignment::Made(a) => a,
+            Assignment::Undeclared(v) => {
+                self.global().define(n, v);
+                Assigned::Done
+            }
+        }
+    }
+
+    fn assign(&self, n: &str, v: Object) -> Assignment {
+        let mut frame = self.0.borrow_mut();
+        let frame = &mut *frame;
+
+        let (slot, is_const, fixed) = match frame.slot(n) {
+            Some(i) => {
+                let l = frame.layout();
+                let (is_const, fixed) = (l.is_const(i), l.is_fixed(i));
+                (Some(&mut frame.slots[i]), is_const, fixed)
+            }
+            None => (
+                frame.vars.get_mut(n),
+                frame.consts.iter().any(|c| c == n),
+                false,
+            ),
+        };
+        match slot {
+            Some(None) => Assignment::Made(Assigned::Uninitialized),
+            Some(_) if is_const => Assignment::Made(Assigned::Const),
+            /* sloppy code drops the assignment without a word */
+            Some(_) if fixed => Assignment::Made(Assigned::Done),
+            Some(slot) => {
+                *slot = Some(v);
+                Assignment::Made(Assigned::Done)
+            }
+            None => match &frame.parent {
+                Some(p) => p.assign(n, v),
+                None => Assignment::Undeclared(v),
+            },
+        }
+    }
+
+    /* a copy of the bindings made directly in this scope */
+    pub fn vars(&self) -> HashMap<String, Object> {
+        let frame = self.0.borrow();
+        let mut vars: HashMap<String, Object> = frame
+            .vars
+            .iter()
+            .filter_map(|(n, v)| Some((n.clone(), v.clone()?)))
+            .collect();
+
+        if let Some(l) = &frame.layout {
+            for (n, v) in l.names.iter().zip(&frame.slots) {
+                if let Some(v) = v {
+                    vars.insert(n.clone(), v.clone());
+                }
+            }
+        }
+
+        vars
+    }
+
+    /**
+     * Gives `n` a slot in this scope, for code compiled to run here, and returns it.
+     * A binding `n` already has here moves into the slot.
+     */
+    pub fn slot_for(&self, n: &str, is_const: bool) -> usize {
+        let mut frame = self.0.borrow_mut();
+        let frame = &mut *frame;
+
+        let layout = Rc::make_mut(frame.layout.get_or_insert_with(Default::default));
+        let i = layout.add(n);
+        layout.consts[i] = is_const;
+        if frame.slots.len() < layout.len() {
+            frame.slots.resize(layout.len(), None);
+        }
+        if let Some(v) = frame.vars.remove(n) {
+            frame.slots[i] = v;
+        }
+
+        i
+    }
+
+    /* the names that have slots in this scope */
+    pub fn layout(&self) -> Rc<Layout> {
+        self.0.borrow().layout.clone().unwrap_or_default()
+    }
+
+    /* the layouts of the scopes around this one, innermost first, up to the first that has none */
+    pub fn outer_layouts(&self) -> Vec<Rc<Layout>> {
+        let mut res = Vec::new();
+        let mut cur = self.parent();
+
+        while let Some(env) = cur {
+            let frame = env.0.borrow();
+            match &frame.layout {
+                Some(l) => res.push(l.clone()),
+                None => break,
+            }
+            cur = frame.parent.clone();
+        }
+
+        res
+    }
+
+    /* the scope `hops` levels out from this one */
+    pub fn outer(&self, hops: usize) -> Env {
+        let mut env = self.clone();
+
+        for _ in 0..hops {
+            env = env.parent().expect(SCOPES);
+        }
+
+        env
+    }
+
+    /* reads the binding in slot `i` of the scope `hops` levels out */
+    pub fn get_slot(&self, hops: usize, i: usize) -> Binding {
+        let frame = self.0.borrow();
+
+        match hops {
+            0 => Binding::of(&frame.slots[i]),
+            _ => frame.parent.as_ref().expect(SCOPES).get_slot(hops - 1, i),
+        }
+    }
+
+    /* assigns to the binding in slot `i` of the scope `hops` levels out, the way `set` does */
+    pub fn set_slot(&self, hops: usize, i: usize, v: Object) -> Assigned {
+        if hops > 0 {
+            let parent = self.0.borrow().parent.clone();
+            return parent.expect(SCOPES).set_slot(hops - 1, i, v);
+        }
+
+        let mut frame = self.0.borrow_mut();
+        let frame = &mut *frame;
+        let layout = frame.layout.as_ref().expect(SCOPES);
+        match &mut frame.slots[i] {
+            None => Assigned::Uninitialized,
+            Some(_) if layout.is_const(i) => Assigned::Const,
+            Some(_) if layout.is_fixed(i) => Assigned::Done,
+            Some(slot) => {
+                *slot = v;
+                Assigned::Done
+            }
+        }
+    }
+
+    /* runs `f` on slot `i` of this scope, which is `None` while its name is not declared */
+    pub fn with_slot<R>(&self, i: usize, f: impl FnOnce(&mut Option<Object>) -> R) -> R {
+        f(&mut self.0.borrow_mut().slots[i])
+    }
+
+    /* declares the name in slot `i` of this scope; `None` undoes the declaration */
+    pub fn put_slot(&self, i: usize, v: Option<Object>) -> Option<Object> {
+        std::mem::replace(&mut self.0.borrow_mut().slots[i], v)
+    }
+}
+
+/* environments can contain functions that point back at them, so never print the contents */
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Env {{ .. }}")
+    }
+}
@@ -0,0 +1,63 @@
+use crate::token::Span;
+
+use super::{
+    classes::ClassO,
+    obj::Object,
+    props::{Property, Props},
+};
+
+/* native Error constructors available to scripts */
+pub const ERROR_TYPES: [&str; 6] = [
+    "Error",
+    "TypeError",
+    "ReferenceError",
+    "RangeError",
+    "SyntaxError",
+    "EvalError",
+];
+
+/**
+ * Builds an Error object like `new <name>(msg)` would.
+ * `stack` records where it was created, e.g. "TypeError: x is not a function\n    at 3:5".
+ */
+pub fn new_error(name: &str, msg: impl Into<String>, span: Span) -> Object {
+    let msg: String = msg.into();
+    let mut props = Props::ordinary();
+
+    let head = if msg.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name}: {msg}")
+    };
+
+    props.define("name", Property::hidden(Object::str(name)));
+    props.define("message", Property::hidden(Object::str(msg)));
+    props.define(
+        "stack",
+        Property::hidden(Object::str(format!("{head}\n    at {span}"))),
+    );
+
+    Object::instance(ClassO {
+        name: name.to_string(),
+        class: None,
+        props,
+        private: Vec::new(),
+        initialized: true,
+    })
+}
+
+/* the exception for making a string longer than `MAX_STR_LENGTH` */
+pub fn invalid_string_length(span: Span) -> Object {
+    new_error("RangeError", "Invalid string length", span)
+}
+
+/* text printed for an exception nobody caught */
+pub fn uncaught_message(e: &Object) -> String {
+    match e {
+        Object::ClassObj(o) => match o.borrow().props.get("stack") {
+            Some(s) => format!("Uncaught {s}"),
+            None => format!("Uncaught {e}"),
+        },
+        _ => format!("Uncaught {e}"),
+    }
+}
@@ -1,16 +1,184 @@
-use crate::ast::{Expr, Stmt};
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt,
+    ops::Deref,
+    rc::Rc,
+};
 
-use super::{module::Module, obj::Object};
+use crate::ast::{Expr, FuncForm, Stmt};
 
-#[derive(Debug, Clone)]
-pub enum Function {
+use super::{
+    builtins,
+    bytecode::Code,
+    compiler,
+    env::{Env, Layout},
+    heap::{self, Node, Trace, Tracer},
+    module::Module,
+    obj::Object,
+    props::{Property, Props},
+};
+
+/* a function implemented in Rust; gets `this` and the arguments */
+pub type NativeFn = fn(&Object, &[Object], &mut Module) -> Result<Object, Object>;
+
+/**
+ * The source of a coded function, shared by every function object made from the same literal.
+ * The VM compiles it the first time one of them is called.
+ */
+#[derive(Debug)]
+pub struct Proto {
+    pub name: String,
+    pub args: Rc<[Expr]>,
+    pub body: Rc<[Stmt]>,
+    pub form: FuncForm,
+    /* layouts of the scopes around the literal, innermost first; empty when they are not known */
+    pub scopes: Vec<Rc<Layout>>,
+    pub code: OnceCell<Code>,
+    /* what `uses_arguments` found */
+    arguments: OnceCell<bool>,
+}
+
+impl Proto {
+    pub fn new(name: &str, args: &Rc<[Expr]>, body: &Rc<[Stmt]>, form: FuncForm) -> Rc<Proto> {
+        Proto::in_scopes(name, args, body, form, Vec::new())
+    }
+
+    /* a literal whose surrounding scopes have the layouts `scopes` */
+    pub fn in_scopes(
+        name: &str,
+        args: &Rc<[Expr]>,
+        body: &Rc<[Stmt]>,
+        form: FuncForm,
+        scopes: Vec<Rc<Layout>>,
+    ) -> Rc<Proto> {
+        Rc::new(Proto {
+            name: name.to_string(),
+            args: args.clone(),
+            body: body.clone(),
+            form,
+            scopes,
+            code: OnceCell::new(),
+            arguments: OnceCell::new(),
+        })
+    }
+
+    /* whether a call needs an `arguments` object: arrows never do, other functions when they use it */
+    pub fn uses_arguments(&self) -> bool {
+        *self.arguments.get_or_init(|| {
+            self.form != FuncForm::Arrow && compiler::uses_arguments(&self.args, &self.body)
+        })
+    }
+}
+
+/* what calling a function runs */
+#[derive(Debug)]
+pub enum FnKind {
     Native {
         name: String,
-        f: fn(&[Object], &mut Module) -> Object,
+        f: NativeFn,
     },
     Coded {
-        name: String,
-        args: Vec<Expr>,
-        body: Vec<Stmt>,
+        proto: Rc<Proto>,
+        env: Env, /* scope the function was created in */
     },
 }
+
+/* a function object: its code, and the properties scripts give it */
+pub struct FunctionO {
+    pub kind: FnKind,
+    pub props: RefCell<Props>,
+}
+
+/**
+ * A reference to a function object. Functions are shared like heap objects,
+ * so a copy is the same function, with the same properties, and compares equal to it.
+ */
+#[derive(Clone)]
+pub struct Function(Rc<FunctionO>);
+
+impl Function {
+    /* a new function inheriting from `Function.prototype` */
+    pub fn new(kind: FnKind) -> Function {
+        Function::with_proto(kind, builtins::object::function_prototype())
+    }
+
+    /* for the functions made while `Function.prototype` itself is set up */
+    pub fn with_proto(kind: FnKind, proto: Object) -> Function {
+        let rc = Rc::new(FunctionO {
+            kind,
+            props: RefCell::new(Props::with_proto(Some(proto))),
+        });
+        heap::track(&rc);
+        Function(rc)
+    }
+
+    pub fn native(name: &str, f: NativeFn) -> Function {
+        Function::new(FnKind::Native {
+            name: name.to_string(),
+            f,
+        })
+    }
+
+    /* a plain function also gets the `prototype` that objects it constructs inherit from */
+    pub fn coded(proto: Rc<Proto>, env: Env) -> Function {
+        let plain = proto.form == FuncForm::Plain;
+        let f = Function::new(FnKind::Coded { proto, env });
+
+        if plain {
+            let mut p = Props::ordinary();
+            p.define("constructor", Property::hidden(Object::Funct(f.clone())));
+            f.props
+                .borrow_mut()
+                .define("prototype", Property::hidden(Object::obj(p)));
+        }
+        f
+    }
+
+    pub fn ptr_eq(a: &Function, b: &Function) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    pub fn trace(&self, t: &mut Tracer) {
+        t.rc(&self.0);
+    }
+}
+
+impl Deref for Function {
+    type Target = FunctionO;
+
+    fn deref(&self) -> &FunctionO {
+        &self.0
+    }
+}
+
+/* a function can be among its own properties, so only its name is printed */
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FnKind::Native { name, .. } => write!(f, "Function({name})"),
+            FnKind::Coded { proto, .. } => write!(f, "Function({})", proto.name),
+        }
+    }
+}
+
+/* a function holds on to the scope it was created in */
+impl Node for FunctionO {
+    fn trace_node(&self, t: &mut Tracer) -> bool {
+        let Ok(props) = self.props.try_borrow() else {
+            return false;
+        };
+
+        if let FnKind::Coded { env, .. } = &self.kind {
+            env.trace(t);
+        }
+        props.trace(t);
+
+        true
+    }
+
+    fn clear_node(&self) {
+        if let Ok(mut p) = self.props.try_borrow_mut() {
+            p.clear();
+        }
+    }
+}
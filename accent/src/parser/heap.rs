@@ -0,0 +1,278 @@
+/*
+ * The object heap and its collector.
+ *
+ * Objects are reference counted, which frees most garbage right away but never a cycle
+ * (a parent and its child pointing at each other, a closure stored in the scope it captures).
+ * The collector finds those by tracing. Its roots are every reference held from outside the
+ * heap: the `Module`s and environments being run, and the values native code has on the
+ * Rust stack. They are not listed anywhere; instead a collection counts the references each
+ * object gets from other heap objects, and an object with more references than that is
+ * held from outside. Everything reachable from those is live; the rest is garbage, and
+ * clearing its contents breaks the cycles so reference counting can free it.
+ */
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    ops::Deref,
+    rc::{Rc, Weak},
+};
+
+/* a collection runs at the next statement once the heap has this many objects, or twice as many as the last one left */
+const MIN_THRESHOLD: usize = 10_000;
+
+/* objects freed inside the frees of this many others wait for the outermost to finish */
+const MAX_DROP_DEPTH: usize = 64;
+
+/* implemented by everything stored on the heap */
+pub trait Trace {
+    /* reports each reference to a heap object that `self` holds, once per reference */
+    fn trace(&self, t: &mut Tracer);
+    /* drops the contents; only done to objects nothing can reach any more */
+    fn clear(&mut self);
+}
+
+/* an object as the collector sees it */
+pub trait Node {
+    /* `false` when the object is in use and cannot be looked at */
+    fn trace_node(&self, t: &mut Tracer) -> bool;
+    fn clear_node(&self);
+}
+
+impl<T: Trace> Node for RefCell<T> {
+    fn trace_node(&self, t: &mut Tracer) -> bool {
+        match self.try_borrow() {
+            Ok(v) => {
+                v.trace(t);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear_node(&self) {
+        if let Ok(mut v) = self.try_borrow_mut() {
+            v.clear();
+        }
+    }
+}
+
+/* collects the references a node holds to the objects taking part in a collection */
+pub struct Tracer<'a> {
+    index: &'a HashMap<*const (), usize>,
+    found: Vec<usize>,
+}
+
+impl Tracer<'_> {
+    pub fn edge<T>(&mut self, h: &Handle<T>) {
+        self.rc(&h.0);
+    }
+
+    /* a reference to a shared object that is not behind a `Handle`, such as a class */
+    pub fn rc<T: ?Sized>(&mut self, rc: &Rc<T>) {
+        if let Some(i) = self.index.get(&(Rc::as_ptr(rc) as *const ())) {
+            self.found.push(*i);
+        }
+    }
+}
+
+/* what `gc()` and hosts can see of the heap */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    /* objects alive right now */
+    pub live: usize,
+    /* objects ever allocated */
+    pub allocated: usize,
+    /* objects the collector freed, over all collections */
+    pub collected: usize,
+    pub collections: usize,
+}
+
+struct Heap {
+    nodes: Vec<Weak<dyn Node>>,
+    stats: HeapStats,
+    /* size of `nodes` that starts the next collection */
+    threshold: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        nodes: Vec::new(),
+        stats: HeapStats::default(),
+        threshold: MIN_THRESHOLD,
+    });
+    /* set while a collection runs, which must not start another */
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+    /* how many `drop_contents` calls are running inside each other */
+    static DROP_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /* contents whose drop was put off because it would have nested too deeply */
+    static DEFERRED: RefCell<Vec<Box<dyn Any>>> = const { RefCell::new(Vec::new()) };
+}
+
+/**
+ * Drops the contents of an object that is being freed. Freeing them can free objects they
+ * refer to in turn, so a long chain of objects (a linked list) would recurse once per link;
+ * past `MAX_DROP_DEPTH` the contents are queued instead, and the outermost call frees them.
+ */
+pub fn drop_contents<T: 'static>(v: T) {
+    let depth = DROP_DEPTH.get();
+    if depth >= MAX_DROP_DEPTH {
+        /* when the thread is exiting there is no queue left, and `v` is dropped right here */
+        let _ = DEFERRED.try_with(|d| d.borrow_mut().push(Box::new(v)));
+        return;
+    }
+
+    DROP_DEPTH.set(depth + 1);
+    drop(v);
+    if depth == 0 {
+        while let Some(v) = DEFERRED.try_with(|d| d.borrow_mut().pop()).ok().flatten() {
+            drop(v);
+        }
+    }
+    DROP_DEPTH.set(depth);
+}
+
+/* registers a shared object with the collector */
+pub fn track<N: Node + 'static>(rc: &Rc<N>) {
+    let w: Weak<dyn Node> = Rc::downgrade(rc) as Weak<dyn Node>;
+
+    HEAP.with_borrow_mut(|h| {
+        h.nodes.push(w);
+        h.stats.allocated += 1;
+    });
+}
+
+/**
+ * A reference to an object on the heap. Values hold objects only through handles,
+ * so every copy of a handle sees the changes made through any other.
+ */
+pub struct Handle<T>(Rc<RefCell<T>>);
+
+impl<T: Trace + 'static> Handle<T> {
+    /* puts `v` on the heap */
+    pub fn new(v: T) -> Handle<T> {
+        let rc = Rc::new(RefCell::new(v));
+        track(&rc);
+        Handle(rc)
+    }
+}
+
+impl<T> Handle<T> {
+    /* whether both handles refer to the same object */
+    pub fn ptr_eq(a: &Handle<T>, b: &Handle<T>) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    /* whether some other handle refers to the object too */
+    pub fn is_shared(&self) -> bool {
+        Rc::strong_count(&self.0) > 1
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = RefCell<T>;
+
+    fn deref(&self) -> &RefCell<T> {
+        &self.0
+    }
+}
+
+/* objects can reach themselves, so only the address is printed */
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
+pub fn stats() -> HeapStats {
+    HEAP.with_borrow(|h| HeapStats {
+        live: h.nodes.iter().filter(|w| w.strong_count() > 0).count(),
+        ..h.stats
+    })
+}
+
+/* runs a collection when enough was allocated since the last one; called between statements */
+pub fn maybe_collect() {
+    if HEAP.with_borrow(|h| h.nodes.len() >= h.threshold) {
+        collect();
+    }
+}
+
+/* a full collection; returns how many objects it freed */
+pub fn collect() -> usize {
+    if COLLECTING.replace(true) {
+        return 0;
+    }
+
+    let nodes: Vec<Rc<dyn Node>> = HEAP.with_borrow_mut(|h| {
+        h.nodes.retain(|w| w.strong_count() > 0);
+        h.nodes.iter().filter_map(Weak::upgrade).collect()
+    });
+    let index: HashMap<*const (), usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (Rc::as_ptr(n) as *const (), i))
+        .collect();
+
+    /* references from outside the heap: all of them, less the ones other objects hold (and `nodes`) */
+    let mut outside: Vec<usize> = nodes.iter().map(|n| Rc::strong_count(n) - 1).collect();
+    let mut roots = Vec::new();
+
+    for (i, n) in nodes.iter().enumerate() {
+        let mut t = Tracer {
+            index: &index,
+            found: Vec::new(),
+        };
+
+        /* an object in use is held by native code */
+        if !n.trace_node(&mut t) {
+            roots.push(i);
+        }
+        for j in t.found {
+            outside[j] -= 1;
+        }
+    }
+    roots.extend((0..nodes.len()).filter(|i| outside[*i] > 0));
+
+    /* mark */
+    let mut live = vec![false; nodes.len()];
+    while let Some(i) = roots.pop() {
+        if live[i] {
+            continue;
+        }
+        live[i] = true;
+
+        let mut t = Tracer {
+            index: &index,
+            found: Vec::new(),
+        };
+        nodes[i].trace_node(&mut t);
+        roots.extend(t.found.into_iter().filter(|j| !live[*j]));
+    }
+
+    /* sweep: the garbage is freed once `nodes` lets go of it */
+    let mut freed = 0;
+    for (n, _) in nodes.iter().zip(&live).filter(|(_, l)| !**l) {
+        n.clear_node();
+        freed += 1;
+    }
+    drop(nodes);
+
+    HEAP.with_borrow_mut(|h| {
+        h.nodes.retain(|w| w.strong_count() > 0);
+        h.stats.collected += freed;
+        h.stats.collections += 1;
+        h.threshold = MIN_THRESHOLD.max(2 * h.nodes.len());
+    });
+    COLLECTING.set(false);
+
+    freed
+}
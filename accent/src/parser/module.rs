@@ -1,41 +1,41 @@
-use crate::ast::{constants, Stmt};
-use std::collections::HashMap;
+use crate::{ast::Stmt, token::Span};
 
-use super::obj::Object;
+use super::{env::Env, obj::Object};
 
-#[derive(Debug, Clone)]
-pub struct Module<'a> {
-    pub vtable: HashMap<String, Object>,
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub env: Env, /* innermost scope the statements run in */
     pub stmts: Vec<Stmt>,
-    pub parent: Box<Option<&'a Module<'a>>>,
-    pub rt: Object,
-    pub got_rt: bool,
+    pub depth: usize,    /* number of enclosing function calls */
+    pub call_span: Span, /* where the native function being run was called from */
 }
 
-impl<'a> Module<'a> {
-    pub fn new() -> Module<'a> {
+impl Module {
+    pub fn new() -> Module {
         Module {
-            vtable: HashMap::new(),
+            env: Env::new(None),
             stmts: Vec::new(),
-            parent: Box::new(None),
-            rt: Object::Const(constants::Const::Undef), /* all functions return undefined by default */
-            got_rt: false,
+            depth: 0,
+            call_span: Span::default(),
         }
     }
 
-    pub fn get_var(&self, n: &String) -> Option<&Object> {
-        if self.vtable.contains_key(n) {
-            return self.vtable.get(n);
-        }
-
-        if let Some(md) = *self.parent {
-            return md.get_var(n);
+    /* a module running `stmts` in a fresh scope nested in `env` */
+    pub fn nested(env: &Env, stmts: Vec<Stmt>, depth: usize) -> Module {
+        Module {
+            env: Env::new(Some(env)),
+            stmts,
+            depth,
+            call_span: Span::default(),
         }
+    }
 
-        return None;
+    pub fn get_var(&self, n: &str) -> Option<Object> {
+        self.env.get(n)
     }
 
-    pub fn add_var(&mut self, n: &String, v: Object) {
-        self.vtable.insert(n.clone(), v);
+    /* declares `n` in the innermost scope */
+    pub fn add_var(&mut self, n: &str, v: Object) {
+        self.env.define(n, v);
     }
 }
@@ -1,308 +1,1610 @@
-use crate::ast::{constants, Arithmetic, EOperator, Expr};
-use std::fmt::{self};
+use crate::ast::{
+    constants::{self, JsStr},
+    ClassMember, EOperator, Expr, ExprKind, FuncForm, MethodKind, ObjProp, PropKey, Stmt,
+    TemplateSite,
+};
+use crate::token::Span;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    fmt::{self},
+    rc::Rc,
+};
 
 use super::{
-    classes::{ClassD, ClassO},
-    function::Function,
-    mod_exec,
+    array::Array,
+    builtins,
+    classes::{self, ClassD, ClassO},
+    coerce,
+    completion::Completion,
+    env::{Assigned, Binding, Env, Layout},
+    errors::{invalid_string_length, new_error, ERROR_TYPES},
+    function::{FnKind, Function, Proto},
+    heap::{Handle, Tracer},
+    mode,
     module::Module,
+    ops,
+    props::{index_key, Property, Props, Slot},
+    stack, tree_exec, vm, Mode,
 };
 
+/* deepest chain of coded function calls before a RangeError is thrown */
+pub const MAX_CALL_DEPTH: usize = 2000;
+
 #[derive(Debug, Clone)]
 pub enum Object {
     Const(constants::Const),
     Funct(Function),
     Class(ClassD),
-    ClassObj(ClassO),
+    /* class instance */
+    ClassObj(Handle<ClassO>),
+    /* plain object */
+    Obj(Handle<Props>),
+    Array(Handle<Array>),
 }
 
-pub fn obj_eval(e: &Expr, md: &Module) -> Object {
-    let mut r: Object = Object::Const(constants::Const::Undef);
+impl Object {
+    /* the heap objects this value refers to */
+    pub fn trace(&self, t: &mut Tracer) {
+        match self {
+            Object::Const(_) => (),
+            Object::Funct(f) => f.trace(t),
+            Object::Class(c) => c.trace(t),
+            Object::ClassObj(o) => t.edge(o),
+            Object::Obj(o) => t.edge(o),
+            Object::Array(a) => t.edge(a),
+        }
+    }
+
+    pub fn undef() -> Object {
+        Object::Const(constants::Const::Undef)
+    }
+
+    pub fn obj(props: Props) -> Object {
+        Object::Obj(Handle::new(props))
+    }
+
+    pub fn instance(o: ClassO) -> Object {
+        Object::ClassObj(Handle::new(o))
+    }
+
+    pub fn array(a: Array) -> Object {
+        Object::Array(Handle::new(a))
+    }
 
-    match e {
-        Expr::Const(v) => {
-            r = Object::Const(v.clone());
+    pub fn str(v: impl Into<JsStr>) -> Object {
+        Object::Const(constants::Const::Str {
+            v: v.into(),
+            is_raw: false,
+            is_fmt: false,
+        })
+    }
+}
+
+/* ToBoolean */
+pub fn truthy(o: &Object) -> bool {
+    match o {
+        Object::Const(c) => match c {
+            constants::Const::Integer(i) => *i != 0,
+            constants::Const::BigInt(i) => !i.is_zero(),
+            constants::Const::Float(f) => *f != 0.0 && !f.is_nan(),
+            constants::Const::Str { v, .. } => !v.is_empty(),
+            constants::Const::Bool(b) => *b,
+            constants::Const::Undef | constants::Const::Null => false,
+        },
+        _ => true,
+    }
+}
+
+/* the `arguments` object of a call: the arguments by index, and their number as a hidden `length` */
+pub fn arguments_object(args: &[Object]) -> Object {
+    let mut props = Props::ordinary();
+    for (i, a) in args.iter().enumerate() {
+        props.set(&i.to_string(), a.clone());
+    }
+    props.define("length", Property::hidden(ops::int(args.len() as i64)));
+
+    Object::obj(props)
+}
+
+/* binds `args` to the parameter names `params`; missing arguments are undefined */
+fn bind_args(fmd: &mut Module, params: &[Expr], args: &[Object]) {
+    for (j, jv) in params.iter().enumerate() {
+        if let ExprKind::Var(vn) = &jv.kind {
+            fmd.add_var(vn, args.get(j).cloned().unwrap_or_else(Object::undef));
         }
-        Expr::Var(name) => {
-            if md.vtable.contains_key(name) {
-                r = md.vtable.get(name).unwrap().clone();
-            } else {
-                println!("undefined variable '{name}'");
+    }
+}
+
+/* calls `f` with `this` bound to `this` (ignored by arrows and natives) */
+pub fn call_function(
+    f: &Function,
+    this: Object,
+    args: Vec<Object>,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    match &f.kind {
+        /* natives run in the scope of their caller */
+        FnKind::Native { f, .. } => f(
+            &this,
+            &args,
+            &mut Module {
+                env: md.env.clone(),
+                stmts: Vec::new(),
+                depth: md.depth,
+                call_span: span,
+            },
+        ),
+        FnKind::Coded { proto, env } => {
+            if md.depth >= MAX_CALL_DEPTH || stack::exhausted() {
+                return Err(new_error(
+                    "RangeError",
+                    "Maximum call stack size exceeded",
+                    span,
+                ));
+            }
+
+            if mode() == Mode::Vm {
+                return vm::call(proto, env, this, args, md.depth + 1);
+            }
+
+            /* the body runs in a scope nested in the one the function was created in */
+            let mut fmd = Module::nested(env, Vec::new(), md.depth + 1);
+            if proto.form != FuncForm::Arrow {
+                fmd.add_var("this", this);
+            }
+            if proto.uses_arguments() {
+                fmd.add_var("arguments", arguments_object(&args));
+            }
+            bind_args(&mut fmd, &proto.args, &args);
+
+            match tree_exec(&proto.body, &mut fmd) {
+                Completion::Return(v) => Ok(v),
+                Completion::Throw(e) => Err(e),
+                _ => Ok(Object::undef()),
             }
         }
-        Expr::FuncCallSimple { name, args } => match md.get_var(name) {
-            Some(Object::Funct(id)) => {
-                let fref = md.get_var(name).unwrap();
-                let args_eval: Vec<Object> = args.iter().map(|arg| obj_eval(arg, md)).collect();
+    }
+}
 
-                match fref {
-                    Object::Funct(Function::Native { f, .. }) => {
-                        let mut mdc = md.clone();
-                        f(&args_eval, &mut mdc);
-                    }
-                    Object::Funct(Function::Coded { name, args, body }) => {
-                        let mut fmd = Module::new();
-
-                        for (j, jv) in args.iter().enumerate() {
-                            match jv {
-                                Expr::Var(vn) => {
-                                    fmd.vtable.insert(
-                                        vn.to_string(),
-                                        args_eval.get(j).unwrap().to_owned(),
-                                    );
-                                }
-                                _ => (),
-                            }
-                        }
-
-                        fmd.parent = Box::new(Some(md));
-                        fmd.stmts = body.to_vec().clone();
-
-                        mod_exec(&mut fmd);
-                        r = fmd.rt;
-                    }
-                    _ => unreachable!(),
+/* `new C(args)` */
+pub fn construct(
+    cd: &ClassD,
+    args: Vec<Object>,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    match cd {
+        ClassD::Native { name } if ERROR_TYPES.contains(&name.as_str()) => {
+            let msg = match args.first() {
+                None | Some(Object::Const(constants::Const::Undef)) => String::new(),
+                Some(m) => coerce::to_string(m, md, span)?,
+            };
+
+            Ok(new_error(name, msg, span))
+        }
+        ClassD::Native { name } => Err(new_error(
+            "TypeError",
+            format!("{name} is not a constructor"),
+            span,
+        )),
+        ClassD::Coded(c) => {
+            let proto = Some(Object::Obj(c.prototype.clone()));
+            let this = match classes::native_base(c) {
+                /* instances of a subclass of `Array` are arrays */
+                Some(f) if matches!(&f.kind, FnKind::Native { name, .. } if name == "Array") => {
+                    let mut a = Array::new(Vec::new());
+                    a.props_mut().proto = proto;
+                    Object::array(a)
                 }
+                _ => Object::instance(ClassO {
+                    name: c.name.clone(),
+                    class: Some(c.clone()),
+                    props: Props::with_proto(proto),
+                    private: Vec::new(),
+                    initialized: c.parent.is_none(),
+                }),
+            };
+
+            /* a constructor may hand back another object in place of the new one */
+            Ok(classes::initialize(cd, &this, args, md, span)?.unwrap_or(this))
+        }
+    }
+}
+
+/* the exception for an operator applied to operands it does not accept */
+fn throw_op((name, msg): ops::OpError, span: Span) -> Object {
+    new_error(name, msg, span)
+}
+
+/* reading a variable that is declared nowhere */
+pub fn not_defined(name: &str, span: Span) -> Object {
+    new_error("ReferenceError", format!("{name} is not defined"), span)
+}
+
+pub fn const_assignment(span: Span) -> Object {
+    new_error("TypeError", "Assignment to constant variable.", span)
+}
+
+/* using a `let`, `const` or `class` before its declaration has run */
+pub fn uninitialized(name: &str, span: Span) -> Object {
+    new_error(
+        "ReferenceError",
+        format!("Cannot access '{name}' before initialization"),
+        span,
+    )
+}
+
+/* the value of a variable `b` is the binding of, or the exception reading it throws */
+pub fn binding_value(name: &str, b: Option<Binding>, span: Span) -> Result<Object, Object> {
+    match b {
+        Some(Binding::Value(v)) => Ok(v),
+        Some(Binding::Uninitialized) => Err(uninitialized(name, span)),
+        None => Err(not_defined(name, span)),
+    }
+}
+
+/* the exception an assignment to the variable `name` that went as `a` throws, if any */
+pub fn assigned(name: &str, a: Assigned, span: Span) -> Result<(), Object> {
+    match a {
+        Assigned::Done => Ok(()),
+        Assigned::Const => Err(const_assignment(span)),
+        Assigned::Uninitialized => Err(uninitialized(name, span)),
+    }
+}
+
+/* `op v` for the prefix operators; all but `!` convert `v` to a number first */
+pub fn unary_op(op: &EOperator, v: Object, md: &Module, span: Span) -> Result<Object, Object> {
+    let v = match op {
+        EOperator::Not => v,
+        _ => coerce::to_numeric(&v, md, span)?,
+    };
+
+    ops::unary(op, &v).map_err(|x| throw_op(x, span))
+}
+
+/* `l op r` for the operators `ops::binary` knows */
+pub fn binary_op(
+    op: &EOperator,
+    l: Object,
+    r: Object,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    if coerce::is_primitive(&l) && coerce::is_primitive(&r) {
+        /* nothing to convert */
+        return ops::binary(op, &l, &r).map_err(|x| throw_op(x, span));
+    }
+
+    let (l, r) = coerce::operands(op, l, r, md, span)?;
+    ops::binary(op, &l, &r).map_err(|x| throw_op(x, span))
+}
+
+/* the old and the new value of a variable or property that `++` / `--` changes from `v` */
+pub fn update_op(
+    op: &EOperator,
+    v: &Object,
+    md: &Module,
+    span: Span,
+) -> Result<(Object, Object), Object> {
+    let old = coerce::to_numeric(v, md, span)?;
+    let new = ops::update(op, &old).map_err(|x| throw_op(x, span))?;
+
+    Ok((old, new))
+}
+
+/* how a callee is named in error messages, e.g. "a.b is not a function" */
+pub fn describe(e: &Expr) -> String {
+    match &e.kind {
+        ExprKind::Var(name) => name.clone(),
+        ExprKind::This => String::from("this"),
+        ExprKind::Member { obj, prop } => format!("{}.{prop}", describe(obj)),
+        ExprKind::Index { obj, .. } => format!("{}[...]", describe(obj)),
+        ExprKind::PrivateMember { obj, name } => format!("{}.{name}", describe(obj)),
+        ExprKind::SuperMember(PropKey::Named(k)) => format!("super.{k}"),
+        ExprKind::Chain(e) | ExprKind::Optional(e) => describe(e),
+        _ => String::from("expression"),
+    }
+}
+
+/* runs `f` on the own properties of `o`, other than array elements; `None` for values without any */
+pub fn with_props<R>(o: &Object, f: impl FnOnce(&Props) -> R) -> Option<R> {
+    match o {
+        Object::Obj(p) => Some(f(&p.borrow())),
+        Object::ClassObj(c) => Some(f(&c.borrow().props)),
+        Object::Array(a) => Some(f(a.borrow().props())),
+        Object::Class(ClassD::Coded(c)) => Some(f(&c.statics.borrow())),
+        Object::Funct(fun) => Some(f(&fun.props.borrow())),
+        _ => None,
+    }
+}
+
+pub fn with_props_mut<R>(o: &Object, f: impl FnOnce(&mut Props) -> R) -> Option<R> {
+    match o {
+        Object::Obj(p) => Some(f(&mut p.borrow_mut())),
+        Object::ClassObj(c) => Some(f(&mut c.borrow_mut().props)),
+        Object::Array(a) => Some(f(a.borrow_mut().props_mut())),
+        Object::Class(ClassD::Coded(c)) => Some(f(&mut c.statics.borrow_mut())),
+        Object::Funct(fun) => Some(f(&mut fun.props.borrow_mut())),
+        _ => None,
+    }
+}
+
+/* whether `o` can have properties of its own, and so be a prototype */
+pub fn is_object(o: &Object) -> bool {
+    with_props(o, |_| ()).is_some()
+}
+
+/* a data property that cannot be changed or deleted */
+fn read_only(value: Object, enumerable: bool, writable: bool) -> Property {
+    Property {
+        slot: Slot::Data { value, writable },
+        enumerable,
+        configurable: writable,
+    }
+}
+
+/* the own property `key` of `o`, array elements and string indices included */
+pub fn own_property(o: &Object, key: &str) -> Option<Property> {
+    match o {
+        Object::Array(a) => {
+            let a = a.borrow();
+
+            if key == "length" {
+                let mut p = read_only(a.get_prop(key), false, !a.frozen);
+                p.configurable = false;
+                return Some(p);
             }
-            Some(Object::Class(cd)) => match cd {
-                ClassD::Coded { name, vars } => {
-                    r = Object::ClassObj(ClassO {
-                        name: name.to_string(),
-                        vars: vars.clone(),
-                    });
+            match index_key(key) {
+                Some(i) => a.get(i as usize).map(|v| read_only(v, true, !a.frozen)),
+                None => a.props().property(key).cloned(),
+            }
+        }
+        Object::Const(constants::Const::Str { v, .. }) => {
+            if key == "length" {
+                return Some(read_only(ops::int(v.len() as i64), false, false));
+            }
+            let u = *v.units().get(index_key(key)? as usize)?;
+            Some(read_only(Object::str(vec![u]), true, false))
+        }
+        _ => with_props(o, |p| p.property(key).cloned()).flatten(),
+    }
+}
 
-                    let args_eval: Vec<Object> = args.iter().map(|arg| obj_eval(arg, md)).collect();
-
-                    if vars.contains_key("constructor") {
-                        match vars.get("constructor").unwrap() {
-                            Object::Funct(Function::Coded {
-                                name: fname,
-                                args: fargs,
-                                body: fbody,
-                            }) => {
-                                let mut cmod = Module::new();
-                                cmod.vtable = vars.clone();
-                                cmod.stmts = fbody.clone();
-
-                                for (j, jv) in fargs.iter().enumerate() {
-                                    match jv {
-                                        Expr::Var(vn) => {
-                                            cmod.vtable.insert(
-                                                vn.to_string(),
-                                                args_eval.get(j).unwrap().to_owned(),
-                                            );
-                                        }
-                                        _ => (),
-                                    }
-                                }
-
-                                cmod.parent = Box::new(Some(md));
-
-                                mod_exec(&mut cmod);
-                            }
-                            _ => (),
-                        }
-                    }
+/* [[Prototype]] of `o`; `None` is null. Strings and numbers look up their methods on the prototype of their wrappers */
+pub fn proto_of(o: &Object) -> Option<Object> {
+    match o {
+        Object::Const(constants::Const::Str { .. }) => Some(builtins::string::prototype()),
+        Object::Const(constants::Const::Integer(_) | constants::Const::Float(_)) => {
+            Some(builtins::number::prototype())
+        }
+        _ => with_props(o, |p| p.proto.clone()).flatten(),
+    }
+}
+
+/* the property `key` of `o` or of the nearest object on its prototype chain that has one */
+pub fn find_property(o: &Object, key: &str) -> Option<Property> {
+    let mut cur = o.clone();
+
+    loop {
+        if let Some(p) = own_property(&cur, key) {
+            return Some(p);
+        }
+        cur = proto_of(&cur)?;
+    }
+}
+
+/* the value a property holds for `this`, running its getter if it has one */
+pub fn read_slot(slot: Slot, this: &Object, md: &Module, span: Span) -> Result<Object, Object> {
+    match slot {
+        Slot::Data { value, .. } => Ok(value),
+        Slot::Accessor { get: Some(g), .. } => {
+            call_function(&g, this.clone(), Vec::new(), md, span)
+        }
+        Slot::Accessor { get: None, .. } => Ok(Object::undef()),
+    }
+}
+
+/* the own keys of `o` in property order; `all` includes the ones that are not enumerable */
+pub fn own_keys(o: &Object, all: bool) -> Vec<String> {
+    let mut keys: Vec<String> = match o {
+        Object::Array(a) => {
+            let a = a.borrow();
+            a.entries()
+                .into_iter()
+                .map(|(i, _)| i.to_string())
+                .collect()
+        }
+        Object::Const(constants::Const::Str { v, .. }) => {
+            (0..v.len()).map(|i| i.to_string()).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    if all
+        && matches!(
+            o,
+            Object::Array(_) | Object::Const(constants::Const::Str { .. })
+        )
+    {
+        keys.push(String::from("length"));
+    }
+    keys.extend(
+        with_props(o, |p| {
+            p.keys()
+                .into_iter()
+                .filter(|k| all || p.property(k).is_some_and(|p| p.enumerable))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default(),
+    );
+
+    keys
+}
+
+/* `o` as error messages name it, e.g. "#<Point>" */
+fn describe_obj(o: &Object) -> String {
+    match o {
+        Object::Obj(_) => String::from("#<Object>"),
+        Object::ClassObj(c) => format!("#<{}>", c.borrow().name),
+        Object::Array(_) => String::from("[object Array]"),
+        Object::Class(c) => format!("class {}", c.name()),
+        _ => o.to_string(),
+    }
+}
+
+/* `obj.key` for an already evaluated `obj` */
+pub fn get_prop(obj: &Object, key: &str, md: &Module, span: Span) -> Result<Object, Object> {
+    if ops::is_nullish(obj) {
+        return Err(new_error(
+            "TypeError",
+            format!("Cannot read properties of {obj} (reading '{key}')"),
+            span,
+        ));
+    }
+
+    match find_property(obj, key) {
+        Some(p) => read_slot(p.slot, obj, md, span),
+        None => Ok(Object::undef()),
+    }
+}
+
+/* whether `proto` is on the prototype chain of `o`, which `instanceof` asks */
+pub fn inherits(o: &Object, proto: &Object) -> bool {
+    let Object::Obj(target) = proto else {
+        return false;
+    };
+    /* primitives only borrow the prototype of their wrapper */
+    if !is_object(o) {
+        return false;
+    }
+    let mut cur = proto_of(o);
+
+    while let Some(p) = cur {
+        if matches!(&p, Object::Obj(h) if Handle::ptr_eq(h, target)) {
+            return true;
+        }
+        cur = proto_of(&p);
+    }
+
+    false
+}
+
+/* whether `key in o` */
+pub fn has_property(o: &Object, key: &str) -> bool {
+    find_property(o, key).is_some()
+}
+
+/**
+ * `base.key = v`: runs an inherited or own setter, and refuses to write read-only properties
+ * or to add properties to objects that are not extensible.
+ * Properties set on primitives and native classes are dropped.
+ */
+pub fn put_prop(
+    base: &Object,
+    key: &str,
+    v: Object,
+    md: &Module,
+    span: Span,
+) -> Result<(), Object> {
+    if ops::is_nullish(base) {
+        return Err(new_error(
+            "TypeError",
+            format!("Cannot set properties of {base} (setting '{key}')"),
+            span,
+        ));
+    }
+
+    if let Object::Array(a) = base {
+        let index = index_key(key);
+
+        if key == "length" || index.is_some() {
+            let added = {
+                let a = a.borrow();
+                if a.frozen {
+                    None
+                } else {
+                    Some(index.is_some_and(|i| !a.has(i as usize)))
                 }
-                _ => (),
-            },
-            Some(_) => println!("'{}' is not a function", name),
-            None => println!("Function '{}' does not exist", name),
+            };
+
+            return match added {
+                None => Err(read_only_error(base, key, span)),
+                Some(true) if !a.borrow().props().extensible => Err(not_extensible(key, span)),
+                _ => a
+                    .borrow_mut()
+                    .set_prop(key, v)
+                    .map_err(|m| new_error("RangeError", m, span)),
+            };
+        }
+    }
+
+    if !is_object(base) {
+        return Ok(());
+    }
+
+    match find_property(base, key).map(|p| p.slot) {
+        Some(Slot::Accessor { set: Some(s), .. }) => {
+            call_function(&s, base.clone(), vec![v], md, span)?;
+            return Ok(());
+        }
+        Some(Slot::Accessor { set: None, .. }) => {
+            return Err(new_error(
+                "TypeError",
+                format!(
+                    "Cannot set property {key} of {} which has only a getter",
+                    describe_obj(base)
+                ),
+                span,
+            ))
+        }
+        Some(Slot::Data {
+            writable: false, ..
+        }) => return Err(read_only_error(base, key, span)),
+        _ => (),
+    }
+
+    let added = with_props_mut(base, |p| {
+        let ok = p.contains(key) || p.extensible;
+        if ok {
+            p.set(key, v);
+        }
+        ok
+    });
+
+    match added {
+        Some(false) => Err(not_extensible(key, span)),
+        _ => Ok(()),
+    }
+}
+
+fn read_only_error(base: &Object, key: &str, span: Span) -> Object {
+    new_error(
+        "TypeError",
+        format!(
+            "Cannot assign to read only property '{key}' of object '{}'",
+            describe_obj(base)
+        ),
+        span,
+    )
+}
+
+fn not_extensible(key: &str, span: Span) -> Object {
+    new_error(
+        "TypeError",
+        format!("Cannot add property {key}, object is not extensible"),
+        span,
+    )
+}
+
+/* `delete base[key]`; properties that are not configurable cannot be deleted */
+pub fn delete_prop(base: &Object, key: &str, span: Span) -> Result<(), Object> {
+    if own_property(base, key).is_some_and(|p| !p.configurable) {
+        return Err(new_error(
+            "TypeError",
+            format!("Cannot delete property '{key}' of {}", describe_obj(base)),
+            span,
+        ));
+    }
+
+    match base {
+        Object::Array(a) => a.borrow_mut().delete_prop(key),
+        _ => {
+            with_props_mut(base, |p| p.remove(key));
+        }
+    }
+
+    Ok(())
+}
+
+/* an assignment target with its object and key already evaluated */
+enum Reference<'e> {
+    Var(&'e str),
+    Prop { base: Object, key: String },
+    Private { base: Object, name: &'e str },
+}
+
+fn reference<'e>(target: &'e Expr, md: &mut Module) -> Result<Reference<'e>, Object> {
+    match &target.kind {
+        ExprKind::Var(name) => Ok(Reference::Var(name)),
+        ExprKind::Member { obj, prop } => Ok(Reference::Prop {
+            base: obj_eval(obj, md)?,
+            key: prop.clone(),
+        }),
+        ExprKind::Index { obj, index } => {
+            let base = obj_eval(obj, md)?;
+            let key = coerce::to_string(&obj_eval(index, md)?, md, index.span)?;
+
+            Ok(Reference::Prop { base, key })
+        }
+        ExprKind::PrivateMember { obj, name } => Ok(Reference::Private {
+            base: obj_eval(obj, md)?,
+            name,
+        }),
+        /* `super.x = v` sets `x` on `this` */
+        ExprKind::SuperMember(key) => {
+            let this = md.get_var("this").unwrap_or_else(Object::undef);
+            let base = classes::checked_this(this, target.span)?;
+            let key = match key {
+                PropKey::Named(k) => k.clone(),
+                PropKey::Computed(k) => coerce::to_string(&obj_eval(k, md)?, md, k.span)?,
+            };
+
+            Ok(Reference::Prop { base, key })
+        }
+        _ => Err(new_error(
+            "SyntaxError",
+            "Invalid left-hand side in assignment",
+            target.span,
+        )),
+    }
+}
+
+fn get_ref(r: &Reference, md: &Module, span: Span) -> Result<Object, Object> {
+    match r {
+        Reference::Var(name) => binding_value(name, md.env.lookup(name), span),
+        Reference::Prop { base, key } => get_prop(base, key, md, span),
+        Reference::Private { base, name } => classes::get_private(base, name, md, span),
+    }
+}
+
+/* assignments create or overwrite the binding in `md` itself */
+fn put_ref(r: Reference, v: Object, md: &mut Module, span: Span) -> Result<(), Object> {
+    match r {
+        Reference::Var(name) => assigned(name, md.env.set(name, v), span)?,
+        Reference::Private { base, name } => classes::put_private(&base, name, v, md, span)?,
+        Reference::Prop { base, key } => put_prop(&base, &key, v, md, span)?,
+    }
+
+    Ok(())
+}
+
+/* stores `v` in the target of an assignment, as a `for...in` or `for...of` loop does */
+pub fn assign_value(target: &Expr, v: Object, md: &mut Module) -> Result<(), Object> {
+    let r = reference(target, md)?;
+    put_ref(r, v, md, target.span)
+}
+
+#[inline(never)]
+fn assign(
+    op: &EOperator,
+    target: &Expr,
+    val: &Expr,
+    md: &mut Module,
+    span: Span,
+) -> Result<Object, Object> {
+    let r = reference(target, md)?;
+
+    let v = match op.compound_base() {
+        None => obj_eval(val, md)?,
+        Some(base @ (EOperator::And | EOperator::Or | EOperator::Nullish)) => {
+            let cur = get_ref(&r, md, span)?;
+
+            /* logical assignment only evaluates and stores the right side when needed */
+            let keep = match base {
+                EOperator::And => !truthy(&cur),
+                EOperator::Or => truthy(&cur),
+                _ => !ops::is_nullish(&cur),
+            };
+
+            if keep {
+                return Ok(cur);
+            }
+
+            obj_eval(val, md)?
+        }
+        Some(base) => {
+            let cur = get_ref(&r, md, span)?;
+
+            binary_op(&base, cur, obj_eval(val, md)?, md, span)?
+        }
+    };
+
+    put_ref(r, v.clone(), md, span)?;
+    Ok(v)
+}
+
+/* the own enumerable properties `{...o}` copies out of `o`, read through their getters */
+pub fn own_entries(o: &Object, md: &Module, span: Span) -> Result<Vec<(String, Object)>, Object> {
+    let mut res = Vec::new();
+
+    for k in own_keys(o, false) {
+        let v = get_prop(o, &k, md, span)?;
+        res.push((k, v));
+    }
+
+    Ok(res)
+}
+
+/* the values `...v` expands to; `src` names `v` in the error when it cannot be spread */
+pub fn spread_items(
+    v: Object,
+    src: impl FnOnce() -> String,
+    span: Span,
+) -> Result<Vec<Object>, Object> {
+    match v {
+        Object::Array(a) => a
+            .borrow()
+            .values()
+            .map_err(|m| new_error("RangeError", m, span)),
+        Object::Const(constants::Const::Str { v, .. }) => {
+            Ok(v.code_points().into_iter().map(Object::str).collect())
+        }
+        _ => Err(new_error(
+            "TypeError",
+            format!("{} is not iterable", src()),
+            span,
+        )),
+    }
+}
+
+fn spread_values(src: &Expr, md: &mut Module) -> Result<Vec<Object>, Object> {
+    spread_items(obj_eval(src, md)?, || describe(src), src.span)
+}
+
+/**
+ * What `for (x of v)` runs over, as an array: `v` itself when it is one, so that the loop
+ * reaches items added to it meanwhile, or the items spreading it gives.
+ */
+pub fn iteration_items(
+    v: Object,
+    src: impl FnOnce() -> String,
+    span: Span,
+) -> Result<Object, Object> {
+    match v {
+        Object::Array(_) => Ok(v),
+        _ => Ok(Object::array(Array::from_values(spread_items(
+            v, src, span,
+        )?))),
+    }
+}
+
+/* item `i` of the array a `for...in` or `for...of` loop runs over; `None` once past its end */
+pub fn nth_item(items: &Object, i: usize) -> Option<Object> {
+    match items {
+        Object::Array(a) => {
+            let a = a.borrow();
+            (i < a.len()).then(|| a.get(i).unwrap_or_else(Object::undef))
+        }
+        _ => unreachable!("loops run over arrays"),
+    }
+}
+
+/**
+ * The keys `for (k in o)` visits, as an array: the enumerable ones of `o`, then those of its
+ * prototypes that nothing before them shadows. `null` and `undefined` have none.
+ */
+pub fn for_in_keys(o: &Object) -> Object {
+    let mut keys = Vec::new();
+    let mut seen = HashSet::new();
+    let mut cur = match o {
+        Object::Const(constants::Const::Undef | constants::Const::Null) => None,
+        _ => Some(o.clone()),
+    };
+
+    while let Some(c) = cur {
+        let enumerable: HashSet<String> = own_keys(&c, false).into_iter().collect();
+        for k in own_keys(&c, true) {
+            if seen.insert(k.clone()) && enumerable.contains(&k) {
+                keys.push(Object::str(k));
+            }
+        }
+        cur = proto_of(&c);
+    }
+
+    Object::array(Array::from_values(keys))
+}
+
+/* `super[key]`, and the `this` a call of it gets */
+fn super_member(key: &PropKey, md: &mut Module, span: Span) -> Result<(Object, Object), Object> {
+    let this = classes::checked_this(md.get_var("this").unwrap_or_else(Object::undef), span)?;
+    let key = match key {
+        PropKey::Named(k) => k.clone(),
+        PropKey::Computed(k) => coerce::to_string(&obj_eval(k, md)?, md, k.span)?,
+    };
+
+    Ok((classes::super_prop(&key, &this, md, span)?, this))
+}
+
+/* evaluates an argument list, expanding `...spread` arguments */
+fn eval_args(args: &[Expr], md: &mut Module) -> Result<Vec<Object>, Object> {
+    let mut res = Vec::with_capacity(args.len());
+
+    for arg in args {
+        match &arg.kind {
+            ExprKind::Spread(src) => res.extend(spread_values(src, md)?),
+            _ => res.push(obj_eval(arg, md)?),
+        }
+    }
+
+    Ok(res)
+}
+
+/* the function a call expression calls; `o.m()` calls `m` with `this` set to `o` */
+fn eval_callee(callee: &Expr, md: &mut Module) -> Result<(Object, Object), Object> {
+    Ok(match &callee.kind {
+        ExprKind::Member { obj, prop } => {
+            let base = obj_eval(obj, md)?;
+            (get_prop(&base, prop, md, callee.span)?, base)
+        }
+        ExprKind::Index { obj, index } => {
+            let base = obj_eval(obj, md)?;
+            let key = coerce::to_string(&obj_eval(index, md)?, md, index.span)?;
+            (get_prop(&base, &key, md, callee.span)?, base)
+        }
+        ExprKind::PrivateMember { obj, name } => {
+            let base = obj_eval(obj, md)?;
+            (classes::get_private(&base, name, md, callee.span)?, base)
+        }
+        ExprKind::SuperMember(key) => super_member(key, md, callee.span)?,
+        _ => (obj_eval(callee, md)?, Object::undef()),
+    })
+}
+
+/* a part of an optional chain; `None` once an `Optional` part has been nullish */
+fn chain_value(e: &Expr, md: &mut Module) -> Result<Option<Object>, Object> {
+    Ok(Some(match &e.kind {
+        ExprKind::Optional(inner) => {
+            return Ok(chain_value(inner, md)?.filter(|v| !ops::is_nullish(v)))
+        }
+        ExprKind::Member { obj, prop } => {
+            let Some(base) = chain_value(obj, md)? else {
+                return Ok(None);
+            };
+            get_prop(&base, prop, md, e.span)?
+        }
+        ExprKind::Index { obj, index } => {
+            let Some(base) = chain_value(obj, md)? else {
+                return Ok(None);
+            };
+            let i = obj_eval(index, md)?;
+            get_index(&base, &i, md, index.span, e.span)?
+        }
+        ExprKind::PrivateMember { obj, name } => {
+            let Some(base) = chain_value(obj, md)? else {
+                return Ok(None);
+            };
+            classes::get_private(&base, name, md, e.span)?
+        }
+        ExprKind::Call { callee, args } => {
+            let Some((f, this)) = chain_callee(callee, md)? else {
+                return Ok(None);
+            };
+            let args = eval_args(args, md)?;
+            call_value(f, this, args, || describe(callee), md, e.span)?
+        }
+        _ => obj_eval(e, md)?,
+    }))
+}
+
+/* `eval_callee` inside an optional chain, so that `o.m?.()` still calls `m` on `o` */
+fn chain_callee(callee: &Expr, md: &mut Module) -> Result<Option<(Object, Object)>, Object> {
+    let base = match &callee.kind {
+        ExprKind::Optional(inner) => {
+            return Ok(chain_callee(inner, md)?.filter(|(f, _)| !ops::is_nullish(f)))
+        }
+        ExprKind::Member { obj, .. }
+        | ExprKind::Index { obj, .. }
+        | ExprKind::PrivateMember { obj, .. } => obj,
+        ExprKind::SuperMember(key) => return Ok(Some(super_member(key, md, callee.span)?)),
+        _ => return Ok(chain_value(callee, md)?.map(|f| (f, Object::undef()))),
+    };
+
+    let Some(this) = chain_value(base, md)? else {
+        return Ok(None);
+    };
+    let f = match &callee.kind {
+        ExprKind::Member { prop, .. } => get_prop(&this, prop, md, callee.span)?,
+        ExprKind::Index { index, .. } => {
+            let i = obj_eval(index, md)?;
+            get_index(&this, &i, md, index.span, callee.span)?
+        }
+        ExprKind::PrivateMember { name, .. } => classes::get_private(&this, name, md, callee.span)?,
+        _ => unreachable!("only member callees have a base"),
+    };
+
+    Ok(Some((f, this)))
+}
+
+/* calls `f` without `new`; `callee` names it in the error when it is not a function */
+pub fn call_value(
+    f: Object,
+    this: Object,
+    args: Vec<Object>,
+    callee: impl FnOnce() -> String,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    match f {
+        Object::Funct(f) => call_function(&f, this, args, md, span),
+        Object::Class(ClassD::Coded(c)) => Err(new_error(
+            "TypeError",
+            format!(
+                "Class constructor {} cannot be invoked without 'new'",
+                c.name
+            ),
+            span,
+        )),
+        /* the native error classes work with and without `new` */
+        Object::Class(cd) => construct(&cd, args, md, span),
+        _ => Err(new_error(
+            "TypeError",
+            format!("{} is not a function", callee()),
+            span,
+        )),
+    }
+}
+
+/* whether `new f()` is allowed: plain coded functions and the native constructors */
+pub fn is_constructor(f: &Function) -> bool {
+    match &f.kind {
+        FnKind::Native { name, .. } => builtins::is_constructor(name),
+        FnKind::Coded { proto, .. } => proto.form == FuncForm::Plain,
+    }
+}
+
+/* `new c(args)`; `callee` names `c` in the error when it is not a constructor */
+pub fn new_value(
+    c: &Object,
+    args: Vec<Object>,
+    callee: impl FnOnce() -> String,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    match c {
+        Object::Class(cd) => construct(cd, args, md, span),
+        Object::Funct(f) if matches!(&f.kind, FnKind::Native { name, .. } if builtins::is_constructor(name)) => {
+            call_function(f, Object::undef(), args, md, span)
+        }
+        /* the new object inherits from `f.prototype`; the function may return another object instead */
+        Object::Funct(f) if matches!(&f.kind, FnKind::Coded { proto, .. } if proto.form == FuncForm::Plain) =>
+        {
+            let proto = match f.props.borrow().get("prototype") {
+                Some(p) if is_object(p) => p.clone(),
+                _ => builtins::object::prototype(),
+            };
+            let this = Object::obj(Props::with_proto(Some(proto)));
+
+            let r = call_function(f, this.clone(), args, md, span)?;
+            Ok(if is_object(&r) { r } else { this })
+        }
+        _ => Err(new_error(
+            "TypeError",
+            format!("{} is not a constructor", callee()),
+            span,
+        )),
+    }
+}
+
+/* `key in o` */
+pub fn in_op(key: &str, o: &Object, span: Span) -> Result<Object, Object> {
+    if !is_object(o) {
+        return Err(new_error(
+            "TypeError",
+            format!("Cannot use 'in' operator to search for '{key}' in {o}"),
+            span,
+        ));
+    }
+
+    Ok(Object::Const(constants::Const::Bool(has_property(o, key))))
+}
+
+/* `l instanceof r` */
+pub fn instanceof_op(l: &Object, r: &Object, span: Span) -> Result<Object, Object> {
+    let is = match r {
+        Object::Class(ClassD::Coded(c)) => inherits(l, &Object::Obj(c.prototype.clone())),
+        Object::Class(ClassD::Native { name }) => {
+            matches!(l, Object::ClassObj(o) if o.borrow().is_native(name))
+        }
+        /* natives such as `Array` make the instances that inherit from their `prototype` */
+        Object::Funct(f) => match f.props.borrow().get("prototype") {
+            Some(p) => inherits(l, p),
+            None => false,
         },
-        Expr::Arith(p) => {
-            let mut mp: Vec<Object> = Vec::new();
+        _ => {
+            return Err(new_error(
+                "TypeError",
+                "Right-hand side of 'instanceof' is not callable",
+                span,
+            ))
+        }
+    };
 
-            for i in p {
-                match i {
-                    Arithmetic::Node(n) => {
-                        mp.push(obj_eval(&n, md));
-                    }
-                    Arithmetic::Op(o) => {
-                        let mut abr = 0.0;
-
-                        match o {
-                            EOperator::Plus => {
-                                let a = mp.pop().unwrap();
-                                let b = mp.pop().unwrap();
-
-                                match (a, b) {
-                                    (Object::Const(a_c), Object::Const(b_c)) => match a_c {
-                                        constants::Const::Integer(a_c_i) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 + a_c_i as f64;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f + a_c_i as f64;
-                                            }
-                                            constants::Const::Str { v: b_s_v, .. } => {
-                                                let mut ress = String::from(b_s_v);
-                                                ress.push_str(&a_c_i.to_string());
-                                                mp.push(Object::Const(constants::Const::Str {
-                                                    v: ress,
-                                                    is_raw: false,
-                                                    is_fmt: false,
-                                                }));
-                                                continue;
-                                            }
-                                            _ => (),
-                                        },
-                                        constants::Const::Float(a_c_f) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 + a_c_f;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f + a_c_f;
-                                            }
-                                            _ => (),
-                                        },
-
-                                        constants::Const::Str { v: a_s_v, .. } => match b_c {
-                                            constants::Const::Str { v: b_s_v, .. } => {
-                                                let mut ress = String::from(b_s_v);
-                                                ress.push_str(&a_s_v);
-                                                mp.push(Object::Const(constants::Const::Str {
-                                                    v: ress,
-                                                    is_raw: false,
-                                                    is_fmt: false,
-                                                }));
-                                                continue;
-                                            }
-                                            constants::Const::Integer(b_i) => {
-                                                let mut ress = String::from(b_i.to_string());
-                                                ress.push_str(&a_s_v);
-                                                mp.push(Object::Const(constants::Const::Str {
-                                                    v: ress,
-                                                    is_raw: false,
-                                                    is_fmt: false,
-                                                }));
-                                                continue;
-                                            }
-                                            _ => (),
-                                        },
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-
-                            EOperator::Minus => {
-                                let a = mp.pop().unwrap();
-                                let b = mp.pop().unwrap();
-
-                                match (a, b) {
-                                    (Object::Const(a_c), Object::Const(b_c)) => match a_c {
-                                        constants::Const::Integer(a_c_i) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 - a_c_i as f64;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f - a_c_i as f64;
-                                            }
-                                            _ => (),
-                                        },
-                                        constants::Const::Float(a_c_f) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 - a_c_f;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f - a_c_f;
-                                            }
-                                            _ => (),
-                                        },
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-
-                            EOperator::Multiply => {
-                                let a = mp.pop().unwrap();
-                                let b = mp.pop().unwrap();
-
-                                match (a, b) {
-                                    (Object::Const(a_c), Object::Const(b_c)) => match a_c {
-                                        constants::Const::Integer(a_c_i) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 * a_c_i as f64;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f * a_c_i as f64;
-                                            }
-                                            _ => (),
-                                        },
-                                        constants::Const::Float(a_c_f) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                abr = b_c_i as f64 * a_c_f;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                abr = b_c_f * a_c_f;
-                                            }
-                                            _ => (),
-                                        },
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-
-                            EOperator::Divide => {
-                                let a = mp.pop().unwrap();
-                                let b = mp.pop().unwrap();
-
-                                match (a, b) {
-                                    (Object::Const(a_c), Object::Const(b_c)) => match a_c {
-                                        constants::Const::Integer(a_c_i) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                if a_c_i == 0 {
-                                                    panic!("err: division by 0");
-                                                }
-                                                abr = b_c_i as f64 / a_c_i as f64;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                if a_c_i == 0 {
-                                                    panic!("err: division by 0");
-                                                }
-                                                abr = b_c_f / a_c_i as f64;
-                                            }
-                                            _ => (),
-                                        },
-                                        constants::Const::Float(a_c_f) => match b_c {
-                                            constants::Const::Integer(b_c_i) => {
-                                                if a_c_f == 0.0 {
-                                                    panic!("err: division by 0");
-                                                }
-                                                abr = b_c_i as f64 / a_c_f;
-                                            }
-                                            constants::Const::Float(b_c_f) => {
-                                                if a_c_f == 0.0 {
-                                                    panic!("err: division by 0");
-                                                }
-                                                abr = b_c_f / a_c_f;
-                                            }
-                                            _ => (),
-                                        },
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-                            _ => (),
-                        }
-
-                        // println!("{}", abr);
-                        mp.push(Object::Const(constants::Const::Float(abr)));
-                    }
-                    _ => (),
-                }
+    Ok(Object::Const(constants::Const::Bool(is)))
+}
+
+/* `o[i]`; `key_span` is where the index is, for errors converting it to a key */
+pub fn get_index(
+    o: &Object,
+    i: &Object,
+    md: &Module,
+    key_span: Span,
+    span: Span,
+) -> Result<Object, Object> {
+    match (o, i) {
+        (Object::Array(a), Object::Const(constants::Const::Integer(n)))
+            if *n >= 0 && *n < u32::MAX as i64 =>
+        {
+            Ok(a.borrow().get(*n as usize).unwrap_or_else(Object::undef))
+        }
+        _ => get_prop(o, &coerce::to_string(i, md, key_span)?, md, span),
+    }
+}
+
+/* evaluates `e`; `Err` carries a thrown value */
+pub fn obj_eval(e: &Expr, md: &mut Module) -> Result<Object, Object> {
+    /* a long left-deep chain such as `1 + 1 + ...` nests without any call or bracket */
+    if e.kind.head().is_some_and(|h| h.kind.head().is_some()) && stack::exhausted() {
+        return Err(new_error(
+            "RangeError",
+            "Maximum call stack size exceeded",
+            e.span,
+        ));
+    }
+
+    /* each arm is a single call, so that nesting expressions costs little native stack */
+    match &e.kind {
+        ExprKind::Const(v) => Ok(Object::Const(v.clone())),
+        ExprKind::Var(name) => binding_value(name, md.env.lookup(name), e.span),
+        ExprKind::Binary { op, lhs, rhs } => binary_value(op, lhs, rhs, md, e.span),
+        ExprKind::Logical { op, lhs, rhs } => logical_value(op, lhs, rhs, md),
+        ExprKind::Conditional { test, cons, alt } => conditional_value(test, cons, alt, md),
+        ExprKind::Assign { op, target, val } => assign(op, target, val, md, e.span),
+        ExprKind::Call { callee, args } => call_expr(callee, args, md, e.span),
+        ExprKind::Member { obj, prop } => member_value(obj, prop, md, e.span),
+        ExprKind::Index { obj, index } => index_value(obj, index, md, e.span),
+        _ => other_value(e, md),
+    }
+}
+
+#[inline(never)]
+fn binary_value(
+    op: &EOperator,
+    lhs: &Expr,
+    rhs: &Expr,
+    md: &mut Module,
+    span: Span,
+) -> Result<Object, Object> {
+    let l = obj_eval(lhs, md)?;
+
+    binary_op(op, l, obj_eval(rhs, md)?, md, span)
+}
+
+#[inline(never)]
+fn logical_value(
+    op: &EOperator,
+    lhs: &Expr,
+    rhs: &Expr,
+    md: &mut Module,
+) -> Result<Object, Object> {
+    let l = obj_eval(lhs, md)?;
+
+    /* the right side only runs when the left one does not decide the result */
+    let done = match op {
+        EOperator::And => !truthy(&l),
+        EOperator::Or => truthy(&l),
+        _ => !ops::is_nullish(&l),
+    };
+
+    if done {
+        Ok(l)
+    } else {
+        obj_eval(rhs, md)
+    }
+}
+
+#[inline(never)]
+fn conditional_value(
+    test: &Expr,
+    cons: &Expr,
+    alt: &Expr,
+    md: &mut Module,
+) -> Result<Object, Object> {
+    if truthy(&obj_eval(test, md)?) {
+        obj_eval(cons, md)
+    } else {
+        obj_eval(alt, md)
+    }
+}
+
+#[inline(never)]
+fn member_value(obj: &Expr, prop: &str, md: &mut Module, span: Span) -> Result<Object, Object> {
+    get_prop(&obj_eval(obj, md)?, prop, md, span)
+}
+
+#[inline(never)]
+fn index_value(obj: &Expr, index: &Expr, md: &mut Module, span: Span) -> Result<Object, Object> {
+    let o = obj_eval(obj, md)?;
+    let i = obj_eval(index, md)?;
+
+    get_index(&o, &i, md, index.span, span)
+}
+
+/* the rest of the expressions */
+#[inline(never)]
+fn other_value(e: &Expr, md: &mut Module) -> Result<Object, Object> {
+    let r: Object;
+
+    match &e.kind {
+        ExprKind::This => {
+            r = classes::checked_this(md.get_var("this").unwrap_or_else(Object::undef), e.span)?;
+        }
+        ExprKind::Unary { op, e } => {
+            r = unary_op(op, obj_eval(e, md)?, md, e.span)?;
+        }
+        ExprKind::Typeof(inner) => {
+            /* `typeof undeclared` is "undefined" rather than a ReferenceError */
+            r = match &inner.kind {
+                ExprKind::Var(name) if md.env.lookup(name).is_none() => Object::str("undefined"),
+                _ => Object::str(ops::type_of(&obj_eval(inner, md)?)),
+            };
+        }
+        ExprKind::Void(inner) => {
+            obj_eval(inner, md)?;
+            r = Object::undef();
+        }
+        ExprKind::Delete(inner) => {
+            r = delete_value(inner, md)?;
+        }
+        ExprKind::Update { op, prefix, target } => {
+            r = update_value(op, *prefix, target, md, e.span)?;
+        }
+        ExprKind::In { lhs, rhs } if matches!(lhs.kind, ExprKind::PrivateName(_)) => {
+            r = private_in(lhs, rhs, md, e.span)?;
+        }
+        ExprKind::In { lhs, rhs } => {
+            let key = coerce::to_string(&obj_eval(lhs, md)?, md, lhs.span)?;
+
+            r = in_op(&key, &obj_eval(rhs, md)?, e.span)?;
+        }
+        ExprKind::Instanceof { lhs, rhs } => {
+            let l = obj_eval(lhs, md)?;
+
+            r = instanceof_op(&l, &obj_eval(rhs, md)?, e.span)?;
+        }
+        ExprKind::Sequence(v) => {
+            let mut last = Object::undef();
+            for x in v {
+                last = obj_eval(x, md)?;
             }
+            r = last;
+        }
+        ExprKind::Template { quasis, exprs } => {
+            r = template_value(quasis, exprs, md)?;
+        }
+        ExprKind::TaggedTemplate { tag, site, exprs } => {
+            r = tagged_template(tag, site, exprs, md, e.span)?;
+        }
+        ExprKind::New { callee, args } => {
+            let c = obj_eval(callee, md)?;
+            let args = eval_args(args, md)?;
 
-            r = mp.pop().unwrap();
+            r = new_value(&c, args, || describe(callee), md, e.span)?;
         }
-        Expr::NewConstruct(e) => {
-            r = obj_eval(e, md);
+        ExprKind::Function {
+            name,
+            args,
+            body,
+            form,
+        } => {
+            r = function_value(name.as_deref(), args, body, *form, md);
         }
-        _ => (),
+        ExprKind::Object(entries) => {
+            r = object_literal(entries, md)?;
+        }
+        ExprKind::PrivateMember { obj, name } => {
+            r = classes::get_private(&obj_eval(obj, md)?, name, md, e.span)?;
+        }
+        ExprKind::Class {
+            name,
+            parent,
+            members,
+        } => {
+            let name = name.as_deref().unwrap_or_default();
+            r = class_value(name, parent.as_deref(), members, md)?;
+        }
+        ExprKind::Chain(inner) => {
+            r = chain_value(inner, md)?.unwrap_or_else(Object::undef);
+        }
+        ExprKind::Optional(inner) => {
+            r = obj_eval(inner, md)?;
+        }
+        ExprKind::PrivateName(name) => {
+            return Err(new_error(
+                "SyntaxError",
+                format!("Unexpected identifier '{name}'"),
+                e.span,
+            ))
+        }
+        ExprKind::SuperCall(args) => {
+            r = super_call(args, md, e.span)?;
+        }
+        ExprKind::SuperMember(key) => {
+            r = super_member(key, md, e.span)?.0;
+        }
+        ExprKind::Array(elems) => {
+            r = array_literal(elems, md)?;
+        }
+        ExprKind::Spread(_) => {
+            return Err(new_error(
+                "SyntaxError",
+                "spread is only allowed in array literals and argument lists",
+                e.span,
+            ))
+        }
+        ExprKind::Const(_)
+        | ExprKind::Var(_)
+        | ExprKind::Binary { .. }
+        | ExprKind::Logical { .. }
+        | ExprKind::Conditional { .. }
+        | ExprKind::Assign { .. }
+        | ExprKind::Call { .. }
+        | ExprKind::Member { .. }
+        | ExprKind::Index { .. } => unreachable!("obj_eval evaluates these"),
+    }
+
+    Ok(r)
+}
+
+/*
+ * The less common expressions are evaluated out of line, which keeps `obj_eval`'s own frame
+ * small: it sits on the native stack once per nested expression and call.
+ */
+
+fn update_value(
+    op: &EOperator,
+    prefix: bool,
+    target: &Expr,
+    md: &mut Module,
+    span: Span,
+) -> Result<Object, Object> {
+    let rf = reference(target, md)?;
+    let (old, new) = update_op(op, &get_ref(&rf, md, span)?, md, span)?;
+
+    put_ref(rf, new.clone(), md, span)?;
+    Ok(if prefix { new } else { old })
+}
+
+#[inline(never)]
+fn call_expr(callee: &Expr, args: &[Expr], md: &mut Module, span: Span) -> Result<Object, Object> {
+    let (f, this) = eval_callee(callee, md)?;
+    let args = eval_args(args, md)?;
+
+    call_value(f, this, args, || describe(callee), md, span)
+}
+
+fn delete_value(inner: &Expr, md: &mut Module) -> Result<Object, Object> {
+    Ok(Object::Const(constants::Const::Bool(match &inner.kind {
+        ExprKind::Var(_) => false,
+        ExprKind::PrivateMember { .. } => {
+            return Err(new_error(
+                "SyntaxError",
+                "Private fields can not be deleted",
+                inner.span,
+            ))
+        }
+        ExprKind::Member { .. } | ExprKind::Index { .. } => {
+            if let Reference::Prop { base, key } = reference(inner, md)? {
+                delete_prop(&base, &key, inner.span)?;
+            }
+            true
+        }
+        _ => {
+            obj_eval(inner, md)?;
+            true
+        }
+    })))
+}
+
+fn private_in(lhs: &Expr, rhs: &Expr, md: &mut Module, span: Span) -> Result<Object, Object> {
+    let ExprKind::PrivateName(name) = &lhs.kind else {
+        unreachable!()
+    };
+
+    private_in_op(name, obj_eval(rhs, md)?, md, lhs.span, span)
+}
+
+/* `#name in o`; `name_span` is where `#name` is */
+pub fn private_in_op(
+    name: &str,
+    o: Object,
+    md: &Module,
+    name_span: Span,
+    span: Span,
+) -> Result<Object, Object> {
+    match o {
+        Object::Const(_) => Err(new_error(
+            "TypeError",
+            format!("Cannot use 'in' operator to search for '{name}' in {o}"),
+            span,
+        )),
+        o => Ok(Object::Const(constants::Const::Bool(classes::has_private(
+            &o, name, md, name_span,
+        )?))),
+    }
+}
+
+fn template_value(quasis: &[JsStr], exprs: &[Expr], md: &mut Module) -> Result<Object, Object> {
+    let mut s = quasis[0].clone();
+
+    for (q, x) in quasis[1..].iter().zip(exprs) {
+        let v = coerce::to_js_str(&obj_eval(x, md)?, md, x.span)?;
+        s.push(&v).map_err(|_| invalid_string_length(x.span))?;
+        s.push(q).map_err(|_| invalid_string_length(x.span))?;
+    }
+
+    Ok(Object::str(s))
+}
+
+fn tagged_template(
+    tag: &Expr,
+    site: &Rc<TemplateSite>,
+    exprs: &[Expr],
+    md: &mut Module,
+    span: Span,
+) -> Result<Object, Object> {
+    let (f, this) = eval_callee(tag, md)?;
+
+    let mut args = vec![template_strings(site)];
+    for x in exprs {
+        args.push(obj_eval(x, md)?);
+    }
+
+    call_value(f, this, args, || describe(tag), md, span)
+}
+
+/* a class declaration or expression */
+pub fn class_value(
+    name: &str,
+    parent: Option<&Expr>,
+    members: &[ClassMember],
+    md: &mut Module,
+) -> Result<Object, Object> {
+    let def = classes::ClassDef::new(name, parent.map(|p| p.span), members, &[]);
+    let parent = match parent {
+        Some(p) => Some(obj_eval(p, md)?),
+        None => None,
+    };
+
+    let mut keys = Vec::new();
+    for m in members {
+        if let ClassMember::Method {
+            key: PropKey::Computed(k),
+            ..
+        }
+        | ClassMember::Field {
+            key: PropKey::Computed(k),
+            ..
+        } = m
+        {
+            keys.push(coerce::to_string(&obj_eval(k, md)?, md, k.span)?);
+        }
+    }
+
+    classes::define_class(&def, parent, keys, md)
+}
+
+thread_local! {
+    /* the strings array of each tagged template site, kept with the site so its address stays unique */
+    static TEMPLATE_SITES: RefCell<HashMap<*const TemplateSite, (Rc<TemplateSite>, Object)>> =
+        RefCell::new(HashMap::new());
+}
+
+/* the frozen array of strings a tag gets first, with the raw ones as its frozen `raw`; made once per site */
+pub fn template_strings(site: &Rc<TemplateSite>) -> Object {
+    if let Some((_, strings)) = TEMPLATE_SITES.with_borrow(|s| s.get(&Rc::as_ptr(site)).cloned()) {
+        return strings;
+    }
+
+    let str_or_undef = |s: &Option<JsStr>| s.clone().map_or_else(Object::undef, Object::str);
+    let mut strings = Array::from_values(site.cooked.iter().map(str_or_undef).collect());
+    let mut raw = Array::from_values(site.raw.iter().cloned().map(Object::str).collect());
+    raw.freeze();
+    strings
+        .set_prop("raw", Object::array(raw))
+        .expect("`raw` is not `length`");
+    strings.freeze();
+
+    let strings = Object::array(strings);
+    TEMPLATE_SITES.with_borrow_mut(|s| s.insert(Rc::as_ptr(site), (site.clone(), strings.clone())));
+    strings
+}
+
+fn function_value(
+    name: Option<&str>,
+    args: &Rc<[Expr]>,
+    body: &Rc<[Stmt]>,
+    form: FuncForm,
+    md: &mut Module,
+) -> Object {
+    /* a named function expression can refer to itself by its name, and only it can */
+    let env = match name {
+        Some(name) => {
+            let mut own = Layout::new();
+            own.add_fixed(name);
+            Env::with_layout(Some(&md.env), &Rc::new(own))
+        }
+        None => md.env.clone(),
+    };
+
+    let f = Object::Funct(Function::coded(
+        Proto::new(name.unwrap_or_default(), args, body, form),
+        env.clone(),
+    ));
+
+    if name.is_some() {
+        env.put_slot(0, Some(f.clone()));
     }
+    f
+}
+
+fn object_literal(entries: &[ObjProp], md: &mut Module) -> Result<Object, Object> {
+    let mut props = Props::ordinary();
+
+    for entry in entries {
+        match entry {
+            ObjProp::KeyValue { key, val } => {
+                let key = match key {
+                    PropKey::Named(k) => k.clone(),
+                    PropKey::Computed(k) => coerce::to_string(&obj_eval(k, md)?, md, k.span)?,
+                };
+                props.set(&key, obj_eval(val, md)?);
+            }
+            ObjProp::Accessor { key, kind, val } => {
+                let key = match key {
+                    PropKey::Named(k) => k.clone(),
+                    PropKey::Computed(k) => coerce::to_string(&obj_eval(k, md)?, md, k.span)?,
+                };
+                let Object::Funct(f) = obj_eval(val, md)? else {
+                    unreachable!("accessors are functions")
+                };
+                define_accessor(&mut props, &key, *kind, f);
+            }
+            ObjProp::Spread(src) => {
+                let o = obj_eval(src, md)?;
+                for (k, v) in own_entries(&o, md, src.span)? {
+                    props.set(&k, v);
+                }
+            }
+            ObjProp::Proto(v) => literal_proto(&mut props, obj_eval(v, md)?),
+        }
+    }
+
+    Ok(Object::obj(props))
+}
+
+/* `__proto__: v` in an object literal, which ignores a `v` that is neither an object nor null */
+pub fn literal_proto(props: &mut Props, v: Object) {
+    match v {
+        Object::Const(constants::Const::Null) => props.proto = None,
+        v if is_object(&v) => props.proto = Some(v),
+        _ => {}
+    }
+}
+
+/* the getter or setter `f` of an object literal, which a later one for the same key joins */
+pub fn define_accessor(props: &mut Props, key: &str, kind: MethodKind, f: Function) {
+    let slot = props.property(key).map(|p| p.slot.clone());
+    props.define(
+        key,
+        Property {
+            slot: classes::with_method(slot, kind, f),
+            enumerable: true,
+            configurable: true,
+        },
+    );
+}
+
+fn super_call(args: &[Expr], md: &mut Module, span: Span) -> Result<Object, Object> {
+    let this = md.get_var("this").unwrap_or_else(Object::undef);
+    let args = eval_args(args, md)?;
+
+    let this = super_init(this, args, md, span)?;
+    assigned("this", md.env.set("this", this.clone()), span)?;
+    Ok(this)
+}
+
+/**
+ * `super(args)` in a constructor called on `this`. Gives back the object the constructor
+ * goes on with as its `this`: the one the parent's constructor returned, if any.
+ */
+pub fn super_init(
+    this: Object,
+    args: Vec<Object>,
+    md: &Module,
+    span: Span,
+) -> Result<Object, Object> {
+    let c = classes::home_class(md, span)?;
+
+    /* arrays made for subclasses of `Array` do not track whether `super(...)` ran */
+    if matches!(this, Object::ClassObj(_)) && classes::checked_this(this.clone(), span).is_ok() {
+        return Err(new_error(
+            "ReferenceError",
+            "Super constructor may only be called once",
+            span,
+        ));
+    }
+    let this = match &c.parent {
+        Some(p) => classes::init_parent(p, &this, args, md, span)?.unwrap_or(this),
+        None => this,
+    };
+    classes::init_fields(&c, &this, md)?;
+    Ok(this)
+}
+
+fn array_literal(elems: &[Option<Expr>], md: &mut Module) -> Result<Object, Object> {
+    let mut v = Vec::with_capacity(elems.len());
+
+    for el in elems {
+        match el.as_ref().map(|el| &el.kind) {
+            None => v.push(None),
+            Some(ExprKind::Spread(src)) => v.extend(spread_values(src, md)?.into_iter().map(Some)),
+            Some(_) => v.push(Some(obj_eval(el.as_ref().unwrap(), md)?)),
+        }
+    }
+
+    Ok(Object::array(Array::new(v)))
+}
+
+thread_local! {
+    /* how many objects are being printed inside each other, so that cycles end */
+    static DISPLAY_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/* the enumerable properties as `{ k: v }`, with accessors shown as `[Getter]`, `[Setter]` or `[Getter/Setter]` */
+fn write_props(f: &mut fmt::Formatter<'_>, props: &Props, nested: &str) -> fmt::Result {
+    let depth = DISPLAY_DEPTH.get();
+    if depth > 2 {
+        return write!(f, "{nested}");
+    }
+
+    let props = props.enumerable();
+    if props.is_empty() {
+        return write!(f, "{{}}");
+    }
+
+    DISPLAY_DEPTH.set(depth + 1);
+    let r = (|| {
+        write!(f, "{{")?;
+        for (i, (k, p)) in props.iter().enumerate() {
+            write!(f, "{} {k}: ", if i > 0 { "," } else { "" })?;
+            match &p.slot {
+                Slot::Data { value, .. } => write!(f, "{value}")?,
+                Slot::Accessor { get, set } => write!(
+                    f,
+                    "[{}]",
+                    match (get.is_some(), set.is_some()) {
+                        (true, true) => "Getter/Setter",
+                        (true, false) => "Getter",
+                        _ => "Setter",
+                    }
+                )?,
+            }
+        }
+        write!(f, " }}")
+    })();
+    DISPLAY_DEPTH.set(depth);
 
     r
 }
@@ -311,8 +1613,46 @@ impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Object::Const(v) => write!(f, "{}", v),
-            Object::Funct(u) => write!(f, "<function>"),
-            _ => unreachable!(),
+            Object::Funct(_) => write!(f, "<function>"),
+            Object::Class(c) => write!(f, "[class {}]", c.name()),
+            Object::ClassObj(o) => {
+                let o = o.borrow();
+                if let Some(m) = o.props.get("message") {
+                    return write!(f, "{}: {m}", o.name);
+                }
+
+                write!(f, "{} ", o.name)?;
+                write_props(f, &o.props, "[Object]")
+            }
+            Object::Array(a) => {
+                let depth = DISPLAY_DEPTH.get();
+                if depth > 2 {
+                    return write!(f, "[Array]");
+                }
+
+                let a = a.borrow();
+                let mut parts: Vec<String> = Vec::new();
+
+                DISPLAY_DEPTH.set(depth + 1);
+                for run in a.runs() {
+                    parts.push(match run {
+                        Ok(v) => v.to_string(),
+                        Err(1) => String::from("<1 empty item>"),
+                        Err(n) => format!("<{n} empty items>"),
+                    });
+                }
+                for (k, v) in a.props().entries() {
+                    parts.push(format!("{k}: {v}"));
+                }
+                DISPLAY_DEPTH.set(depth);
+
+                if parts.is_empty() {
+                    write!(f, "[]")
+                } else {
+                    write!(f, "[ {} ]", parts.join(", "))
+                }
+            }
+            Object::Obj(o) => write_props(f, &o.borrow(), "[Object]"),
         }
     }
 }
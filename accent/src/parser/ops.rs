@@ -0,0 +1,436 @@
+use std::cmp::Ordering;
+
+use crate::ast::{
+    bigint::{BigInt, MAX_BITS},
+    constants::{Const, JsStr, MAX_EXACT_INT},
+    EOperator,
+};
+
+use super::{
+    builtins,
+    classes::ClassD,
+    function::Function,
+    heap::Handle,
+    obj::{truthy, Object},
+};
+
+/* ToNumber for the values the runtime can hold so far; objects are NaN */
+pub fn to_number(o: &Object) -> f64 {
+    match o {
+        Object::Const(c) => match c {
+            Const::Integer(i) => *i as f64,
+            Const::BigInt(i) => i.to_f64(),
+            Const::Float(f) => *f,
+            Const::Bool(b) => {
+                if *b {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Const::Undef => f64::NAN,
+            Const::Null => 0.0,
+            Const::Str { v, .. } => string_to_number(&v.to_string()),
+        },
+        _ => f64::NAN,
+    }
+}
+
+/* StringToNumber: decimal literals, `Infinity` and the `0x`/`0o`/`0b` forms, without separators */
+pub fn string_to_number(s: &str) -> f64 {
+    let t = s.trim_matches(builtins::string::is_js_space);
+
+    let radix = match t.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+
+    if radix != 10 {
+        let digits = &t[2..];
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return f64::NAN;
+        }
+
+        return digits.chars().fold(0.0, |acc, c| {
+            acc * radix as f64 + c.to_digit(radix).unwrap_or(0) as f64
+        });
+    }
+
+    match t {
+        "" => 0.0,
+        "Infinity" | "+Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        /* Rust would also take "inf", "NaN" and the like */
+        _ if !t
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b".eE+-".contains(&b)) =>
+        {
+            f64::NAN
+        }
+        _ => t.parse().unwrap_or(f64::NAN),
+    }
+}
+
+/* ToUint32: truncate and wrap modulo 2^32; NaN and infinities become 0 */
+pub fn to_uint32(n: f64) -> u32 {
+    if !n.is_finite() {
+        return 0;
+    }
+
+    n.trunc().rem_euclid(4294967296.0) as u32
+}
+
+/* ToInt32 */
+pub fn to_int32(n: f64) -> i32 {
+    to_uint32(n) as i32
+}
+
+/* ToString; plain objects become "[object Object]" rather than their printed form */
+pub fn to_string(o: &Object) -> String {
+    match o {
+        Object::Obj(_) => String::from("[object Object]"),
+        /* errors print as "Name: message", like Error.prototype.toString */
+        Object::ClassObj(o) if !o.borrow().props.contains("message") => {
+            String::from("[object Object]")
+        }
+        Object::Array(a) => builtins::array::join_values(&a.borrow(), ",").to_string(),
+        Object::Const(Const::BigInt(i)) => i.to_string(),
+        _ => o.to_string(),
+    }
+}
+
+/* ToString as code units, so concatenating two halves of a pair gives the pair back */
+pub fn to_js_str(o: &Object) -> JsStr {
+    match o {
+        Object::Const(Const::Str { v, .. }) => v.clone(),
+        Object::Array(a) => builtins::array::join_values(&a.borrow(), ","),
+        _ => JsStr::from(to_string(o)),
+    }
+}
+
+fn is_number(c: &Const) -> bool {
+    matches!(c, Const::Integer(_) | Const::Float(_))
+}
+
+pub fn is_nullish(o: &Object) -> bool {
+    matches!(o, Object::Const(Const::Undef | Const::Null))
+}
+
+/**
+ * IsStrictlyEqual.
+ * Functions and classes are copied around by value for now,
+ * so they never compare equal to anything.
+ */
+pub fn strict_equals(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Const(x), Object::Const(y)) if is_number(x) && is_number(y) => {
+            to_number(a) == to_number(b)
+        }
+        (Object::Const(Const::Str { v: x, .. }), Object::Const(Const::Str { v: y, .. })) => x == y,
+        (Object::Const(Const::Bool(x)), Object::Const(Const::Bool(y))) => x == y,
+        (Object::Const(Const::BigInt(x)), Object::Const(Const::BigInt(y))) => x == y,
+        (Object::Const(Const::Undef), Object::Const(Const::Undef)) => true,
+        (Object::Const(Const::Null), Object::Const(Const::Null)) => true,
+        (Object::Obj(x), Object::Obj(y)) => Handle::ptr_eq(x, y),
+        (Object::Array(x), Object::Array(y)) => Handle::ptr_eq(x, y),
+        (Object::ClassObj(x), Object::ClassObj(y)) => Handle::ptr_eq(x, y),
+        (Object::Funct(x), Object::Funct(y)) => Function::ptr_eq(x, y),
+        (Object::Class(x), Object::Class(y)) => ClassD::same(x, y),
+        _ => false,
+    }
+}
+
+/* IsLooselyEqual (`==`) */
+pub fn loose_equals(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        _ if is_nullish(a) || is_nullish(b) => is_nullish(a) && is_nullish(b),
+        (Object::Const(Const::Bool(_)), _) => loose_equals(&num(to_number(a)), b),
+        (_, Object::Const(Const::Bool(_))) => loose_equals(a, &num(to_number(b))),
+        (Object::Const(Const::BigInt(_)), Object::Const(Const::BigInt(_))) => strict_equals(a, b),
+        (Object::Const(Const::BigInt(_)), Object::Const(_))
+        | (Object::Const(_), Object::Const(Const::BigInt(_))) => to_number(a) == to_number(b),
+        (Object::Const(x), Object::Const(y)) if is_number(x) != is_number(y) => {
+            to_number(a) == to_number(b)
+        }
+        _ => strict_equals(a, b),
+    }
+}
+
+/* IsLessThan and friends; `None` when either side is NaN */
+pub fn compare(a: &Object, b: &Object) -> Option<Ordering> {
+    match (a, b) {
+        (Object::Const(Const::Str { v: x, .. }), Object::Const(Const::Str { v: y, .. })) => {
+            /* strings are ordered by UTF-16 code units, not by code points */
+            Some(x.cmp(y))
+        }
+        (Object::Const(Const::BigInt(x)), Object::Const(Const::BigInt(y))) => Some(x.cmp(y)),
+        _ => to_number(a).partial_cmp(&to_number(b)),
+    }
+}
+
+/* a Number; integral values get the faster `Integer` form */
+pub fn num(n: f64) -> Object {
+    if n.fract() == 0.0 && n.abs() <= MAX_EXACT_INT as f64 && !(n == 0.0 && n.is_sign_negative()) {
+        Object::Const(Const::Integer(n as i64))
+    } else {
+        Object::Const(Const::Float(n))
+    }
+}
+
+/* `i` must be within `-2^53..=2^53`; use `num` for anything that may not be */
+pub fn int(i: i64) -> Object {
+    Object::Const(Const::Integer(i))
+}
+
+/**
+ * `a op b` for two `Integer`s without going through floats.
+ * `None` for other operators and when the result would be -0, a fraction or too big
+ * to be exact, so the float path gives every result a script can see.
+ */
+pub fn int_binary(op: &EOperator, a: i64, b: i64) -> Option<Object> {
+    match int_compare(op, a, b) {
+        Some(r) => Some(boolean(r)),
+        None => int_arith(op, a, b).map(int),
+    }
+}
+
+/* the same for the arithmetic, shift and bitwise operators, which give a number */
+pub fn int_arith(op: &EOperator, a: i64, b: i64) -> Option<i64> {
+    let r = match op {
+        EOperator::Plus => a + b,
+        EOperator::Minus => a - b,
+        EOperator::Multiply if (a < 0 || b < 0) && (a == 0 || b == 0) => return None,
+        EOperator::Multiply => a.checked_mul(b)?,
+        EOperator::Modulus if b == 0 || (a < 0 && a % b == 0) => return None,
+        EOperator::Modulus => a % b,
+        /* ToInt32 of an integer keeps its low 32 bits */
+        EOperator::BitAnd => (a as i32 & b as i32) as i64,
+        EOperator::BitOr => (a as i32 | b as i32) as i64,
+        EOperator::BitXor => (a as i32 ^ b as i32) as i64,
+        EOperator::Lshift => (a as i32).wrapping_shl(b as u32 & 31) as i64,
+        EOperator::Rshift => ((a as i32) >> (b as u32 & 31)) as i64,
+        EOperator::Urshift => ((a as u32) >> (b as u32 & 31)) as i64,
+        _ => return None,
+    };
+
+    (r.abs() <= MAX_EXACT_INT).then_some(r)
+}
+
+/* `a op b` for two `Integer`s and a comparison or equality operator; `None` for the others */
+pub fn int_compare(op: &EOperator, a: i64, b: i64) -> Option<bool> {
+    Some(match op {
+        EOperator::Le => a < b,
+        EOperator::Ge => a > b,
+        EOperator::Leq => a <= b,
+        EOperator::Geq => a >= b,
+        EOperator::Eqeqeq | EOperator::Eqeq => a == b,
+        EOperator::Neqeq | EOperator::Neq => a != b,
+        _ => return None,
+    })
+}
+
+/* Number::exponentiate, which differs from `powf` when the base is ±1 */
+fn pow(base: f64, exp: f64) -> f64 {
+    if exp.is_nan() || (base.abs() == 1.0 && exp.is_infinite()) {
+        f64::NAN
+    } else {
+        base.powf(exp)
+    }
+}
+
+pub fn boolean(b: bool) -> Object {
+    Object::Const(Const::Bool(b))
+}
+
+fn is_string(o: &Object) -> bool {
+    matches!(o, Object::Const(Const::Str { .. }))
+}
+
+fn is_bigint(o: &Object) -> bool {
+    matches!(o, Object::Const(Const::BigInt(_)))
+}
+
+fn bigint(i: BigInt) -> Object {
+    Object::Const(Const::BigInt(i))
+}
+
+/* what an operator throws: the name of the Error type and the message */
+pub type OpError = (&'static str, String);
+
+fn too_big() -> OpError {
+    ("RangeError", String::from("Maximum BigInt size exceeded"))
+}
+
+fn too_long() -> OpError {
+    ("RangeError", String::from("Invalid string length"))
+}
+
+/* a BigInt result, unless it is too wide */
+fn checked_bigint(r: Option<BigInt>) -> Result<Object, OpError> {
+    match r {
+        Some(r) if r.bits() <= MAX_BITS => Ok(bigint(r)),
+        _ => Err(too_big()),
+    }
+}
+
+/* `a op b` for two BigInts; `None` for the operators that treat them like any other value */
+fn bigint_binary(op: &EOperator, a: &BigInt, b: &BigInt) -> Option<Result<Object, OpError>> {
+    let r = match op {
+        EOperator::Plus => Some(a.add(b)),
+        EOperator::Minus => Some(a.sub(b)),
+        EOperator::Multiply => Some(a.mul(b)),
+        EOperator::Divide | EOperator::Modulus if b.is_zero() => {
+            return Some(Err(("RangeError", String::from("Division by zero"))))
+        }
+        EOperator::Divide => a.div_rem(b).map(|(q, _)| q),
+        EOperator::Modulus => a.div_rem(b).map(|(_, r)| r),
+        EOperator::Pow if b.is_negative() => {
+            return Some(Err((
+                "RangeError",
+                String::from("Exponent must be non-negative"),
+            )))
+        }
+        EOperator::Pow => a.pow(b),
+        EOperator::BitAnd => Some(a.bitwise(b, |x, y| x & y)),
+        EOperator::BitOr => Some(a.bitwise(b, |x, y| x | y)),
+        EOperator::BitXor => Some(a.bitwise(b, |x, y| x ^ y)),
+        EOperator::Lshift => a.shl(b),
+        EOperator::Rshift => a.shl(&b.neg()),
+        EOperator::Urshift => {
+            return Some(Err((
+                "TypeError",
+                String::from("BigInts have no unsigned right shift, use >> instead"),
+            )))
+        }
+        _ => return None,
+    };
+
+    Some(checked_bigint(r))
+}
+
+/* `lhs op rhs` for the arithmetic, comparison, equality, shift and bitwise operators */
+pub fn binary(op: &EOperator, lhs: &Object, rhs: &Object) -> Result<Object, OpError> {
+    let i32s = || (to_int32(to_number(lhs)), to_int32(to_number(rhs)));
+    let shift = || to_uint32(to_number(rhs)) & 31;
+
+    if let (Object::Const(Const::Integer(a)), Object::Const(Const::Integer(b))) = (lhs, rhs) {
+        if let Some(r) = int_binary(op, *a, *b) {
+            return Ok(r);
+        }
+    }
+
+    if let (Object::Const(Const::BigInt(a)), Object::Const(Const::BigInt(b))) = (lhs, rhs) {
+        if let Some(r) = bigint_binary(op, a, b) {
+            return r;
+        }
+    } else if (is_bigint(lhs) || is_bigint(rhs))
+        && !(*op == EOperator::Plus && (is_string(lhs) || is_string(rhs)))
+        /* `op` is one that does arithmetic on BigInts */
+        && bigint_binary(op, &BigInt::default(), &BigInt::from_i64(1)).is_some()
+    {
+        return Err((
+            "TypeError",
+            String::from("Cannot mix BigInt and other types, use explicit conversions"),
+        ));
+    }
+
+    Ok(match op {
+        EOperator::Plus if is_string(lhs) || is_string(rhs) => {
+            let mut s = to_js_str(lhs);
+            s.push(&to_js_str(rhs)).map_err(|_| too_long())?;
+            Object::str(s)
+        }
+        EOperator::Plus => num(to_number(lhs) + to_number(rhs)),
+        EOperator::Minus => num(to_number(lhs) - to_number(rhs)),
+        EOperator::Multiply => num(to_number(lhs) * to_number(rhs)),
+        EOperator::Divide => num(to_number(lhs) / to_number(rhs)),
+        EOperator::Pow => num(pow(to_number(lhs), to_number(rhs))),
+        EOperator::Eqeqeq => boolean(strict_equals(lhs, rhs)),
+        EOperator::Neqeq => boolean(!strict_equals(lhs, rhs)),
+        EOperator::Eqeq => boolean(loose_equals(lhs, rhs)),
+        EOperator::Neq => boolean(!loose_equals(lhs, rhs)),
+        EOperator::Le => boolean(compare(lhs, rhs) == Some(Ordering::Less)),
+        EOperator::Ge => boolean(compare(lhs, rhs) == Some(Ordering::Greater)),
+        EOperator::Leq => boolean(matches!(
+            compare(lhs, rhs),
+            Some(Ordering::Less | Ordering::Equal)
+        )),
+        EOperator::Geq => boolean(matches!(
+            compare(lhs, rhs),
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        EOperator::Modulus => num(to_number(lhs) % to_number(rhs)),
+        EOperator::BitAnd => int((i32s().0 & i32s().1) as i64),
+        EOperator::BitOr => int((i32s().0 | i32s().1) as i64),
+        EOperator::BitXor => int((i32s().0 ^ i32s().1) as i64),
+        EOperator::Lshift => int(i32s().0.wrapping_shl(shift()) as i64),
+        EOperator::Rshift => int((i32s().0 >> shift()) as i64),
+        EOperator::Urshift => int((to_uint32(to_number(lhs)) >> shift()) as i64),
+        _ => Object::undef(),
+    })
+}
+
+/* `e + 1` / `e - 1` for `++` and `--` (`op` is `Incr` or `Decr`) */
+pub fn update(op: &EOperator, e: &Object) -> Result<Object, OpError> {
+    let d = if *op == EOperator::Incr { 1 } else { -1 };
+
+    Ok(match e {
+        Object::Const(Const::Integer(i)) if (i + d).abs() <= MAX_EXACT_INT => int(i + d),
+        Object::Const(Const::BigInt(i)) => checked_bigint(Some(i.add(&BigInt::from_i64(d))))?,
+        _ => num(to_number(e) + d as f64),
+    })
+}
+
+/* `++` or `--` on an `Integer`; `None` when the result would not be exact */
+pub fn int_step(op: &EOperator, i: i64) -> Option<i64> {
+    let r = if *op == EOperator::Incr { i + 1 } else { i - 1 };
+    (r.abs() <= MAX_EXACT_INT).then_some(r)
+}
+
+/* ToNumeric, as returned by postfix `++` / `--` */
+pub fn to_numeric(e: &Object) -> Object {
+    match e {
+        Object::Const(Const::Integer(_) | Const::Float(_) | Const::BigInt(_)) => e.clone(),
+        _ => num(to_number(e)),
+    }
+}
+
+/* `typeof e` */
+pub fn type_of(e: &Object) -> &'static str {
+    match e {
+        Object::Const(c) => match c {
+            Const::Undef => "undefined",
+            Const::Null => "object",
+            Const::Bool(_) => "boolean",
+            Const::Integer(_) | Const::Float(_) => "number",
+            Const::BigInt(_) => "bigint",
+            Const::Str { .. } => "string",
+        },
+        Object::Funct(_) | Object::Class(_) => "function",
+        Object::ClassObj(_) | Object::Obj(_) | Object::Array(_) => "object",
+    }
+}
+
+/* `op e` for the prefix operators */
+pub fn unary(op: &EOperator, e: &Object) -> Result<Object, OpError> {
+    Ok(match (op, e) {
+        (EOperator::Minus, Object::Const(Const::BigInt(i))) => bigint(i.neg()),
+        (EOperator::Plus, Object::Const(Const::BigInt(_))) => {
+            return Err((
+                "TypeError",
+                String::from("Cannot convert a BigInt value to a number"),
+            ))
+        }
+        (EOperator::BitNot, Object::Const(Const::BigInt(i))) => checked_bigint(Some(i.not()))?,
+        /* `-0` has to be a float */
+        (EOperator::Minus, Object::Const(Const::Integer(i))) if *i != 0 => int(-i),
+        (EOperator::Minus, _) => num(-to_number(e)),
+        (EOperator::Plus, Object::Const(Const::Integer(i))) => int(*i),
+        (EOperator::Plus, _) => num(to_number(e)),
+        (EOperator::Not, _) => boolean(!truthy(e)),
+        (EOperator::BitNot, _) => int(!to_int32(to_number(e)) as i64),
+        _ => Object::undef(),
+    })
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use super::{
+    builtins,
+    function::Function,
+    heap::{self, Trace, Tracer},
+    obj::Object,
+};
+
+/* whether `k` is an array index ("0", "1", ...), which objects list first and in ascending order */
+pub fn index_key(k: &str) -> Option<u32> {
+    if k == "0" || (!k.starts_with('0') && !k.is_empty()) {
+        k.parse::<u32>().ok().filter(|i| *i != u32::MAX)
+    } else {
+        None
+    }
+}
+
+/* what a property holds: a value, or the functions that read and write it */
+#[derive(Debug, Clone)]
+pub enum Slot {
+    Data {
+        value: Object,
+        writable: bool,
+    },
+    Accessor {
+        get: Option<Function>,
+        set: Option<Function>,
+    },
+}
+
+impl Slot {
+    pub fn trace(&self, t: &mut Tracer) {
+        match self {
+            Slot::Data { value, .. } => value.trace(t),
+            Slot::Accessor { get, set } => {
+                for f in [get, set].into_iter().flatten() {
+                    f.trace(t);
+                }
+            }
+        }
+    }
+}
+
+/* a property with its attributes */
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub slot: Slot,
+    pub enumerable: bool,
+    pub configurable: bool,
+}
+
+impl Property {
+    /* a writable, enumerable and configurable data property, the kind assignment creates */
+    pub fn data(value: Object) -> Property {
+        Property {
+            slot: Slot::Data {
+                value,
+                writable: true,
+            },
+            enumerable: true,
+            configurable: true,
+        }
+    }
+
+    /* like `data`, but left out of enumerations, as methods are */
+    pub fn hidden(value: Object) -> Property {
+        Property {
+            enumerable: false,
+            ..Property::data(value)
+        }
+    }
+}
+
+/**
+ * The properties of an object, remembering the order they were added in,
+ * and its [[Prototype]], where lookups of properties it does not have go on.
+ */
+#[derive(Debug, Clone)]
+pub struct Props {
+    map: HashMap<String, Property>,
+    order: Vec<String>,
+    /* `None` is a null prototype */
+    pub proto: Option<Object>,
+    /* whether properties may be added; `Object.preventExtensions` clears it */
+    pub extensible: bool,
+}
+
+impl Default for Props {
+    fn default() -> Props {
+        Props::with_proto(None)
+    }
+}
+
+impl Trace for Props {
+    fn trace(&self, t: &mut Tracer) {
+        for p in self.map.values() {
+            p.slot.trace(t);
+        }
+        if let Some(p) = &self.proto {
+            p.trace(t);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.order.clear();
+        self.proto = None;
+    }
+}
+
+/* the values may be the last references to long chains of objects, which `drop_contents` frees without deep recursion */
+impl Drop for Props {
+    fn drop(&mut self) {
+        if !self.map.is_empty() || self.proto.is_some() {
+            heap::drop_contents((std::mem::take(&mut self.map), self.proto.take()));
+        }
+    }
+}
+
+impl Props {
+    /* no properties and a null prototype */
+    pub fn new() -> Props {
+        Props::default()
+    }
+
+    /* the properties of an ordinary object, which inherits from `Object.prototype` */
+    pub fn ordinary() -> Props {
+        Props::with_proto(Some(builtins::object::prototype()))
+    }
+
+    pub fn with_proto(proto: Option<Object>) -> Props {
+        Props {
+            map: HashMap::new(),
+            order: Vec::new(),
+            proto,
+            extensible: true,
+        }
+    }
+
+    pub fn property(&self, k: &str) -> Option<&Property> {
+        self.map.get(k)
+    }
+
+    /* the value of the own data property `k` */
+    pub fn get(&self, k: &str) -> Option<&Object> {
+        match &self.map.get(k)?.slot {
+            Slot::Data { value, .. } => Some(value),
+            Slot::Accessor { .. } => None,
+        }
+    }
+
+    pub fn contains(&self, k: &str) -> bool {
+        self.map.contains_key(k)
+    }
+
+    /**
+     * Stores `v` in `k`: an existing data property keeps its attributes,
+     * anything else becomes a plain data property. Attributes are not checked here.
+     */
+    pub fn set(&mut self, k: &str, v: Object) {
+        match self.map.get_mut(k) {
+            Some(Property {
+                slot: Slot::Data { value, .. },
+                ..
+            }) => *value = v,
+            _ => self.define(k, Property::data(v)),
+        }
+    }
+
+    /* creates or replaces the property `k` */
+    pub fn define(&mut self, k: &str, p: Property) {
+        if self.map.insert(k.to_string(), p).is_none() {
+            self.order.push(k.to_string());
+        }
+    }
+
+    pub fn remove(&mut self, k: &str) -> Option<Property> {
+        let p = self.map.remove(k)?;
+        self.order.retain(|o| o != k);
+        Some(p)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /* keys in property order: array indices ascending, then the rest as they were added */
+    pub fn keys(&self) -> Vec<String> {
+        let mut idx: Vec<(u32, &String)> = self
+            .order
+            .iter()
+            .filter_map(|k| index_key(k).map(|i| (i, k)))
+            .collect();
+        idx.sort();
+
+        idx.into_iter()
+            .map(|(_, k)| k.clone())
+            .chain(
+                self.order
+                    .iter()
+                    .filter(|k| index_key(k).is_none())
+                    .cloned(),
+            )
+            .collect()
+    }
+
+    /* the enumerable properties in property order */
+    pub fn enumerable(&self) -> Vec<(String, Property)> {
+        self.keys()
+            .into_iter()
+            .filter_map(|k| {
+                let p = self.map[&k].clone();
+                p.enumerable.then_some((k, p))
+            })
+            .collect()
+    }
+
+    /* `(key, value)` pairs of the enumerable data properties, in property order */
+    pub fn entries(&self) -> Vec<(String, Object)> {
+        self.enumerable()
+            .into_iter()
+            .filter_map(|(k, p)| match p.slot {
+                Slot::Data { value, .. } => Some((k, value)),
+                Slot::Accessor { .. } => None,
+            })
+            .collect()
+    }
+
+    /* `Object.freeze`: no property can be added, removed or changed any more */
+    pub fn freeze(&mut self) {
+        self.extensible = false;
+
+        for p in self.map.values_mut() {
+            p.configurable = false;
+            if let Slot::Data { writable, .. } = &mut p.slot {
+                *writable = false;
+            }
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        !self.extensible
+            && self
+                .map
+                .values()
+                .all(|p| !p.configurable && !matches!(p.slot, Slot::Data { writable: true, .. }))
+    }
+}
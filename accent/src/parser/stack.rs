@@ -0,0 +1,47 @@
+/*
+ * Keeps scripts from overflowing the native stack. The VM runs calls from one coded function
+ * to another in place, but calls through native code and the tree-walker nest Rust frames, so
+ * rather than trusting a fixed call depth to fit, each of those checks how much stack the
+ * script has used since it started and throws a RangeError once that passes the thread's budget.
+ */
+
+use std::cell::Cell;
+
+/* what a script may use when the host sets nothing; leaves room on a 2 MiB thread */
+pub const DEFAULT_BUDGET: usize = 1024 * 1024;
+
+thread_local! {
+    /* where the outermost running script started; 0 while none runs */
+    static BASE: Cell<usize> = const { Cell::new(0) };
+    static BUDGET: Cell<usize> = const { Cell::new(DEFAULT_BUDGET) };
+}
+
+/* lets scripts on this thread use up to `bytes` of stack, e.g. on a thread spawned with more */
+pub fn set_budget(bytes: usize) {
+    BUDGET.set(bytes);
+}
+
+/* an address in the current stack frame */
+#[inline(never)]
+fn here() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/* runs `f` as a script, measuring stack use from here unless an outer script already does */
+pub fn run<R>(f: impl FnOnce() -> R) -> R {
+    if BASE.get() != 0 {
+        return f();
+    }
+
+    BASE.set(here());
+    let r = f();
+    BASE.set(0);
+    r
+}
+
+/* whether the running script has used up its stack budget */
+pub fn exhausted() -> bool {
+    let base = BASE.get();
+    base != 0 && base.abs_diff(here()) > BUDGET.get()
+}
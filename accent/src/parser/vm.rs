@@ -0,0 +1,956 @@
+/*
+ * The VM, which runs the code the compiler makes.
+ *
+ * Each call of a coded function runs its `Code` with registers and a value stack of its own,
+ * in a new scope for the names closures see when it has any; blocks with such names push
+ * scopes inside that one. Exceptions go to the innermost handler that `EnterTry` installed
+ * in the running code, or else to the caller.
+ *
+ * A call the running code makes to a coded function does not nest on the native stack:
+ * `run` keeps the calls in progress in a list of its own, so recursion in a script is only
+ * bounded by `MAX_CALL_DEPTH`. Calls made by native code, such as the callback of `map`,
+ * a getter or a constructor, start a `run` of their own.
+ */
+
+use std::{rc::Rc, vec::Drain};
+
+use crate::ast::{
+    constants::{Const, JsStr},
+    EOperator, Stmt,
+};
+use crate::token::Span;
+
+use super::{
+    array::Array,
+    bytecode::{Arg, At, Code, Op, Var},
+    classes, coerce, compiler,
+    completion::Completion,
+    env::{Assigned, Binding, Env},
+    errors::{invalid_string_length, new_error},
+    function::{FnKind, Function, Proto},
+    heap,
+    module::Module,
+    obj::{
+        arguments_object, assigned, binary_op, binding_value, call_value, define_accessor,
+        delete_prop, for_in_keys, get_index, get_prop, in_op, instanceof_op, iteration_items,
+        literal_proto, new_value, nth_item, own_entries, private_in_op, put_prop, spread_items,
+        super_init, template_strings, truthy, unary_op, update_op, with_props_mut, Object,
+        MAX_CALL_DEPTH,
+    },
+    ops,
+    props::Props,
+};
+
+const BALANCED: &str = "compiled code keeps its stack balanced";
+
+/* where an exception thrown in a `try` block goes */
+struct Handler {
+    pc: usize,
+    /* stack size at the `try`, which the handler starts from */
+    stack: usize,
+    /* the scope the `try` statement runs in */
+    env: Env,
+}
+
+/* the state of one running `Code` */
+struct Frame {
+    pc: usize,
+    stack: Vec<Object>,
+    /* `None` until the name is declared */
+    regs: Vec<Option<Object>>,
+    handlers: Vec<Handler>,
+}
+
+/* why `exec` stopped */
+enum Exit {
+    Done(Completion),
+    /**
+     * The code calls a coded function, which `run` starts without leaving it.
+     * `this`, the function and the `argc` arguments are on top of the stack.
+     */
+    Call {
+        argc: usize,
+        span: Span,
+    },
+}
+
+/* the vectors of finished calls, which later calls reuse instead of allocating their own */
+#[derive(Default)]
+struct Spare {
+    stacks: Vec<Vec<Object>>,
+    regs: Vec<Vec<Option<Object>>>,
+}
+
+impl Spare {
+    fn keep(&mut self, f: Frame) {
+        let Frame {
+            mut stack,
+            mut regs,
+            ..
+        } = f;
+        stack.clear();
+        regs.clear();
+        self.stacks.push(stack);
+        self.regs.push(regs);
+    }
+}
+
+/* a call of a coded function that `run` carries out for the code it was given */
+struct Activation {
+    proto: Rc<Proto>,
+    frame: Frame,
+    md: Module,
+}
+
+/* compiles `stmts` and runs them in `md` */
+pub fn run_script(stmts: &[Stmt], md: &mut Module) -> Completion {
+    let code = match compiler::script(stmts, &md.env) {
+        Ok(code) => code,
+        Err(span) => return Completion::Throw(too_deep(span)),
+    };
+    let env = md.env.clone();
+
+    let frame = Frame::new(Vec::new(), vec![None; code.registers.len()]);
+    match run(&code, md, frame) {
+        Ok(c) => c,
+        Err(e) => {
+            /* the exception may have left blocks whose scopes were never popped */
+            md.env = env;
+            Completion::Throw(e)
+        }
+    }
+}
+
+/* calls the coded function made from `proto` in `env` */
+pub fn call(
+    proto: &Rc<Proto>,
+    env: &Env,
+    this: Object,
+    mut args: Vec<Object>,
+    depth: usize,
+) -> Result<Object, Object> {
+    let mut a = activation(
+        proto,
+        env,
+        this,
+        args.drain(..),
+        depth,
+        &mut Spare::default(),
+    )?;
+
+    match run(compiled(proto), &mut a.md, a.frame)? {
+        Completion::Return(v) => Ok(v),
+        _ => Ok(Object::undef()),
+    }
+}
+
+/* the code of `proto`, which `activation` compiled */
+fn compiled(proto: &Proto) -> &Code {
+    proto
+        .code
+        .get()
+        .expect("the function was compiled before it ran")
+}
+
+/* the RangeError for calls nested too deeply, or code too deep to compile on the stack that is left */
+fn too_deep(span: Span) -> Object {
+    new_error("RangeError", "Maximum call stack size exceeded", span)
+}
+
+/**
+ * A call of the coded function made from `proto` in `env`, ready to run; compiles it on its first call.
+ * Code that can't be compiled yet is tried again on the next call, which may have more stack.
+ */
+fn activation(
+    proto: &Rc<Proto>,
+    env: &Env,
+    this: Object,
+    mut args: Drain<Object>,
+    depth: usize,
+    spare: &mut Spare,
+) -> Result<Activation, Object> {
+    if proto.code.get().is_none() {
+        let code = compiler::function(proto).map_err(too_deep)?;
+        let _ = proto.code.set(code);
+    }
+    let code = compiled(proto);
+
+    let scope = match code.layout.is_empty() {
+        true => env.clone(),
+        false => Env::with_layout(Some(env), &code.layout),
+    };
+    let mut regs = spare.regs.pop().unwrap_or_default();
+    regs.resize(code.registers.len(), None);
+    let mut put = |at: Option<At>, v: Object| match at {
+        Some(At::Reg(r)) => regs[r] = Some(v),
+        Some(At::Slot { slot, .. }) => {
+            scope.put_slot(slot, Some(v));
+        }
+        _ => (),
+    };
+
+    put(code.this, this);
+    if code.arguments.is_some() {
+        put(code.arguments, arguments_object(args.as_slice()));
+    }
+    for p in &code.params {
+        put(*p, args.next().unwrap_or_else(Object::undef));
+    }
+
+    Ok(Activation {
+        proto: proto.clone(),
+        frame: Frame::new(spare.stacks.pop().unwrap_or_default(), regs),
+        md: Module {
+            env: scope,
+            stmts: Vec::new(),
+            depth,
+            call_span: Span::default(),
+        },
+    })
+}
+
+/**
+ * Runs `code` in the scope of `md` in `frame`, until it returns, ends or throws, along with
+ * the calls of coded functions it makes. An exception nothing in a call catches goes on to
+ * the code that made the call.
+ */
+fn run(code: &Code, md: &mut Module, mut frame: Frame) -> Result<Completion, Object> {
+    heap::maybe_collect();
+    /* the calls in progress, innermost last */
+    let mut calls: Vec<Activation> = Vec::new();
+    let mut spare = Spare::default();
+
+    loop {
+        let (f, running, cur) = match calls.last_mut() {
+            Some(a) => (&mut a.frame, compiled(&a.proto), &mut a.md),
+            None => (&mut frame, code, &mut *md),
+        };
+        let depth = cur.depth;
+
+        let e = match f.exec(running, cur) {
+            Ok(Exit::Done(c)) => {
+                let Some(done) = calls.pop() else {
+                    return Ok(c);
+                };
+                spare.keep(done.frame);
+                let v = match c {
+                    Completion::Return(v) => v,
+                    _ => Object::undef(),
+                };
+                let caller = calls.last_mut().map_or(&mut frame, |a| &mut a.frame);
+                caller.stack.push(v);
+                continue;
+            }
+            Ok(Exit::Call { span, .. }) if depth >= MAX_CALL_DEPTH => too_deep(span),
+            Ok(Exit::Call { argc, .. }) => {
+                heap::maybe_collect();
+                let caller = calls.last_mut().map_or(&mut frame, |a| &mut a.frame);
+                let at = caller.stack.len() - argc - 2;
+                let mut args = caller.stack.drain(at..);
+                let this = args.next().expect(BALANCED);
+                let Some(Object::Funct(callee)) = args.next() else {
+                    unreachable!("only coded functions are called in place");
+                };
+                let FnKind::Coded { proto, env } = &callee.kind else {
+                    unreachable!("only coded functions are called in place");
+                };
+                match activation(proto, env, this, args, depth + 1, &mut spare) {
+                    Ok(a) => {
+                        calls.push(a);
+                        continue;
+                    }
+                    Err(e) => e,
+                }
+            }
+            Err(e) => e,
+        };
+
+        /* the innermost handler of the calls in progress takes the exception */
+        loop {
+            let (f, cur) = match calls.last_mut() {
+                Some(a) => (&mut a.frame, &mut a.md),
+                None => (&mut frame, &mut *md),
+            };
+            if let Some(h) = f.handlers.pop() {
+                f.stack.truncate(h.stack);
+                f.stack.push(e);
+                f.pc = h.pc;
+                cur.env = h.env;
+                break;
+            }
+            if calls.pop().is_none() {
+                return Err(e);
+            }
+        }
+    }
+}
+
+/* whether `f` is a coded function, which `run` calls without nesting */
+fn is_coded(f: &Object) -> bool {
+    matches!(f, Object::Funct(f) if matches!(f.kind, FnKind::Coded { .. }))
+}
+
+/* the values of an argument list built as an array */
+fn array_values(args: Object, span: Span) -> Result<Vec<Object>, Object> {
+    match args {
+        Object::Array(a) => a
+            .borrow()
+            .values()
+            .map_err(|m| new_error("RangeError", m, span)),
+        _ => unreachable!("argument lists with spreads are arrays"),
+    }
+}
+
+/* a primitive key as a property name */
+fn key_string(key: &Object) -> String {
+    match key {
+        Object::Const(Const::Str { v, .. }) => v.to_string(),
+        _ => ops::to_string(key),
+    }
+}
+
+impl Frame {
+    fn new(stack: Vec<Object>, regs: Vec<Option<Object>>) -> Frame {
+        Frame {
+            pc: 0,
+            stack,
+            regs,
+            handlers: Vec::new(),
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect(BALANCED)
+    }
+
+    fn top(&self) -> &Object {
+        self.stack.last().expect(BALANCED)
+    }
+
+    /* the last `n` values, taken off the stack */
+    fn take(&mut self, n: u32) -> Vec<Object> {
+        let at = self.stack.len() - n as usize;
+        self.stack.split_off(at)
+    }
+
+    /* the binding of `var`; `None` when no scope declares it */
+    fn binding(&self, var: &Var, md: &Module) -> Option<Binding> {
+        match var.at {
+            At::Reg(r) => Some(self.reg(r)),
+            At::Slot { hops, slot } => Some(md.env.get_slot(hops, slot)),
+            At::Name => md.env.lookup(&var.name),
+        }
+    }
+
+    fn reg(&self, r: usize) -> Binding {
+        match &self.regs[r] {
+            Some(v) => Binding::Value(v.clone()),
+            None => Binding::Uninitialized,
+        }
+    }
+
+    fn get_var(&self, var: &Var, md: &Module, span: Span) -> Result<Object, Object> {
+        binding_value(&var.name, self.binding(var, md), span)
+    }
+
+    fn set_var(&mut self, var: &Var, v: Object, md: &Module, span: Span) -> Result<(), Object> {
+        let done = match var.at {
+            _ if var.is_const => match self.binding(var, md) {
+                Some(Binding::Uninitialized) => Assigned::Uninitialized,
+                _ => Assigned::Const,
+            },
+            At::Reg(r) => self.set_reg(r, v),
+            At::Slot { hops, slot } => md.env.set_slot(hops, slot, v),
+            At::Name => md.env.set(&var.name, v),
+        };
+
+        assigned(&var.name, done, span)
+    }
+
+    fn set_reg(&mut self, r: usize, v: Object) -> Assigned {
+        match &mut self.regs[r] {
+            Some(x) => {
+                *x = v;
+                Assigned::Done
+            }
+            None => Assigned::Uninitialized,
+        }
+    }
+
+    /**
+     * `++` or `--` in place on a register or a variable of the running scope that holds an
+     * `Integer`, giving the old and the new value; `None` leaves everything else to `update_op`.
+     */
+    fn step_var(&mut self, var: &Var, op: &EOperator, md: &Module) -> Option<(i64, i64)> {
+        let step = |v: &mut Option<Object>| match v {
+            Some(Object::Const(Const::Integer(n))) => {
+                let old = *n;
+                *n = ops::int_step(op, old)?;
+                Some((old, *n))
+            }
+            _ => None,
+        };
+
+        match var.at {
+            _ if var.is_const => None,
+            At::Reg(r) => step(&mut self.regs[r]),
+            At::Slot { hops: 0, slot } => md.env.with_slot(slot, step),
+            _ => None,
+        }
+    }
+
+    fn arg(&mut self, a: Arg, code: &Code, span: Span) -> Result<Object, Object> {
+        match a {
+            Arg::Stack => Ok(self.pop()),
+            Arg::Reg(r) => {
+                let r = r as usize;
+                binding_value(&code.registers[r], Some(self.reg(r)), span)
+            }
+            Arg::Const(i) => Ok(code.consts[i as usize].clone()),
+        }
+    }
+
+    /* the operands of `Binary`; `r` is only on the stack when `l` is too */
+    fn operands(
+        &mut self,
+        l: Arg,
+        r: Arg,
+        code: &Code,
+        span: Span,
+    ) -> Result<(Object, Object), Object> {
+        if let Arg::Stack = r {
+            let r = self.pop();
+            return Ok((self.arg(l, code, span)?, r));
+        }
+
+        Ok((self.arg(l, code, span)?, self.arg(r, code, span)?))
+    }
+
+    /**
+     * `l op r` when both operands are `Integer`s and the result needs no conversions,
+     * reading them where they are; the operands on the stack go only when it succeeds.
+     */
+    fn int_binary(&mut self, op: &EOperator, l: Arg, r: Arg, code: &Code) -> Option<Object> {
+        let (l, r, on_stack) = self.int_operands(l, r, code)?;
+        let v = ops::int_binary(op, l, r)?;
+
+        self.drop_ints(on_stack);
+        Some(v)
+    }
+
+    /* the same for a comparison that a jump tests, without making a boolean of it */
+    fn int_test(&mut self, op: &EOperator, l: Arg, r: Arg, code: &Code) -> Option<bool> {
+        let (l, r, on_stack) = self.int_operands(l, r, code)?;
+        let v = match ops::int_compare(op, l, r) {
+            Some(v) => v,
+            None => ops::int_arith(op, l, r)? != 0,
+        };
+
+        self.drop_ints(on_stack);
+        Some(v)
+    }
+
+    /* takes `n` integers off the stack; they own nothing, so they are not dropped one by one */
+    fn drop_ints(&mut self, n: usize) {
+        for _ in 0..n {
+            std::mem::forget(self.stack.pop());
+        }
+    }
+
+    /* the operands of `int_binary` and how many of them are on the stack */
+    fn int_operands(&self, l: Arg, r: Arg, code: &Code) -> Option<(i64, i64, usize)> {
+        let n = self.stack.len();
+        let int = |a: Arg, depth: usize| match a {
+            Arg::Stack => match self.stack.get(n.checked_sub(depth)?)? {
+                Object::Const(Const::Integer(i)) => Some(*i),
+                _ => None,
+            },
+            Arg::Reg(r) => match &self.regs[r as usize] {
+                Some(Object::Const(Const::Integer(i))) => Some(*i),
+                _ => None,
+            },
+            Arg::Const(i) => match &code.consts[i as usize] {
+                Object::Const(Const::Integer(i)) => Some(*i),
+                _ => None,
+            },
+        };
+
+        /* `r` is only on the stack when `l` is too */
+        match (l, r) {
+            (Arg::Stack, Arg::Stack) => Some((int(l, 2)?, int(r, 1)?, 2)),
+            (Arg::Stack, _) => Some((int(l, 1)?, int(r, 0)?, 1)),
+            _ => Some((int(l, 0)?, int(r, 0)?, 0)),
+        }
+    }
+
+    fn jump(&mut self, to: u32) {
+        /* every loop jumps back, which is where the collector gets its chance */
+        if (to as usize) < self.pc {
+            heap::maybe_collect();
+        }
+        self.pc = to as usize;
+    }
+
+    /**
+     * Runs instructions until the code completes or calls a coded function;
+     * `Err` is an exception for `run` to route.
+     */
+    fn exec(&mut self, code: &Code, md: &mut Module) -> Result<Exit, Object> {
+        loop {
+            let pc = self.pc;
+            let span = code.spans[pc];
+            self.pc += 1;
+
+            match &code.ops[pc] {
+                Op::Const(i) => self.stack.push(code.consts[*i as usize].clone()),
+                Op::Undef => self.stack.push(Object::undef()),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => self.stack.push(self.top().clone()),
+                Op::Dup2 => {
+                    let n = self.stack.len();
+                    self.stack.extend_from_within(n - 2..);
+                }
+                Op::Swap => {
+                    let n = self.stack.len();
+                    self.stack.swap(n - 1, n - 2);
+                }
+                Op::Nip(n) => {
+                    let v = self.pop();
+                    self.stack.truncate(self.stack.len() - *n as usize);
+                    self.stack.push(v);
+                }
+
+                Op::GetReg(r) => {
+                    let v = self.arg(Arg::Reg(*r), code, span)?;
+                    self.stack.push(v);
+                }
+                Op::SetReg(r) => {
+                    let v = self.pop();
+                    let r = *r as usize;
+                    assigned(&code.registers[r], self.set_reg(r, v), span)?;
+                }
+                Op::InitReg(r) => self.regs[*r as usize] = Some(self.pop()),
+                Op::ClearRegs { from, count } => {
+                    let (from, count) = (*from as usize, *count as usize);
+                    self.regs[from..from + count].fill(None);
+                }
+                Op::Declare(i) => {
+                    let v = self.pop();
+                    md.env.put_slot(*i as usize, Some(v));
+                }
+                Op::DeclareVar(i) => {
+                    let v = self.pop();
+                    let var = &code.vars[*i as usize];
+                    match var.at {
+                        At::Reg(r) => self.regs[r] = Some(v),
+                        At::Slot { hops, slot } => {
+                            md.env.outer(hops).put_slot(slot, Some(v));
+                        }
+                        At::Name => md.env.define_var(&var.name, v),
+                    }
+                }
+                Op::Get(i) => {
+                    let v = self.get_var(&code.vars[*i as usize], md, span)?;
+                    self.stack.push(v);
+                }
+                Op::Set(i) => {
+                    let v = self.pop();
+                    self.set_var(&code.vars[*i as usize], v, md, span)?;
+                }
+                Op::This(i) => {
+                    let this = self
+                        .get_var(&code.vars[*i as usize], md, span)
+                        .unwrap_or_else(|_| Object::undef());
+                    self.stack.push(classes::checked_this(this, span)?);
+                }
+                Op::TypeofVar(i) => {
+                    let var = &code.vars[*i as usize];
+                    let t = match self.binding(var, md) {
+                        None => "undefined",
+                        b => ops::type_of(&binding_value(&var.name, b, span)?),
+                    };
+                    self.stack.push(Object::str(t));
+                }
+                Op::UpdateVar { var, op, prefix } => {
+                    let var = &code.vars[*var as usize];
+                    if let Some((old, new)) = self.step_var(var, op, md) {
+                        self.stack.push(ops::int(if *prefix { new } else { old }));
+                        continue;
+                    }
+                    let cur = self.get_var(var, md, span)?;
+                    let (old, new) = update_op(op, &cur, md, span)?;
+
+                    self.set_var(var, new.clone(), md, span)?;
+                    self.stack.push(if *prefix { new } else { old });
+                }
+                Op::StepVar { var, op } => {
+                    let var = &code.vars[*var as usize];
+                    if self.step_var(var, op, md).is_some() {
+                        continue;
+                    }
+                    let cur = self.get_var(var, md, span)?;
+                    let (_, new) = update_op(op, &cur, md, span)?;
+
+                    self.set_var(var, new, md, span)?;
+                }
+                Op::PushScope(i) => {
+                    md.env = Env::with_layout(Some(&md.env), &code.blocks[*i as usize]);
+                }
+                Op::PopScope => md.env = md.env.parent().expect(BALANCED),
+                Op::NextIteration => md.env = md.env.next_iteration(),
+
+                Op::Unary(op) => {
+                    let v = self.pop();
+                    self.stack.push(unary_op(op, v, md, span)?);
+                }
+                Op::Typeof => {
+                    let v = self.pop();
+                    self.stack.push(Object::str(ops::type_of(&v)));
+                }
+                Op::Binary { op, l, r } => {
+                    let v = match self.int_binary(op, *l, *r, code) {
+                        Some(v) => v,
+                        None => {
+                            let (l, r) = self.operands(*l, *r, code, span)?;
+                            binary_op(op, l, r, md, span)?
+                        }
+                    };
+                    self.stack.push(v);
+                }
+                Op::In => {
+                    let o = self.pop();
+                    let key = self.pop();
+                    self.stack.push(in_op(&key_string(&key), &o, span)?);
+                }
+                Op::Instanceof => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    self.stack.push(instanceof_op(&l, &r, span)?);
+                }
+                Op::ToKey => {
+                    let v = self.pop();
+                    self.stack.push(match v {
+                        Object::Const(Const::Integer(n)) if n >= 0 => v,
+                        _ => Object::str(coerce::to_string(&v, md, span)?),
+                    });
+                }
+                Op::ToStr => {
+                    let v = self.pop();
+                    self.stack.push(match v {
+                        Object::Const(Const::Str { .. }) => v,
+                        _ => Object::str(coerce::to_js_str(&v, md, span)?),
+                    });
+                }
+                Op::Concat(n) => {
+                    let mut s = JsStr::default();
+                    for part in self.take(*n) {
+                        s.push(&ops::to_js_str(&part))
+                            .map_err(|_| invalid_string_length(span))?;
+                    }
+                    self.stack.push(Object::str(s));
+                }
+
+                Op::Jump(to) => self.jump(*to),
+                Op::JumpIfFalse(to) => {
+                    if !truthy(&self.pop()) {
+                        self.jump(*to);
+                    }
+                }
+                Op::JumpIfTrue(to) => {
+                    if truthy(&self.pop()) {
+                        self.jump(*to);
+                    }
+                }
+                Op::JumpUnless { op, l, r, to } | Op::JumpIf { op, l, r, to } => {
+                    let v = match self.int_test(op, *l, *r, code) {
+                        Some(v) => v,
+                        None => {
+                            let (l, r) = self.operands(*l, *r, code, span)?;
+                            truthy(&binary_op(op, l, r, md, span)?)
+                        }
+                    };
+                    if v == matches!(code.ops[pc], Op::JumpIf { .. }) {
+                        self.jump(*to);
+                    }
+                }
+                Op::And(to) | Op::Or(to) | Op::Nullish(to) => {
+                    let v = self.top();
+                    let decided = match &code.ops[pc] {
+                        Op::And(_) => !truthy(v),
+                        Op::Or(_) => truthy(v),
+                        _ => !ops::is_nullish(v),
+                    };
+
+                    if decided {
+                        self.jump(*to);
+                    } else {
+                        self.pop();
+                    }
+                }
+
+                Op::GetProp(i) => {
+                    let o = self.pop();
+                    let name = &code.names[*i as usize];
+                    let v = match &o {
+                        /* which loops read on every iteration */
+                        Object::Array(a) if name == "length" => ops::int(a.borrow().len() as i64),
+                        _ => get_prop(&o, name, md, span)?,
+                    };
+                    self.stack.push(v);
+                }
+                Op::SetProp(i) => {
+                    let v = self.pop();
+                    let o = self.pop();
+                    put_prop(&o, &code.names[*i as usize], v.clone(), md, span)?;
+                    self.stack.push(v);
+                }
+                Op::GetIndex => {
+                    let key = self.pop();
+                    let o = self.pop();
+                    self.stack.push(get_index(&o, &key, md, span, span)?);
+                }
+                Op::SetIndex => {
+                    let v = self.pop();
+                    let key = self.pop();
+                    let o = self.pop();
+                    put_prop(&o, &key_string(&key), v.clone(), md, span)?;
+                    self.stack.push(v);
+                }
+                Op::UpdateProp { name, op, prefix } => {
+                    let o = self.pop();
+                    let name = &code.names[*name as usize];
+                    let cur = get_prop(&o, name, md, span)?;
+                    let (old, new) = update_op(op, &cur, md, span)?;
+
+                    put_prop(&o, name, new.clone(), md, span)?;
+                    self.stack.push(if *prefix { new } else { old });
+                }
+                Op::UpdateIndex { op, prefix } => {
+                    let key = key_string(&self.pop());
+                    let o = self.pop();
+                    let cur = get_prop(&o, &key, md, span)?;
+                    let (old, new) = update_op(op, &cur, md, span)?;
+
+                    put_prop(&o, &key, new.clone(), md, span)?;
+                    self.stack.push(if *prefix { new } else { old });
+                }
+                Op::Delete => {
+                    let key = key_string(&self.pop());
+                    let o = self.pop();
+                    delete_prop(&o, &key, span)?;
+                    self.stack.push(Object::Const(Const::Bool(true)));
+                }
+                Op::GetPrivate(i) => {
+                    let o = self.pop();
+                    let name = &code.names[*i as usize];
+                    self.stack.push(classes::get_private(&o, name, md, span)?);
+                }
+                Op::SetPrivate(i) => {
+                    let v = self.pop();
+                    let o = self.pop();
+                    classes::put_private(&o, &code.names[*i as usize], v.clone(), md, span)?;
+                    self.stack.push(v);
+                }
+                Op::UpdatePrivate { name, op, prefix } => {
+                    let o = self.pop();
+                    let name = &code.names[*name as usize];
+                    let cur = classes::get_private(&o, name, md, span)?;
+                    let (old, new) = update_op(op, &cur, md, span)?;
+
+                    classes::put_private(&o, name, new.clone(), md, span)?;
+                    self.stack.push(if *prefix { new } else { old });
+                }
+                Op::HasPrivate(i) => {
+                    let o = self.pop();
+                    let name = &code.names[*i as usize];
+                    self.stack.push(private_in_op(name, o, md, span, span)?);
+                }
+                Op::GetSuper => {
+                    let key = key_string(&self.pop());
+                    let this = self.pop();
+                    self.stack.push(classes::super_prop(&key, &this, md, span)?);
+                }
+
+                Op::Call { argc, desc } => {
+                    let argc = *argc as usize;
+                    if is_coded(&self.stack[self.stack.len() - argc - 1]) {
+                        return Ok(Exit::Call { argc, span });
+                    }
+                    let args = self.take(argc as u32);
+                    let f = self.pop();
+                    let this = self.pop();
+                    let desc = || code.names[*desc as usize].clone();
+
+                    self.stack.push(call_value(f, this, args, desc, md, span)?);
+                }
+                Op::New { argc, desc } => {
+                    let args = self.take(*argc);
+                    let c = self.pop();
+                    let desc = || code.names[*desc as usize].clone();
+
+                    self.stack.push(new_value(&c, args, desc, md, span)?);
+                }
+                Op::CallArgs(desc) => {
+                    let args = self.pop();
+                    let f = self.pop();
+                    let this = self.pop();
+                    let desc = || code.names[*desc as usize].clone();
+
+                    let args = array_values(args, span)?;
+                    if is_coded(&f) {
+                        let argc = args.len();
+                        self.stack.extend([this, f].into_iter().chain(args));
+                        return Ok(Exit::Call { argc, span });
+                    }
+                    self.stack.push(call_value(f, this, args, desc, md, span)?);
+                }
+                Op::NewArgs(desc) => {
+                    let args = array_values(self.pop(), span)?;
+                    let c = self.pop();
+                    let desc = || code.names[*desc as usize].clone();
+
+                    self.stack.push(new_value(&c, args, desc, md, span)?);
+                }
+                Op::SuperCall => {
+                    let args = array_values(self.pop(), span)?;
+                    let this = self.pop();
+                    self.stack.push(super_init(this, args, md, span)?);
+                }
+                Op::Closure(i) => self.stack.push(Object::Funct(Function::coded(
+                    code.protos[*i as usize].clone(),
+                    md.env.clone(),
+                ))),
+                Op::NamedClosure(i) => {
+                    /* only the function itself sees its name */
+                    let proto = &code.protos[*i as usize];
+                    let env = Env::with_layout(Some(&md.env), &proto.scopes[0]);
+                    let f = Object::Funct(Function::coded(proto.clone(), env.clone()));
+
+                    env.put_slot(0, Some(f.clone()));
+                    self.stack.push(f);
+                }
+                Op::Array(n) => {
+                    let values = self.take(*n);
+                    self.stack.push(Object::array(Array::from_values(values)));
+                }
+                Op::Append => {
+                    let v = self.pop();
+                    if let Object::Array(a) = self.top() {
+                        a.borrow_mut().push(v);
+                    }
+                }
+                Op::AppendSpread(desc) => {
+                    let v = self.pop();
+                    let items = spread_items(v, || code.names[*desc as usize].clone(), span)?;
+                    if let Object::Array(a) = self.top() {
+                        let mut a = a.borrow_mut();
+                        for v in items {
+                            a.push(v);
+                        }
+                    }
+                }
+                Op::AppendHole => {
+                    if let Object::Array(a) = self.top() {
+                        let mut a = a.borrow_mut();
+                        let n = a.len();
+                        a.set_len(n + 1);
+                    }
+                }
+                Op::Strings(i) => {
+                    self.stack
+                        .push(template_strings(&code.templates[*i as usize]));
+                }
+                Op::NewObject => self.stack.push(Object::obj(Props::ordinary())),
+                Op::InitProp(i) => {
+                    let v = self.pop();
+                    with_props_mut(self.top(), |p| p.set(&code.names[*i as usize], v));
+                }
+                Op::InitIndex => {
+                    let v = self.pop();
+                    let key = key_string(&self.pop());
+                    with_props_mut(self.top(), |p| p.set(&key, v));
+                }
+                Op::InitAccessor(kind) => {
+                    let f = self.pop();
+                    let key = key_string(&self.pop());
+                    let Object::Funct(f) = f else {
+                        unreachable!("accessors are functions")
+                    };
+                    with_props_mut(self.top(), |p| define_accessor(p, &key, *kind, f));
+                }
+                Op::InitProto => {
+                    let v = self.pop();
+                    with_props_mut(self.top(), |p| literal_proto(p, v));
+                }
+                Op::InitSpread => {
+                    let v = self.pop();
+                    let entries = own_entries(&v, md, span)?;
+                    with_props_mut(self.top(), |p| {
+                        for (k, v) in entries {
+                            p.set(&k, v);
+                        }
+                    });
+                }
+                Op::Class { class, extends } => {
+                    let def = &code.classes[*class as usize];
+                    let keys = self.take(def.computed.len() as u32);
+                    let keys = keys.iter().map(key_string).collect();
+                    let parent = extends.then(|| self.pop());
+
+                    self.stack
+                        .push(classes::define_class(def, parent, keys, md)?);
+                }
+                Op::JumpIfNullish { to, pop } => {
+                    if ops::is_nullish(self.top()) {
+                        self.stack.truncate(self.stack.len() - *pop as usize);
+                        self.jump(*to);
+                    }
+                }
+
+                Op::ForOf(desc) => {
+                    let v = self.pop();
+                    let desc = || code.names[*desc as usize].clone();
+                    self.stack.push(iteration_items(v, desc, span)?);
+                    self.stack.push(ops::int(0));
+                }
+                Op::ForIn => {
+                    let v = self.pop();
+                    self.stack.push(for_in_keys(&v));
+                    self.stack.push(ops::int(0));
+                }
+                Op::Next(to) => {
+                    let n = self.stack.len();
+                    let Object::Const(Const::Integer(i)) = self.stack[n - 1] else {
+                        unreachable!("{BALANCED}");
+                    };
+                    match nth_item(&self.stack[n - 2], i as usize) {
+                        Some(_) => self.stack[n - 1] = ops::int(i + 1),
+                        None => self.jump(*to),
+                    }
+                }
+                Op::Item(above) => {
+                    let at = self.stack.len() - *above as usize - 2;
+                    let Object::Const(Const::Integer(i)) = self.stack[at + 1] else {
+                        unreachable!("{BALANCED}");
+                    };
+                    let item = nth_item(&self.stack[at], i as usize - 1);
+                    self.stack.push(item.unwrap_or_else(Object::undef));
+                }
+
+                Op::Throw => return Err(self.pop()),
+                Op::EnterTry(to) => self.handlers.push(Handler {
+                    pc: *to as usize,
+                    stack: self.stack.len(),
+                    env: md.env.clone(),
+                }),
+                Op::LeaveTry => {
+                    self.handlers.pop();
+                }
+                Op::Return => return Ok(Exit::Done(Completion::Return(self.pop()))),
+                Op::End => return Ok(Exit::Done(Completion::Normal)),
+            }
+        }
+    }
+}
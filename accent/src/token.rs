@@ -1,4 +1,9 @@
-use std::vec;
+use std::fmt;
+
+use crate::ast::{
+    bigint::BigInt,
+    constants::{JsStr, MAX_EXACT_INT},
+};
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
 pub enum EOperator {
@@ -7,6 +12,9 @@ pub enum EOperator {
     Divide,   // /
     Multiply, // *
     Modulus,  // %
+    Pow,      // **
+    Incr,     // ++
+    Decr,     // --
 
     Colon,     // :
     Semicolon, // ;
@@ -17,6 +25,10 @@ pub enum EOperator {
     LParen,    // (
     RParen,    // )
     Comma,     // ,
+    Dot,       // .
+    Ellipsis,  // ...
+    Arrow,     // =>
+    OptChain,  // ?.
 
     Eqeq,   // ==
     Eqeqeq, // ===
@@ -30,251 +42,859 @@ pub enum EOperator {
     Not,    // !
     Lshift, // <<
     Rshift, // >>
+
+    Urshift,  // >>>
+    And,      // &&
+    Or,       // ||
+    Nullish,  // ??
+    BitAnd,   // &
+    BitOr,    // |
+    BitXor,   // ^
+    BitNot,   // ~
+    Question, // ?
+
+    PlusEq,     // +=
+    MinusEq,    // -=
+    MultiplyEq, // *=
+    DivideEq,   // /=
+    ModulusEq,  // %=
+    PowEq,      // **=
+    LshiftEq,   // <<=
+    RshiftEq,   // >>=
+    UrshiftEq,  // >>>=
+    BitAndEq,   // &=
+    BitOrEq,    // |=
+    BitXorEq,   // ^=
+    AndEq,      // &&=
+    OrEq,       // ||=
+    NullishEq,  // ??=
 }
 
+/* every punctuator with its spelling, longest first so that lexing can take the first match */
+const PUNCTUATORS: &[(&str, EOperator)] = &[
+    (">>>=", EOperator::UrshiftEq),
+    ("===", EOperator::Eqeqeq),
+    ("!==", EOperator::Neqeq),
+    (">>>", EOperator::Urshift),
+    ("**=", EOperator::PowEq),
+    ("<<=", EOperator::LshiftEq),
+    (">>=", EOperator::RshiftEq),
+    ("&&=", EOperator::AndEq),
+    ("||=", EOperator::OrEq),
+    ("??=", EOperator::NullishEq),
+    ("...", EOperator::Ellipsis),
+    ("==", EOperator::Eqeq),
+    ("!=", EOperator::Neq),
+    ("<=", EOperator::Leq),
+    (">=", EOperator::Geq),
+    ("<<", EOperator::Lshift),
+    (">>", EOperator::Rshift),
+    ("&&", EOperator::And),
+    ("||", EOperator::Or),
+    ("??", EOperator::Nullish),
+    ("?.", EOperator::OptChain),
+    ("=>", EOperator::Arrow),
+    ("**", EOperator::Pow),
+    ("++", EOperator::Incr),
+    ("--", EOperator::Decr),
+    ("+=", EOperator::PlusEq),
+    ("-=", EOperator::MinusEq),
+    ("*=", EOperator::MultiplyEq),
+    ("/=", EOperator::DivideEq),
+    ("%=", EOperator::ModulusEq),
+    ("&=", EOperator::BitAndEq),
+    ("|=", EOperator::BitOrEq),
+    ("^=", EOperator::BitXorEq),
+    ("+", EOperator::Plus),
+    ("-", EOperator::Minus),
+    ("/", EOperator::Divide),
+    ("*", EOperator::Multiply),
+    ("%", EOperator::Modulus),
+    (":", EOperator::Colon),
+    (";", EOperator::Semicolon),
+    ("{", EOperator::LBrace),
+    ("}", EOperator::RBrace),
+    ("[", EOperator::LBracket),
+    ("]", EOperator::RBracket),
+    ("(", EOperator::LParen),
+    (")", EOperator::RParen),
+    (",", EOperator::Comma),
+    (".", EOperator::Dot),
+    ("<", EOperator::Le),
+    (">", EOperator::Ge),
+    ("=", EOperator::Eq),
+    ("!", EOperator::Not),
+    ("&", EOperator::BitAnd),
+    ("|", EOperator::BitOr),
+    ("^", EOperator::BitXor),
+    ("~", EOperator::BitNot),
+    ("?", EOperator::Question),
+];
+
 #[derive(Debug, Clone)]
 pub enum Token {
     Identifier(String),
+    /* `#name` in a class body; the name keeps its `#` */
+    PrivateName(String),
     Keyword(String),
     Operator(EOperator),
     Integer(i64),
     Float(f64),
+    BigInt(BigInt), /* `123n` */
     Bool(bool),
     Newline,
     Comment(String),
     Str {
-        v: String,
+        v: JsStr,
         is_raw: bool,
         is_fmt: bool,
     },
+    /**
+     * A piece of a template literal: `head` starts at the opening "`", `tail` ends at the
+     * closing one, and anything else borders on `${...}` substitutions.
+     * `cooked` is `None` when the piece has an invalid escape, which only tagged templates allow.
+     * The strings are boxed so the pieces take no more room than a string token.
+     */
+    Template {
+        cooked: Option<Box<[u16]>>,
+        raw: Box<str>,
+        head: bool,
+        tail: bool,
+    },
     Undef,
+    Null,
     Eof,
+    /* a malformed literal; the parser reports the message */
+    Invalid(String),
 }
 
 impl Token {}
 
-pub fn gen_toks(data: String) -> Vec<Token> {
-    let RESERVED_KEYWORDS = vec![
-        "let", "const", "var", "if", "else", "for", "while", "do", "function", "return", "class",
+impl EOperator {
+    /* `+` for `+=`, `&&` for `&&=` and so on */
+    pub fn compound_base(&self) -> Option<EOperator> {
+        let base = match self {
+            EOperator::PlusEq => EOperator::Plus,
+            EOperator::MinusEq => EOperator::Minus,
+            EOperator::MultiplyEq => EOperator::Multiply,
+            EOperator::DivideEq => EOperator::Divide,
+            EOperator::ModulusEq => EOperator::Modulus,
+            EOperator::PowEq => EOperator::Pow,
+            EOperator::LshiftEq => EOperator::Lshift,
+            EOperator::RshiftEq => EOperator::Rshift,
+            EOperator::UrshiftEq => EOperator::Urshift,
+            EOperator::BitAndEq => EOperator::BitAnd,
+            EOperator::BitOrEq => EOperator::BitOr,
+            EOperator::BitXorEq => EOperator::BitXor,
+            EOperator::AndEq => EOperator::And,
+            EOperator::OrEq => EOperator::Or,
+            EOperator::NullishEq => EOperator::Nullish,
+            _ => return None,
+        };
+
+        Some(base)
+    }
+}
+
+impl fmt::Display for EOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = PUNCTUATORS
+            .iter()
+            .find(|(_, o)| o == self)
+            .map_or("", |(s, _)| s);
+
+        write!(f, "{s}")
+    }
+}
+
+/* used by diagnostics, e.g. "expected ';', found identifier 'b'" */
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(id) => write!(f, "identifier '{id}'"),
+            Token::PrivateName(n) => write!(f, "private name '{n}'"),
+            Token::Keyword(k) => write!(f, "keyword '{k}'"),
+            Token::Operator(o) => write!(f, "'{o}'"),
+            Token::Integer(i) => write!(f, "number '{i}'"),
+            Token::Float(fl) => write!(f, "number '{fl}'"),
+            Token::BigInt(i) => write!(f, "bigint '{i}n'"),
+            Token::Bool(b) => write!(f, "'{b}'"),
+            Token::Newline => write!(f, "newline"),
+            Token::Comment(_) => write!(f, "comment"),
+            Token::Str { v, .. } => write!(f, "string {v:?}"),
+            Token::Template { .. } => write!(f, "template string"),
+            Token::Undef => write!(f, "'undefined'"),
+            Token::Null => write!(f, "'null'"),
+            Token::Eof => write!(f, "end of input"),
+            Token::Invalid(_) => write!(f, "invalid token"),
+        }
+    }
+}
+
+/**
+ * Location of a token (or of a node built from tokens) in the source.
+ * `start`/`end` are byte offsets, `line`/`col` are 1-based and point at `start`.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /* smallest span covering both `self` and `other` */
+    pub fn to(self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(self);
+        }
+
+        Span {
+            start: self.start,
+            end: self.end.max(other.end),
+            line: self.line,
+            col: self.col,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/**
+ * A token together with the span it was read from.
+ */
+#[derive(Debug, Clone)]
+pub struct Lexeme {
+    pub tok: Token,
+    pub span: Span,
+}
+
+/* the longest punctuator at the start of `src`; `?.` is not one when a digit follows (`a?.5:b`) */
+fn punctuator(src: &[u8]) -> Option<&'static (&'static str, EOperator)> {
+    PUNCTUATORS.iter().find(|(p, op)| {
+        src.starts_with(p.as_bytes())
+            && !(*op == EOperator::OptChain && src.get(2).is_some_and(u8::is_ascii_digit))
+    })
+}
+
+/* reserved words the lexer has no keyword for yet; they still cannot be spelled with escapes */
+const FUTURE_RESERVED: &[&str] = &["debugger", "enum", "export", "import", "with"];
+
+/* ID_Start plus the `$` and `_` that ECMAScript adds */
+fn is_ident_start(c: char) -> bool {
+    c == '$' || c == '_' || unicode_id_start::is_id_start(c)
+}
+
+/* LineTerminator: each one is a line break, for ASI and for the line numbers in spans */
+pub fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+/* WhiteSpace: tab, vertical tab, form feed, space, the Zs separators and the BOM */
+fn is_space(c: char) -> bool {
+    c == '\u{FEFF}' || (c.is_whitespace() && c != '\u{85}' && !is_line_terminator(c))
+}
+
+/* where the line breaks in `s` end; `\r\n` is a single one */
+fn line_ends(s: &str) -> impl Iterator<Item = usize> + '_ {
+    s.char_indices()
+        .filter(|&(n, c)| is_line_terminator(c) && !(c == '\r' && s[n + 1..].starts_with('\n')))
+        .map(|(n, c)| n + c.len_utf8())
+}
+
+/* ID_Continue plus `$`, ZWNJ and ZWJ */
+fn is_ident_part(c: char) -> bool {
+    c == '$' || c == '\u{200C}' || c == '\u{200D}' || unicode_id_start::is_id_continue(c)
+}
+
+/**
+ * The code point of a `\uXXXX` or `\u{X...}` escape, given the source right after `\u`,
+ * together with the number of bytes the escape takes there.
+ */
+fn unicode_escape(src: &str) -> Option<(u32, usize)> {
+    if let Some(rest) = src.strip_prefix('{') {
+        let end = rest.find('}')?;
+        let digits = &rest[..end];
+
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        return u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|cp| *cp <= 0x10FFFF)
+            .map(|cp| (cp, end + 2));
+    }
+
+    let digits = src.get(..4)?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok().map(|cp| (cp, 4))
+}
+
+/**
+ * A `\u` escape inside a string. Surrogate pairs written as two escapes (`"\uD83D\uDE00"`)
+ * combine into one code point; a lone surrogate is kept as it is.
+ */
+fn string_unicode_escape(src: &str) -> Option<(u32, usize)> {
+    let (cp, len) = unicode_escape(src)?;
+
+    if (0xD800..0xDC00).contains(&cp) {
+        if let Some((lo, lo_len)) = src[len..]
+            .strip_prefix("\\u")
+            .and_then(unicode_escape)
+            .filter(|(lo, _)| (0xDC00..0xE000).contains(lo))
+        {
+            let c = 0x10000 + ((cp - 0xD800) << 10) + (lo - 0xDC00);
+            return Some((c, len + 2 + lo_len));
+        }
+    }
+
+    Some((cp, len))
+}
+
+/* appends the code point `cp`, which may be a lone surrogate, as UTF-16 */
+fn push_code(out: &mut Vec<u16>, cp: u32) {
+    match char::from_u32(cp) {
+        Some(c) => out.extend(c.encode_utf16(&mut [0; 2]).iter()),
+        None => out.push(cp as u16),
+    }
+}
+
+/* length of the run of digits in `radix` and `_` separators at the start of `s` */
+fn digits_len(s: &[u8], radix: u32) -> usize {
+    s.iter()
+        .position(|b| *b != b'_' && !(*b as char).is_digit(radix))
+        .unwrap_or(s.len())
+}
+
+/* `_` may only appear between two digits: not `1__0`, `1_`, `1_.5` or `0x_1` */
+fn separators_ok(s: &str, radix: u32) -> bool {
+    let b = s.as_bytes();
+    let digit = |j: Option<&u8>| j.is_some_and(|c| (*c as char).is_digit(radix));
+
+    b.iter()
+        .enumerate()
+        .filter(|(_, c)| **c == b'_')
+        .all(|(j, _)| j > 0 && digit(b.get(j - 1)) && digit(b.get(j + 1)))
+}
+
+/**
+ * Reads the numeric literal at the start of `src`: `12`, `1.5`, `.5`, `1e-9`, `0xff`, `0o17`,
+ * `0b11`, `1_000` or `123n`. Returns the token, `Token::Invalid` for a malformed literal,
+ * and the number of bytes it takes.
+ */
+fn number(src: &str) -> (Token, usize) {
+    let b = src.as_bytes();
+    let radix = match (b[0], b.get(1)) {
+        (b'0', Some(b'x' | b'X')) => 16,
+        (b'0', Some(b'o' | b'O')) => 8,
+        (b'0', Some(b'b' | b'B')) => 2,
+        _ => 10,
+    };
+
+    let invalid = |m: &str, len: usize| (Token::Invalid(m.to_string()), len);
+
+    let (tok, mut i) = if radix != 10 {
+        let end = 2 + digits_len(&b[2..], radix);
+        let digits = &src[2..end];
+        let is_big = b.get(end) == Some(&b'n');
+        let i = end + is_big as usize;
+
+        if digits.is_empty() {
+            return invalid(&format!("missing digits after '{}'", &src[..2]), i);
+        }
+        if !separators_ok(digits, radix) {
+            return invalid("numeric separators are only allowed between digits", i);
+        }
+
+        let digits = digits.replace('_', "");
+        let tok = if is_big {
+            Token::BigInt(BigInt::parse(&digits, radix).expect("only digits were read"))
+        } else {
+            match u128::from_str_radix(&digits, radix) {
+                Ok(v) if v <= MAX_EXACT_INT as u128 => Token::Integer(v as i64),
+                Ok(v) => Token::Float(v as f64),
+                Err(_) => Token::Float(digits.chars().fold(0.0, |acc, c| {
+                    acc * radix as f64 + c.to_digit(radix).unwrap_or(0) as f64
+                })),
+            }
+        };
+
+        (tok, i)
+    } else {
+        let int_len = digits_len(b, 10);
+        let mut i = int_len;
+        let mut is_float = false;
+
+        if b.get(i) == Some(&b'.') {
+            is_float = true;
+            i += 1;
+            i += digits_len(&b[i..], 10);
+        }
+
+        if matches!(b.get(i), Some(b'e' | b'E')) {
+            is_float = true;
+            i += 1;
+            if matches!(b.get(i), Some(b'+' | b'-')) {
+                i += 1;
+            }
+
+            let exp_len = digits_len(&b[i..], 10);
+            if exp_len == 0 {
+                return invalid("missing digits in exponent", i);
+            }
+            i += exp_len;
+        }
+
+        let text = &src[..i];
+        let is_big = b.get(i) == Some(&b'n');
+        if is_big {
+            i += 1;
+        }
+
+        if !separators_ok(text, 10) {
+            return invalid("numeric separators are only allowed between digits", i);
+        }
+        if int_len > 1 && b[0] == b'0' {
+            let msg = if src[..int_len].bytes().all(|c| c < b'8') {
+                "octal literals are not allowed, use the 0o prefix"
+            } else {
+                "decimals with leading zeros are not allowed"
+            };
+            return invalid(msg, i);
+        }
+
+        let text = text.replace('_', "");
+        let tok = match (is_big, is_float) {
+            (true, true) => return invalid("BigInt literals must be integers", i),
+            (true, false) => {
+                Token::BigInt(BigInt::parse(&text, 10).expect("only digits were read"))
+            }
+            /* integers past 2^53 round to the nearest Number, like any other literal */
+            (false, false) => match text.parse::<i64>() {
+                Ok(v) if v <= MAX_EXACT_INT => Token::Integer(v),
+                _ => Token::Float(text.parse().unwrap_or(f64::INFINITY)),
+            },
+            (false, true) => Token::Float(text.parse().unwrap_or(f64::NAN)),
+        };
+
+        (tok, i)
+    };
+
+    /* `3in`, `1x` and `0b12` are errors rather than two tokens */
+    if let Some(c) = src[i..].chars().next() {
+        if is_ident_start(c) || c.is_ascii_alphanumeric() || c == '\\' {
+            i += src[i..]
+                .find(|c: char| !is_ident_part(c))
+                .unwrap_or(src.len() - i);
+            return invalid("identifier starts immediately after numeric literal", i);
+        }
+    }
+
+    (tok, i)
+}
+
+/**
+ * Reads the escape sequence that follows a backslash at the start of `src`.
+ * Gives the character it stands for (`None` for a line continuation), or a message
+ * for a malformed escape, together with the number of bytes to skip.
+ */
+fn escape(src: &str) -> (Result<Option<u32>, &'static str>, usize) {
+    let Some(e) = src.chars().next() else {
+        return (Ok(None), 0);
+    };
+    let len = e.len_utf8();
+
+    let c = match e {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'b' => '\u{8}',
+        'f' => '\u{C}',
+        'v' => '\u{B}',
+        '0' if !src[1..].starts_with(|d: char| d.is_ascii_digit()) => '\0',
+        '0'..='9' => return (Err("octal escape sequences are not allowed"), len),
+        'x' => {
+            return match src
+                .get(1..3)
+                .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                Some(b) => (Ok(Some(b as u32)), 3),
+                None => (Err("invalid hexadecimal escape sequence"), len),
+            }
+        }
+        'u' => {
+            return match string_unicode_escape(&src[1..]) {
+                Some((c, n)) => (Ok(Some(c)), 1 + n),
+                None => (Err("invalid Unicode escape sequence"), len),
+            }
+        }
+        /* a line continuation adds nothing to the string */
+        '\r' if src[1..].starts_with('\n') => return (Ok(None), 2),
+        '\n' | '\r' | '\u{2028}' | '\u{2029}' => return (Ok(None), len),
+        _ => e,
+    };
+
+    (Ok(Some(c as u32)), len)
+}
+
+/**
+ * Reads a template piece up to and including the closing "`" or the "${" of a substitution.
+ * `src` starts right after the opening "`" (`head`) or the "}" that ended a substitution.
+ */
+fn template_piece(src: &str, head: bool) -> (Token, usize) {
+    let mut i = 0;
+    let mut cooked = Some(Vec::new());
+    let mut end = None;
+
+    while let Some(c) = src[i..].chars().next() {
+        let at = i;
+        i += c.len_utf8();
+
+        let c = match c {
+            '`' => {
+                end = Some((at, true));
+                break;
+            }
+            '$' if src[i..].starts_with('{') => {
+                i += 1;
+                end = Some((at, false));
+                break;
+            }
+            '\\' => {
+                let (e, len) = escape(&src[i..]);
+                i += len;
+
+                match (e, &mut cooked) {
+                    (Ok(Some(cp)), Some(s)) => push_code(s, cp),
+                    (Ok(_), _) => (),
+                    (Err(_), _) => cooked = None,
+                }
+                continue;
+            }
+            /* CRLF and CR are read as LF */
+            '\r' => {
+                if src[i..].starts_with('\n') {
+                    i += 1;
+                }
+                '\n'
+            }
+            c => c,
+        };
+
+        if let Some(s) = &mut cooked {
+            push_code(s, c as u32);
+        }
+    }
+
+    let Some((end, tail)) = end else {
+        return (
+            Token::Invalid(String::from("unterminated template literal")),
+            i,
+        );
+    };
+
+    let raw = src[..end].replace("\r\n", "\n").replace('\r', "\n");
+    (
+        Token::Template {
+            cooked: cooked.map(Vec::into_boxed_slice),
+            raw: raw.into_boxed_str(),
+            head,
+            tail,
+        },
+        i,
+    )
+}
+
+/**
+ * Splits `data` into tokens.
+ * The source is decoded as UTF-8; spans keep byte offsets while columns count characters.
+ */
+pub fn gen_toks(data: String) -> Vec<Lexeme> {
+    let reserved_keywords = [
+        "let",
+        "const",
+        "var",
+        "if",
+        "else",
+        "for",
+        "while",
+        "do",
+        "switch",
+        "case",
+        "default",
+        "function",
+        "return",
+        "class",
+        "extends",
+        "super",
         "new",
+        "try",
+        "catch",
+        "finally",
+        "throw",
+        "break",
+        "continue",
+        "typeof",
+        "void",
+        "delete",
+        "in",
+        "instanceof",
+        "this",
     ];
 
     let mut res: Vec<Token> = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
     let dred = data.as_bytes();
     let mut i = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+    /* column of byte `col_pos`, so columns are counted in characters without rescanning the line */
+    let (mut col_pos, mut col) = (0, 1);
+    /* for every template substitution being read, the number of `{` it has open */
+    let mut templates: Vec<usize> = Vec::new();
+
+    while let Some(iv) = data[i..].chars().next() {
+        let tok_start = i;
+        let tok_line = line;
+        if col_pos < line_start {
+            (col_pos, col) = (line_start, 1);
+        }
+        col += data[col_pos..i].chars().count();
+        col_pos = i;
+        let tok_col = col;
+        let tok_count = res.len();
 
-    while i < dred.len() {
-        let iv = dred[i] as char;
         match iv {
-            'a'..='z' | 'A'..='Z' | '_' => {
+            c if is_ident_start(c) || (c == '\\' && data[i..].starts_with("\\u")) => {
                 let mut ident = String::new();
+                let mut escaped = false;
 
-                for j in i..=data.len() - 1 {
-                    match dred[j] as char {
-                        'a'..='z' | 'A'..='Z' | '_' | '0'..='9' => {
-                            ident.push(dred[j] as char);
+                while let Some(c) = data[i..].chars().next() {
+                    let (c, len) = if data[i..].starts_with("\\u") {
+                        match unicode_escape(&data[i + 2..])
+                            .and_then(|(cp, len)| Some((char::from_u32(cp)?, len + 2)))
+                        {
+                            Some(e) => {
+                                escaped = true;
+                                e
+                            }
+                            None => break,
                         }
-                        _ => break,
+                    } else {
+                        (c, c.len_utf8())
+                    };
+
+                    let ok = if ident.is_empty() {
+                        is_ident_start(c)
+                    } else {
+                        is_ident_part(c)
+                    };
+                    if !ok {
+                        break;
                     }
-                }
 
-                i += ident.len() - 1;
+                    ident.push(c);
+                    i += len;
+                }
 
-                if RESERVED_KEYWORDS.contains(&&ident[..]) {
+                if ident.is_empty() {
+                    /* a `\u` that does not spell an identifier character */
+                    i += 2;
+                    res.push(Token::Invalid(String::from("invalid or unexpected token")));
+                } else if escaped
+                    && (reserved_keywords.contains(&&ident[..])
+                        || ["true", "false", "null"].contains(&&ident[..])
+                        || FUTURE_RESERVED.contains(&&ident[..]))
+                {
+                    /* `\u0069f` neither spells the keyword `if` nor names a variable */
+                    res.push(Token::Invalid(String::from(
+                        "keyword must not contain escaped characters",
+                    )));
+                } else if escaped {
+                    res.push(Token::Identifier(ident));
+                } else if reserved_keywords.contains(&&ident[..]) {
                     let tk = Token::Keyword(ident);
                     res.push(tk);
                 } else if ident == "false" || ident == "true" {
-                    let tk = Token::Bool(if ident == "true" { true } else { false });
+                    let tk = Token::Bool(ident == "true");
                     res.push(tk);
                 } else if ident == "undefined" {
                     res.push(Token::Undef);
+                } else if ident == "null" {
+                    res.push(Token::Null);
                 } else {
                     let tk = Token::Identifier(ident);
                     res.push(tk);
                 }
             }
 
-            '+' => {
-                res.push(Token::Operator(EOperator::Plus));
-            }
-            '-' => {
-                res.push(Token::Operator(EOperator::Minus));
-            }
-            '/' => {
-                res.push(Token::Operator(EOperator::Divide));
-            }
-            '*' => {
-                res.push(Token::Operator(EOperator::Multiply));
-            }
-            '%' => {
-                res.push(Token::Operator(EOperator::Modulus));
-            }
-            ':' => {
-                res.push(Token::Operator(EOperator::Colon));
-            }
-            ';' => {
-                res.push(Token::Operator(EOperator::Semicolon));
-            }
-            '{' => {
-                res.push(Token::Operator(EOperator::LBrace));
-            }
-            '}' => {
-                res.push(Token::Operator(EOperator::RBrace));
-            }
-            '[' => {
-                res.push(Token::Operator(EOperator::LBracket));
-            }
-            ']' => {
-                res.push(Token::Operator(EOperator::RBracket));
-            }
-            '(' => {
-                res.push(Token::Operator(EOperator::LParen));
+            c if c.is_ascii_digit()
+                || (c == '.' && dred.get(i + 1).is_some_and(u8::is_ascii_digit)) =>
+            {
+                let (tok, len) = number(&data[i..]);
+                res.push(tok);
+                i += len;
             }
-            ')' => {
-                res.push(Token::Operator(EOperator::RParen));
-            }
-            ',' => {
-                res.push(Token::Operator(EOperator::Comma));
-            }
-            '=' => {
-                if i + 1 < dred.len() && dred[i + 1] as char == '=' {
-                    if i + 2 < dred.len() && dred[i + 2] as char == '=' {
-                        res.push(Token::Operator(EOperator::Eqeqeq));
-                        i += 1;
-                    } else {
-                        res.push(Token::Operator(EOperator::Eqeq));
+
+            '"' | '\'' => {
+                let q = iv;
+                i += 1;
+                let mut scont = Vec::new();
+                let mut err = None;
+                let mut closed = false;
+
+                while let Some(c) = data[i..].chars().next() {
+                    i += c.len_utf8();
+
+                    if c == q {
+                        closed = true;
+                        break;
                     }
-                    i += 1;
-                } else {
-                    res.push(Token::Operator(EOperator::Eq));
-                }
-            }
-            '!' => {
-                if i + 1 < dred.len() && dred[i + 1] as char == '=' {
-                    if i + 2 < dred.len() && dred[i + 2] as char == '=' {
-                        res.push(Token::Operator(EOperator::Neqeq));
-                        i += 1;
-                    } else {
-                        res.push(Token::Operator(EOperator::Neq));
+
+                    if c == '\\' {
+                        let (e, len) = escape(&data[i..]);
+                        i += len;
+
+                        match e {
+                            Ok(Some(cp)) => push_code(&mut scont, cp),
+                            Ok(None) => (),
+                            Err(m) => {
+                                err.get_or_insert(m);
+                            }
+                        }
+                        continue;
                     }
-                    i += 1;
-                } else {
-                    res.push(Token::Operator(EOperator::Not));
+
+                    push_code(&mut scont, c as u32);
                 }
+
+                res.push(match err {
+                    _ if !closed => Token::Invalid(String::from("unterminated string literal")),
+                    Some(m) => Token::Invalid(m.to_string()),
+                    None => Token::Str {
+                        v: JsStr::from(scont),
+                        is_raw: false,
+                        is_fmt: false,
+                    },
+                });
             }
-            '<' => {
-                if i + 1 < dred.len() && dred[i + 1] as char == '=' {
-                    res.push(Token::Operator(EOperator::Leq));
-                    i += 1;
-                } else if i + 1 < dred.len() && dred[i + 1] as char == '<' {
-                    res.push(Token::Operator(EOperator::Lshift));
-                    i += 1;
-                } else {
-                    res.push(Token::Operator(EOperator::Le));
+
+            '`' => {
+                let (tok, len) = template_piece(&data[i + 1..], true);
+                i += 1 + len;
+
+                if matches!(tok, Token::Template { tail: false, .. }) {
+                    templates.push(0);
                 }
+                res.push(tok);
             }
-            '>' => {
-                if i + 1 < dred.len() && dred[i + 1] as char == '=' {
-                    res.push(Token::Operator(EOperator::Geq));
-                    i += 1;
-                } else if i + 1 < dred.len() && dred[i + 1] as char == '>' {
-                    res.push(Token::Operator(EOperator::Rshift));
-                    i += 1;
-                } else {
-                    res.push(Token::Operator(EOperator::Ge));
+
+            /* the `}` closing a substitution continues the template */
+            '}' if templates.last() == Some(&0) => {
+                templates.pop();
+                let (tok, len) = template_piece(&data[i + 1..], false);
+                i += 1 + len;
+
+                if matches!(tok, Token::Template { tail: false, .. }) {
+                    templates.push(0);
                 }
+                res.push(tok);
             }
-            '0'..='9' => {
-                let mut num_str = String::new();
-                let mut saw_dot = false;
-
-                for j in i..data.len() {
-                    let c = dred[j] as char;
-                    match c {
-                        '0'..='9' => num_str.push(c),
-                        '.' if !saw_dot => {
-                            saw_dot = true;
-                            num_str.push(c);
-                        }
-                        _ => break,
-                    }
-                }
 
-                i += num_str.len() - 1;
+            /* comments keep their delimiters, so tooling can tell the kinds apart */
+            '#' if i == 0 && data.starts_with("#!") => {
+                i = data.find(is_line_terminator).unwrap_or(data.len());
+                res.push(Token::Comment(data[..i].to_string()));
+            }
 
-                if saw_dot {
-                    if let Ok(value) = num_str.parse::<f64>() {
-                        res.push(Token::Float(value));
-                    }
-                } else {
-                    if let Ok(value) = num_str.parse::<i64>() {
-                        res.push(Token::Integer(value));
-                    }
-                }
+            '#' if data[i + 1..].chars().next().is_some_and(is_ident_start) => {
+                let len = data[i + 1..]
+                    .find(|c| !is_ident_part(c))
+                    .unwrap_or(data.len() - i - 1);
+                res.push(Token::PrivateName(data[i..i + 1 + len].to_string()));
+                i += 1 + len;
             }
 
-            '"' | '\'' | '`' => {
-                let q = iv;
-                let sp = i;
-                let mut is_raw = false;
-                let mut is_fmt = q == '`';
-
-                /* if sp > 0 {
-                    if dred[sp - 1] as char == 'r' {
-                        is_raw = true;
-                        i -= 1;
-                    } else if dred[sp - 1] as char == 'f' {
-                        is_fmt = true;
-                        i -= 1;
-                    }
-                } */
+            '/' if data[i..].starts_with("//") => {
+                i = data[i..]
+                    .find(is_line_terminator)
+                    .map_or(data.len(), |n| i + n);
+                res.push(Token::Comment(data[tok_start..i].to_string()));
+            }
 
-                i += 1;
-                let mut scont = String::new();
-                let mut saw_bs = false;
-
-                while i < dred.len() {
-                    let c = dred[i] as char;
-
-                    if saw_bs && !is_raw {
-                        match c {
-                            'n' => scont.push('\n'),
-                            't' => scont.push('\t'),
-                            'r' => scont.push('\r'),
-                            '\\' => scont.push('\\'),
-                            '\'' => scont.push('\''),
-                            '"' => scont.push('"'),
-                            _ => scont.push(c),
+            '/' if data[i..].starts_with("/*") => match data[i + 2..].find("*/") {
+                Some(n) => {
+                    i += 2 + n + 2;
+                    res.push(Token::Comment(data[tok_start..i].to_string()));
+                }
+                None => {
+                    i = data.len();
+                    res.push(Token::Invalid(String::from("unterminated comment")));
+                }
+            },
+
+            c if is_line_terminator(c) => {
+                res.push(Token::Newline);
+                i += if data[i..].starts_with("\r\n") {
+                    2
+                } else {
+                    c.len_utf8()
+                };
+                line += 1;
+                line_start = i;
+            }
+
+            _ => match punctuator(&dred[i..]) {
+                Some((p, op)) => {
+                    if let Some(depth) = templates.last_mut() {
+                        match op {
+                            EOperator::LBrace => *depth += 1,
+                            EOperator::RBrace => *depth -= 1,
+                            _ => (),
                         }
-                        saw_bs = false;
-                    } else if c == '\\' && !is_raw {
-                        saw_bs = true;
-                    } else if c == q && !saw_bs {
-                        break;
-                    } else {
-                        scont.push(c);
-                        saw_bs = false;
                     }
 
-                    i += 1;
+                    res.push(Token::Operator(op.clone()));
+                    i += p.len();
                 }
+                None => {
+                    if !is_space(iv) {
+                        res.push(Token::Invalid(String::from("invalid or unexpected token")));
+                    }
+                    i += iv.len_utf8();
+                }
+            },
+        }
 
-                res.push(Token::Str {
-                    v: scont,
-                    is_raw,
-                    is_fmt,
-                });
-            }
-
-            '\n' => {
-                res.push(Token::Newline);
+        /* line breaks inside multi-line strings, templates and comments */
+        if !is_line_terminator(iv) {
+            for n in line_ends(&data[tok_start..i]) {
+                line += 1;
+                line_start = tok_start + n;
             }
-
-            _ => (),
         }
 
-        i += 1;
+        if res.len() > tok_count {
+            spans.push(Span {
+                start: tok_start,
+                end: i,
+                line: tok_line,
+                col: tok_col,
+            });
+        }
     }
 
-    res
+    res.into_iter()
+        .zip(spans)
+        .map(|(tok, span)| Lexeme { tok, span })
+        .collect()
 }